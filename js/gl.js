@@ -12,6 +12,8 @@ const version = 2;
 
 const canvas = document.querySelector("#glcanvas");
 var gl;
+// 1 or 2, extensions are acquired again when a lost context is restored
+var webgl_version;
 
 var clipboard = null;
 
@@ -25,6 +27,7 @@ var high_dpi = false;
 var blocking_event_loop = false;
 
 function init_webgl(version) {
+    webgl_version = version;
     if (version == 1) {
         gl = canvas.getContext("webgl");
 
@@ -89,6 +92,10 @@ function init_webgl(version) {
         }
     } else {
         gl = canvas.getContext("webgl2");
+        // WebGL 2 has queries, GL_TIME_ELAPSED comes with the extension
+        if (gl != null && gl.getExtension('EXT_disjoint_timer_query_webgl2')) {
+            gl['getQueryObject'] = gl['getQueryParameter'];
+        }
     }
     if (gl === null) {
         alert("Unable to initialize WebGL. Your browser or machine may not support it.");
@@ -299,7 +306,10 @@ function _glGenObject(n, buffers, createFunction, objectTable, functionName) {
             console.error("GL_INVALID_OPERATION");
             GL.recordError(0x0502 /* GL_INVALID_OPERATION */);
 
-            alert('GL_INVALID_OPERATION in ' + functionName + ': GLctx.' + createFunction + ' returned null - most likely GL context is lost!');
+            // a lost context is reported with context_lost_event instead
+            if (!gl.isContextLost()) {
+                alert('GL_INVALID_OPERATION in ' + functionName + ': GLctx.' + createFunction + ' returned null - most likely GL context is lost!');
+            }
         }
         getArray(buffers + i * 4, Int32Array, 1)[0] = id;
     }
@@ -1118,7 +1128,7 @@ var importObject = {
         },
         glDeleteQueries: function (n, ids) {
             for (var i = 0; i < n; i++) {
-                var id = getArray(textures + i * 4, Uint32Array, 1)[0];
+                var id = getArray(ids + i * 4, Uint32Array, 1)[0];
                 var query = GL.timerQueries[id];
                 if (!query) {
                     continue;
@@ -1375,6 +1385,16 @@ var importObject = {
             window.addEventListener("focus", checkFocus);
             window.addEventListener("blur", checkFocus);
 
+            canvas.addEventListener("webglcontextlost", function (event) {
+                // without preventDefault the context is never restored
+                event.preventDefault();
+                wasm_exports.on_context_lost();
+            }, false);
+            canvas.addEventListener("webglcontextrestored", function () {
+                init_webgl(webgl_version);
+                wasm_exports.on_context_restored();
+            }, false);
+
             window.blocking_event_loop = blocking;
             window.requestAnimationFrame(animation);
         },
@@ -1456,6 +1476,18 @@ var importObject = {
             }
             animation_frame_timeout = window.requestAnimationFrame(animation);
         },
+        sapp_is_elapsed_timer_supported: function () {
+            return typeof gl.getQueryObject === 'function';
+        },
+        sapp_lose_context: function () {
+            var ext = gl.getExtension('WEBGL_lose_context');
+            if (ext == null) {
+                console.warn("WEBGL_lose_context is not supported");
+                return;
+            }
+            ext.loseContext();
+            setTimeout(function () { ext.restoreContext(); }, 1000);
+        },
         init_webgl
     }
 };
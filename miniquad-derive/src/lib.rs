@@ -0,0 +1,204 @@
+//! `#[derive(Vertex)]` and `#[derive(Uniforms)]` for miniquad, enabled with
+//! miniquad's `derive` feature. See `miniquad::graphics::layout` for the details.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, LitStr, Type};
+
+/// Implement `miniquad::VertexLayout`, one attribute per field.
+///
+/// Attribute names are the field names, or `#[vertex(name = "in_pos")]`.
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_vertex(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implement `miniquad::UniformsLayout`, one uniform per field.
+///
+/// Uniform names are the field names, or `#[uniform(name = "mvp")]`.
+/// `#[uniform(array)]` on a `[T; N]` field declares an array of `N` uniforms of type `T`.
+#[proc_macro_derive(Uniforms, attributes(uniform))]
+pub fn derive_uniforms(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_uniforms(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct Field {
+    name: String,
+    ty: Type,
+    // `#[uniform(array)]`: element type and length
+    array: Option<(Type, syn::Expr)>,
+}
+
+/// The fields of a `#[repr(C)]` struct, with their `#[<attribute>(..)]` options applied.
+fn fields(input: &DeriveInput, attribute: &str) -> Result<Vec<Field>, Error> {
+    let derive = if attribute == "vertex" {
+        "Vertex"
+    } else {
+        "Uniforms"
+    };
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    format!("#[derive({derive})] needs named fields"),
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                format!("#[derive({derive})] is only for structs"),
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            format!("#[derive({derive})] does not support generic structs"),
+        ));
+    }
+    let mut repr_c = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        attr.parse_nested_meta(|meta| {
+            repr_c |= meta.path.is_ident("C");
+            Ok(())
+        })?;
+    }
+    if !repr_c {
+        return Err(Error::new(
+            input.ident.span(),
+            format!("#[derive({derive})] needs #[repr(C)], the field order matters"),
+        ));
+    }
+
+    let mut fields = vec![];
+    for field in named {
+        let mut name = field.ident.as_ref().unwrap().to_string();
+        let mut array = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident(attribute))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("array") && attribute == "uniform" {
+                    array = true;
+                    Ok(())
+                } else {
+                    Err(meta.error(format!("unknown {attribute} option")))
+                }
+            })?;
+        }
+        let array = match (&field.ty, array) {
+            (_, false) => None,
+            (Type::Array(ty), true) => Some(((*ty.elem).clone(), ty.len.clone())),
+            (ty, true) => {
+                return Err(Error::new(
+                    ty.span(),
+                    "#[uniform(array)] needs an array type",
+                ))
+            }
+        };
+        fields.push(Field {
+            name,
+            ty: field.ty.clone(),
+            array,
+        });
+    }
+    Ok(fields)
+}
+
+/// Compile time check that the struct has no padding: miniquad reads the
+/// fields one after the other.
+fn packed_check(input: &DeriveInput, fields: &[Field], what: &str) -> TokenStream2 {
+    let ident = &input.ident;
+    let types = fields.iter().map(|field| &field.ty);
+    let message = format!(
+        "{ident} has padding between {what}s, reorder the fields or use types with a smaller alignment"
+    );
+    quote! {
+        const _: () = assert!(
+            ::std::mem::size_of::<#ident>() == 0 #(+ ::std::mem::size_of::<#types>())*,
+            #message
+        );
+    }
+}
+
+fn expand_vertex(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = fields(input, "vertex")?;
+    let ident = &input.ident;
+    let attributes = fields.iter().map(|Field { name, ty, .. }| {
+        quote! {
+            ::miniquad::VertexAttribute::with_buffer(
+                #name,
+                <#ty as ::miniquad::graphics::layout::VertexField>::FORMAT,
+                buffer_index,
+            )
+        }
+    });
+    let check = packed_check(input, &fields, "vertex attribute");
+
+    Ok(quote! {
+        impl ::miniquad::VertexLayout for #ident {
+            fn buffer_layout() -> ::miniquad::BufferLayout {
+                ::miniquad::BufferLayout {
+                    stride: ::std::mem::size_of::<#ident>() as i32,
+                    ..::std::default::Default::default()
+                }
+            }
+
+            fn attributes(buffer_index: usize) -> ::std::vec::Vec<::miniquad::VertexAttribute> {
+                ::std::vec![#(#attributes),*]
+            }
+        }
+
+        #check
+    })
+}
+
+fn expand_uniforms(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = fields(input, "uniform")?;
+    let ident = &input.ident;
+    let uniforms = fields.iter().map(|Field { name, ty, array }| {
+        let (ty, count) = match array {
+            Some((elem, len)) => (elem, quote!(#len)),
+            None => (ty, quote!(1)),
+        };
+        quote! {
+            ::miniquad::UniformDesc::new(
+                #name,
+                <#ty as ::miniquad::graphics::layout::UniformField>::TYPE,
+            )
+            .array(#count)
+        }
+    });
+    let check = packed_check(input, &fields, "uniform");
+
+    Ok(quote! {
+        impl ::miniquad::UniformsLayout for #ident {
+            fn uniform_block_layout() -> ::miniquad::UniformBlockLayout {
+                ::miniquad::UniformBlockLayout {
+                    uniforms: ::std::vec![#(#uniforms),*],
+                }
+            }
+        }
+
+        #check
+    })
+}
@@ -0,0 +1,55 @@
+use miniquad::*;
+
+#[derive(Vertex)]
+#[repr(C)]
+#[allow(dead_code)]
+struct Vert {
+    #[vertex(name = "in_pos")]
+    pos: [f32; 2],
+    #[vertex(name = "in_color")]
+    color: [u8; 4],
+    uv: [f32; 2],
+}
+
+#[derive(Uniforms)]
+#[repr(C)]
+#[allow(dead_code)]
+struct Uniforms {
+    mvp: [[f32; 4]; 4],
+    #[uniform(name = "u_offset")]
+    offset: [f32; 2],
+    #[uniform(array)]
+    lights: [[f32; 4]; 3],
+}
+
+#[test]
+fn test_vertex() {
+    assert_eq!(Vert::buffer_layout().stride, 20);
+    let attributes = Vert::attributes(1);
+    let attributes: Vec<_> = attributes
+        .iter()
+        .map(|a| (a.name, a.format, a.buffer_index))
+        .collect();
+    assert_eq!(
+        attributes,
+        [
+            ("in_pos", VertexFormat::Float2, 1),
+            ("in_color", VertexFormat::Byte4, 1),
+            ("uv", VertexFormat::Float2, 1),
+        ]
+    );
+}
+
+#[test]
+fn test_uniforms() {
+    let layout = Uniforms::uniform_block_layout();
+    let uniforms: Vec<_> = layout
+        .uniforms
+        .iter()
+        .map(|u| (u.name.as_str(), u.uniform_type.size(), u.array_count))
+        .collect();
+    assert_eq!(
+        uniforms,
+        [("mvp", 64, 1), ("u_offset", 8, 1), ("lights", 16, 3)]
+    );
+}
@@ -101,6 +101,83 @@ pub enum WebGLVersion {
     WebGL2,
 }
 
+/// Which flavour of OpenGL to create a context for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GlApi {
+    /// Desktop OpenGL.
+    OpenGl,
+    /// OpenGL ES. On desktop GLX this requires `GLX_EXT_create_context_es2_profile`.
+    Gles,
+}
+
+/// OpenGL profile, only meaningful for desktop GL 3.2 and later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GlProfile {
+    Core,
+    Compatibility,
+}
+
+/// Settings for the OpenGL context, used by GLX and EGL (X11, Wayland and Android).
+///
+/// If the exact configuration can't be created, the request is relaxed step by step:
+/// first `no_error` and `robustness` are dropped, then the api, version and profile
+/// are reset to the platform default, and finally [`Platform::debug_context`] is dropped.
+/// The context actually created is reported in [`ContextInfo::gl_context`].
+///
+/// To mirror an Android GLES3 target during development on X11:
+/// ```ignore
+/// conf.platform.gl_context = GlContextRequest {
+///     api: Some(GlApi::Gles),
+///     version: Some((3, 0)),
+///     ..Default::default()
+/// };
+/// ```
+///
+/// [`ContextInfo::gl_context`]: crate::graphics::ContextInfo::gl_context
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GlContextRequest {
+    /// `None` keeps the platform default: desktop GL on GLX, GLES on EGL.
+    pub api: Option<GlApi>,
+    /// `(major, minor)`. `None` keeps the platform default: 2.1 on GLX, 2.0 on EGL.
+    pub version: Option<(u8, u8)>,
+    pub profile: Option<GlProfile>,
+    /// Robust buffer access, with the context being lost on GPU reset instead of
+    /// hanging or crashing the application.
+    pub robustness: bool,
+    /// Ask the driver to skip error checking (`KHR_no_error`).
+    /// Errors become undefined behavior, so only use it for well tested release builds.
+    /// Can't be combined with `Platform::debug_context`, the debug context wins.
+    pub no_error: bool,
+}
+
+impl GlContextRequest {
+    /// Configurations to try, from the requested one to the platform default,
+    /// along with whether to ask for a debug context (`Platform::debug_context`).
+    pub(crate) fn fallbacks(&self, debug: bool) -> Vec<(GlContextRequest, bool)> {
+        let mut attempt = (*self, debug);
+        attempt.0.no_error &= !debug;
+
+        let relax: [fn(&mut (GlContextRequest, bool)); 4] = [
+            |(r, _)| r.no_error = false,
+            |(r, _)| r.robustness = false,
+            |(r, _)| {
+                r.api = None;
+                r.version = None;
+                r.profile = None;
+            },
+            |(_, debug)| *debug = false,
+        ];
+        let mut attempts = vec![attempt];
+        for relax in &relax {
+            relax(&mut attempt);
+            if attempts.last() != Some(&attempt) {
+                attempts.push(attempt);
+            }
+        }
+        attempts
+    }
+}
+
 /// Platform-specific settings.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Platform {
@@ -146,6 +223,17 @@ pub struct Platform {
     /// When using Wayland, this controls whether to draw the default window decorations.
     pub wayland_use_fallback_decorations: bool,
 
+    /// Request a debug OpenGL context (`GLX_CONTEXT_DEBUG_BIT_ARB` on GLX,
+    /// `EGL_CONTEXT_OPENGL_DEBUG` on EGL).
+    ///
+    /// With a debug context and `GL_KHR_debug` available, driver messages are
+    /// routed into the `log` module (or stderr without the `log-impl` feature).
+    /// Debug contexts may be noticeably slower, keep it off for release builds.
+    pub debug_context: bool,
+
+    /// OpenGL version, profile and flags to request on GLX and EGL.
+    pub gl_context: GlContextRequest,
+
     /// Set the `WM_CLASS` window property on X11
     // in fact `WM_CLASS` contains two strings "instance name" and "class name"
     // for most purposes they are the same so we just use class name for simplicity
@@ -164,11 +252,67 @@ impl Default for Platform {
             swap_interval: None,
             framebuffer_alpha: false,
             wayland_use_fallback_decorations: true,
+            debug_context: false,
+            gl_context: GlContextRequest::default(),
             linux_x11_wm_class: "miniquad-application",
         }
     }
 }
 
+/// Pixel format of the default framebuffer's color buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FramebufferColor {
+    /// 8 bits per channel.
+    #[default]
+    Rgba8,
+    /// 10 bits per color channel, 2 bits of alpha.
+    Rgb10A2,
+    /// 16 bit floating point per channel, for HDR output.
+    /// Supported on GLX with `GLX_ARB_fbconfig_float` and on EGL with
+    /// `EGL_EXT_pixel_format_float`.
+    Rgba16F,
+}
+
+impl FramebufferColor {
+    /// Bits per color channel and alpha bits.
+    pub(crate) fn bits(self) -> (u8, u8) {
+        match self {
+            FramebufferColor::Rgba8 => (8, 8),
+            FramebufferColor::Rgb10A2 => (10, 2),
+            FramebufferColor::Rgba16F => (16, 16),
+        }
+    }
+}
+
+/// Requested format of the default framebuffer.
+///
+/// This is a hint: the closest available configuration is picked.
+/// Use `ContextInfo::framebuffer` to check what was actually created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FramebufferConfig {
+    /// Color buffer format. Alpha is still controlled by `Platform::framebuffer_alpha`
+    /// on Android.
+    pub color: FramebufferColor,
+    /// Depth buffer bits, `0` for no depth buffer.
+    pub depth_bits: u8,
+    /// Stencil buffer bits, `0` for no stencil buffer.
+    pub stencil_bits: u8,
+    /// If `true`, request an sRGB capable default framebuffer and enable
+    /// `GL_FRAMEBUFFER_SRGB`, so linear shader output gets encoded on write.
+    pub srgb: bool,
+}
+
+impl Default for FramebufferConfig {
+    fn default() -> FramebufferConfig {
+        FramebufferConfig {
+            color: FramebufferColor::Rgba8,
+            depth_bits: 24,
+            stencil_bits: 8,
+            srgb: false,
+        }
+    }
+}
+
 /// Describes a hardware and platform-specific setup.
 #[derive(Debug)]
 pub struct Conf {
@@ -193,8 +337,17 @@ pub struct Conf {
 
     /// MSAA sample count.
     /// Defaults to `1`.
+    ///
+    /// This is a request, the achieved count is `ContextInfo::framebuffer.sample_count`.
+    /// `RenderingBackend::set_default_pass_sample_count` can change it at runtime.
     pub sample_count: i32,
 
+    /// Depth, stencil and color format of the default framebuffer.
+    /// Defaults to RGBA8 with 24 bit depth and 8 bit stencil. On EGL (Android, Wayland)
+    /// the default keeps the previous 16 bit depth, no stencil config, set it
+    /// explicitly to ask for anything else.
+    pub framebuffer: FramebufferConfig,
+
     /// If `true`, the user can resize the window.
     pub window_resizable: bool,
 
@@ -250,6 +403,7 @@ impl Default for Conf {
             high_dpi: false,
             fullscreen: false,
             sample_count: 1,
+            framebuffer: FramebufferConfig::default(),
             window_resizable: true,
             icon: Some(Icon::miniquad_logo()),
             platform: Default::default(),
@@ -267,6 +421,7 @@ impl Default for Conf {
             high_dpi: true,
             fullscreen: true, //
             sample_count: 1,
+            framebuffer: FramebufferConfig::default(),
             window_resizable: false, //
             icon: Some(Icon::miniquad_logo()),
             platform: Default::default(),
@@ -230,4 +230,15 @@ pub trait EventHandler {
     /// `ctx.dropped_file_path()`, and for wasm targets the file bytes
     /// can be requested with `ctx.dropped_file_bytes()`.
     fn files_dropped_event(&mut self) {}
+
+    /// The rendering context was lost, all GPU resources are gone.
+    /// Only sent on wasm (WebGL `webglcontextlost`) and Android (`EGL_CONTEXT_LOST`).
+    /// Drawing is ignored until `context_restored_event`.
+    fn context_lost_event(&mut self) {}
+
+    /// A new rendering context replaced the lost one.
+    /// `RenderingBackend::restore_context` runs on the next `begin_pass`, or call it here:
+    /// resources created with `set_restore_resources(true)` are recreated under the
+    /// same handles, the others have to be created again.
+    fn context_restored_event(&mut self) {}
 }
@@ -1,19 +1,40 @@
 //mod texture;
 
 use crate::native::gl::*;
+use crate::ResourceId;
 
-use std::{error::Error, fmt::Display};
+use std::{collections::HashMap, error::Error, fmt::Display};
 
 //pub use texture::{FilterMode, TextureAccess, TextureFormat, TextureParams, TextureWrap};
 
+pub mod batch;
+pub mod capture;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod egl_image;
 mod gl;
+pub mod graph;
+pub mod layout;
+pub mod null;
+pub mod overlay;
+pub mod owned;
+pub mod resolution;
+pub mod trace;
+pub mod typed;
+pub mod video;
 
 pub use gl::raw_gl;
 
 #[cfg(target_vendor = "apple")]
 mod metal;
 
+pub use gl::upload::{UploadContext, UploadedBuffer, UploadedTexture};
 pub use gl::GlContext;
+pub use layout::{UniformsLayout, VertexLayout};
+pub use null::NullContext;
+pub use owned::{
+    DeletionQueue, Owned, OwnedBuffer, OwnedPipeline, OwnedRenderPass, OwnedShader, OwnedTexture,
+};
+pub use typed::{TypedBindings, TypedBuffer, TypedPipeline};
 
 #[cfg(target_vendor = "apple")]
 pub use metal::MetalContext;
@@ -325,11 +346,30 @@ pub enum TextureFormat {
     Depth,
     Depth32,
     Alpha,
+    /// Two 8 bit channels. In GLSL they are sampled as `.r` and `.a` on every platform,
+    /// in Metal as `.r` and `.g`.
+    RG8,
+    /// RGBA8 with sRGB encoded color, decoded to linear when sampled.
+    SRGBA8,
+    /// BC1 (DXT1) with 1-bit alpha. Requires `features.texture_compression_bc`.
+    Bc1,
+    /// BC3 (DXT5). Requires `features.texture_compression_bc`.
+    Bc3,
+    /// BC7. Requires `features.texture_compression_bc`.
+    Bc7,
+    /// ETC2 RGB. Requires `features.texture_compression_etc2`.
+    Etc2Rgb8,
+    /// ETC2 RGBA with EAC alpha. Requires `features.texture_compression_etc2`.
+    Etc2Rgba8,
+    /// ASTC LDR with 4x4 blocks. Requires `features.texture_compression_astc`.
+    Astc4x4,
 }
 impl TextureFormat {
     /// Returns the size in bytes of texture with `dimensions`.
+    /// Compressed formats are padded to whole 4x4 blocks.
     pub fn size(self, width: u32, height: u32) -> u32 {
         let square = width * height;
+        let blocks = ((width + 3) / 4) * ((height + 3) / 4);
         match self {
             TextureFormat::RGB8 => 3 * square,
             TextureFormat::RGBA8 => 4 * square,
@@ -337,8 +377,29 @@ impl TextureFormat {
             TextureFormat::Depth => 2 * square,
             TextureFormat::Depth32 => 4 * square,
             TextureFormat::Alpha => 1 * square,
+            TextureFormat::RG8 => 2 * square,
+            TextureFormat::SRGBA8 => 4 * square,
+            TextureFormat::Bc1 | TextureFormat::Etc2Rgb8 => 8 * blocks,
+            TextureFormat::Bc3
+            | TextureFormat::Bc7
+            | TextureFormat::Etc2Rgba8
+            | TextureFormat::Astc4x4 => 16 * blocks,
         }
     }
+
+    /// Block compressed formats can't be rendered to or read back,
+    /// and are updated in whole 4x4 blocks.
+    pub fn is_compressed(self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Bc1
+                | TextureFormat::Bc3
+                | TextureFormat::Bc7
+                | TextureFormat::Etc2Rgb8
+                | TextureFormat::Etc2Rgba8
+                | TextureFormat::Astc4x4
+        )
+    }
 }
 
 /// Sets the wrap parameter for texture.
@@ -377,6 +438,13 @@ pub enum TextureAccess {
 pub enum TextureKind {
     Texture2D,
     CubeMap,
+    /// A multisampled render target that shaders can read, with `sampler2DMS` and
+    /// `texelFetch(tex, coord, sample)`. Unlike `Texture2D` render targets with a
+    /// `sample_count > 1`, which can only be resolved. Allows custom resolves and
+    /// multisampled depth reads.
+    ///
+    /// Requires `features.multisample_textures` and `TextureAccess::RenderTarget`.
+    Texture2DMultisample,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -419,13 +487,96 @@ impl Default for TextureParams {
     }
 }
 
+impl TextureParams {
+    /// Size of a mipmap level: half the previous level, rounded down, but at least 1.
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        let shrink = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+        (shrink(self.width), shrink(self.height))
+    }
+}
+
+/// Part of one mipmap level of a texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TextureRegion {
+    pub level: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Memory layout of the CPU side pixels, the same as `GL_UNPACK_ROW_LENGTH`
+/// and `GL_UNPACK_ALIGNMENT`.
+///
+/// Allows uploading a part of a bigger image without copying it out first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PixelLayout {
+    /// Length of a source row in pixels, 0 for the width of the uploaded region.
+    pub row_length: u32,
+    /// Every row starts at a multiple of `alignment` bytes: 1, 2, 4 or 8.
+    pub alignment: u32,
+}
+
+impl Default for PixelLayout {
+    fn default() -> Self {
+        PixelLayout {
+            row_length: 0,
+            alignment: 1,
+        }
+    }
+}
+
+impl PixelLayout {
+    /// Pixels of a `width` wide region laid out `row_length` pixels apart.
+    pub fn with_row_length(row_length: u32) -> PixelLayout {
+        PixelLayout {
+            row_length,
+            ..Default::default()
+        }
+    }
+
+    /// Bytes from the start of one row to the start of the next,
+    /// for compressed formats a row of 4x4 blocks.
+    pub fn row_stride(&self, format: TextureFormat, width: u32) -> usize {
+        assert!(
+            matches!(self.alignment, 1 | 2 | 4 | 8),
+            "PixelLayout alignment should be 1, 2, 4 or 8"
+        );
+        let row_length = if self.row_length == 0 {
+            width
+        } else {
+            assert!(self.row_length >= width, "PixelLayout row_length < width");
+            self.row_length
+        };
+        let alignment = self.alignment as usize;
+        (format.size(row_length, 1) as usize + alignment - 1) / alignment * alignment
+    }
+
+    /// Bytes needed for a `width`x`height` region, the last row doesn't need padding.
+    pub fn data_size(&self, format: TextureFormat, width: u32, height: u32) -> usize {
+        if width == 0 || height == 0 {
+            return 0;
+        }
+        if format.is_compressed() {
+            // rows of blocks, always tightly packed
+            return format.size(width, height) as usize;
+        }
+        self.row_stride(format, width) * (height as usize - 1) + format.size(width, 1) as usize
+    }
+
+    /// True if the rows follow each other without any padding.
+    pub fn is_tight(&self, format: TextureFormat, width: u32) -> bool {
+        self.row_stride(format, width) == format.size(width, 1) as usize
+    }
+}
+
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
-pub struct ShaderId(usize);
+pub struct ShaderId(pub(crate) ResourceId);
 
 // Inner hence we can't have private data in enum fields
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub(crate) enum TextureIdInner {
-    Managed(usize),
+    Managed(ResourceId),
     Raw(RawId),
 }
 
@@ -566,8 +717,8 @@ impl Default for PassAction {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub struct RenderPass(usize);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderPass(pub(crate) ResourceId);
 
 pub const MAX_VERTEX_ATTRIBUTES: usize = 16;
 pub const MAX_SHADERSTAGE_IMAGES: usize = 12;
@@ -582,6 +733,14 @@ pub struct Features {
     /// With resolve_attachments: false, not-none resolve_img in new_render_pass will
     /// result in a runtime panic.
     pub resolve_attachments: bool,
+    /// `TextureFormat::Bc1`, `Bc3` and `Bc7`, usually on desktops.
+    pub texture_compression_bc: bool,
+    /// `TextureFormat::Etc2Rgb8` and `Etc2Rgba8`, on GLES3 and GL4.3.
+    pub texture_compression_etc2: bool,
+    /// `TextureFormat::Astc4x4`, on most mobile GPUs.
+    pub texture_compression_astc: bool,
+    /// `TextureKind::Texture2DMultisample`, on GL3.2+ and GLES3.1+.
+    pub multisample_textures: bool,
 }
 
 impl Default for Features {
@@ -589,6 +748,10 @@ impl Default for Features {
         Features {
             instancing: true,
             resolve_attachments: true,
+            texture_compression_bc: false,
+            texture_compression_etc2: false,
+            texture_compression_astc: false,
+            multisample_textures: false,
         }
     }
 }
@@ -738,7 +901,7 @@ pub struct PipelineParams {
 
 // TODO(next major version bump): should be PipelineId
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-pub struct Pipeline(usize);
+pub struct Pipeline(pub(crate) ResourceId);
 
 impl Default for PipelineParams {
     fn default() -> PipelineParams {
@@ -805,7 +968,50 @@ fn gl_usage(usage: &BufferUsage) -> GLenum {
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
-pub struct BufferId(usize);
+pub struct BufferId(pub(crate) ResourceId);
+
+/// Any of the resource handles handed out by a `RenderingBackend`.
+///
+/// Used by the queries that work the same way for every kind of resource,
+/// like [`RenderingBackend::is_valid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceHandle {
+    Buffer(BufferId),
+    Texture(TextureId),
+    Shader(ShaderId),
+    Pipeline(Pipeline),
+    RenderPass(RenderPass),
+}
+
+impl From<BufferId> for ResourceHandle {
+    fn from(id: BufferId) -> Self {
+        ResourceHandle::Buffer(id)
+    }
+}
+
+impl From<TextureId> for ResourceHandle {
+    fn from(id: TextureId) -> Self {
+        ResourceHandle::Texture(id)
+    }
+}
+
+impl From<ShaderId> for ResourceHandle {
+    fn from(id: ShaderId) -> Self {
+        ResourceHandle::Shader(id)
+    }
+}
+
+impl From<Pipeline> for ResourceHandle {
+    fn from(id: Pipeline) -> Self {
+        ResourceHandle::Pipeline(id)
+    }
+}
+
+impl From<RenderPass> for ResourceHandle {
+    fn from(id: RenderPass) -> Self {
+        ResourceHandle::RenderPass(id)
+    }
+}
 
 /// `ElapsedQuery` is used to measure duration of GPU operations.
 ///
@@ -920,17 +1126,35 @@ impl ElapsedQuery {
     ///
     /// Use [`ElapsedQuery::is_supported()`] to check if functionality is available and the method can be called.
     pub fn get_result(&self) -> u64 {
-        // let mut time: GLuint64 = 0;
-        // assert!(self.gl_query != 0);
-        // unsafe { glGetQueryObjectui64v(self.gl_query, GL_QUERY_RESULT, &mut time) };
-        // time
-        0
+        let mut time: GLuint64 = 0;
+        assert!(self.gl_query != 0);
+        unsafe { glGetQueryObjectui64v(self.gl_query, GL_QUERY_RESULT, &mut time) };
+        time
     }
 
     /// Reports whenever elapsed timer is supported and other methods can be invoked.
+    ///
+    /// Requires `GL_ARB_timer_query` or `GL_EXT_timer_query` on desktop GL,
+    /// `EXT_disjoint_timer_query` on WebGL 1 and `EXT_disjoint_timer_query_webgl2` on WebGL 2.
+    /// Always false with the metal backend.
     pub fn is_supported() -> bool {
-        unimplemented!();
-        //unsafe { sapp_is_elapsed_timer_supported() }
+        #[cfg(target_arch = "wasm32")]
+        unsafe {
+            crate::native::wasm::sapp_is_elapsed_timer_supported()
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        unsafe {
+            [
+                "glBeginQuery",
+                "glEndQuery",
+                "glGetQueryObjectiv",
+                "glGetQueryObjectui64v",
+            ]
+            .iter()
+            .all(|f| is_gl_func_loaded(f))
+                && (gl::has_gl_extension("GL_ARB_timer_query")
+                    || gl::has_gl_extension("GL_EXT_timer_query"))
+        }
     }
 
     /// Reports whenever result of submitted query is available for retrieval with
@@ -941,17 +1165,15 @@ impl ElapsedQuery {
     ///
     /// Use [`ElapsedQuery::is_supported()`] to check if functionality is available and the method can be called.
     pub fn is_available(&self) -> bool {
-        // let mut available: GLint = 0;
-
-        // // begin_query was not called yet
-        // if self.gl_query == 0 {
-        //     return false;
-        // }
+        let mut available: GLint = 0;
 
-        //unsafe { glGetQueryObjectiv(self.gl_query, GL_QUERY_RESULT_AVAILABLE, &mut available) };
-        //available != 0
+        // begin_query was not called yet
+        if self.gl_query == 0 {
+            return false;
+        }
 
-        false
+        unsafe { glGetQueryObjectiv(self.gl_query, GL_QUERY_RESULT_AVAILABLE, &mut available) };
+        available != 0
     }
 
     /// Delete query.
@@ -976,11 +1198,17 @@ pub struct Arg<'a> {
     _phantom: std::marker::PhantomData<&'a ()>,
 }
 
+#[derive(Clone, Copy)]
 pub enum TextureSource<'a> {
     Empty,
     Bytes(&'a [u8]),
-    /// Array of `[cubemap_face][mipmap_level][bytes]`
+    /// Array of `[cubemap_face][mipmap_level][bytes]`, levels sized as in `Mips`.
     Array(&'a [&'a [&'a [u8]]]),
+    /// Pre-baked mipmap chain of a 2D texture, `[mipmap_level][bytes]`.
+    ///
+    /// Every level is tightly packed and sized by `TextureParams::level_size`.
+    /// The chain may stop before 1x1, on OpenGL sampling is then limited to the given levels.
+    Mips(&'a [&'a [u8]]),
 }
 
 pub enum BufferSource<'a> {
@@ -1084,6 +1312,32 @@ pub struct ContextInfo {
     /// List of platform-dependent features that miniquad failed to make cross-platforms
     /// and therefore they might be missing.
     pub features: Features,
+    /// The OpenGL context that was actually created, as reported by the driver.
+    /// It may differ from `conf::Platform::gl_context` if the request had to be relaxed.
+    /// None on metal.
+    pub gl_context: Option<crate::conf::GlContextRequest>,
+    /// The context is a debug context, see `conf::Platform::debug_context`.
+    pub debug_context: bool,
+    /// The default framebuffer that was actually created.
+    /// It may differ from `conf::Conf::framebuffer`, which is only a request.
+    pub framebuffer: FramebufferInfo,
+}
+
+/// Format of the default framebuffer, as reported by the driver.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    pub red_bits: u8,
+    pub green_bits: u8,
+    pub blue_bits: u8,
+    pub alpha_bits: u8,
+    pub depth_bits: u8,
+    pub stencil_bits: u8,
+    /// MSAA samples per pixel, `1` without multisampling.
+    pub sample_count: i32,
+    /// Color writes are converted from linear to sRGB.
+    pub srgb: bool,
+    /// Color channels are floating point.
+    pub float: bool,
 }
 
 impl ContextInfo {
@@ -1097,8 +1351,248 @@ impl ContextInfo {
     }
 }
 
+/// Number of alive resources of one kind and their estimated GPU memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceCount {
+    pub count: usize,
+    /// Estimation based on `TextureFormat::size` and `buffer_size`, the driver
+    /// may allocate more due to padding and alignment.
+    pub bytes: usize,
+}
+
+/// A snapshot of currently alive resources, see `RenderingBackend::resource_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+    pub buffers: ResourceCount,
+    pub textures: ResourceCount,
+    pub shaders: usize,
+    pub pipelines: usize,
+    pub render_passes: usize,
+}
+
+impl ResourceStats {
+    /// Estimated GPU memory used by buffers and textures, in bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.buffers.bytes + self.textures.bytes
+    }
+}
+
+/// Per-frame counters, reset on every `commit_frame`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub draw_calls: usize,
+    /// `apply_pipeline` calls that actually changed the pipeline.
+    pub pipeline_switches: usize,
+    /// Texture bindings that reached the driver.
+    pub texture_binds: usize,
+    /// Data sent to the GPU through buffer and texture creation and updates.
+    pub bytes_uploaded: usize,
+    /// State changes skipped because the state was already set.
+    /// Only counted by the GL backend, always 0 on metal.
+    pub skipped_state_changes: usize,
+}
+
+/// A resource that was created and not yet deleted, see `RenderingBackend::live_resources`.
+#[derive(Clone, Debug)]
+pub struct LiveResource {
+    pub handle: ResourceHandle,
+    /// Human readable description, like "texture RGBA8 256x256".
+    pub label: String,
+    pub bytes: usize,
+}
+
+impl Display for LiveResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} bytes)", self.label, self.bytes)
+    }
+}
+
+/// Estimated GPU memory of a texture, including mipmaps, cubemap faces and MSAA samples.
+pub(crate) fn texture_memory_size(params: &TextureParams) -> usize {
+    let mut bytes = params.format.size(params.width, params.height) as usize;
+    if params.allocate_mipmaps {
+        // full mip chain is ~1/3 of the base level
+        bytes += bytes / 3;
+    }
+    if params.kind == TextureKind::CubeMap {
+        bytes *= 6;
+    }
+    bytes * params.sample_count.max(1) as usize
+}
+
+pub(crate) fn texture_label(params: &TextureParams) -> String {
+    let kind = match params.kind {
+        TextureKind::Texture2D => "texture",
+        TextureKind::CubeMap => "cubemap",
+        TextureKind::Texture2DMultisample => "multisampled texture",
+    };
+    format!(
+        "{} {:?} {}x{}",
+        kind, params.format, params.width, params.height
+    )
+}
+
+/// Prefix the generated description of a resource with the user label, if any.
+pub(crate) fn labeled(
+    labels: &HashMap<ResourceHandle, String>,
+    handle: ResourceHandle,
+    description: String,
+) -> String {
+    match labels.get(&handle) {
+        Some(label) => format!("\"{label}\" {description}"),
+        None => description,
+    }
+}
+
+pub(crate) fn print_leak_report(live: &[LiveResource]) {
+    if live.is_empty() {
+        return;
+    }
+    eprintln!("miniquad: {} resources were never deleted:", live.len());
+    for resource in live {
+        eprintln!("    {resource}");
+    }
+}
+
+/// Deletions queued by `RenderingBackend::set_deferred_deletion`
+/// and sent through the `RenderingBackend::deletion_queue`.
+#[derive(Default)]
+pub(crate) struct DeferredDeletions {
+    enabled: bool,
+    queue: Vec<ResourceHandle>,
+    owned: owned::DeletionReceiver,
+}
+
+impl DeferredDeletions {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(crate) fn deletion_queue(&self) -> DeletionQueue {
+        self.owned.queue()
+    }
+
+    /// Queue `handle` when deletions are deferred, false if it goes right away.
+    pub(crate) fn defer(&mut self, handle: ResourceHandle) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        assert!(
+            !self.queue.contains(&handle),
+            "{:?} was deleted twice",
+            handle
+        );
+        self.queue.push(handle);
+        true
+    }
+
+    /// Run the queued and the dropped owned handles deletions on `ctx`,
+    /// at the end of its `commit_frame`.
+    pub(crate) fn flush<B: RenderingBackend>(ctx: &mut B, deferred: fn(&mut B) -> &mut Self) {
+        let this = deferred(ctx);
+        while let Some(handle) = this.owned.try_recv() {
+            this.queue.push(handle);
+        }
+        if this.queue.is_empty() {
+            return;
+        }
+        let queue = std::mem::take(&mut this.queue);
+        let enabled = std::mem::replace(&mut this.enabled, false);
+        for handle in queue {
+            // a queued render pass takes its attachments along,
+            // the attachments may be queued as well
+            if !ctx.is_valid(handle) {
+                continue;
+            }
+            match handle {
+                ResourceHandle::Buffer(id) => ctx.delete_buffer(id),
+                ResourceHandle::Texture(id) => ctx.delete_texture(id),
+                ResourceHandle::Shader(id) => ctx.delete_shader(id),
+                ResourceHandle::Pipeline(id) => ctx.delete_pipeline(id),
+                ResourceHandle::RenderPass(id) => ctx.delete_render_pass(id),
+            }
+        }
+        deferred(ctx).enabled = enabled;
+    }
+}
+
 pub trait RenderingBackend {
     fn info(&self) -> ContextInfo;
+
+    /// Counts and estimated sizes of all alive resources.
+    fn resource_stats(&self) -> ResourceStats;
+
+    /// Counters of the last committed frame.
+    fn frame_stats(&self) -> FrameStats;
+
+    /// All resources that were created and not yet deleted.
+    fn live_resources(&self) -> Vec<LiveResource>;
+
+    /// If enabled, `live_resources` are printed to stderr when the context is dropped.
+    /// Useful to catch leaked textures and buffers at shutdown.
+    /// Disabled by default.
+    fn set_leak_report(&mut self, enabled: bool);
+
+    /// True from the moment the rendering context is lost until `restore_context`.
+    ///
+    /// WebGL and Android report the loss to the application with
+    /// `EventHandler::context_lost_event`. On desktop, contexts created with
+    /// `GlContextRequest::robustness` notice GPU resets here, but miniquad can't
+    /// replace the window's context there: the application has to restart.
+    fn is_context_lost(&self) -> bool;
+
+    /// Recreate context-level state and kept resources once the platform
+    /// provides a new context, see `set_restore_resources`.
+    /// Runs automatically on the first `begin_pass` after `context_restored_event`.
+    fn restore_context(&mut self);
+
+    /// Keep creation parameters and CPU data of resources created from now on,
+    /// so `restore_context` recreates them under the same handles.
+    ///
+    /// Costs a CPU copy of every texture and buffer. Render target contents are
+    /// not kept, they come back cleared. Resources created before enabling this and
+    /// the ones from `publish_texture`/`publish_buffer` are not kept either: they stay
+    /// allocated as dead handles, delete and recreate them in `context_restored_event`.
+    /// Disabled by default.
+    fn set_restore_resources(&mut self, enabled: bool);
+
+    /// If enabled, `delete_buffer`, `delete_texture`, `delete_shader`, `delete_pipeline`
+    /// and `delete_render_pass` only queue the deletion, done at the end of the next
+    /// `commit_frame`, once the frame's commands are submitted. Resources may then be
+    /// freed in the middle of a frame still using them. The handles stay valid until
+    /// then, deleting one twice panics right away.
+    /// Disabled by default.
+    fn set_deferred_deletion(&mut self, enabled: bool);
+
+    /// Where `owned::Owned` handles go when dropped, they get deleted
+    /// at the end of the next `commit_frame`.
+    fn deletion_queue(&mut self) -> DeletionQueue;
+
+    /// Test hook: lose the context on purpose.
+    ///
+    /// On wasm the browser loses the WebGL context (`WEBGL_lose_context`) and
+    /// restores it a second later, with the usual events. Elsewhere every GL object
+    /// is deleted and `is_context_lost` stays true until `restore_context` is called,
+    /// no events are sent.
+    ///
+    /// OpenGL only: returns false and does nothing on Metal, which has no context to lose.
+    fn simulate_context_loss(&mut self) -> bool;
+
+    /// Attach a human readable name to a resource.
+    ///
+    /// The name shows up in `live_resources` and, when the driver supports
+    /// `GL_KHR_debug` (or on Metal), in graphics debuggers like RenderDoc or apitrace.
+    /// ```ignore
+    /// ctx.set_label(texture.into(), "player atlas");
+    /// ```
+    fn set_label(&mut self, handle: ResourceHandle, label: &str);
+
+    /// Open a named group of commands, groups may be nested.
+    /// Has no effect without debugging support in the driver.
+    fn push_debug_group(&mut self, name: &str);
+
+    /// Close the group opened by the last `push_debug_group`.
+    fn pop_debug_group(&mut self);
     /// For metal context's ShaderSource should contain MSL source string, for GL - glsl.
     ///
     /// If in doubt, _most_ OpenGL contexts support "#version 100" glsl shaders.
@@ -1212,6 +1706,29 @@ pub trait RenderingBackend {
         height: i32,
         bytes: &[u8],
     );
+    /// Update a part of any mipmap level, reading the pixels with a row stride.
+    ///
+    /// The level has to exist: allocated with `allocate_mipmaps`, generated with
+    /// `texture_generate_mipmaps` or uploaded with `TextureSource::Mips`.
+    /// `bytes` should hold at least `layout.data_size(..)` bytes.
+    ///
+    /// ```ignore
+    /// // upload a 64x64 tile at (128, 192) of a 1024 pixels wide RGBA8 atlas
+    /// let start = (192 * 1024 + 128) * 4;
+    /// ctx.texture_update_region(
+    ///     texture,
+    ///     TextureRegion { level: 0, x: 0, y: 0, width: 64, height: 64 },
+    ///     PixelLayout::with_row_length(1024),
+    ///     &atlas[start..],
+    /// );
+    /// ```
+    fn texture_update_region(
+        &mut self,
+        texture: TextureId,
+        region: TextureRegion,
+        layout: PixelLayout,
+        bytes: &[u8],
+    );
     fn new_render_pass(
         &mut self,
         color_img: TextureId,
@@ -1286,13 +1803,25 @@ pub trait RenderingBackend {
     /// For 1 element, u16 buffer this will return 2.
     fn buffer_size(&mut self, buffer: BufferId) -> usize;
 
+    /// Take over a texture uploaded on another thread with an `UploadContext`.
+    ///
+    /// Never blocks: while the GPU is still busy with the upload the texture
+    /// is handed back as `Err`, to be published again on a later frame.
+    ///
+    /// OpenGL only, Metal never hands out an `UploadContext` to upload with.
+    fn publish_texture(&mut self, upload: UploadedTexture) -> Result<TextureId, UploadedTexture>;
+    /// Take over a buffer uploaded on another thread with an `UploadContext`.
+    /// Same as `publish_texture`.
+    fn publish_buffer(&mut self, upload: UploadedBuffer) -> Result<BufferId, UploadedBuffer>;
+
     /// Delete GPU buffer, leaving handle unmodified.
     ///
     /// More high-level code on top of miniquad probably is going to call this in Drop
     /// implementation of some more RAII buffer object.
     ///
-    /// There is no protection against using deleted buffers later. However its not an UB in OpenGl
-    /// and thats why this function is not marked as unsafe
+    /// Handles are generation-checked: using a deleted buffer later panics with
+    /// a "stale buffer handle" message instead of touching some other buffer.
+    /// Use `is_valid` to check a handle upfront.
     fn delete_buffer(&mut self, buffer: BufferId);
 
     /// Delete GPU texture, leaving handle unmodified.
@@ -1300,8 +1829,7 @@ pub trait RenderingBackend {
     /// More high-level code on top of miniquad probably is going to call this in Drop
     /// implementation of some more RAII buffer object.
     ///
-    /// There is no protection against using deleted textures later. However its not a CPU-level UB
-    /// and thats why this function is not marked as unsafe
+    /// Using a deleted texture later panics with a "stale texture handle" message.
     fn delete_texture(&mut self, texture: TextureId);
 
     /// Delete GPU program, leaving handle unmodified.
@@ -1309,10 +1837,21 @@ pub trait RenderingBackend {
     /// More high-level code on top of miniquad probably is going to call this in Drop
     /// implementation of some more RAII buffer object.
     ///
-    /// There is no protection against using deleted programs later. However its not a CPU-level
-    /// Porgram and thats why this function is not marked as unsafe
+    /// Using a deleted shader later panics with a "stale shader handle" message.
     fn delete_shader(&mut self, program: ShaderId);
 
+    /// False if the resource behind `handle` was already deleted, or if the handle
+    /// was never created by this context.
+    ///
+    /// Textures wrapped with `TextureId::from_raw_id` are not managed by miniquad
+    /// and are always reported as valid.
+    /// ```ignore
+    /// if ctx.is_valid(texture.into()) {
+    ///     ctx.texture_update(texture, &bytes);
+    /// }
+    /// ```
+    fn is_valid(&self, handle: ResourceHandle) -> bool;
+
     /// Set a new viewport rectangle.
     /// Should be applied after begin_pass.
     fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32);
@@ -1354,7 +1893,39 @@ pub trait RenderingBackend {
 
     fn end_render_pass(&mut self);
 
+    /// Render the default pass into a managed multisampled target, resolved into the
+    /// window on `commit_frame`. The target follows the window size.
+    /// `sample_count` is clamped to what the driver supports, `1` renders straight
+    /// into the window again.
+    ///
+    /// Requires `features.resolve_attachments`, ignored on Metal.
+    fn set_default_pass_sample_count(&mut self, sample_count: i32);
+    /// Sample count of the default pass: the managed target's one,
+    /// or the window's one from `info().framebuffer`.
+    fn default_pass_sample_count(&self) -> i32;
+
+    /// Render the default pass at `scale` times the `window::screen_size()`,
+    /// upscaled into the window on `commit_frame`. The target follows the window size,
+    /// `apply_viewport` and `apply_scissor_rect` keep taking window pixels in the
+    /// default pass. `scale` is clamped to `0.1..=1.0`, `1.0` renders straight into
+    /// the window again. `resolution::DynamicResolution` drives it from frame timings.
+    ///
+    /// Requires `features.resolve_attachments`, ignored on Metal.
+    fn set_default_pass_scale(&mut self, scale: f32);
+    /// Scale of the default pass, `1.0` when it renders straight into the window.
+    fn default_pass_scale(&self) -> f32;
+    /// Filter used to upscale the default pass, `FilterMode::Linear` by default.
+    fn set_default_pass_upscale_filter(&mut self, filter: FilterMode);
+
     fn commit_frame(&mut self);
+    /// Read back the window's framebuffer, top row first.
+    ///
+    /// Call right after `commit_frame`, before the frame is presented: the
+    /// framebuffer content is undefined once the buffers are swapped.
+    /// See `capture::FrameRecorder` for saving screenshots and recordings.
+    ///
+    /// OpenGL only, None on Metal.
+    fn capture_frame(&mut self) -> Option<capture::CapturedFrame>;
 
     /// Draw elements using currently applied bindings and pipeline.
     ///
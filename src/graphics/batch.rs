@@ -0,0 +1,617 @@
+//! Immediate-mode 2D batching: colored and textured quads, triangles and lines.
+//!
+//! Geometry is collected on the CPU every frame and drawn with as few draw calls as
+//! possible: a new one starts only when the texture, pipeline or scissor rectangle
+//! changes. Coordinates are in pixels, origin at the top left.
+//!
+//! ```ignore
+//! // once
+//! let mut batch = Batch::new(&mut *ctx);
+//!
+//! // each frame
+//! batch.rect(10.0, 10.0, 100.0, 50.0, [255, 0, 0, 255]);
+//! batch.line([0.0, 0.0], [200.0, 100.0], 2.0, [255, 255, 255, 255]);
+//! batch.set_texture(Some(sprite));
+//! batch.push_transform(Transform2D::translation(300.0, 200.0).rotate(angle));
+//! batch.textured_rect(-16.0, -16.0, 32.0, 32.0, [0.0, 0.0, 1.0, 1.0], [255; 4]);
+//! batch.pop_transform();
+//!
+//! ctx.begin_default_pass(PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+//! batch.draw(&mut *ctx);
+//! ctx.end_render_pass();
+//! ```
+//!
+//! Custom pipelines set with `set_pipeline` have to read `Batch::vertex_attributes`
+//! and take the `Batch::shader_meta` uniforms.
+
+use super::*;
+
+/// A vertex of the batch: position in pixels, texture coordinates and color.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BatchVertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [u8; 4],
+}
+
+impl BatchVertex {
+    pub fn new(x: f32, y: f32, u: f32, v: f32, color: [u8; 4]) -> BatchVertex {
+        BatchVertex {
+            pos: [x, y],
+            uv: [u, v],
+            color,
+        }
+    }
+}
+
+/// 2D affine transform, `[a, b, c, d, tx, ty]` maps `(x, y)` to
+/// `(a * x + c * y + tx, b * x + d * y + ty)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform2D(pub [f32; 6]);
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Transform2D::IDENTITY
+    }
+}
+
+impl Transform2D {
+    pub const IDENTITY: Transform2D = Transform2D([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    pub fn translation(x: f32, y: f32) -> Transform2D {
+        Transform2D([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    pub fn scaling(x: f32, y: f32) -> Transform2D {
+        Transform2D([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    /// Rotation by `angle` radians, clockwise on screen since y points down.
+    pub fn rotation(angle: f32) -> Transform2D {
+        let (sin, cos) = angle.sin_cos();
+        Transform2D([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+
+    /// `other` applied first, then `self`.
+    pub fn then(self, other: Transform2D) -> Transform2D {
+        let [a, b, c, d, tx, ty] = self.0;
+        let [oa, ob, oc, od, otx, oty] = other.0;
+        Transform2D([
+            a * oa + c * ob,
+            b * oa + d * ob,
+            a * oc + c * od,
+            b * oc + d * od,
+            a * otx + c * oty + tx,
+            b * otx + d * oty + ty,
+        ])
+    }
+
+    pub fn translate(self, x: f32, y: f32) -> Transform2D {
+        self.then(Transform2D::translation(x, y))
+    }
+
+    pub fn scale(self, x: f32, y: f32) -> Transform2D {
+        self.then(Transform2D::scaling(x, y))
+    }
+
+    pub fn rotate(self, angle: f32) -> Transform2D {
+        self.then(Transform2D::rotation(angle))
+    }
+
+    pub fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [a, b, c, d, tx, ty] = self.0;
+        [a * x + c * y + tx, b * x + d * y + ty]
+    }
+}
+
+/// Scissor rectangle in pixels, origin at the top left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScissorRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl ScissorRect {
+    fn intersect(self, other: ScissorRect) -> ScissorRect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.w).min(other.x + other.w);
+        let bottom = (self.y + self.h).min(other.y + other.h);
+        ScissorRect {
+            x,
+            y,
+            w: (right - x).max(0),
+            h: (bottom - y).max(0),
+        }
+    }
+}
+
+// u16 indices: vertices are split into chunks of at most this many
+const MAX_CHUNK_VERTICES: usize = u16::MAX as usize + 1;
+
+#[derive(Default)]
+struct Chunk {
+    vertices: Vec<BatchVertex>,
+    indices: Vec<u16>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DrawCall {
+    chunk: usize,
+    pipeline: Option<Pipeline>,
+    texture: Option<TextureId>,
+    scissor: Option<ScissorRect>,
+    first_index: usize,
+    index_count: usize,
+}
+
+/// Everything recorded for a frame, no GPU involved.
+#[derive(Default)]
+struct Geometry {
+    chunks: Vec<Chunk>,
+    calls: Vec<DrawCall>,
+    pipeline: Option<Pipeline>,
+    texture: Option<TextureId>,
+    transforms: Vec<Transform2D>,
+    scissors: Vec<ScissorRect>,
+}
+
+impl Geometry {
+    fn transform(&self) -> Transform2D {
+        self.transforms
+            .last()
+            .copied()
+            .unwrap_or(Transform2D::IDENTITY)
+    }
+
+    fn add(&mut self, vertices: &[BatchVertex], indices: &[u16]) {
+        assert!(vertices.len() <= MAX_CHUNK_VERTICES);
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.vertices.len() + vertices.len() <= MAX_CHUNK_VERTICES);
+        if !fits {
+            self.chunks.push(Chunk::default());
+        }
+        let chunk_index = self.chunks.len() - 1;
+        let chunk = &mut self.chunks[chunk_index];
+
+        let state = DrawCall {
+            chunk: chunk_index,
+            pipeline: self.pipeline,
+            texture: self.texture,
+            scissor: self.scissors.last().copied(),
+            first_index: chunk.indices.len(),
+            index_count: 0,
+        };
+        let call = match self.calls.last_mut() {
+            Some(last)
+                if DrawCall {
+                    first_index: state.first_index,
+                    index_count: 0,
+                    ..*last
+                } == state
+                    && last.first_index + last.index_count == state.first_index =>
+            {
+                last
+            }
+            _ => {
+                self.calls.push(state);
+                self.calls.last_mut().unwrap()
+            }
+        };
+        call.index_count += indices.len();
+
+        let base = chunk.vertices.len() as u16;
+        let transform = self.transforms.last().copied();
+        chunk
+            .vertices
+            .extend(vertices.iter().map(|vertex| match transform {
+                Some(transform) => BatchVertex {
+                    pos: transform.apply(vertex.pos),
+                    ..*vertex
+                },
+                None => *vertex,
+            }));
+        chunk
+            .indices
+            .extend(indices.iter().map(|index| base + index));
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.calls.clear();
+    }
+}
+
+struct ChunkBuffers {
+    vertex_buffer: BufferId,
+    index_buffer: BufferId,
+    vertex_capacity: usize,
+    index_capacity: usize,
+}
+
+pub struct Batch {
+    geometry: Geometry,
+    buffers: Vec<ChunkBuffers>,
+    shader: ShaderId,
+    pipeline: Pipeline,
+    white: TextureId,
+}
+
+impl Batch {
+    /// Batch drawing with alpha blending.
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Batch {
+        let source = match ctx.info().backend {
+            Backend::OpenGl => ShaderSource::Glsl {
+                vertex: shader::VERTEX,
+                fragment: shader::FRAGMENT,
+            },
+            Backend::Metal => ShaderSource::Msl {
+                program: shader::METAL,
+            },
+        };
+        let shader = ctx
+            .new_shader(source, Batch::shader_meta())
+            .expect("batch shader");
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &Batch::vertex_attributes(),
+            shader,
+            PipelineParams {
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                ..Default::default()
+            },
+        );
+        let white = ctx.new_texture_from_rgba8(1, 1, &[255; 4]);
+        ctx.set_label(shader.into(), "batch");
+        ctx.set_label(white.into(), "batch white");
+
+        Batch {
+            geometry: Geometry::default(),
+            buffers: vec![],
+            shader,
+            pipeline,
+            white,
+        }
+    }
+
+    /// Attributes of `BatchVertex`: `in_pos`, `in_uv` and `in_color`.
+    pub fn vertex_attributes() -> Vec<VertexAttribute> {
+        vec![
+            VertexAttribute::new("in_pos", VertexFormat::Float2),
+            VertexAttribute::new("in_uv", VertexFormat::Float2),
+            VertexAttribute::new("in_color", VertexFormat::Byte4),
+        ]
+    }
+
+    /// A `mvp` matrix uniform and a `tex` image. The colors arrive as 0..255.
+    pub fn shader_meta() -> ShaderMeta {
+        shader::meta()
+    }
+
+    /// Pipeline for the next primitives, `None` for the default one.
+    pub fn set_pipeline(&mut self, pipeline: Option<Pipeline>) {
+        self.geometry.pipeline = pipeline;
+    }
+
+    /// Texture for the next primitives, `None` for plain colors.
+    pub fn set_texture(&mut self, texture: Option<TextureId>) {
+        self.geometry.texture = texture;
+    }
+
+    /// Transform the next primitives by `transform` on top of the current transform.
+    pub fn push_transform(&mut self, transform: Transform2D) {
+        let current = self.geometry.transform();
+        self.geometry.transforms.push(current.then(transform));
+    }
+
+    pub fn pop_transform(&mut self) {
+        self.geometry
+            .transforms
+            .pop()
+            .expect("pop_transform without push_transform");
+    }
+
+    /// Clip the next primitives to `rect`, intersected with the current scissor.
+    /// Scissors are not affected by transforms.
+    pub fn push_scissor(&mut self, rect: ScissorRect) {
+        let rect = match self.geometry.scissors.last() {
+            Some(current) => current.intersect(rect),
+            None => rect,
+        };
+        self.geometry.scissors.push(rect);
+    }
+
+    pub fn pop_scissor(&mut self) {
+        self.geometry
+            .scissors
+            .pop()
+            .expect("pop_scissor without push_scissor");
+    }
+
+    pub fn triangle(&mut self, vertices: &[BatchVertex; 3]) {
+        self.geometry.add(vertices, &[0, 1, 2]);
+    }
+
+    /// Quad from the corners in order around it.
+    pub fn quad(&mut self, vertices: &[BatchVertex; 4]) {
+        self.geometry.add(vertices, &[0, 1, 2, 0, 2, 3]);
+    }
+
+    /// Arbitrary indexed geometry, indices relative to `vertices`.
+    pub fn mesh(&mut self, vertices: &[BatchVertex], indices: &[u16]) {
+        self.geometry.add(vertices, indices);
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [u8; 4]) {
+        self.textured_rect(x, y, w, h, [0.0, 0.0, 1.0, 1.0], color);
+    }
+
+    /// Rectangle showing the `[u, v, w, h]` part of the current texture.
+    pub fn textured_rect(&mut self, x: f32, y: f32, w: f32, h: f32, uv: [f32; 4], color: [u8; 4]) {
+        let [u, v, uw, vh] = uv;
+        self.quad(&[
+            BatchVertex::new(x, y, u, v, color),
+            BatchVertex::new(x + w, y, u + uw, v, color),
+            BatchVertex::new(x + w, y + h, u + uw, v + vh, color),
+            BatchVertex::new(x, y + h, u, v + vh, color),
+        ]);
+    }
+
+    pub fn line(&mut self, from: [f32; 2], to: [f32; 2], thickness: f32, color: [u8; 4]) {
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return;
+        }
+        let (nx, ny) = (
+            -dy / length * thickness / 2.0,
+            dx / length * thickness / 2.0,
+        );
+        self.quad(&[
+            BatchVertex::new(from[0] + nx, from[1] + ny, 0.0, 0.0, color),
+            BatchVertex::new(to[0] + nx, to[1] + ny, 1.0, 0.0, color),
+            BatchVertex::new(to[0] - nx, to[1] - ny, 1.0, 1.0, color),
+            BatchVertex::new(from[0] - nx, from[1] - ny, 0.0, 1.0, color),
+        ]);
+    }
+
+    /// Number of draw calls the recorded geometry takes.
+    pub fn draw_calls(&self) -> usize {
+        self.geometry.calls.len()
+    }
+
+    /// Drop the recorded geometry without drawing it.
+    pub fn clear(&mut self) {
+        self.geometry.clear();
+    }
+
+    /// Draw and clear the recorded geometry, inside a pass rendering to the screen.
+    pub fn draw(&mut self, ctx: &mut dyn RenderingBackend) {
+        let (width, height) = crate::window::screen_size();
+        self.draw_sized(ctx, width as u32, height as u32);
+    }
+
+    /// Draw and clear the recorded geometry, inside a pass rendering to a
+    /// `width` x `height` target.
+    pub fn draw_sized(&mut self, ctx: &mut dyn RenderingBackend, width: u32, height: u32) {
+        for (index, chunk) in self.geometry.chunks.iter().enumerate() {
+            if index == self.buffers.len() {
+                self.buffers.push(ChunkBuffers {
+                    vertex_buffer: new_stream_buffer::<BatchVertex>(
+                        ctx,
+                        BufferType::VertexBuffer,
+                        chunk.vertices.len(),
+                    ),
+                    index_buffer: new_stream_buffer::<u16>(
+                        ctx,
+                        BufferType::IndexBuffer,
+                        chunk.indices.len(),
+                    ),
+                    vertex_capacity: chunk.vertices.len(),
+                    index_capacity: chunk.indices.len(),
+                });
+            }
+            let buffers = &mut self.buffers[index];
+            // grow by doubling, streaming buffers can't be resized
+            if chunk.vertices.len() > buffers.vertex_capacity {
+                ctx.delete_buffer(buffers.vertex_buffer);
+                buffers.vertex_capacity = chunk.vertices.len().next_power_of_two();
+                buffers.vertex_buffer = new_stream_buffer::<BatchVertex>(
+                    ctx,
+                    BufferType::VertexBuffer,
+                    buffers.vertex_capacity,
+                );
+            }
+            if chunk.indices.len() > buffers.index_capacity {
+                ctx.delete_buffer(buffers.index_buffer);
+                buffers.index_capacity = chunk.indices.len().next_power_of_two();
+                buffers.index_buffer =
+                    new_stream_buffer::<u16>(ctx, BufferType::IndexBuffer, buffers.index_capacity);
+            }
+            ctx.buffer_update(buffers.vertex_buffer, BufferSource::slice(&chunk.vertices));
+            ctx.buffer_update(buffers.index_buffer, BufferSource::slice(&chunk.indices));
+        }
+
+        // pixels, y down
+        let (w, h) = (width as f32, height as f32);
+        #[rustfmt::skip]
+        let mvp: [f32; 16] = [
+            2.0 / w, 0.0,      0.0, 0.0,
+            0.0,     -2.0 / h, 0.0, 0.0,
+            0.0,     0.0,      1.0, 0.0,
+            -1.0,    1.0,      0.0, 1.0,
+        ];
+        let mut scissor_applied = false;
+        for call in &self.geometry.calls {
+            ctx.apply_pipeline(&call.pipeline.unwrap_or(self.pipeline));
+            match call.scissor {
+                // miniquad scissors start at the bottom
+                Some(rect) => {
+                    ctx.apply_scissor_rect(rect.x, height as i32 - rect.y - rect.h, rect.w, rect.h);
+                    scissor_applied = true;
+                }
+                None if scissor_applied => {
+                    ctx.apply_scissor_rect(0, 0, width as i32, height as i32);
+                    scissor_applied = false;
+                }
+                None => {}
+            }
+            let buffers = &self.buffers[call.chunk];
+            ctx.apply_bindings_from_slice(
+                &[buffers.vertex_buffer],
+                buffers.index_buffer,
+                &[call.texture.unwrap_or(self.white)],
+            );
+            ctx.apply_uniforms(UniformsSource::table(&mvp));
+            ctx.draw(call.first_index as i32, call.index_count as i32, 1);
+        }
+        if scissor_applied {
+            ctx.apply_scissor_rect(0, 0, width as i32, height as i32);
+        }
+        self.geometry.clear();
+    }
+
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        for buffers in self.buffers {
+            ctx.delete_buffer(buffers.vertex_buffer);
+            ctx.delete_buffer(buffers.index_buffer);
+        }
+        ctx.delete_pipeline(self.pipeline);
+        ctx.delete_shader(self.shader);
+        ctx.delete_texture(self.white);
+    }
+}
+
+fn new_stream_buffer<T>(
+    ctx: &mut dyn RenderingBackend,
+    buffer_type: BufferType,
+    len: usize,
+) -> BufferId {
+    ctx.new_buffer(
+        buffer_type,
+        BufferUsage::Stream,
+        BufferSource::empty::<T>(len.max(1)),
+    )
+}
+
+mod shader {
+    use super::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 in_pos;
+    attribute vec2 in_uv;
+    attribute vec4 in_color;
+
+    uniform mat4 mvp;
+
+    varying lowp vec4 color;
+    varying highp vec2 uv;
+
+    void main() {
+        gl_Position = mvp * vec4(in_pos, 0, 1);
+        color = in_color / 255.0;
+        uv = in_uv;
+    }"#;
+
+    pub const FRAGMENT: &str = r#"#version 100
+    varying lowp vec4 color;
+    varying highp vec2 uv;
+
+    uniform sampler2D tex;
+
+    void main() {
+        gl_FragColor = texture2D(tex, uv) * color;
+    }"#;
+
+    pub const METAL: &str = r#"
+    #include <metal_stdlib>
+
+    using namespace metal;
+
+    struct Uniforms
+    {
+        float4x4 mvp;
+    };
+
+    struct Vertex
+    {
+        float2 in_pos [[attribute(0)]];
+        float2 in_uv [[attribute(1)]];
+        uchar4 in_color [[attribute(2)]];
+    };
+
+    struct RasterizerData
+    {
+        float4 position [[position]];
+        float2 uv [[user(locn0)]];
+        float4 color [[user(locn1)]];
+    };
+
+    vertex RasterizerData vertexShader(Vertex v [[stage_in]], constant Uniforms& uniforms [[buffer(0)]])
+    {
+        RasterizerData out;
+        out.position = uniforms.mvp * float4(v.in_pos, 0.0, 1.0);
+        out.uv = v.in_uv;
+        out.color = float4(v.in_color) / 255.0;
+        return out;
+    }
+
+    fragment float4 fragmentShader(RasterizerData in [[stage_in]], texture2d<float> tex [[texture(0)]], sampler texSmplr [[sampler(0)]])
+    {
+        return tex.sample(texSmplr, in.uv) * in.color;
+    }"#;
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["tex".to_string()],
+            uniforms: UniformBlockLayout {
+                uniforms: vec![UniformDesc::new("mvp", UniformType::Mat4)],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geometry() {
+        let mut geometry = Geometry::default();
+        let quad = [BatchVertex::default(); 4];
+        geometry.add(&quad, &[0, 1, 2, 0, 2, 3]);
+        geometry.add(&quad, &[0, 1, 2, 0, 2, 3]);
+        assert_eq!(geometry.calls.len(), 1);
+        assert_eq!(geometry.chunks[0].indices[6..], [4, 5, 6, 4, 6, 7]);
+
+        // a texture change starts a new draw call, going back too
+        geometry.texture = Some(TextureId::from_raw_id(RawId::OpenGl(1)));
+        geometry.add(&quad, &[0, 1, 2]);
+        geometry.texture = None;
+        geometry.add(&quad, &[0, 1, 2]);
+        assert_eq!(geometry.calls.len(), 3);
+        assert_eq!(geometry.calls[2].first_index, 15);
+
+        geometry
+            .transforms
+            .push(Transform2D::translation(10.0, 0.0).scale(2.0, 2.0));
+        geometry.add(&[BatchVertex::new(1.0, 1.0, 0.0, 0.0, [255; 4])], &[0]);
+        assert_eq!(geometry.chunks[0].vertices.last().unwrap().pos, [12.0, 2.0]);
+        assert_eq!(geometry.calls.len(), 3);
+
+        // chunks hold at most 65536 vertices
+        let many = vec![BatchVertex::default(); MAX_CHUNK_VERTICES - 16];
+        geometry.add(&many, &[0]);
+        assert_eq!(geometry.chunks.len(), 2);
+        assert_eq!(geometry.calls.len(), 4);
+    }
+}
@@ -0,0 +1,480 @@
+//! Screenshots and frame recording.
+//!
+//! ```ignore
+//! // screenshot: right after commit_frame, before the frame is presented
+//! ctx.commit_frame();
+//! if let Some(frame) = ctx.capture_frame() {
+//!     frame.save_png("screenshot.png")?;
+//! }
+//!
+//! // clip: a Y4M video at 30 fps, advancing the simulation by a fixed timestep
+//! let mut recorder = FrameRecorder::y4m_file("clip.y4m", 30)?;
+//! // each frame
+//! self.update(recorder.timestep());
+//! self.draw();
+//! ctx.commit_frame();
+//! recorder.record(&mut *ctx)?;
+//! ```
+//!
+//! Encoding needs no GPU, `FrameRecorder::write_frame` takes frames from anywhere.
+
+use super::*;
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Pixels read back from the default framebuffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA8, top row first. Alpha is always 255.
+    pub rgba: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// Build a frame from `glReadPixels` output: flip the bottom-up rows
+    /// and drop the alpha, which means nothing for a window.
+    pub fn from_bottom_up(width: u32, height: u32, mut rgba: Vec<u8>) -> CapturedFrame {
+        assert_eq!(rgba.len(), width as usize * height as usize * 4);
+        let row = width as usize * 4;
+        for y in 0..height as usize / 2 {
+            let (top, bottom) = rgba.split_at_mut((height as usize - 1 - y) * row);
+            top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+        }
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+        CapturedFrame {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    pub fn write_png(&self, out: &mut impl Write) -> io::Result<()> {
+        png::write(out, self.width, self.height, &self.rgba)
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_png(&mut file)?;
+        file.flush()
+    }
+}
+
+enum Output {
+    /// Numbered PNG files in a directory.
+    Png(PathBuf),
+    /// YUV4MPEG2 stream, with the frame size fixed by the first frame.
+    Y4m {
+        out: Box<dyn Write>,
+        size: Option<(u32, u32)>,
+    },
+}
+
+/// Writes captured frames as numbered PNGs or as an uncompressed Y4M video.
+///
+/// Frames are spaced by a fixed timestep, `1 / fps` seconds. For a smooth clip, advance
+/// the application by `timestep()` for each recorded frame instead of the wall clock.
+pub struct FrameRecorder {
+    output: Output,
+    fps: u32,
+    frame: u64,
+}
+
+impl FrameRecorder {
+    /// Write `frame_00000.png`, `frame_00001.png`, ... into `directory`, creating it if needed.
+    pub fn png_sequence(directory: impl Into<PathBuf>, fps: u32) -> io::Result<FrameRecorder> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(FrameRecorder::new(Output::Png(directory), fps))
+    }
+
+    /// Write a Y4M video to `out`. 4:2:0 BT.601, playable by ffmpeg, mpv and VLC.
+    pub fn y4m(out: impl Write + 'static, fps: u32) -> FrameRecorder {
+        FrameRecorder::new(
+            Output::Y4m {
+                out: Box::new(out),
+                size: None,
+            },
+            fps,
+        )
+    }
+
+    pub fn y4m_file(path: impl AsRef<Path>, fps: u32) -> io::Result<FrameRecorder> {
+        Ok(FrameRecorder::y4m(BufWriter::new(File::create(path)?), fps))
+    }
+
+    fn new(output: Output, fps: u32) -> FrameRecorder {
+        assert!(fps > 0, "FrameRecorder fps should be positive");
+        FrameRecorder {
+            output,
+            fps,
+            frame: 0,
+        }
+    }
+
+    /// Seconds between two recorded frames.
+    pub fn timestep(&self) -> f64 {
+        1.0 / self.fps as f64
+    }
+
+    /// Position of the next frame in the recording, in seconds.
+    pub fn time(&self) -> f64 {
+        self.frame as f64 * self.timestep()
+    }
+
+    /// Number of frames written so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    /// Capture the default framebuffer and write it, call right after `commit_frame`.
+    /// Fails with `io::ErrorKind::Unsupported` where `capture_frame` is not available.
+    pub fn record(&mut self, ctx: &mut dyn RenderingBackend) -> io::Result<()> {
+        let frame = ctx.capture_frame().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Unsupported, "capture_frame is not supported")
+        })?;
+        self.write_frame(&frame)
+    }
+
+    pub fn write_frame(&mut self, frame: &CapturedFrame) -> io::Result<()> {
+        match &mut self.output {
+            Output::Png(directory) => {
+                frame.save_png(directory.join(format!("frame_{:05}.png", self.frame)))?
+            }
+            Output::Y4m { out, size } => {
+                match size {
+                    None => {
+                        writeln!(
+                            out,
+                            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg",
+                            frame.width, frame.height, self.fps
+                        )?;
+                        *size = Some((frame.width, frame.height));
+                    }
+                    Some(size) if *size != (frame.width, frame.height) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "Y4M frame size changed, the window was resized while recording",
+                        ));
+                    }
+                    Some(_) => {}
+                }
+                out.write_all(b"FRAME\n")?;
+                out.write_all(&rgba_to_yuv420(frame))?;
+            }
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Flush the Y4M stream. Also done when the recorder is dropped, ignoring errors.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Output::Png(_) => Ok(()),
+            Output::Y4m { out, .. } => out.flush(),
+        }
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Planar Y, U, V with 2x2 subsampled chroma, limited range BT.601.
+fn rgba_to_yuv420(frame: &CapturedFrame) -> Vec<u8> {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+    let mut yuv = vec![0u8; width * height + 2 * chroma_width * chroma_height];
+    let (luma, chroma) = yuv.split_at_mut(width * height);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_width * chroma_height);
+
+    let rgb = |x: usize, y: usize| {
+        let i = (y.min(height - 1) * width + x.min(width - 1)) * 4;
+        let p = &frame.rgba[i..i + 3];
+        (p[0] as i32, p[1] as i32, p[2] as i32)
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = rgb(x, y);
+            luma[y * width + x] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        }
+    }
+    for y in 0..chroma_height {
+        for x in 0..chroma_width {
+            let (mut r, mut g, mut b) = (0, 0, 0);
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let p = rgb(x * 2 + dx, y * 2 + dy);
+                r += p.0;
+                g += p.1;
+                b += p.2;
+            }
+            let (r, g, b) = (r / 4, g / 4, b / 4);
+            u_plane[y * chroma_width + x] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            v_plane[y * chroma_width + x] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+    }
+    yuv
+}
+
+/// Minimal PNG encoder: adaptive row filters and fixed Huffman DEFLATE.
+mod png {
+    use std::io::{self, Write};
+
+    const LENGTH_BASE: [u16; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const DIST_BASE: [u16; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    const WINDOW: usize = 32768;
+    const HASH_BITS: u32 = 15;
+
+    pub(super) fn write(
+        out: &mut impl Write,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> io::Result<()> {
+        let mut header = vec![];
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 8 bit RGBA, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        out.write_all(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a])?;
+        chunk(out, b"IHDR", &header)?;
+        chunk(out, b"IDAT", &zlib(&filter(width as usize * 4, rgba)))?;
+        chunk(out, b"IEND", &[])
+    }
+
+    fn chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        out.write_all(&(data.len() as u32).to_be_bytes())?;
+        out.write_all(kind)?;
+        out.write_all(data)?;
+        let crc = crc32(crc32(!0, kind), data);
+        out.write_all(&(!crc).to_be_bytes())
+    }
+
+    fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+        for byte in data {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    0xedb88320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        crc
+    }
+
+    /// Filter each row with the filter giving the smallest sum of absolute differences.
+    fn filter(row_bytes: usize, rgba: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(rgba.len() + rgba.len() / row_bytes);
+        let zeros = vec![0u8; row_bytes];
+        let mut candidate = vec![0u8; row_bytes];
+        let mut best = vec![0u8; row_bytes];
+        for (y, row) in rgba.chunks_exact(row_bytes).enumerate() {
+            let up = match y {
+                0 => &zeros[..],
+                _ => &rgba[(y - 1) * row_bytes..y * row_bytes],
+            };
+            let mut best_filter = 0;
+            let mut best_score = u64::MAX;
+            for filter in 0..5u8 {
+                for x in 0..row_bytes {
+                    let a = if x >= 4 { row[x - 4] } else { 0 };
+                    let b = up[x];
+                    let c = if x >= 4 { up[x - 4] } else { 0 };
+                    candidate[x] = row[x].wrapping_sub(match filter {
+                        0 => 0,
+                        1 => a,
+                        2 => b,
+                        3 => ((a as u16 + b as u16) / 2) as u8,
+                        _ => paeth(a, b, c),
+                    });
+                }
+                let score = candidate
+                    .iter()
+                    .map(|v| (*v as i8).unsigned_abs() as u64)
+                    .sum();
+                if score < best_score {
+                    best_score = score;
+                    best_filter = filter;
+                    std::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            out.push(best_filter);
+            out.extend_from_slice(&best);
+        }
+        out
+    }
+
+    fn paeth(a: u8, b: u8, c: u8) -> u8 {
+        let p = a as i16 + b as i16 - c as i16;
+        let (pa, pb, pc) = (
+            (p - a as i16).abs(),
+            (p - b as i16).abs(),
+            (p - c as i16).abs(),
+        );
+        if pa <= pb && pa <= pc {
+            a
+        } else if pb <= pc {
+            b
+        } else {
+            c
+        }
+    }
+
+    struct BitWriter {
+        out: Vec<u8>,
+        bits: u64,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, n: u32) {
+            self.bits |= (value as u64) << self.count;
+            self.count += n;
+            while self.count >= 8 {
+                self.out.push(self.bits as u8);
+                self.bits >>= 8;
+                self.count -= 8;
+            }
+        }
+
+        /// Huffman codes go most significant bit first.
+        fn code(&mut self, code: u32, n: u32) {
+            self.write(code.reverse_bits() >> (32 - n), n);
+        }
+
+        fn literal(&mut self, literal: u32) {
+            match literal {
+                0..=143 => self.code(0x30 + literal, 8),
+                144..=255 => self.code(0x190 + literal - 144, 9),
+                256..=279 => self.code(literal - 256, 7),
+                _ => self.code(0xc0 + literal - 280, 8),
+            }
+        }
+
+        fn copy(&mut self, length: usize, distance: usize) {
+            let code = LENGTH_BASE
+                .iter()
+                .rposition(|base| *base as usize <= length)
+                .unwrap();
+            self.literal(257 + code as u32);
+            if (8..28).contains(&code) {
+                self.write(
+                    (length - LENGTH_BASE[code] as usize) as u32,
+                    (code as u32 - 4) / 4,
+                );
+            }
+            let code = DIST_BASE
+                .iter()
+                .rposition(|base| *base as usize <= distance)
+                .unwrap();
+            self.code(code as u32, 5);
+            if code >= 4 {
+                self.write(
+                    (distance - DIST_BASE[code] as usize) as u32,
+                    (code as u32 - 2) / 2,
+                );
+            }
+        }
+    }
+
+    /// zlib stream with a single fixed Huffman block and greedy LZ77 matching.
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut w = BitWriter {
+            out: vec![0x78, 0x01],
+            bits: 0,
+            count: 0,
+        };
+        // final block, fixed Huffman codes
+        w.write(0b011, 3);
+
+        let hash = |i: usize| {
+            let v = u32::from_le_bytes([data[i], data[i + 1], data[i + 2], 0]);
+            (v.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+        };
+        let mut head = vec![usize::MAX; 1 << HASH_BITS];
+        let mut i = 0;
+        while i < data.len() {
+            let mut length = 0;
+            let mut distance = 0;
+            if i + 3 <= data.len() {
+                let h = hash(i);
+                let candidate = head[h];
+                head[h] = i;
+                if candidate != usize::MAX && i - candidate <= WINDOW {
+                    let max = (data.len() - i).min(258);
+                    while length < max && data[candidate + length] == data[i + length] {
+                        length += 1;
+                    }
+                    distance = i - candidate;
+                }
+            }
+            if length >= 3 {
+                w.copy(length, distance);
+                for j in i + 1..(i + length).min(data.len().saturating_sub(2)) {
+                    head[hash(j)] = j;
+                }
+                i += length;
+            } else {
+                w.literal(data[i] as u32);
+                i += 1;
+            }
+        }
+        w.literal(256);
+        // pad to a byte boundary
+        w.write(0, (8 - w.count % 8) % 8);
+
+        let mut out = w.out;
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in data {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_y4m() {
+        let frame = CapturedFrame::from_bottom_up(2, 2, vec![255; 16]);
+        let mut recorder = FrameRecorder::y4m(vec![], 25);
+        recorder.write_frame(&frame).unwrap();
+        recorder.write_frame(&frame).unwrap();
+        assert_eq!(recorder.frame_count(), 2);
+        assert_eq!(recorder.time(), 0.08);
+        let resized = CapturedFrame::from_bottom_up(1, 1, vec![0; 4]);
+        assert!(recorder.write_frame(&resized).is_err());
+    }
+
+    #[test]
+    fn test_flip() {
+        #[rustfmt::skip]
+        let frame = CapturedFrame::from_bottom_up(1, 3, vec![
+            1, 1, 1, 0,
+            2, 2, 2, 0,
+            3, 3, 3, 0,
+        ]);
+        assert_eq!(frame.rgba, [3, 3, 3, 255, 2, 2, 2, 255, 1, 1, 1, 255]);
+    }
+}
@@ -0,0 +1,518 @@
+//! Zero copy texture sharing through `EGLImage` and dma-buf, on the EGL backends.
+//!
+//! `TextureId::from_raw_id` only wraps GL names of the same context. `EglImages`
+//! wraps buffers coming from elsewhere, a camera or a hardware video decoder, into
+//! textures without copying them, and exports textures as dma-buf file descriptors
+//! for a compositor or a streaming pipeline:
+//!
+//! ```ignore
+//! let egl_images = EglImages::new(&mut *ctx)?;
+//! let frame = egl_images.import_dma_buf(&mut *ctx, &DmaBuf {
+//!     width: 1280,
+//!     height: 720,
+//!     fourcc: DRM_FORMAT_XRGB8888,
+//!     modifier: None,
+//!     planes: vec![DmaBufPlane { fd, offset: 0, stride: 1280 * 4 }],
+//! })?;
+//! // frame.texture() is bound and sampled like any other texture
+//! egl_images.delete(&mut *ctx, frame);
+//! ```
+//!
+//! Requires a GL context created through EGL (Wayland, Android, or X11 with
+//! `conf::LinuxX11Gl::EGLOnly`/`EGLWithGLXFallback` when EGL succeeded), plus
+//! `EGL_EXT_image_dma_buf_import` and `GL_OES_EGL_image` for imports,
+//! `EGL_MESA_image_dma_buf_export` and `EGL_KHR_gl_texture_2d_image` for exports.
+//! Mesa's software drivers (llvmpipe with `LIBGL_ALWAYS_SOFTWARE=1`) have all of them.
+//!
+//! Imported buffers are sampled as `GL_TEXTURE_2D`: RGB formats only, YUV buffers
+//! need `samplerExternalOES`, convert them with `video::VideoTexture` instead.
+//! Imported textures are not restored after a context loss.
+
+use super::*;
+use crate::native::egl::{self, EGLContext, EGLDisplay, EGLint, LibEgl};
+
+use std::{ffi::c_void, os::unix::io::RawFd};
+
+type EGLImage = *mut c_void;
+
+const EGL_LINUX_DMA_BUF_EXT: u32 = 0x3270;
+const EGL_LINUX_DRM_FOURCC_EXT: u32 = 0x3271;
+const EGL_GL_TEXTURE_2D_KHR: u32 = 0x30B1;
+const EGL_IMAGE_PRESERVED_KHR: u32 = 0x30D2;
+// fd, offset, pitch, modifier low and high bits of each plane
+const EGL_DMA_BUF_PLANE_ATTRIBUTES: [[u32; 5]; 4] = [
+    [0x3272, 0x3273, 0x3274, 0x3443, 0x3444],
+    [0x3275, 0x3276, 0x3277, 0x3445, 0x3446],
+    [0x3278, 0x3279, 0x327A, 0x3447, 0x3448],
+    [0x3440, 0x3441, 0x3442, 0x3449, 0x344A],
+];
+const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    (code[0] as u32) | ((code[1] as u32) << 8) | ((code[2] as u32) << 16) | ((code[3] as u32) << 24)
+}
+
+/// `A R G B` in a little endian 32 bit word, that is `B G R A` bytes.
+pub const DRM_FORMAT_ARGB8888: u32 = fourcc(b"AR24");
+pub const DRM_FORMAT_XRGB8888: u32 = fourcc(b"XR24");
+/// `R G B A` bytes, `TextureFormat::RGBA8`'s layout.
+pub const DRM_FORMAT_ABGR8888: u32 = fourcc(b"AB24");
+pub const DRM_FORMAT_XBGR8888: u32 = fourcc(b"XB24");
+
+type CreateImage =
+    unsafe extern "C" fn(EGLDisplay, EGLContext, u32, *mut c_void, *const EGLint) -> EGLImage;
+type DestroyImage = unsafe extern "C" fn(EGLDisplay, EGLImage) -> u32;
+type ExportQuery = unsafe extern "C" fn(EGLDisplay, EGLImage, *mut i32, *mut i32, *mut u64) -> u32;
+type Export = unsafe extern "C" fn(EGLDisplay, EGLImage, *mut i32, *mut EGLint, *mut EGLint) -> u32;
+type ImageTargetTexture = unsafe extern "C" fn(GLenum, *mut c_void);
+
+#[derive(Debug)]
+pub enum EglImageError {
+    /// libEGL could not be loaded, or the current context is not an EGL one.
+    NoEglContext,
+    /// A required extension is missing.
+    Unsupported(&'static str),
+    /// The dma-buf has no plane, or more than 4.
+    InvalidPlanes(usize),
+    /// An EGL or GL call failed with this `eglGetError` or `glGetError` code.
+    Egl(&'static str, EGLint),
+}
+
+impl Display for EglImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoEglContext => write!(f, "The GL context was not created with EGL"),
+            Self::Unsupported(extension) => write!(f, "{extension} is not supported"),
+            Self::InvalidPlanes(count) => write!(f, "dma-buf with {count} planes"),
+            Self::Egl(call, code) => write!(f, "{call} failed with EGL error {code:#x}"),
+        }
+    }
+}
+
+impl Error for EglImageError {}
+
+/// One plane of a dma-buf.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaBufPlane {
+    pub fd: RawFd,
+    /// In bytes, from the start of the buffer.
+    pub offset: u32,
+    /// Bytes per row.
+    pub stride: u32,
+}
+
+/// A dma-buf backed image, as `EGL_EXT_image_dma_buf_import` describes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmaBuf {
+    pub width: u32,
+    pub height: u32,
+    /// DRM fourcc code, like `DRM_FORMAT_XRGB8888`.
+    pub fourcc: u32,
+    /// DRM format modifier, `None` for the implicit one.
+    pub modifier: Option<u64>,
+    pub planes: Vec<DmaBufPlane>,
+}
+
+/// A texture sampling an `EGLImage`. Delete it with `EglImages::delete`.
+#[derive(Debug)]
+pub struct ImportedImage {
+    texture: TextureId,
+    // owned when imported from a dma-buf
+    image: Option<EGLImage>,
+}
+
+impl ImportedImage {
+    pub fn texture(&self) -> TextureId {
+        self.texture
+    }
+}
+
+/// EGL entry points for image sharing, bound to the current context's display.
+pub struct EglImages {
+    // keeps libEGL loaded
+    _egl: LibEgl,
+    display: EGLDisplay,
+    context: EGLContext,
+    create_image: CreateImage,
+    destroy_image: DestroyImage,
+    get_error: unsafe extern "C" fn() -> EGLint,
+    image_target_texture: Option<ImageTargetTexture>,
+    dma_buf_import: bool,
+    dma_buf_modifiers: bool,
+    export: Option<(ExportQuery, Export)>,
+}
+
+impl EglImages {
+    /// Look up the extensions of the current EGL context, created by `ctx`.
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Result<EglImages, EglImageError> {
+        if ctx.info().backend != Backend::OpenGl {
+            return Err(EglImageError::NoEglContext);
+        }
+        let egl = LibEgl::try_load().map_err(|_| EglImageError::NoEglContext)?;
+        unsafe {
+            let display = (egl.eglGetCurrentDisplay)();
+            let context = (egl.eglGetCurrentContext)();
+            // EGL_NO_DISPLAY: the current context is a GLX/WGL one
+            if display.is_null() || context.is_null() {
+                return Err(EglImageError::NoEglContext);
+            }
+            let extensions = (egl.eglQueryString)(display, egl::EGL_EXTENSIONS as _);
+            let extensions = if extensions.is_null() {
+                String::new()
+            } else {
+                std::ffi::CStr::from_ptr(extensions)
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            let has = |name: &str| extensions.split(' ').any(|ext| ext == name);
+            let proc = |name: &[u8]| (egl.eglGetProcAddress)(name.as_ptr() as _);
+
+            let (create_image, destroy_image) =
+                match (proc(b"eglCreateImageKHR\0"), proc(b"eglDestroyImageKHR\0")) {
+                    (Some(create), Some(destroy)) if has("EGL_KHR_image_base") => (
+                        std::mem::transmute::<unsafe extern "C" fn(), CreateImage>(create),
+                        std::mem::transmute::<unsafe extern "C" fn(), DestroyImage>(destroy),
+                    ),
+                    _ => return Err(EglImageError::Unsupported("EGL_KHR_image_base")),
+                };
+            let image_target_texture = match proc(b"glEGLImageTargetTexture2DOES\0") {
+                Some(f) if gl::has_gl_extension("GL_OES_EGL_image") => Some(std::mem::transmute::<
+                    unsafe extern "C" fn(),
+                    ImageTargetTexture,
+                >(f)),
+                _ => None,
+            };
+            let export = match (
+                proc(b"eglExportDMABUFImageQueryMESA\0"),
+                proc(b"eglExportDMABUFImageMESA\0"),
+            ) {
+                (Some(query), Some(export))
+                    if has("EGL_MESA_image_dma_buf_export")
+                        && has("EGL_KHR_gl_texture_2d_image") =>
+                {
+                    Some((
+                        std::mem::transmute::<unsafe extern "C" fn(), ExportQuery>(query),
+                        std::mem::transmute::<unsafe extern "C" fn(), Export>(export),
+                    ))
+                }
+                _ => None,
+            };
+            Ok(EglImages {
+                display,
+                context,
+                create_image,
+                destroy_image,
+                get_error: egl.eglGetError,
+                image_target_texture,
+                dma_buf_import: has("EGL_EXT_image_dma_buf_import"),
+                dma_buf_modifiers: has("EGL_EXT_image_dma_buf_import_modifiers"),
+                export,
+                _egl: egl,
+            })
+        }
+    }
+
+    /// `import_dma_buf` and `import_egl_image` are available.
+    pub fn can_import(&self) -> bool {
+        self.dma_buf_import && self.image_target_texture.is_some()
+    }
+
+    /// `export_dma_buf` is available.
+    pub fn can_export(&self) -> bool {
+        self.export.is_some()
+    }
+
+    /// Wrap a dma-buf into a texture. The file descriptors stay owned by the caller,
+    /// EGL keeps its own references to the buffer.
+    pub fn import_dma_buf(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        buf: &DmaBuf,
+    ) -> Result<ImportedImage, EglImageError> {
+        if !self.dma_buf_import {
+            return Err(EglImageError::Unsupported("EGL_EXT_image_dma_buf_import"));
+        }
+        if buf.planes.is_empty() || buf.planes.len() > EGL_DMA_BUF_PLANE_ATTRIBUTES.len() {
+            return Err(EglImageError::InvalidPlanes(buf.planes.len()));
+        }
+        let modifier = buf.modifier.filter(|&m| m != DRM_FORMAT_MOD_INVALID);
+        if modifier.is_some() && !self.dma_buf_modifiers {
+            return Err(EglImageError::Unsupported(
+                "EGL_EXT_image_dma_buf_import_modifiers",
+            ));
+        }
+
+        let attributes = dma_buf_attributes(buf, modifier);
+
+        let image = unsafe {
+            (self.create_image)(
+                self.display,
+                /* EGL_NO_CONTEXT */ std::ptr::null_mut(),
+                EGL_LINUX_DMA_BUF_EXT,
+                std::ptr::null_mut(),
+                attributes.as_ptr(),
+            )
+        };
+        if image.is_null() {
+            return Err(self.error("eglCreateImageKHR"));
+        }
+        match self.texture_from_image(ctx, image, buf.width, buf.height) {
+            Ok(texture) => Ok(ImportedImage {
+                texture,
+                image: Some(image),
+            }),
+            Err(e) => {
+                unsafe { (self.destroy_image)(self.display, image) };
+                Err(e)
+            }
+        }
+    }
+
+    /// Wrap an `EGLImage` created by someone else, a video decoder for example, into
+    /// a texture. The image must outlive the texture and stays owned by the caller.
+    ///
+    /// # Safety
+    ///
+    /// `image` must be a valid `EGLImage` of the current display.
+    pub unsafe fn import_egl_image(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        image: *mut c_void,
+        width: u32,
+        height: u32,
+    ) -> Result<ImportedImage, EglImageError> {
+        let texture = self.texture_from_image(ctx, image, width, height)?;
+        Ok(ImportedImage {
+            texture,
+            image: None,
+        })
+    }
+
+    fn texture_from_image(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        image: EGLImage,
+        width: u32,
+        height: u32,
+    ) -> Result<TextureId, EglImageError> {
+        let image_target_texture = self
+            .image_target_texture
+            .ok_or(EglImageError::Unsupported("GL_OES_EGL_image"))?;
+        // the storage is replaced by the image's one right away
+        let texture = ctx.new_texture(
+            TextureAccess::Static,
+            TextureSource::Empty,
+            TextureParams {
+                width,
+                height,
+                ..Default::default()
+            },
+        );
+        let RawId::OpenGl(raw) = unsafe { ctx.texture_raw_id(texture) };
+        unsafe {
+            // behind the backend's binding cache, put the previous texture back
+            let mut bound: GLint = 0;
+            glGetIntegerv(GL_TEXTURE_BINDING_2D, &mut bound);
+            glBindTexture(GL_TEXTURE_2D, raw);
+            image_target_texture(GL_TEXTURE_2D, image);
+            let error = glGetError();
+            glBindTexture(GL_TEXTURE_2D, bound as GLuint);
+            if error != GL_NO_ERROR {
+                ctx.delete_texture(texture);
+                return Err(EglImageError::Egl(
+                    "glEGLImageTargetTexture2DOES",
+                    error as EGLint,
+                ));
+            }
+        }
+        Ok(texture)
+    }
+
+    /// Delete the texture and the `EGLImage` created by `import_dma_buf`.
+    pub fn delete(&self, ctx: &mut dyn RenderingBackend, image: ImportedImage) {
+        ctx.delete_texture(image.texture);
+        if let Some(image) = image.image {
+            unsafe { (self.destroy_image)(self.display, image) };
+        }
+    }
+
+    /// Export `texture`, a render target usually, as a dma-buf. The returned file
+    /// descriptors are owned by the caller, close them once done. What is rendered
+    /// into `texture` reaches the buffer once the GL commands are flushed,
+    /// by `commit_frame` for example.
+    pub fn export_dma_buf(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        texture: TextureId,
+    ) -> Result<DmaBuf, EglImageError> {
+        let (query, export) = self
+            .export
+            .ok_or(EglImageError::Unsupported("EGL_MESA_image_dma_buf_export"))?;
+        let RawId::OpenGl(raw) = unsafe { ctx.texture_raw_id(texture) };
+        let (width, height) = ctx.texture_size(texture);
+        let attributes = [
+            EGL_IMAGE_PRESERVED_KHR as EGLint,
+            egl::EGL_TRUE as EGLint,
+            egl::EGL_NONE as EGLint,
+        ];
+        unsafe {
+            let image = (self.create_image)(
+                self.display,
+                self.context,
+                EGL_GL_TEXTURE_2D_KHR,
+                raw as usize as *mut c_void,
+                attributes.as_ptr(),
+            );
+            if image.is_null() {
+                return Err(self.error("eglCreateImageKHR"));
+            }
+            let result = self.export_image(query, export, image, width, height);
+            // the exported fds keep the buffer alive
+            (self.destroy_image)(self.display, image);
+            result
+        }
+    }
+
+    unsafe fn export_image(
+        &self,
+        query: ExportQuery,
+        export: Export,
+        image: EGLImage,
+        width: u32,
+        height: u32,
+    ) -> Result<DmaBuf, EglImageError> {
+        let mut fourcc = 0;
+        let mut plane_count = 0;
+        if query(
+            self.display,
+            image,
+            &mut fourcc,
+            &mut plane_count,
+            std::ptr::null_mut(),
+        ) == 0
+        {
+            return Err(self.error("eglExportDMABUFImageQueryMESA"));
+        }
+        if !(1..=4).contains(&plane_count) {
+            return Err(EglImageError::InvalidPlanes(plane_count as usize));
+        }
+        // one per plane, all the same. Every output of the query may be null
+        let mut modifiers = vec![0u64; plane_count as usize];
+        if query(
+            self.display,
+            image,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            modifiers.as_mut_ptr(),
+        ) == 0
+        {
+            return Err(self.error("eglExportDMABUFImageQueryMESA"));
+        }
+        let mut fds = [-1; 4];
+        let mut strides = [0; 4];
+        let mut offsets = [0; 4];
+        if export(
+            self.display,
+            image,
+            fds.as_mut_ptr(),
+            strides.as_mut_ptr(),
+            offsets.as_mut_ptr(),
+        ) == 0
+        {
+            return Err(self.error("eglExportDMABUFImageMESA"));
+        }
+        Ok(DmaBuf {
+            width,
+            height,
+            fourcc: fourcc as u32,
+            modifier: Some(modifiers[0]).filter(|&m| m != DRM_FORMAT_MOD_INVALID),
+            planes: (0..plane_count as usize)
+                .map(|i| DmaBufPlane {
+                    fd: fds[i],
+                    offset: offsets[i] as u32,
+                    stride: strides[i] as u32,
+                })
+                .collect(),
+        })
+    }
+
+    fn error(&self, call: &'static str) -> EglImageError {
+        EglImageError::Egl(call, unsafe { (self.get_error)() })
+    }
+}
+
+/// `eglCreateImageKHR` attributes for `buf`, `modifier` needs
+/// `EGL_EXT_image_dma_buf_import_modifiers`.
+fn dma_buf_attributes(buf: &DmaBuf, modifier: Option<u64>) -> Vec<EGLint> {
+    let mut attributes = vec![
+        egl::EGL_WIDTH as EGLint,
+        buf.width as EGLint,
+        egl::EGL_HEIGHT as EGLint,
+        buf.height as EGLint,
+        EGL_LINUX_DRM_FOURCC_EXT as EGLint,
+        buf.fourcc as EGLint,
+    ];
+    for (plane, names) in buf.planes.iter().zip(&EGL_DMA_BUF_PLANE_ATTRIBUTES) {
+        attributes.extend([
+            names[0] as EGLint,
+            plane.fd,
+            names[1] as EGLint,
+            plane.offset as EGLint,
+            names[2] as EGLint,
+            plane.stride as EGLint,
+        ]);
+        if let Some(modifier) = modifier {
+            attributes.extend([
+                names[3] as EGLint,
+                modifier as u32 as EGLint,
+                names[4] as EGLint,
+                (modifier >> 32) as u32 as EGLint,
+            ]);
+        }
+    }
+    attributes.push(egl::EGL_NONE as EGLint);
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dma_buf_attributes() {
+        let buf = DmaBuf {
+            width: 64,
+            height: 32,
+            fourcc: DRM_FORMAT_XRGB8888,
+            modifier: None,
+            planes: vec![
+                DmaBufPlane {
+                    fd: 7,
+                    offset: 0,
+                    stride: 256,
+                },
+                DmaBufPlane {
+                    fd: 8,
+                    offset: 4096,
+                    stride: 128,
+                },
+            ],
+        };
+        let attributes = dma_buf_attributes(&buf, None);
+        #[rustfmt::skip]
+        assert_eq!(attributes, [
+            0x3057, 64, 0x3056, 32, 0x3271, 0x3432_5258,
+            0x3272, 7, 0x3273, 0, 0x3274, 256,
+            0x3275, 8, 0x3276, 4096, 0x3277, 128,
+            0x3038,
+        ]);
+
+        let attributes = dma_buf_attributes(&buf, Some(0x0100_0000_0000_0002));
+        #[rustfmt::skip]
+        assert_eq!(attributes[6..], [
+            0x3272, 7, 0x3273, 0, 0x3274, 256, 0x3443, 2, 0x3444, 0x0100_0000,
+            0x3275, 8, 0x3276, 4096, 0x3277, 128, 0x3445, 2, 0x3446, 0x0100_0000,
+            0x3038,
+        ]);
+    }
+}
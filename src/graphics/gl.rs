@@ -1,8 +1,10 @@
-use std::ffi::CString;
+use std::{cell::Cell, collections::HashMap, ffi::CString};
 
 use crate::{window, ResourceManager};
 
 mod cache;
+mod restore;
+pub mod upload;
 
 use super::*;
 use cache::*;
@@ -57,12 +59,6 @@ impl TextureOrRenderbuffer {
             _ => None,
         }
     }
-    fn renderbuffer(&self) -> Option<GLuint> {
-        match self {
-            TextureOrRenderbuffer::Renderbuffer(id) => Some(*id),
-            _ => None,
-        }
-    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -83,7 +79,39 @@ impl TextureFormat {
             TextureFormat::Alpha => GL_ALPHA,
             #[cfg(not(target_arch = "wasm32"))]
             TextureFormat::Alpha => GL_R8,
+            #[cfg(target_arch = "wasm32")]
+            TextureFormat::RG8 => GL_LUMINANCE_ALPHA,
+            #[cfg(not(target_arch = "wasm32"))]
+            TextureFormat::RG8 => GL_RG8,
+            TextureFormat::SRGBA8 => GL_SRGB8_ALPHA8,
+            TextureFormat::Bc1 => GL_COMPRESSED_RGBA_S3TC_DXT1_EXT,
+            TextureFormat::Bc3 => GL_COMPRESSED_RGBA_S3TC_DXT5_EXT,
+            TextureFormat::Bc7 => GL_COMPRESSED_RGBA_BPTC_UNORM,
+            TextureFormat::Etc2Rgb8 => GL_COMPRESSED_RGB8_ETC2,
+            TextureFormat::Etc2Rgba8 => GL_COMPRESSED_RGBA8_ETC2_EAC,
+            TextureFormat::Astc4x4 => GL_COMPRESSED_RGBA_ASTC_4x4_KHR,
+        }
+    }
+}
+
+/// Make non-WASM textures sample like their WASM counterparts,
+/// which use GL_ALPHA and GL_LUMINANCE_ALPHA.
+unsafe fn swizzle(target: GLenum, format: TextureFormat) {
+    if cfg!(target_arch = "wasm32") {
+        return;
+    }
+    match format {
+        // if alpha miniquad texture, the value on non-WASM is stored in red channel
+        // swizzle red -> alpha
+        TextureFormat::Alpha => glTexParameteri(target, GL_TEXTURE_SWIZZLE_A, GL_RED as _),
+        // red -> luminance, green -> alpha
+        TextureFormat::RG8 => {
+            glTexParameteri(target, GL_TEXTURE_SWIZZLE_G, GL_RED as _);
+            glTexParameteri(target, GL_TEXTURE_SWIZZLE_B, GL_RED as _);
+            glTexParameteri(target, GL_TEXTURE_SWIZZLE_A, GL_GREEN as _);
         }
+        // keep alpha -> alpha
+        _ => glTexParameteri(target, GL_TEXTURE_SWIZZLE_A, GL_ALPHA as _),
     }
 }
 
@@ -100,6 +128,13 @@ impl From<TextureFormat> for (GLenum, GLenum, GLenum) {
             TextureFormat::Alpha => (GL_ALPHA, GL_ALPHA, GL_UNSIGNED_BYTE),
             #[cfg(not(target_arch = "wasm32"))]
             TextureFormat::Alpha => (GL_R8, GL_RED, GL_UNSIGNED_BYTE), // texture updates will swizzle Red -> Alpha to match WASM
+            #[cfg(target_arch = "wasm32")]
+            TextureFormat::RG8 => (GL_LUMINANCE_ALPHA, GL_LUMINANCE_ALPHA, GL_UNSIGNED_BYTE),
+            #[cfg(not(target_arch = "wasm32"))]
+            TextureFormat::RG8 => (GL_RG8, GL_RG, GL_UNSIGNED_BYTE), // swizzled to RRRG to match WASM
+            TextureFormat::SRGBA8 => (GL_SRGB8_ALPHA8, GL_RGBA, GL_UNSIGNED_BYTE),
+            // compressed formats are uploaded with glCompressedTex*, only the internal format matters
+            compressed => (compressed.sized_internal_format(), 0, 0),
         }
     }
 }
@@ -109,6 +144,7 @@ impl From<TextureKind> for GLuint {
         match kind {
             TextureKind::Texture2D => GL_TEXTURE_2D,
             TextureKind::CubeMap => GL_TEXTURE_CUBE_MAP,
+            TextureKind::Texture2DMultisample => GL_TEXTURE_2D_MULTISAMPLE,
         }
     }
 }
@@ -189,8 +225,16 @@ impl Texture {
                 "Multisampling is only supported for render textures"
             );
         }
-        let (internal_format, format, pixel_type) = params.format.into();
+        if access == TextureAccess::RenderTarget {
+            assert!(
+                !params.format.is_compressed(),
+                "Compressed textures can't be render targets"
+            );
+        }
 
+        if params.kind == TextureKind::Texture2DMultisample {
+            return Self::new_multisample(ctx, access, params);
+        }
         if access == TextureAccess::RenderTarget && params.sample_count > 1 {
             let mut renderbuffer: u32 = 0;
             unsafe {
@@ -220,45 +264,27 @@ impl Texture {
             ctx.cache.bind_texture(0, params.kind.into(), texture);
             glPixelStorei(GL_UNPACK_ALIGNMENT, 1); // miniquad always uses row alignment of 1
 
-            if cfg!(not(target_arch = "wasm32")) {
-                // if not WASM
-                if params.format == TextureFormat::Alpha {
-                    // if alpha miniquad texture, the value on non-WASM is stored in red channel
-                    // swizzle red -> alpha
-                    glTexParameteri(params.kind.into(), GL_TEXTURE_SWIZZLE_A, GL_RED as _);
-                } else {
-                    // keep alpha -> alpha
-                    glTexParameteri(params.kind.into(), GL_TEXTURE_SWIZZLE_A, GL_ALPHA as _);
-                }
-            }
+            swizzle(params.kind.into(), params.format);
 
             match source {
                 TextureSource::Empty => {
                     // not quite sure if glTexImage2D(null) is really a requirement
                     // but it was like this for quite a while and apparantly it works?
-                    glTexImage2D(
+                    Self::tex_image(
                         GL_TEXTURE_2D,
                         0,
-                        internal_format as i32,
-                        params.width as i32,
-                        params.height as i32,
-                        0,
-                        format,
-                        pixel_type,
-                        std::ptr::null() as _,
+                        params.format,
+                        (params.width, params.height),
+                        std::ptr::null(),
                     );
                 }
                 TextureSource::Bytes(source) => {
                     assert!(params.kind == TextureKind::Texture2D, "incompatible TextureKind and TextureSource. Cubemaps require TextureSource::Array of 6 textures.");
-                    glTexImage2D(
+                    Self::tex_image(
                         GL_TEXTURE_2D,
                         0,
-                        internal_format as i32,
-                        params.width as i32,
-                        params.height as i32,
-                        0,
-                        format,
-                        pixel_type,
+                        params.format,
+                        (params.width, params.height),
                         source.as_ptr() as *const _,
                     );
                 }
@@ -270,31 +296,23 @@ impl Texture {
                         );
                     }
                     for (cubemap_face, mipmaps) in array.iter().enumerate() {
-                        if mipmaps.len() != 1 {
-                            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_BASE_LEVEL, 0);
-                            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAX_LEVEL, array.len() as _);
-                        }
-                        for (mipmap_level, bytes) in mipmaps.iter().enumerate() {
-                            let target = match params.kind {
-                                TextureKind::Texture2D => GL_TEXTURE_2D,
-                                TextureKind::CubeMap => {
-                                    GL_TEXTURE_CUBE_MAP_POSITIVE_X + cubemap_face as u32
-                                }
-                            };
-                            glTexImage2D(
-                                target,
-                                mipmap_level as _,
-                                internal_format as i32,
-                                params.width as i32,
-                                params.height as i32,
-                                0,
-                                format,
-                                pixel_type,
-                                bytes.as_ptr() as *const _,
-                            );
-                        }
+                        let target = match params.kind {
+                            TextureKind::Texture2D => GL_TEXTURE_2D,
+                            TextureKind::CubeMap => {
+                                GL_TEXTURE_CUBE_MAP_POSITIVE_X + cubemap_face as u32
+                            }
+                            TextureKind::Texture2DMultisample => unreachable!(),
+                        };
+                        Self::upload_levels(&params, target, mipmaps);
                     }
                 }
+                TextureSource::Mips(mipmaps) => {
+                    assert!(
+                        params.kind == TextureKind::Texture2D,
+                        "TextureSource::Mips is for 2D textures, use TextureSource::Array for cubemaps."
+                    );
+                    Self::upload_levels(&params, GL_TEXTURE_2D, mipmaps);
+                }
             }
 
             let wrap = match params.wrap {
@@ -322,29 +340,140 @@ impl Texture {
         }
     }
 
+    /// A `GL_TEXTURE_2D_MULTISAMPLE` render target, shaders read it with `sampler2DMS`.
+    fn new_multisample(
+        ctx: &mut GlContext,
+        access: TextureAccess,
+        params: TextureParams,
+    ) -> Texture {
+        assert!(
+            ctx.info.features.multisample_textures,
+            "Multisampled textures are not supported"
+        );
+        assert!(
+            access == TextureAccess::RenderTarget,
+            "Multisampled textures are render targets"
+        );
+        ctx.cache.store_texture_binding(0);
+        let mut texture: GLuint = 0;
+        #[cfg(not(target_arch = "wasm32"))]
+        unsafe {
+            glGenTextures(1, &mut texture as *mut _);
+            ctx.cache
+                .bind_texture(0, GL_TEXTURE_2D_MULTISAMPLE, texture);
+            let samples = params.sample_count.max(1);
+            let internal_format = params.format.sized_internal_format();
+            let (width, height) = (params.width as _, params.height as _);
+            // immutable storage on GLES3.1 and GL4.3, GL3.2 only has glTexImage2DMultisample
+            if is_gl_func_loaded("glTexStorage2DMultisample") {
+                glTexStorage2DMultisample(
+                    GL_TEXTURE_2D_MULTISAMPLE,
+                    samples,
+                    internal_format,
+                    width,
+                    height,
+                    GL_TRUE as _,
+                );
+            } else {
+                glTexImage2DMultisample(
+                    GL_TEXTURE_2D_MULTISAMPLE,
+                    samples,
+                    internal_format,
+                    width,
+                    height,
+                    GL_TRUE as _,
+                );
+            }
+        }
+        ctx.cache.restore_texture_binding(0);
+
+        Texture {
+            raw: TextureOrRenderbuffer::Texture(texture),
+            params,
+        }
+    }
+
+    /// Upload a mipmap chain into `target`, the texture has to be bound.
+    unsafe fn upload_levels(params: &TextureParams, target: GLenum, mipmaps: &[&[u8]]) {
+        if mipmaps.len() != 1 {
+            // let partial chains be complete
+            glTexParameteri(params.kind.into(), GL_TEXTURE_BASE_LEVEL, 0);
+            glTexParameteri(
+                params.kind.into(),
+                GL_TEXTURE_MAX_LEVEL,
+                mipmaps.len().saturating_sub(1) as _,
+            );
+        }
+        for (mipmap_level, bytes) in mipmaps.iter().enumerate() {
+            let (width, height) = params.level_size(mipmap_level as _);
+            assert_eq!(
+                params.format.size(width, height) as usize,
+                bytes.len(),
+                "wrong size of mipmap level {mipmap_level}"
+            );
+            Self::tex_image(
+                target,
+                mipmap_level as _,
+                params.format,
+                (width, height),
+                bytes.as_ptr() as *const _,
+            );
+        }
+    }
+
+    /// glTexImage2D, or glCompressedTexImage2D for compressed formats.
+    unsafe fn tex_image(
+        target: GLenum,
+        level: GLint,
+        texture_format: TextureFormat,
+        (width, height): (u32, u32),
+        data: *const std::ffi::c_void,
+    ) {
+        let (internal_format, format, pixel_type) = texture_format.into();
+        if texture_format.is_compressed() {
+            glCompressedTexImage2D(
+                target,
+                level,
+                internal_format,
+                width as i32,
+                height as i32,
+                0,
+                texture_format.size(width, height) as i32,
+                data as _,
+            );
+        } else {
+            glTexImage2D(
+                target,
+                level,
+                internal_format as i32,
+                width as i32,
+                height as i32,
+                0,
+                format,
+                pixel_type,
+                data as _,
+            );
+        }
+    }
+
     pub fn resize(&mut self, ctx: &mut GlContext, width: u32, height: u32, source: Option<&[u8]>) {
         let raw = self
             .raw
             .texture()
-            .expect("Resize not yet implemented for RenderBuffer(multisampled) textures");
+            .filter(|_| self.params.kind != TextureKind::Texture2DMultisample)
+            .expect("Resize not yet implemented for multisampled textures");
         ctx.cache.store_texture_binding(0);
         ctx.cache.bind_texture(0, self.params.kind.into(), raw);
 
-        let (internal_format, format, pixel_type) = self.params.format.into();
-
         self.params.width = width;
         self.params.height = height;
 
         unsafe {
-            glTexImage2D(
+            Self::tex_image(
                 GL_TEXTURE_2D,
                 0,
-                internal_format as i32,
-                self.params.width as i32,
-                self.params.height as i32,
-                0,
-                format,
-                pixel_type,
+                self.params.format,
+                (width, height),
                 match source {
                     Some(source) => source.as_ptr() as *const _,
                     Option::None => std::ptr::null(),
@@ -365,54 +494,142 @@ impl Texture {
         source: &[u8],
     ) {
         assert_eq!(self.size(width as _, height as _), source.len());
-        assert!(x_offset + width <= self.params.width as _);
-        assert!(y_offset + height <= self.params.height as _);
+        let region = TextureRegion {
+            level: 0,
+            x: x_offset,
+            y: y_offset,
+            width,
+            height,
+        };
+        self.update_region(ctx, region, PixelLayout::default(), source);
+    }
+
+    pub fn update_region(
+        &self,
+        ctx: &mut GlContext,
+        region: TextureRegion,
+        layout: PixelLayout,
+        source: &[u8],
+    ) {
+        let (level_width, level_height) = self.params.level_size(region.level);
+        assert!(region.x >= 0 && region.y >= 0);
+        assert!(region.x + region.width <= level_width as _);
+        assert!(region.y + region.height <= level_height as _);
+        let (width, height) = (region.width as u32, region.height as u32);
+        let format = self.params.format;
+        assert!(
+            source.len() >= layout.data_size(format, width, height),
+            "not enough pixel data for the texture region"
+        );
         let raw = self.raw.texture().expect(
             "update_texture_part not yet implemented for RenderBuffer(multisampled) textures",
         );
 
+        if format.is_compressed() {
+            self.update_compressed_region(ctx, raw, region, layout, source);
+            return;
+        }
+
+        // GLES2 and WebGL1 have no GL_UNPACK_ROW_LENGTH, repack the rows there
+        let repacked: Vec<u8>;
+        let mut source = source;
+        let mut layout = layout;
+        if !layout.is_tight(format, width) && !ctx.unpack_row_length() {
+            let stride = layout.row_stride(format, width);
+            let row = format.size(width, 1) as usize;
+            repacked = (0..height as usize)
+                .flat_map(|y| &source[y * stride..y * stride + row])
+                .copied()
+                .collect();
+            source = &repacked;
+            layout = PixelLayout::default();
+        }
+
         ctx.cache.store_texture_binding(0);
         ctx.cache.bind_texture(0, self.params.kind.into(), raw);
 
-        let (_, format, pixel_type) = self.params.format.into();
+        let (_, gl_format, pixel_type) = format.into();
 
         unsafe {
-            glPixelStorei(GL_UNPACK_ALIGNMENT, 1); // miniquad always uses row alignment of 1
-
-            if cfg!(not(target_arch = "wasm32")) {
-                // if not WASM
-                if self.params.format == TextureFormat::Alpha {
-                    // if alpha miniquad texture, the value on non-WASM is stored in red channel
-                    // swizzle red -> alpha
-                    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_SWIZZLE_A, GL_RED as _);
-                } else {
-                    // keep alpha -> alpha
-                    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_SWIZZLE_A, GL_ALPHA as _);
-                }
+            glPixelStorei(GL_UNPACK_ALIGNMENT, layout.alignment as _);
+            if layout.row_length != 0 {
+                glPixelStorei(GL_UNPACK_ROW_LENGTH, layout.row_length as _);
             }
 
+            swizzle(GL_TEXTURE_2D, format);
+
             glTexSubImage2D(
                 GL_TEXTURE_2D,
-                0,
-                x_offset as _,
-                y_offset as _,
+                region.level as _,
+                region.x as _,
+                region.y as _,
                 width as _,
                 height as _,
-                format,
+                gl_format,
                 pixel_type,
                 source.as_ptr() as *const _,
             );
+
+            glPixelStorei(GL_UNPACK_ALIGNMENT, 1); // miniquad always uses row alignment of 1
+            if layout.row_length != 0 {
+                glPixelStorei(GL_UNPACK_ROW_LENGTH, 0);
+            }
         }
 
         ctx.cache.restore_texture_binding(0);
     }
 
+    fn update_compressed_region(
+        &self,
+        ctx: &mut GlContext,
+        raw: GLuint,
+        region: TextureRegion,
+        layout: PixelLayout,
+        source: &[u8],
+    ) {
+        let (level_width, level_height) = self.params.level_size(region.level);
+        assert!(
+            layout.row_length == 0,
+            "Compressed textures take tightly packed blocks"
+        );
+        assert!(
+            region.x % 4 == 0
+                && region.y % 4 == 0
+                && (region.width % 4 == 0 || region.x + region.width == level_width as i32)
+                && (region.height % 4 == 0 || region.y + region.height == level_height as i32),
+            "Compressed textures are updated in whole 4x4 blocks"
+        );
+        let format = self.params.format;
+        let size = format.size(region.width as _, region.height as _);
+
+        ctx.cache.store_texture_binding(0);
+        ctx.cache.bind_texture(0, self.params.kind.into(), raw);
+        unsafe {
+            glCompressedTexSubImage2D(
+                GL_TEXTURE_2D,
+                region.level as _,
+                region.x as _,
+                region.y as _,
+                region.width as _,
+                region.height as _,
+                format.sized_internal_format(),
+                size as _,
+                source.as_ptr() as *const _,
+            );
+        }
+        ctx.cache.restore_texture_binding(0);
+    }
+
     /// Read texture data into CPU memory
     pub fn read_pixels(&self, bytes: &mut [u8]) {
         let raw = self
             .raw
             .texture()
             .expect("read_pixels not yet implemented for RenderBuffer(multisampled) textures");
+        assert!(
+            !self.params.format.is_compressed(),
+            "Compressed textures can't be read back"
+        );
 
         let (_, format, pixel_type) = self.params.format.into();
 
@@ -496,7 +713,35 @@ pub(crate) struct RenderPassInternal {
     depth_texture: Option<TextureId>,
 }
 
-struct Textures(Vec<Texture>);
+/// Multisampled target the default pass renders into,
+/// see `RenderingBackend::set_default_pass_sample_count`.
+struct DefaultPassMsaa {
+    sample_count: i32,
+    width: i32,
+    height: i32,
+    gl_fb: GLuint,
+    color: GLuint,
+    depth_stencil: GLuint,
+    // the default pass was used since the last resolve
+    dirty: bool,
+}
+
+/// Reduced resolution target the default pass renders into,
+/// see `RenderingBackend::set_default_pass_scale`.
+struct DefaultPassScaled {
+    width: i32,
+    height: i32,
+    // the window size it gets upscaled to
+    window_width: i32,
+    window_height: i32,
+    gl_fb: GLuint,
+    color: GLuint,
+    depth_stencil: GLuint,
+    // the default pass was used since the last upscale
+    dirty: bool,
+}
+
+struct Textures(ResourceManager<Texture>);
 impl Textures {
     fn get(&self, texture: TextureId) -> Texture {
         match texture.0 {
@@ -517,8 +762,27 @@ pub struct GlContext {
     buffers: ResourceManager<Buffer>,
     textures: Textures,
     default_framebuffer: GLuint,
+    default_pass_msaa: Option<DefaultPassMsaa>,
+    default_pass_scaled: Option<DefaultPassScaled>,
+    default_pass_scale: f32,
+    default_pass_upscale_filter: FilterMode,
     pub(crate) cache: GlCache,
     pub(crate) info: ContextInfo,
+    // draw() takes &self, so its counter lives outside of the cache
+    draw_calls: Cell<usize>,
+    last_frame_stats: FrameStats,
+    leak_report: bool,
+    deferred: DeferredDeletions,
+    labels: HashMap<ResourceHandle, String>,
+    // GL_KHR_debug is available: object labels and debug groups are forwarded to GL
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    khr_debug: bool,
+    // kept creation data, see `RenderingBackend::set_restore_resources`
+    resource_data: Option<restore::ResourceData>,
+    // `NativeDisplayData::context_generation` the GL objects belong to
+    context_generation: u32,
+    // set by simulate_context_loss and by GPU resets reported through robustness
+    lost: Cell<bool>,
 }
 
 impl Default for GlContext {
@@ -539,37 +803,33 @@ impl GlContext {
 
             glGenVertexArrays(1, &mut vao as *mut _);
             glBindVertexArray(vao);
-            let info = gl_info();
+            let info = gl_info(default_framebuffer);
+            #[cfg(not(target_arch = "wasm32"))]
+            let khr_debug = init_khr_debug();
+            #[cfg(target_arch = "wasm32")]
+            let khr_debug = false;
             GlContext {
                 default_framebuffer,
-                shaders: ResourceManager::default(),
-                pipelines: ResourceManager::default(),
-                passes: ResourceManager::default(),
-                buffers: ResourceManager::default(),
-                textures: Textures(vec![]),
+                default_pass_msaa: None,
+                default_pass_scaled: None,
+                default_pass_scale: 1.0,
+                default_pass_upscale_filter: FilterMode::Linear,
+                shaders: ResourceManager::new("shader"),
+                pipelines: ResourceManager::new("pipeline"),
+                passes: ResourceManager::new("render pass"),
+                buffers: ResourceManager::new("buffer"),
+                textures: Textures(ResourceManager::new("texture")),
                 info,
-                cache: GlCache {
-                    stored_index_buffer: 0,
-                    stored_index_type: None,
-                    stored_vertex_buffer: 0,
-                    index_buffer: 0,
-                    index_type: None,
-                    vertex_buffer: 0,
-                    cur_pipeline: None,
-                    cur_pass: None,
-                    color_blend: None,
-                    alpha_blend: None,
-                    stencil: None,
-                    color_write: (true, true, true, true),
-                    cull_face: CullFace::Nothing,
-                    stored_texture: 0,
-                    stored_target: 0,
-                    textures: [CachedTexture {
-                        target: 0,
-                        texture: 0,
-                    }; MAX_SHADERSTAGE_IMAGES],
-                    attributes: [None; MAX_VERTEX_ATTRIBUTES],
-                },
+                cache: GlCache::new(),
+                draw_calls: Cell::new(0),
+                last_frame_stats: FrameStats::default(),
+                leak_report: false,
+                deferred: DeferredDeletions::default(),
+                labels: HashMap::new(),
+                khr_debug,
+                resource_data: None,
+                context_generation: crate::native_display().lock().unwrap().context_generation,
+                lost: Cell::new(false),
             }
         }
     }
@@ -577,6 +837,295 @@ impl GlContext {
     pub fn features(&self) -> &Features {
         &self.info.features
     }
+
+    /// GL_UNPACK_ROW_LENGTH is available everywhere but on GLES2 and WebGL1.
+    fn unpack_row_length(&self) -> bool {
+        let version = &self.info.gl_version_string;
+        !version.contains("OpenGL ES 2") && !version.starts_with("WebGL 1")
+    }
+
+    /// Color format of the managed default pass targets,
+    /// blits to the window require matching color formats on GLES.
+    fn default_pass_color_format(&self) -> GLenum {
+        let fb = self.info.framebuffer;
+        if fb.float {
+            GL_RGBA16F
+        } else if fb.srgb {
+            GL_SRGB8_ALPHA8
+        } else if fb.red_bits == 10 {
+            GL_RGB10_A2
+        } else if fb.alpha_bits == 0 {
+            GL_RGB8
+        } else {
+            GL_RGBA8
+        }
+    }
+
+    /// Size the default pass renders at for a `width`x`height` window.
+    fn default_pass_size(&self, width: i32, height: i32) -> (i32, i32) {
+        if self.default_pass_scaled.is_none() {
+            return (width, height);
+        }
+        let scale = |size: i32| ((size as f32 * self.default_pass_scale).round() as i32).max(1);
+        (scale(width), scale(height))
+    }
+
+    /// (Re)allocate the managed default pass targets when the window was resized,
+    /// returns the size the default pass renders at.
+    fn resize_default_pass_targets(&mut self, width: i32, height: i32) -> (i32, i32) {
+        let (w, h) = self.default_pass_size(width, height);
+        let color_format = self.default_pass_color_format();
+        if let Some(scaled) = &mut self.default_pass_scaled {
+            scaled.window_width = width;
+            scaled.window_height = height;
+            if (scaled.width, scaled.height) != (w, h) {
+                unsafe {
+                    glBindRenderbuffer(GL_RENDERBUFFER, scaled.color);
+                    glRenderbufferStorage(GL_RENDERBUFFER, color_format, w, h);
+                    glBindRenderbuffer(GL_RENDERBUFFER, scaled.depth_stencil);
+                    glRenderbufferStorage(GL_RENDERBUFFER, GL_DEPTH24_STENCIL8, w, h);
+                    glBindRenderbuffer(GL_RENDERBUFFER, 0);
+                }
+                scaled.width = w;
+                scaled.height = h;
+            }
+        }
+        if let Some(msaa) = &mut self.default_pass_msaa {
+            if (msaa.width, msaa.height) != (w, h) {
+                unsafe {
+                    glBindRenderbuffer(GL_RENDERBUFFER, msaa.color);
+                    glRenderbufferStorageMultisample(
+                        GL_RENDERBUFFER,
+                        msaa.sample_count,
+                        color_format,
+                        w,
+                        h,
+                    );
+                    glBindRenderbuffer(GL_RENDERBUFFER, msaa.depth_stencil);
+                    glRenderbufferStorageMultisample(
+                        GL_RENDERBUFFER,
+                        msaa.sample_count,
+                        GL_DEPTH24_STENCIL8,
+                        w,
+                        h,
+                    );
+                    glBindRenderbuffer(GL_RENDERBUFFER, 0);
+                }
+                msaa.width = w;
+                msaa.height = h;
+            }
+        }
+        (w, h)
+    }
+
+    /// Framebuffer and size of the default pass, rendering into the managed
+    /// MSAA and scaled targets when there are some.
+    fn default_pass_framebuffer(&mut self, width: i32, height: i32) -> (GLuint, i32, i32) {
+        let (w, h) = self.resize_default_pass_targets(width, height);
+        if let Some(scaled) = &mut self.default_pass_scaled {
+            scaled.dirty = true;
+        }
+        let framebuffer = match (&mut self.default_pass_msaa, &self.default_pass_scaled) {
+            (Some(msaa), _) => {
+                msaa.dirty = true;
+                msaa.gl_fb
+            }
+            (None, Some(scaled)) => scaled.gl_fb,
+            (None, None) => self.default_framebuffer,
+        };
+        (framebuffer, w, h)
+    }
+
+    /// Resolve the MSAA target, then upscale the scaled one into the window.
+    fn resolve_default_pass(&mut self) {
+        let scaled_fb = self.default_pass_scaled.as_ref().map(|scaled| scaled.gl_fb);
+        let mut blitted = false;
+        if let Some(msaa) = self.default_pass_msaa.as_mut().filter(|msaa| msaa.dirty) {
+            msaa.dirty = false;
+            blitted = true;
+            unsafe {
+                // blits are clipped by the scissor rect, apply_pipeline enables it back
+                glDisable(GL_SCISSOR_TEST);
+                glBindFramebuffer(GL_READ_FRAMEBUFFER, msaa.gl_fb);
+                glBindFramebuffer(
+                    GL_DRAW_FRAMEBUFFER,
+                    scaled_fb.unwrap_or(self.default_framebuffer),
+                );
+                glBlitFramebuffer(
+                    0,
+                    0,
+                    msaa.width,
+                    msaa.height,
+                    0,
+                    0,
+                    msaa.width,
+                    msaa.height,
+                    GL_COLOR_BUFFER_BIT,
+                    GL_NEAREST,
+                );
+            }
+        }
+        if let Some(scaled) = self
+            .default_pass_scaled
+            .as_mut()
+            .filter(|scaled| scaled.dirty)
+        {
+            scaled.dirty = false;
+            blitted = true;
+            let filter = match self.default_pass_upscale_filter {
+                FilterMode::Linear => GL_LINEAR,
+                FilterMode::Nearest => GL_NEAREST,
+            };
+            unsafe {
+                glDisable(GL_SCISSOR_TEST);
+                glBindFramebuffer(GL_READ_FRAMEBUFFER, scaled.gl_fb);
+                glBindFramebuffer(GL_DRAW_FRAMEBUFFER, self.default_framebuffer);
+                glBlitFramebuffer(
+                    0,
+                    0,
+                    scaled.width,
+                    scaled.height,
+                    0,
+                    0,
+                    scaled.window_width,
+                    scaled.window_height,
+                    GL_COLOR_BUFFER_BIT,
+                    filter,
+                );
+            }
+        }
+        if blitted {
+            unsafe { glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer) };
+        }
+    }
+
+    /// Viewport and scissor rects are given in window pixels,
+    /// map them to the scaled target in the default pass.
+    fn scale_default_pass_rect(&self, x: i32, y: i32, w: i32, h: i32) -> (i32, i32, i32, i32) {
+        if self.cache.cur_pass.is_some() || self.default_pass_scaled.is_none() {
+            return (x, y, w, h);
+        }
+        let scale = |v: i32| (v as f32 * self.default_pass_scale).round() as i32;
+        let (x0, y0) = (scale(x), scale(y));
+        (x0, y0, scale(x + w) - x0, scale(y + h) - y0)
+    }
+
+    fn delete_default_pass_scaled(&mut self) {
+        if let Some(scaled) = self.default_pass_scaled.take() {
+            unsafe {
+                glDeleteFramebuffers(1, &scaled.gl_fb);
+                glDeleteRenderbuffers(1, &scaled.color);
+                glDeleteRenderbuffers(1, &scaled.depth_stencil);
+            }
+        }
+    }
+
+    fn delete_default_pass_msaa(&mut self) {
+        if let Some(msaa) = self.default_pass_msaa.take() {
+            unsafe {
+                glDeleteFramebuffers(1, &msaa.gl_fb);
+                glDeleteRenderbuffers(1, &msaa.color);
+                glDeleteRenderbuffers(1, &msaa.depth_stencil);
+            }
+        }
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        if self.leak_report {
+            print_leak_report(&self.live_resources());
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) unsafe fn has_gl_extension(name: &str) -> bool {
+    let to_str = |s: *const GLubyte| std::ffi::CStr::from_ptr(s as _).to_string_lossy();
+
+    if is_gl2() {
+        let extensions = glGetString(GL_EXTENSIONS);
+        return !extensions.is_null() && to_str(extensions).split(' ').any(|ext| ext == name);
+    }
+    let mut count: GLint = 0;
+    glGetIntegerv(GL_NUM_EXTENSIONS, &mut count);
+    (0..count as GLuint).any(|i| {
+        let ext = glGetStringi(GL_EXTENSIONS, i);
+        !ext.is_null() && to_str(ext) == name
+    })
+}
+
+/// Check for GL_KHR_debug and, on a debug context, route driver messages
+/// into the log. Returns true if labels and debug groups may be used.
+#[cfg(not(target_arch = "wasm32"))]
+fn init_khr_debug() -> bool {
+    unsafe {
+        let loaded = [
+            "glDebugMessageCallback",
+            "glObjectLabel",
+            "glPushDebugGroup",
+            "glPopDebugGroup",
+        ]
+        .iter()
+        .all(|f| is_gl_func_loaded(f));
+        if !loaded || !has_gl_extension("GL_KHR_debug") {
+            return false;
+        }
+
+        // GL_CONTEXT_FLAGS is GL3+, GL2 contexts just do not get the callback
+        let mut flags: GLint = 0;
+        if !is_gl2() {
+            glGetIntegerv(GL_CONTEXT_FLAGS, &mut flags);
+        }
+        if flags as u32 & GL_CONTEXT_FLAG_DEBUG_BIT != 0 {
+            glEnable(GL_DEBUG_OUTPUT);
+            glEnable(GL_DEBUG_OUTPUT_SYNCHRONOUS);
+            glDebugMessageCallback(Some(gl_debug_message), std::ptr::null());
+        }
+        true
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+extern "C" fn gl_debug_message(
+    _source: GLenum,
+    _type: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut GLvoid,
+) {
+    let message = unsafe {
+        if length < 0 {
+            std::ffi::CStr::from_ptr(message).to_string_lossy()
+        } else {
+            String::from_utf8_lossy(std::slice::from_raw_parts(
+                message as *const u8,
+                length as usize,
+            ))
+        }
+    };
+
+    #[cfg(feature = "log-impl")]
+    match severity {
+        GL_DEBUG_SEVERITY_HIGH => {
+            crate::error!("GL [{}]: {}", id, message);
+        }
+        GL_DEBUG_SEVERITY_MEDIUM => {
+            crate::warn!("GL [{}]: {}", id, message);
+        }
+        GL_DEBUG_SEVERITY_LOW => {
+            crate::info!("GL [{}]: {}", id, message);
+        }
+        _ => {
+            crate::debug!("GL [{}]: {}", id, message);
+        }
+    }
+    #[cfg(not(feature = "log-impl"))]
+    if severity != GL_DEBUG_SEVERITY_NOTIFICATION {
+        eprintln!("GL [{}]: {}", id, message);
+    }
 }
 
 fn load_shader_internal(
@@ -692,31 +1241,219 @@ pub fn load_shader(shader_type: GLenum, source: &str) -> Result<GLuint, ShaderEr
 }
 
 impl GlContext {
-    fn set_blend(&mut self, color_blend: Option<BlendState>, alpha_blend: Option<BlendState>) {
-        if color_blend.is_none() && alpha_blend.is_some() {
-            panic!("AlphaBlend without ColorBlend");
-        }
-        if self.cache.color_blend == color_blend && self.cache.alpha_blend == alpha_blend {
-            return;
+    fn pipeline_internal(
+        &self,
+        buffer_layout: &[BufferLayout],
+        attributes: &[VertexAttribute],
+        shader: ShaderId,
+        params: PipelineParams,
+    ) -> PipelineInternal {
+        #[derive(Clone, Copy, Default)]
+        struct BufferCacheData {
+            stride: i32,
+            offset: i64,
         }
 
-        unsafe {
-            if let Some(color_blend) = color_blend {
-                if self.cache.color_blend.is_none() {
-                    glEnable(GL_BLEND);
-                }
+        let mut buffer_cache: Vec<BufferCacheData> =
+            vec![BufferCacheData::default(); buffer_layout.len()];
 
-                let BlendState {
-                    equation: eq_rgb,
-                    sfactor: src_rgb,
-                    dfactor: dst_rgb,
-                } = color_blend;
+        for VertexAttribute {
+            format,
+            buffer_index,
+            ..
+        } in attributes
+        {
+            let layout = buffer_layout.get(*buffer_index).unwrap_or_else(|| panic!());
+            let cache = buffer_cache
+                .get_mut(*buffer_index)
+                .unwrap_or_else(|| panic!());
 
-                if let Some(BlendState {
-                    equation: eq_alpha,
-                    sfactor: src_alpha,
-                    dfactor: dst_alpha,
-                }) = alpha_blend
+            if layout.stride == 0 {
+                cache.stride += format.size_bytes();
+            } else {
+                cache.stride = layout.stride;
+            }
+            // WebGL 1 limitation
+            assert!(cache.stride <= 255);
+        }
+
+        let program = self.shaders[shader.0].program;
+
+        let attributes_len = attributes
+            .iter()
+            .map(|layout| match layout.format {
+                VertexFormat::Mat4 => 4,
+                _ => 1,
+            })
+            .sum();
+
+        let mut vertex_layout: Vec<Option<VertexAttributeInternal>> = vec![None; attributes_len];
+
+        for VertexAttribute {
+            name,
+            format,
+            buffer_index,
+            gl_pass_as_float,
+        } in attributes
+        {
+            let buffer_data = &mut buffer_cache
+                .get_mut(*buffer_index)
+                .unwrap_or_else(|| panic!());
+            let layout = buffer_layout.get(*buffer_index).unwrap_or_else(|| panic!());
+
+            let cname = CString::new(*name).unwrap_or_else(|e| panic!("{}", e));
+            let attr_loc = unsafe { glGetAttribLocation(program, cname.as_ptr() as *const _) };
+            let attr_loc = if attr_loc == -1 { None } else { Some(attr_loc) };
+            let divisor = if layout.step_func == VertexStep::PerVertex {
+                0
+            } else {
+                layout.step_rate
+            };
+
+            let mut attributes_count: usize = 1;
+            let mut format = *format;
+
+            if format == VertexFormat::Mat4 {
+                format = VertexFormat::Float4;
+                attributes_count = 4;
+            }
+            for i in 0..attributes_count {
+                if let Some(attr_loc) = attr_loc {
+                    let attr_loc = attr_loc as GLuint + i as GLuint;
+
+                    let attr = VertexAttributeInternal {
+                        attr_loc,
+                        size: format.components(),
+                        type_: format.type_(),
+                        offset: buffer_data.offset,
+                        stride: buffer_data.stride,
+                        buffer_index: *buffer_index,
+                        divisor,
+                        gl_pass_as_float: *gl_pass_as_float,
+                    };
+
+                    assert!(
+                        attr_loc < vertex_layout.len() as u32,
+                        "attribute: {} outside of allocated attributes array len: {}",
+                        name,
+                        vertex_layout.len()
+                    );
+                    vertex_layout[attr_loc as usize] = Some(attr);
+                }
+                buffer_data.offset += format.size_bytes() as i64
+            }
+        }
+
+        PipelineInternal {
+            layout: vertex_layout,
+            shader,
+            params,
+        }
+    }
+
+    fn render_pass_internal(
+        &mut self,
+        color_img: &[TextureId],
+        resolve_img: Option<&[TextureId]>,
+        depth_img: Option<TextureId>,
+    ) -> RenderPassInternal {
+        if color_img.is_empty() && depth_img.is_none() {
+            panic!("Render pass should have at least one non-none target");
+        }
+        let mut gl_fb = 0;
+
+        let mut resolves = None;
+        unsafe {
+            glGenFramebuffers(1, &mut gl_fb as *mut _);
+            glBindFramebuffer(GL_FRAMEBUFFER, gl_fb);
+            let attach = |attachment: GLenum, texture: Texture| match texture.raw {
+                TextureOrRenderbuffer::Renderbuffer(raw) => {
+                    glFramebufferRenderbuffer(GL_FRAMEBUFFER, attachment, GL_RENDERBUFFER, raw)
+                }
+                TextureOrRenderbuffer::Texture(raw) => {
+                    let target = match texture.params.kind {
+                        TextureKind::Texture2DMultisample => GL_TEXTURE_2D_MULTISAMPLE,
+                        _ => GL_TEXTURE_2D,
+                    };
+                    glFramebufferTexture2D(GL_FRAMEBUFFER, attachment, target, raw, 0)
+                }
+            };
+            for (i, color_img) in color_img.iter().enumerate() {
+                attach(
+                    GL_COLOR_ATTACHMENT0 + i as u32,
+                    self.textures.get(*color_img),
+                );
+            }
+            if let Some(depth_img) = depth_img {
+                attach(GL_DEPTH_ATTACHMENT, self.textures.get(depth_img));
+            }
+            let mut attachments = vec![];
+            for i in 0..color_img.len() {
+                attachments.push(GL_COLOR_ATTACHMENT0 + i as u32);
+            }
+
+            if color_img.len() > 1 {
+                glDrawBuffers(color_img.len() as _, attachments.as_ptr() as _);
+            }
+
+            if let Some(resolve_img) = resolve_img {
+                resolves = Some(vec![]);
+                let resolves = resolves.as_mut().unwrap();
+                for (i, resolve_img) in resolve_img.iter().enumerate() {
+                    let mut resolve_fb = 0;
+                    glGenFramebuffers(1, &mut resolve_fb as *mut _);
+                    glBindFramebuffer(GL_FRAMEBUFFER, resolve_fb);
+                    resolves.push((resolve_fb, *resolve_img));
+                    let texture = self.textures.get(*resolve_img);
+                    let raw = texture.raw.texture().unwrap();
+                    glFramebufferTexture2D(
+                        GL_FRAMEBUFFER,
+                        GL_COLOR_ATTACHMENT0 + i as u32,
+                        GL_TEXTURE_2D,
+                        raw,
+                        0,
+                    );
+                    let fb_status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+                    assert!(fb_status != 0);
+                    glDrawBuffers(1, attachments.as_ptr() as _);
+                }
+            }
+            glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer);
+        }
+        RenderPassInternal {
+            gl_fb,
+            color_textures: color_img.to_vec(),
+            resolves,
+            depth_texture: depth_img,
+        }
+    }
+
+    fn set_blend(&mut self, color_blend: Option<BlendState>, alpha_blend: Option<BlendState>) {
+        if color_blend.is_none() && alpha_blend.is_some() {
+            panic!("AlphaBlend without ColorBlend");
+        }
+        if self.cache.color_blend == color_blend && self.cache.alpha_blend == alpha_blend {
+            self.cache.stats.skipped_state_changes += 1;
+            return;
+        }
+
+        unsafe {
+            if let Some(color_blend) = color_blend {
+                if self.cache.color_blend.is_none() {
+                    glEnable(GL_BLEND);
+                }
+
+                let BlendState {
+                    equation: eq_rgb,
+                    sfactor: src_rgb,
+                    dfactor: dst_rgb,
+                } = color_blend;
+
+                if let Some(BlendState {
+                    equation: eq_alpha,
+                    sfactor: src_alpha,
+                    dfactor: dst_alpha,
+                }) = alpha_blend
                 {
                     glBlendFuncSeparate(
                         src_rgb.into(),
@@ -740,6 +1477,7 @@ impl GlContext {
 
     fn set_stencil(&mut self, stencil_test: Option<StencilState>) {
         if self.cache.stencil == stencil_test {
+            self.cache.stats.skipped_state_changes += 1;
             return;
         }
         unsafe {
@@ -787,6 +1525,7 @@ impl GlContext {
 
     fn set_cull_face(&mut self, cull_face: CullFace) {
         if self.cache.cull_face == cull_face {
+            self.cache.stats.skipped_state_changes += 1;
             return;
         }
 
@@ -808,6 +1547,7 @@ impl GlContext {
 
     fn set_color_write(&mut self, color_write: ColorMask) {
         if self.cache.color_write == color_write {
+            self.cache.stats.skipped_state_changes += 1;
             return;
         }
         let (r, g, b, a) = color_write;
@@ -817,7 +1557,7 @@ impl GlContext {
 }
 
 #[allow(clippy::field_reassign_with_default)]
-fn gl_info() -> ContextInfo {
+fn gl_info(default_framebuffer: GLuint) -> ContextInfo {
     let version_string = unsafe { glGetString(super::gl::GL_VERSION) };
     let gl_version_string = unsafe { std::ffi::CStr::from_ptr(version_string as _) }
         .to_str()
@@ -830,9 +1570,11 @@ fn gl_info() -> ContextInfo {
         || gl_version_string.starts_with("OpenGL ES 2");
     let webgl1 = gl_version_string == "WebGL 1.0";
 
-    let features = Features {
+    #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+    let mut features = Features {
         instancing: !gl2,
         resolve_attachments: !webgl1 && !gl2,
+        ..Default::default()
     };
 
     let mut glsl_support = GlslSupport::default();
@@ -873,12 +1615,200 @@ fn gl_info() -> ContextInfo {
         glsl_support.v130 = true;
     }
 
+    let (gl_context, debug_context) = gl_context_info(&gl_version_string);
+    let framebuffer = framebuffer_info(&gl_context, default_framebuffer);
+    #[cfg(not(target_arch = "wasm32"))]
+    texture_compression(&gl_context, &mut features);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        features.multisample_textures = multisample_textures(&gl_context);
+    }
+
     ContextInfo {
         backend: Backend::OpenGl,
         gl_version_string,
         glsl_support,
         features,
+        gl_context: Some(gl_context),
+        debug_context,
+        framebuffer,
+    }
+}
+
+/// Compressed texture formats from the context version and extensions.
+#[cfg(not(target_arch = "wasm32"))]
+fn texture_compression(gl_context: &crate::conf::GlContextRequest, features: &mut Features) {
+    let gles = gl_context.api == Some(crate::conf::GlApi::Gles);
+    let version = gl_context.version;
+    unsafe {
+        features.texture_compression_bc = has_gl_extension("GL_EXT_texture_compression_s3tc")
+            && (version >= Some((4, 2)) && !gles
+                || has_gl_extension("GL_ARB_texture_compression_bptc")
+                || has_gl_extension("GL_EXT_texture_compression_bptc"));
+        features.texture_compression_etc2 = if gles {
+            version >= Some((3, 0))
+        } else {
+            version >= Some((4, 3)) || has_gl_extension("GL_ARB_ES3_compatibility")
+        };
+        features.texture_compression_astc = has_gl_extension("GL_KHR_texture_compression_astc_ldr");
+    }
+}
+
+/// `GL_TEXTURE_2D_MULTISAMPLE` support, from the context version and the loaded functions.
+#[cfg(not(target_arch = "wasm32"))]
+fn multisample_textures(gl_context: &crate::conf::GlContextRequest) -> bool {
+    let version = gl_context.version;
+    let supported = if gl_context.api == Some(crate::conf::GlApi::Gles) {
+        version >= Some((3, 1))
+    } else {
+        version >= Some((3, 2)) || unsafe { has_gl_extension("GL_ARB_texture_multisample") }
+    };
+    supported
+        && (is_gl_func_loaded("glTexStorage2DMultisample")
+            || is_gl_func_loaded("glTexImage2DMultisample"))
+}
+
+/// Query the format of the default framebuffer, which has to be bound.
+fn framebuffer_info(
+    gl_context: &crate::conf::GlContextRequest,
+    default_framebuffer: GLuint,
+) -> FramebufferInfo {
+    let get = |pname| {
+        let mut value: GLint = 0;
+        unsafe { glGetIntegerv(pname, &mut value) };
+        value
+    };
+    // a default framebuffer without multisampling reports 0
+    let sample_count = get(GL_SAMPLES).max(1);
+
+    // GL_RED_BITS and friends are gone from core profiles,
+    // GL3 and GLES3 ask the framebuffer attachments instead
+    #[cfg(not(target_arch = "wasm32"))]
+    if gl_context.version >= Some((3, 0))
+        && crate::native::gl::is_gl_func_loaded("glGetFramebufferAttachmentParameteriv")
+    {
+        let desktop = gl_context.api == Some(crate::conf::GlApi::OpenGl);
+        let (color, depth, stencil) = match (default_framebuffer, desktop) {
+            // iOS style default framebuffer, an fbo with regular attachments
+            (fbo, _) if fbo != 0 => (
+                GL_COLOR_ATTACHMENT0,
+                GL_DEPTH_ATTACHMENT,
+                GL_STENCIL_ATTACHMENT,
+            ),
+            (_, true) => (GL_BACK_LEFT, GL_DEPTH, GL_STENCIL),
+            (_, false) => (GL_BACK, GL_DEPTH, GL_STENCIL),
+        };
+        let attachment = |attachment, pname| {
+            let mut value: GLint = 0;
+            unsafe {
+                glGetFramebufferAttachmentParameteriv(
+                    GL_DRAW_FRAMEBUFFER,
+                    attachment,
+                    pname,
+                    &mut value,
+                )
+            };
+            value
+        };
+        let srgb_encoding =
+            attachment(color, GL_FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING) as u32 == GL_SRGB;
+        // desktop GL only encodes with GL_FRAMEBUFFER_SRGB enabled
+        let srgb = srgb_encoding && (!desktop || unsafe { glIsEnabled(GL_FRAMEBUFFER_SRGB) } != 0);
+        return FramebufferInfo {
+            red_bits: attachment(color, GL_FRAMEBUFFER_ATTACHMENT_RED_SIZE) as u8,
+            green_bits: attachment(color, GL_FRAMEBUFFER_ATTACHMENT_GREEN_SIZE) as u8,
+            blue_bits: attachment(color, GL_FRAMEBUFFER_ATTACHMENT_BLUE_SIZE) as u8,
+            alpha_bits: attachment(color, GL_FRAMEBUFFER_ATTACHMENT_ALPHA_SIZE) as u8,
+            depth_bits: attachment(depth, GL_FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE) as u8,
+            stencil_bits: attachment(stencil, GL_FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE) as u8,
+            sample_count,
+            srgb,
+            float: attachment(color, GL_FRAMEBUFFER_ATTACHMENT_COMPONENT_TYPE) as u32 == GL_FLOAT,
+        };
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = (gl_context, default_framebuffer);
+
+    FramebufferInfo {
+        red_bits: get(GL_RED_BITS) as u8,
+        green_bits: get(GL_GREEN_BITS) as u8,
+        blue_bits: get(GL_BLUE_BITS) as u8,
+        alpha_bits: get(GL_ALPHA_BITS) as u8,
+        depth_bits: get(GL_DEPTH_BITS) as u8,
+        stencil_bits: get(GL_STENCIL_BITS) as u8,
+        sample_count,
+        srgb: false,
+        float: false,
+    }
+}
+
+/// Describe the current context in terms of `conf::GlContextRequest`,
+/// and tell whether it is a debug context.
+fn gl_context_info(gl_version_string: &str) -> (crate::conf::GlContextRequest, bool) {
+    use crate::conf::{GlApi, GlContextRequest, GlProfile};
+
+    let (api, version) = if let Some(webgl) = gl_version_string.strip_prefix("WebGL ") {
+        // WebGL 1 is GLES 2, WebGL 2 is GLES 3
+        let version = if webgl.starts_with('2') { "3.0" } else { "2.0" };
+        (GlApi::Gles, version)
+    } else if let Some(gles) = gl_version_string.strip_prefix("OpenGL ES ") {
+        (GlApi::Gles, gles)
+    } else {
+        (GlApi::OpenGl, gl_version_string)
+    };
+    let mut numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse::<u8>().ok());
+    let version = match (numbers.next().flatten(), numbers.next().flatten()) {
+        (Some(major), Some(minor)) => Some((major, minor)),
+        _ => None,
+    };
+
+    #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+    let mut info = GlContextRequest {
+        api: Some(api),
+        version,
+        ..Default::default()
+    };
+    #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+    let mut debug = false;
+
+    // an invalid enum would leave an error behind, and be reported to the debug callback
+    #[cfg(not(target_arch = "wasm32"))]
+    unsafe {
+        let desktop = api == GlApi::OpenGl;
+        let gles32 = !desktop && version >= Some((3, 2));
+        if desktop && version >= Some((3, 0)) || gles32 || has_gl_extension("GL_KHR_debug") {
+            let mut flags: GLint = 0;
+            glGetIntegerv(GL_CONTEXT_FLAGS, &mut flags);
+            let flags = flags as u32;
+            debug = flags & GL_CONTEXT_FLAG_DEBUG_BIT != 0;
+            info.no_error = flags & GL_CONTEXT_FLAG_NO_ERROR_BIT != 0;
+            info.robustness = flags & GL_CONTEXT_FLAG_ROBUST_ACCESS_BIT != 0;
+        }
+        if desktop && version >= Some((4, 5))
+            || gles32
+            || has_gl_extension("GL_ARB_robustness")
+            || has_gl_extension("GL_EXT_robustness")
+            || has_gl_extension("GL_KHR_robustness")
+        {
+            let mut strategy: GLint = 0;
+            glGetIntegerv(GL_RESET_NOTIFICATION_STRATEGY, &mut strategy);
+            info.robustness |= strategy as u32 == GL_LOSE_CONTEXT_ON_RESET;
+        }
+
+        // profiles exist since desktop GL 3.2
+        if desktop && version >= Some((3, 2)) {
+            let mut mask: GLint = 0;
+            glGetIntegerv(GL_CONTEXT_PROFILE_MASK, &mut mask);
+            if mask as u32 & GL_CONTEXT_CORE_PROFILE_BIT != 0 {
+                info.profile = Some(GlProfile::Core);
+            } else if mask as u32 & GL_CONTEXT_COMPATIBILITY_PROFILE_BIT != 0 {
+                info.profile = Some(GlProfile::Compatibility);
+            }
+        }
     }
+    (info, debug)
 }
 
 impl RenderingBackend for GlContext {
@@ -886,6 +1816,165 @@ impl RenderingBackend for GlContext {
         self.info.clone()
     }
 
+    fn resource_stats(&self) -> ResourceStats {
+        let mut stats = ResourceStats {
+            shaders: self.shaders.len(),
+            pipelines: self.pipelines.len(),
+            render_passes: self.passes.len(),
+            ..Default::default()
+        };
+        for (_, buffer) in self.buffers.iter() {
+            stats.buffers.count += 1;
+            stats.buffers.bytes += buffer.size;
+        }
+        for (_, texture) in self.textures.0.iter() {
+            stats.textures.count += 1;
+            stats.textures.bytes += texture_memory_size(&texture.params);
+        }
+        stats
+    }
+
+    fn frame_stats(&self) -> FrameStats {
+        self.last_frame_stats
+    }
+
+    fn live_resources(&self) -> Vec<LiveResource> {
+        let mut live = vec![];
+        for (id, buffer) in self.buffers.iter() {
+            let kind = match buffer.buffer_type {
+                BufferType::VertexBuffer => "vertex buffer",
+                BufferType::IndexBuffer => "index buffer",
+            };
+            let handle = ResourceHandle::Buffer(BufferId(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, kind.to_string()),
+                bytes: buffer.size,
+            });
+        }
+        for (id, texture) in self.textures.0.iter() {
+            let handle = ResourceHandle::Texture(TextureId(TextureIdInner::Managed(id)));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, texture_label(&texture.params)),
+                bytes: texture_memory_size(&texture.params),
+            });
+        }
+        for (id, _) in self.shaders.iter() {
+            let handle = ResourceHandle::Shader(ShaderId(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "shader".to_string()),
+                bytes: 0,
+            });
+        }
+        for (id, _) in self.pipelines.iter() {
+            let handle = ResourceHandle::Pipeline(Pipeline(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "pipeline".to_string()),
+                bytes: 0,
+            });
+        }
+        for (id, pass) in self.passes.iter() {
+            let handle = ResourceHandle::RenderPass(RenderPass(id));
+            let description = format!(
+                "render pass, {} color attachments",
+                pass.color_textures.len()
+            );
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, description),
+                bytes: 0,
+            });
+        }
+        live
+    }
+
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.leak_report = enabled;
+    }
+
+    fn set_deferred_deletion(&mut self, enabled: bool) {
+        self.deferred.set_enabled(enabled);
+    }
+
+    fn deletion_queue(&mut self) -> DeletionQueue {
+        self.deferred.deletion_queue()
+    }
+
+    fn is_context_lost(&self) -> bool {
+        self.context_lost()
+    }
+
+    fn restore_context(&mut self) {
+        self.restore();
+    }
+
+    fn set_restore_resources(&mut self, enabled: bool) {
+        if enabled != self.resource_data.is_some() {
+            self.resource_data = enabled.then(Default::default);
+        }
+    }
+
+    fn simulate_context_loss(&mut self) -> bool {
+        #[cfg(target_arch = "wasm32")]
+        unsafe {
+            crate::native::wasm::sapp_lose_context()
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        self.lose_objects();
+        true
+    }
+
+    fn set_label(&mut self, handle: ResourceHandle, label: &str) {
+        // resolving the GL object also checks that the handle is still alive
+        let object = match handle {
+            ResourceHandle::Buffer(id) => Some((GL_BUFFER, self.buffers[id.0].gl_buf)),
+            ResourceHandle::Texture(id) => Some(match self.textures.get(id).raw {
+                TextureOrRenderbuffer::Texture(raw) => (GL_TEXTURE, raw),
+                TextureOrRenderbuffer::Renderbuffer(raw) => (GL_RENDERBUFFER, raw),
+            }),
+            ResourceHandle::Shader(id) => Some((GL_PROGRAM, self.shaders[id.0].program)),
+            ResourceHandle::Pipeline(id) => {
+                // pipelines are not GL objects, the label is only kept on our side
+                let _ = &self.pipelines[id.0];
+                None
+            }
+            ResourceHandle::RenderPass(id) => Some((GL_FRAMEBUFFER, self.passes[id.0].gl_fb)),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if let (true, Some((identifier, name))) = (self.khr_debug, object) {
+            unsafe { glObjectLabel(identifier, name, label.len() as _, label.as_ptr() as _) };
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = object;
+        self.labels.insert(handle, label.to_string());
+    }
+
+    fn push_debug_group(&mut self, name: &str) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.khr_debug {
+            unsafe {
+                glPushDebugGroup(
+                    GL_DEBUG_SOURCE_APPLICATION,
+                    0,
+                    name.len() as _,
+                    name.as_ptr() as _,
+                )
+            };
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = name;
+    }
+
+    fn pop_debug_group(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.khr_debug {
+            unsafe { glPopDebugGroup() };
+        }
+    }
+
     fn new_shader(
         &mut self,
         shader: ShaderSource,
@@ -895,8 +1984,12 @@ impl RenderingBackend for GlContext {
             ShaderSource::Glsl { fragment, vertex } => (fragment, vertex),
             _ => panic!("Metal source on OpenGl context"),
         };
-        let shader = load_shader_internal(vertex, fragment, meta)?;
-        Ok(ShaderId(self.shaders.add(shader)))
+        let shader = load_shader_internal(vertex, fragment, meta.clone())?;
+        let shader = ShaderId(self.shaders.add(shader));
+        if let Some(data) = &mut self.resource_data {
+            data.shader_created(shader, vertex, fragment, &meta);
+        }
+        Ok(shader)
     }
 
     fn new_texture(
@@ -905,12 +1998,28 @@ impl RenderingBackend for GlContext {
         source: TextureSource,
         params: TextureParams,
     ) -> TextureId {
+        self.cache.stats.bytes_uploaded += match source {
+            TextureSource::Empty => 0,
+            TextureSource::Bytes(bytes) => bytes.len(),
+            TextureSource::Array(array) => array
+                .iter()
+                .flat_map(|face| face.iter())
+                .map(|level| level.len())
+                .sum(),
+            TextureSource::Mips(levels) => levels.iter().map(|level| level.len()).sum(),
+        };
         let texture = Texture::new(self, access, source, params);
-        self.textures.0.push(texture);
-        TextureId(TextureIdInner::Managed(self.textures.0.len() - 1))
+        let texture = TextureId(TextureIdInner::Managed(self.textures.0.add(texture)));
+        if let Some(data) = &mut self.resource_data {
+            data.texture_created(texture, access, &source);
+        }
+        texture
     }
 
     fn delete_texture(&mut self, texture: TextureId) {
+        if self.deferred.defer(texture.into()) {
+            return;
+        }
         //self.cache.clear_texture_bindings();
 
         let t = self.textures.get(texture);
@@ -922,19 +2031,56 @@ impl RenderingBackend for GlContext {
                 glDeleteRenderbuffers(1, raw as *const _);
             },
         }
+        if let TextureIdInner::Managed(id) = texture.0 {
+            self.textures.0.remove(id);
+        }
+        self.labels.remove(&texture.into());
+        if let Some(data) = &mut self.resource_data {
+            data.deleted(texture.into());
+        }
     }
 
     fn delete_shader(&mut self, program: ShaderId) {
+        if self.deferred.defer(program.into()) {
+            return;
+        }
         unsafe { glDeleteProgram(self.shaders[program.0].program) };
         self.shaders.remove(program.0);
+        self.labels.remove(&program.into());
+        if let Some(data) = &mut self.resource_data {
+            data.deleted(program.into());
+        }
         self.cache.cur_pipeline = None;
     }
 
     fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        if self.deferred.defer(pipeline.into()) {
+            return;
+        }
         self.pipelines.remove(pipeline.0);
+        self.labels.remove(&pipeline.into());
+        if let Some(data) = &mut self.resource_data {
+            data.deleted(pipeline.into());
+        }
+    }
+
+    fn is_valid(&self, handle: ResourceHandle) -> bool {
+        match handle {
+            ResourceHandle::Buffer(id) => self.buffers.contains(id.0),
+            ResourceHandle::Texture(TextureId(TextureIdInner::Managed(id))) => {
+                self.textures.0.contains(id)
+            }
+            ResourceHandle::Texture(TextureId(TextureIdInner::Raw(_))) => true,
+            ResourceHandle::Shader(id) => self.shaders.contains(id.0),
+            ResourceHandle::Pipeline(id) => self.pipelines.contains(id.0),
+            ResourceHandle::RenderPass(id) => self.passes.contains(id.0),
+        }
     }
 
     fn texture_set_wrap(&mut self, texture: TextureId, wrap_x: TextureWrap, wrap_y: TextureWrap) {
+        if let Some(data) = &mut self.resource_data {
+            data.texture_wrap_set(texture, (wrap_x, wrap_y));
+        }
         let t = self.textures.get(texture);
         let raw = t
             .raw
@@ -976,6 +2122,10 @@ impl RenderingBackend for GlContext {
         self.cache.store_texture_binding(0);
         self.cache.bind_texture(0, t.params.kind.into(), raw);
 
+        if let TextureIdInner::Managed(id) = texture.0 {
+            self.textures.0[id].params.min_filter = filter;
+            self.textures.0[id].params.mipmap_filter = mipmap_filter;
+        }
         let filter = Texture::gl_filter(filter, mipmap_filter);
         unsafe {
             glTexParameteri(t.params.kind.into(), GL_TEXTURE_MIN_FILTER, filter as i32);
@@ -992,6 +2142,9 @@ impl RenderingBackend for GlContext {
         self.cache.store_texture_binding(0);
         self.cache.bind_texture(0, t.params.kind.into(), raw);
 
+        if let TextureIdInner::Managed(id) = texture.0 {
+            self.textures.0[id].params.mag_filter = filter;
+        }
         let filter = match filter {
             FilterMode::Nearest => GL_NEAREST,
             FilterMode::Linear => GL_LINEAR,
@@ -999,178 +2152,132 @@ impl RenderingBackend for GlContext {
         unsafe {
             glTexParameteri(t.params.kind.into(), GL_TEXTURE_MAG_FILTER, filter as i32);
         }
-        self.cache.restore_texture_binding(0);
-    }
-    fn texture_resize(
-        &mut self,
-        texture: TextureId,
-        width: u32,
-        height: u32,
-        source: Option<&[u8]>,
-    ) {
-        let mut t = self.textures.get(texture);
-        t.resize(self, width, height, source);
-        if let TextureIdInner::Managed(tex_id) = texture.0 {
-            self.textures.0[tex_id].params = t.params;
-        };
-    }
-    fn texture_read_pixels(&mut self, texture: TextureId, source: &mut [u8]) {
-        let t = self.textures.get(texture);
-        t.read_pixels(source);
-    }
-    fn texture_generate_mipmaps(&mut self, texture: TextureId) {
-        let t = self.textures.get(texture);
-        let raw = t.raw.texture().expect(
-            "texture_generate_mipmaps not yet implemented for RenderBuffer(multisampled) textures",
-        );
-
-        self.cache.store_texture_binding(0);
-        self.cache.bind_texture(0, t.params.kind.into(), raw);
-        unsafe {
-            glGenerateMipmap(t.params.kind.into());
-        }
-        self.cache.restore_texture_binding(0);
-    }
-    fn texture_update_part(
-        &mut self,
-        texture: TextureId,
-        x_offset: i32,
-        y_offset: i32,
-        width: i32,
-        height: i32,
-        source: &[u8],
-    ) {
-        let t = self.textures.get(texture);
-        t.update_texture_part(self, x_offset, y_offset, width, height, source);
-    }
-    fn texture_params(&self, texture: TextureId) -> TextureParams {
-        let texture = self.textures.get(texture);
-        texture.params
-    }
-    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
-        let texture = self.textures.get(texture);
-        let raw = texture
-            .raw
-            .texture()
-            .expect("For multisampled texture raw_id is not supported");
-
-        RawId::OpenGl(raw)
-    }
-
-    fn new_render_pass_mrt(
-        &mut self,
-        color_img: &[TextureId],
-        resolve_img: Option<&[TextureId]>,
-        depth_img: Option<TextureId>,
-    ) -> RenderPass {
-        if color_img.is_empty() && depth_img.is_none() {
-            panic!("Render pass should have at least one non-none target");
-        }
-        let mut gl_fb = 0;
-
-        let mut resolves = None;
-        unsafe {
-            glGenFramebuffers(1, &mut gl_fb as *mut _);
-            glBindFramebuffer(GL_FRAMEBUFFER, gl_fb);
-            for (i, color_img) in color_img.iter().enumerate() {
-                let texture = self.textures.get(*color_img);
-                if texture.params.sample_count > 1 {
-                    let raw = texture.raw.renderbuffer().unwrap();
-                    glFramebufferRenderbuffer(
-                        GL_FRAMEBUFFER,
-                        GL_COLOR_ATTACHMENT0 + i as u32,
-                        GL_RENDERBUFFER,
-                        raw,
-                    );
-                } else {
-                    let raw = texture.raw.texture().unwrap();
-                    glFramebufferTexture2D(
-                        GL_FRAMEBUFFER,
-                        GL_COLOR_ATTACHMENT0 + i as u32,
-                        GL_TEXTURE_2D,
-                        raw,
-                        0,
-                    );
-                }
-            }
-            if let Some(depth_img) = depth_img {
-                let texture = self.textures.get(depth_img);
-                if texture.params.sample_count > 1 {
-                    let raw = texture.raw.texture().unwrap();
-                    glFramebufferRenderbuffer(
-                        GL_FRAMEBUFFER,
-                        GL_DEPTH_ATTACHMENT,
-                        GL_RENDERBUFFER,
-                        raw,
-                    );
-                } else {
-                    let raw = texture.raw.texture().unwrap();
-                    glFramebufferTexture2D(
-                        GL_FRAMEBUFFER,
-                        GL_DEPTH_ATTACHMENT,
-                        GL_TEXTURE_2D,
-                        raw,
-                        0,
-                    );
-                }
-            }
-            let mut attachments = vec![];
-            for i in 0..color_img.len() {
-                attachments.push(GL_COLOR_ATTACHMENT0 + i as u32);
-            }
-
-            if color_img.len() > 1 {
-                glDrawBuffers(color_img.len() as _, attachments.as_ptr() as _);
-            }
-
-            if let Some(resolve_img) = resolve_img {
-                resolves = Some(vec![]);
-                let resolves = resolves.as_mut().unwrap();
-                for (i, resolve_img) in resolve_img.iter().enumerate() {
-                    let mut resolve_fb = 0;
-                    glGenFramebuffers(1, &mut resolve_fb as *mut _);
-                    glBindFramebuffer(GL_FRAMEBUFFER, resolve_fb);
-                    resolves.push((resolve_fb, *resolve_img));
-                    let texture = self.textures.get(*resolve_img);
-                    let raw = texture.raw.texture().unwrap();
-                    glFramebufferTexture2D(
-                        GL_FRAMEBUFFER,
-                        GL_COLOR_ATTACHMENT0 + i as u32,
-                        GL_TEXTURE_2D,
-                        raw,
-                        0,
-                    );
-                    let fb_status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
-                    assert!(fb_status != 0);
-                    glDrawBuffers(1, attachments.as_ptr() as _);
-                }
-            }
-            glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer);
-        }
-        let pass = RenderPassInternal {
-            gl_fb,
-            color_textures: color_img.to_vec(),
-            resolves,
-            depth_texture: depth_img,
+        self.cache.restore_texture_binding(0);
+    }
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        source: Option<&[u8]>,
+    ) {
+        let mut t = self.textures.get(texture);
+        self.cache.stats.bytes_uploaded += source.map_or(0, |source| source.len());
+        t.resize(self, width, height, source);
+        if let TextureIdInner::Managed(tex_id) = texture.0 {
+            self.textures.0[tex_id].params = t.params;
         };
+        if let Some(data) = &mut self.resource_data {
+            data.texture_resized(texture, source);
+        }
+    }
+    fn texture_read_pixels(&mut self, texture: TextureId, source: &mut [u8]) {
+        let t = self.textures.get(texture);
+        t.read_pixels(source);
+    }
+    fn texture_generate_mipmaps(&mut self, texture: TextureId) {
+        let t = self.textures.get(texture);
+        let raw = t.raw.texture().expect(
+            "texture_generate_mipmaps not yet implemented for RenderBuffer(multisampled) textures",
+        );
+
+        self.cache.store_texture_binding(0);
+        self.cache.bind_texture(0, t.params.kind.into(), raw);
+        unsafe {
+            glGenerateMipmap(t.params.kind.into());
+        }
+        self.cache.restore_texture_binding(0);
+        if let Some(data) = &mut self.resource_data {
+            data.texture_mipmaps_generated(texture);
+        }
+    }
+    fn texture_update_part(
+        &mut self,
+        texture: TextureId,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        source: &[u8],
+    ) {
+        let t = self.textures.get(texture);
+        self.cache.stats.bytes_uploaded += source.len();
+        t.update_texture_part(self, x_offset, y_offset, width, height, source);
+        if let Some(data) = &mut self.resource_data {
+            let region = TextureRegion {
+                level: 0,
+                x: x_offset,
+                y: y_offset,
+                width,
+                height,
+            };
+            data.texture_updated(texture, &t.params, region, PixelLayout::default(), source);
+        }
+    }
+    fn texture_update_region(
+        &mut self,
+        texture: TextureId,
+        region: TextureRegion,
+        layout: PixelLayout,
+        source: &[u8],
+    ) {
+        let t = self.textures.get(texture);
+        self.cache.stats.bytes_uploaded +=
+            t.params
+                .format
+                .size(region.width.max(0) as _, region.height.max(0) as _) as usize;
+        t.update_region(self, region, layout, source);
+        if let Some(data) = &mut self.resource_data {
+            data.texture_updated(texture, &t.params, region, layout, source);
+        }
+    }
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        let texture = self.textures.get(texture);
+        texture.params
+    }
+    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
+        let texture = self.textures.get(texture);
+        let raw = texture
+            .raw
+            .texture()
+            .expect("For multisampled texture raw_id is not supported");
 
+        RawId::OpenGl(raw)
+    }
+
+    fn new_render_pass_mrt(
+        &mut self,
+        color_img: &[TextureId],
+        resolve_img: Option<&[TextureId]>,
+        depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        let pass = self.render_pass_internal(color_img, resolve_img, depth_img);
         RenderPass(self.passes.add(pass))
     }
     fn render_pass_color_attachments(&self, render_pass: RenderPass) -> &[TextureId] {
         &self.passes[render_pass.0].color_textures
     }
     fn delete_render_pass(&mut self, render_pass: RenderPass) {
+        if self.deferred.defer(render_pass.into()) {
+            return;
+        }
         let pass_id = render_pass.0;
 
         let render_pass = self.passes.remove(pass_id);
+        self.labels.remove(&RenderPass(pass_id).into());
 
         unsafe { glDeleteFramebuffers(1, &render_pass.gl_fb as *const _) }
 
+        // attachments might have been deleted by the user already
         for color_texture in &render_pass.color_textures {
-            self.delete_texture(*color_texture);
+            if self.is_valid((*color_texture).into()) {
+                self.delete_texture(*color_texture);
+            }
         }
         if let Some(depth_texture) = render_pass.depth_texture {
-            self.delete_texture(depth_texture);
+            if self.is_valid(depth_texture.into()) {
+                self.delete_texture(depth_texture);
+            }
         }
     }
 
@@ -1181,112 +2288,18 @@ impl RenderingBackend for GlContext {
         shader: ShaderId,
         params: PipelineParams,
     ) -> Pipeline {
-        #[derive(Clone, Copy, Default)]
-        struct BufferCacheData {
-            stride: i32,
-            offset: i64,
-        }
-
-        let mut buffer_cache: Vec<BufferCacheData> =
-            vec![BufferCacheData::default(); buffer_layout.len()];
-
-        for VertexAttribute {
-            format,
-            buffer_index,
-            ..
-        } in attributes
-        {
-            let layout = buffer_layout.get(*buffer_index).unwrap_or_else(|| panic!());
-            let cache = buffer_cache
-                .get_mut(*buffer_index)
-                .unwrap_or_else(|| panic!());
-
-            if layout.stride == 0 {
-                cache.stride += format.size_bytes();
-            } else {
-                cache.stride = layout.stride;
-            }
-            // WebGL 1 limitation
-            assert!(cache.stride <= 255);
-        }
-
-        let program = self.shaders[shader.0].program;
-
-        let attributes_len = attributes
-            .iter()
-            .map(|layout| match layout.format {
-                VertexFormat::Mat4 => 4,
-                _ => 1,
-            })
-            .sum();
-
-        let mut vertex_layout: Vec<Option<VertexAttributeInternal>> = vec![None; attributes_len];
-
-        for VertexAttribute {
-            name,
-            format,
-            buffer_index,
-            gl_pass_as_float,
-        } in attributes
-        {
-            let buffer_data = &mut buffer_cache
-                .get_mut(*buffer_index)
-                .unwrap_or_else(|| panic!());
-            let layout = buffer_layout.get(*buffer_index).unwrap_or_else(|| panic!());
-
-            let cname = CString::new(*name).unwrap_or_else(|e| panic!("{}", e));
-            let attr_loc = unsafe { glGetAttribLocation(program, cname.as_ptr() as *const _) };
-            let attr_loc = if attr_loc == -1 { None } else { Some(attr_loc) };
-            let divisor = if layout.step_func == VertexStep::PerVertex {
-                0
-            } else {
-                layout.step_rate
-            };
-
-            let mut attributes_count: usize = 1;
-            let mut format = *format;
-
-            if format == VertexFormat::Mat4 {
-                format = VertexFormat::Float4;
-                attributes_count = 4;
-            }
-            for i in 0..attributes_count {
-                if let Some(attr_loc) = attr_loc {
-                    let attr_loc = attr_loc as GLuint + i as GLuint;
-
-                    let attr = VertexAttributeInternal {
-                        attr_loc,
-                        size: format.components(),
-                        type_: format.type_(),
-                        offset: buffer_data.offset,
-                        stride: buffer_data.stride,
-                        buffer_index: *buffer_index,
-                        divisor,
-                        gl_pass_as_float: *gl_pass_as_float,
-                    };
-
-                    assert!(
-                        attr_loc < vertex_layout.len() as u32,
-                        "attribute: {} outside of allocated attributes array len: {}",
-                        name,
-                        vertex_layout.len()
-                    );
-                    vertex_layout[attr_loc as usize] = Some(attr);
-                }
-                buffer_data.offset += format.size_bytes() as i64
-            }
+        let pipeline = self.pipeline_internal(buffer_layout, attributes, shader, params);
+        let pipeline = Pipeline(self.pipelines.add(pipeline));
+        if let Some(data) = &mut self.resource_data {
+            data.pipeline_created(pipeline, buffer_layout, attributes);
         }
-
-        let pipeline = PipelineInternal {
-            layout: vertex_layout,
-            shader,
-            params,
-        };
-
-        Pipeline(self.pipelines.add(pipeline))
+        pipeline
     }
 
     fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        if self.cache.cur_pipeline != Some(*pipeline) {
+            self.cache.stats.pipeline_switches += 1;
+        }
         self.cache.cur_pipeline = Some(*pipeline);
 
         {
@@ -1360,9 +2373,10 @@ impl RenderingBackend for GlContext {
             self.cache.bind_buffer(gl_target, gl_buf, index_type);
 
             glBufferData(gl_target, size as _, std::ptr::null() as *const _, gl_usage);
-            if let BufferSource::Slice(data) = data {
+            if let BufferSource::Slice(data) = &data {
                 debug_assert!(data.is_slice);
                 glBufferSubData(gl_target, 0, size as _, data.ptr as _);
+                self.cache.stats.bytes_uploaded += size;
             }
             self.cache.restore_buffer_binding(gl_target);
         }
@@ -1374,16 +2388,20 @@ impl RenderingBackend for GlContext {
             index_type,
         };
 
-        BufferId(self.buffers.add(buffer))
+        let buffer = BufferId(self.buffers.add(buffer));
+        if let Some(kept) = &mut self.resource_data {
+            kept.buffer_created(buffer, usage, &data);
+        }
+        buffer
     }
 
-    fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
+    fn buffer_update(&mut self, buffer_id: BufferId, data: BufferSource) {
         let data = match data {
             BufferSource::Slice(data) => data,
             _ => panic!("buffer_update expects BufferSource::slice"),
         };
         debug_assert!(data.is_slice);
-        let buffer = &self.buffers[buffer.0];
+        let buffer = self.buffers[buffer_id.0];
 
         if matches!(buffer.buffer_type, BufferType::IndexBuffer) {
             assert!(buffer.index_type.is_some());
@@ -1400,6 +2418,11 @@ impl RenderingBackend for GlContext {
             .bind_buffer(gl_target, buffer.gl_buf, buffer.index_type);
         unsafe { glBufferSubData(gl_target, 0, size as _, data.ptr as _) };
         self.cache.restore_buffer_binding(gl_target);
+        self.cache.stats.bytes_uploaded += size;
+        let buffer_size = buffer.size;
+        if let Some(kept) = &mut self.resource_data {
+            kept.buffer_updated(buffer_id, buffer_size, &data);
+        }
     }
 
     /// Size of buffer in bytes
@@ -1414,16 +2437,32 @@ impl RenderingBackend for GlContext {
     ///
     /// There is no protection against using deleted textures later. However its not an UB in OpenGl and thats why
     /// this function is not marked as unsafe
+    fn publish_texture(&mut self, upload: UploadedTexture) -> Result<TextureId, UploadedTexture> {
+        self.publish_uploaded_texture(upload)
+    }
+
+    fn publish_buffer(&mut self, upload: UploadedBuffer) -> Result<BufferId, UploadedBuffer> {
+        self.publish_uploaded_buffer(upload)
+    }
+
     fn delete_buffer(&mut self, buffer: BufferId) {
+        if self.deferred.defer(buffer.into()) {
+            return;
+        }
         unsafe { glDeleteBuffers(1, &self.buffers[buffer.0].gl_buf as *const _) }
         self.cache.clear_buffer_bindings();
         self.cache.clear_vertex_attributes();
         self.buffers.remove(buffer.0);
+        self.labels.remove(&buffer.into());
+        if let Some(data) = &mut self.resource_data {
+            data.deleted(buffer.into());
+        }
     }
 
     /// Set a new viewport rectangle.
     /// Should be applied after begin_pass.
     fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        let (x, y, w, h) = self.scale_default_pass_rect(x, y, w, h);
         unsafe {
             glViewport(x, y, w, h);
         }
@@ -1432,6 +2471,7 @@ impl RenderingBackend for GlContext {
     /// Set a new scissor rectangle.
     /// Should be applied after begin_pass.
     fn apply_scissor_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        let (x, y, w, h) = self.scale_default_pass_rect(x, y, w, h);
         unsafe {
             glScissor(x, y, w, h);
         }
@@ -1628,16 +2668,19 @@ impl RenderingBackend for GlContext {
     }
 
     fn begin_pass(&mut self, pass: Option<RenderPass>, action: PassAction) {
+        let generation = {
+            let d = crate::native_display().lock().unwrap();
+            (!d.context_lost).then_some(d.context_generation)
+        };
+        if generation.is_some_and(|generation| generation != self.context_generation) {
+            // the platform replaced a lost context since the last frame
+            self.restore();
+        }
         self.cache.cur_pass = pass;
         let (framebuffer, w, h) = match pass {
             None => {
                 let (screen_width, screen_height) = window::screen_size();
-
-                (
-                    self.default_framebuffer,
-                    screen_width as i32,
-                    screen_height as i32,
-                )
+                self.default_pass_framebuffer(screen_width as i32, screen_height as i32)
             }
             Some(pass) => {
                 let pass = &self.passes[pass.0];
@@ -1706,9 +2749,189 @@ impl RenderingBackend for GlContext {
         }
     }
 
+    fn set_default_pass_sample_count(&mut self, sample_count: i32) {
+        if sample_count <= 1 {
+            self.delete_default_pass_msaa();
+            return;
+        }
+        if !self.info.features.resolve_attachments {
+            eprintln!("Multisampled default pass is not supported on GL2 and WebGL1");
+            return;
+        }
+        let mut max_samples: GLint = 0;
+        unsafe { glGetIntegerv(GL_MAX_SAMPLES, &mut max_samples) };
+        let sample_count = sample_count.min(max_samples.max(1));
+        if sample_count <= 1 {
+            self.delete_default_pass_msaa();
+            return;
+        }
+        if let Some(msaa) = &mut self.default_pass_msaa {
+            if msaa.sample_count != sample_count {
+                msaa.sample_count = sample_count;
+                // storage gets reallocated on the next begin_default_pass
+                msaa.width = 0;
+                msaa.height = 0;
+            }
+            return;
+        }
+
+        let mut msaa = DefaultPassMsaa {
+            sample_count,
+            width: 0,
+            height: 0,
+            gl_fb: 0,
+            color: 0,
+            depth_stencil: 0,
+            dirty: false,
+        };
+        unsafe {
+            glGenFramebuffers(1, &mut msaa.gl_fb);
+            glGenRenderbuffers(1, &mut msaa.color);
+            glGenRenderbuffers(1, &mut msaa.depth_stencil);
+        }
+        self.default_pass_msaa = Some(msaa);
+
+        // attachments need storage before being attached
+        let (w, h) = window::screen_size();
+        self.resize_default_pass_targets(w as i32, h as i32);
+        let msaa = self.default_pass_msaa.as_ref().unwrap();
+        unsafe {
+            glBindFramebuffer(GL_FRAMEBUFFER, msaa.gl_fb);
+            glFramebufferRenderbuffer(
+                GL_FRAMEBUFFER,
+                GL_COLOR_ATTACHMENT0,
+                GL_RENDERBUFFER,
+                msaa.color,
+            );
+            glFramebufferRenderbuffer(
+                GL_FRAMEBUFFER,
+                GL_DEPTH_STENCIL_ATTACHMENT,
+                GL_RENDERBUFFER,
+                msaa.depth_stencil,
+            );
+            let fb_status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+            glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer);
+            if fb_status != GL_FRAMEBUFFER_COMPLETE {
+                eprintln!("Multisampled default pass framebuffer is incomplete: {fb_status:#x}");
+                self.delete_default_pass_msaa();
+            }
+        }
+    }
+
+    fn default_pass_sample_count(&self) -> i32 {
+        match &self.default_pass_msaa {
+            Some(msaa) => msaa.sample_count,
+            None => self.info.framebuffer.sample_count,
+        }
+    }
+
+    fn set_default_pass_scale(&mut self, scale: f32) {
+        let scale = if scale.is_finite() {
+            scale.clamp(0.1, 1.0)
+        } else {
+            1.0
+        };
+        if scale == 1.0 {
+            self.default_pass_scale = 1.0;
+            self.delete_default_pass_scaled();
+            return;
+        }
+        if !self.info.features.resolve_attachments {
+            eprintln!("Scaled default pass is not supported on GL2 and WebGL1");
+            return;
+        }
+        self.default_pass_scale = scale;
+        if self.default_pass_scaled.is_some() {
+            // storage gets reallocated on the next begin_default_pass
+            return;
+        }
+
+        let mut scaled = DefaultPassScaled {
+            width: 0,
+            height: 0,
+            window_width: 0,
+            window_height: 0,
+            gl_fb: 0,
+            color: 0,
+            depth_stencil: 0,
+            dirty: false,
+        };
+        unsafe {
+            glGenFramebuffers(1, &mut scaled.gl_fb);
+            glGenRenderbuffers(1, &mut scaled.color);
+            glGenRenderbuffers(1, &mut scaled.depth_stencil);
+        }
+        self.default_pass_scaled = Some(scaled);
+
+        // attachments need storage before being attached
+        let (w, h) = window::screen_size();
+        self.resize_default_pass_targets(w as i32, h as i32);
+        let scaled = self.default_pass_scaled.as_ref().unwrap();
+        unsafe {
+            glBindFramebuffer(GL_FRAMEBUFFER, scaled.gl_fb);
+            glFramebufferRenderbuffer(
+                GL_FRAMEBUFFER,
+                GL_COLOR_ATTACHMENT0,
+                GL_RENDERBUFFER,
+                scaled.color,
+            );
+            glFramebufferRenderbuffer(
+                GL_FRAMEBUFFER,
+                GL_DEPTH_STENCIL_ATTACHMENT,
+                GL_RENDERBUFFER,
+                scaled.depth_stencil,
+            );
+            let fb_status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+            glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer);
+            if fb_status != GL_FRAMEBUFFER_COMPLETE {
+                eprintln!("Scaled default pass framebuffer is incomplete: {fb_status:#x}");
+                self.delete_default_pass_scaled();
+                self.default_pass_scale = 1.0;
+            }
+        }
+    }
+
+    fn default_pass_scale(&self) -> f32 {
+        self.default_pass_scale
+    }
+
+    fn set_default_pass_upscale_filter(&mut self, filter: FilterMode) {
+        self.default_pass_upscale_filter = filter;
+    }
+
     fn commit_frame(&mut self) {
+        self.resolve_default_pass();
         self.cache.clear_buffer_bindings();
         self.cache.clear_texture_bindings();
+
+        self.last_frame_stats = FrameStats {
+            draw_calls: self.draw_calls.replace(0),
+            ..std::mem::take(&mut self.cache.stats)
+        };
+        DeferredDeletions::flush(self, |ctx| &mut ctx.deferred);
+    }
+
+    fn capture_frame(&mut self) -> Option<capture::CapturedFrame> {
+        let (width, height) = window::screen_size();
+        let (width, height) = (width as u32, height as u32);
+        let mut rgba = vec![0u8; width as usize * height as usize * 4];
+        unsafe {
+            let mut binded_fbo: i32 = 0;
+            glGetIntegerv(gl::GL_FRAMEBUFFER_BINDING, &mut binded_fbo);
+            glBindFramebuffer(gl::GL_FRAMEBUFFER, self.default_framebuffer);
+            // RGBA8 rows are always 4 bytes aligned, the default GL_PACK_ALIGNMENT is fine
+            glReadPixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                GL_RGBA,
+                GL_UNSIGNED_BYTE,
+                rgba.as_mut_ptr() as _,
+            );
+            glBindFramebuffer(gl::GL_FRAMEBUFFER, binded_fbo as _);
+        }
+        Some(capture::CapturedFrame::from_bottom_up(width, height, rgba))
     }
 
     fn draw(&self, base_element: i32, num_elements: i32, num_instances: i32) {
@@ -1723,6 +2946,8 @@ impl RenderingBackend for GlContext {
             return;
         }
 
+        self.draw_calls.set(self.draw_calls.get() + 1);
+
         let pip = &self.pipelines[self.cache.cur_pipeline.unwrap().0];
         let primitive_type = pip.params.primitive_type.into();
         let index_type = self.cache.index_type.expect("Unset index buffer type");
@@ -43,9 +43,37 @@ pub struct GlCache {
     pub color_write: ColorMask,
     pub cull_face: CullFace,
     pub attributes: [Option<CachedAttribute>; MAX_VERTEX_ATTRIBUTES],
+    // counters of the current frame, draw_calls are tracked by GlContext
+    pub stats: FrameStats,
 }
 
 impl GlCache {
+    pub fn new() -> GlCache {
+        GlCache {
+            stored_index_buffer: 0,
+            stored_index_type: None,
+            stored_vertex_buffer: 0,
+            index_buffer: 0,
+            index_type: None,
+            vertex_buffer: 0,
+            cur_pipeline: None,
+            cur_pass: None,
+            color_blend: None,
+            alpha_blend: None,
+            stencil: None,
+            color_write: (true, true, true, true),
+            cull_face: CullFace::Nothing,
+            stored_texture: 0,
+            stored_target: 0,
+            textures: [CachedTexture {
+                target: 0,
+                texture: 0,
+            }; MAX_SHADERSTAGE_IMAGES],
+            attributes: [None; MAX_VERTEX_ATTRIBUTES],
+            stats: FrameStats::default(),
+        }
+    }
+
     pub fn bind_buffer(&mut self, target: GLenum, buffer: GLuint, index_type: Option<u32>) {
         if target == GL_ARRAY_BUFFER {
             if self.vertex_buffer != buffer {
@@ -53,6 +81,8 @@ impl GlCache {
                 unsafe {
                     glBindBuffer(target, buffer);
                 }
+            } else {
+                self.stats.skipped_state_changes += 1;
             }
         } else {
             if self.index_buffer != buffer {
@@ -60,6 +90,8 @@ impl GlCache {
                 unsafe {
                     glBindBuffer(target, buffer);
                 }
+            } else {
+                self.stats.skipped_state_changes += 1;
             }
             self.index_type = index_type;
         }
@@ -95,6 +127,9 @@ impl GlCache {
                 let target = if target == 0 { GL_TEXTURE_2D } else { target };
                 glBindTexture(target, texture);
                 self.textures[slot_index] = CachedTexture { target, texture };
+                self.stats.texture_binds += 1;
+            } else {
+                self.stats.skipped_state_changes += 1;
             }
         }
     }
@@ -0,0 +1,498 @@
+//! Context loss: detection, and recreating resources from their kept creation data,
+//! see `RenderingBackend::set_restore_resources`.
+
+use super::*;
+use crate::ResourceId;
+
+struct TextureData {
+    access: TextureAccess,
+    // `[cubemap_face][mipmap_level][bytes]`, empty while the content is unknown
+    faces: Vec<Vec<Vec<u8>>>,
+    // levels past the first one come from glGenerateMipmap
+    generated_mipmaps: bool,
+    wrap: Option<(TextureWrap, TextureWrap)>,
+}
+
+struct BufferData {
+    usage: BufferUsage,
+    // None until an empty buffer is written
+    bytes: Option<Vec<u8>>,
+}
+
+struct ShaderData {
+    vertex: String,
+    fragment: String,
+    meta: ShaderMeta,
+}
+
+struct PipelineData {
+    buffer_layout: Vec<BufferLayout>,
+    attributes: Vec<VertexAttribute>,
+}
+
+/// CPU side copies of the resources created with `set_restore_resources(true)`.
+#[derive(Default)]
+pub(super) struct ResourceData {
+    textures: HashMap<ResourceId, TextureData>,
+    buffers: HashMap<ResourceId, BufferData>,
+    shaders: HashMap<ResourceId, ShaderData>,
+    pipelines: HashMap<ResourceId, PipelineData>,
+}
+
+fn managed(texture: TextureId) -> Option<ResourceId> {
+    match texture.0 {
+        TextureIdInner::Managed(id) => Some(id),
+        TextureIdInner::Raw(_) => None,
+    }
+}
+
+impl ResourceData {
+    pub(super) fn texture_created(
+        &mut self,
+        texture: TextureId,
+        access: TextureAccess,
+        source: &TextureSource,
+    ) {
+        let faces = match (access, source) {
+            (TextureAccess::RenderTarget, _) | (_, TextureSource::Empty) => vec![],
+            (_, TextureSource::Bytes(bytes)) => vec![vec![bytes.to_vec()]],
+            (_, TextureSource::Mips(levels)) => {
+                vec![levels.iter().map(|level| level.to_vec()).collect()]
+            }
+            (_, TextureSource::Array(faces)) => faces
+                .iter()
+                .map(|levels| levels.iter().map(|level| level.to_vec()).collect())
+                .collect(),
+        };
+        let data = TextureData {
+            access,
+            faces,
+            generated_mipmaps: false,
+            wrap: None,
+        };
+        self.textures.insert(managed(texture).unwrap(), data);
+    }
+
+    /// Patch the kept pixels of a 2D texture, the same way `Texture::update_region` does.
+    pub(super) fn texture_updated(
+        &mut self,
+        texture: TextureId,
+        params: &TextureParams,
+        region: TextureRegion,
+        layout: PixelLayout,
+        source: &[u8],
+    ) {
+        let data = match managed(texture).and_then(|id| self.textures.get_mut(&id)) {
+            Some(data) if data.access != TextureAccess::RenderTarget => data,
+            _ => return,
+        };
+        let format = params.format;
+        if data.faces.is_empty() {
+            data.faces.push(vec![]);
+        }
+        let levels = &mut data.faces[0];
+        while levels.len() <= region.level as usize {
+            let (width, height) = params.level_size(levels.len() as _);
+            levels.push(vec![0; format.size(width, height) as usize]);
+        }
+        let level = &mut levels[region.level as usize];
+
+        // compressed formats are patched in rows of 4x4 blocks
+        let block = if format.is_compressed() { 4 } else { 1 };
+        let (level_width, _) = params.level_size(region.level);
+        let dst_stride = format.size(level_width, 1) as usize;
+        let row = format.size(region.width as _, 1) as usize;
+        let src_stride = if format.is_compressed() {
+            row
+        } else {
+            layout.row_stride(format, region.width as _)
+        };
+        let x = format.size(region.x as _, 1) as usize;
+        let y = region.y as usize / block;
+        for i in 0..(region.height as usize + block - 1) / block {
+            let dst = (y + i) * dst_stride + x;
+            level[dst..dst + row].copy_from_slice(&source[i * src_stride..i * src_stride + row]);
+        }
+    }
+
+    pub(super) fn texture_resized(&mut self, texture: TextureId, source: Option<&[u8]>) {
+        if let Some(data) = managed(texture).and_then(|id| self.textures.get_mut(&id)) {
+            data.faces = match source {
+                Some(source) if data.access != TextureAccess::RenderTarget => {
+                    vec![vec![source.to_vec()]]
+                }
+                _ => vec![],
+            };
+            data.generated_mipmaps = false;
+        }
+    }
+
+    pub(super) fn texture_mipmaps_generated(&mut self, texture: TextureId) {
+        if let Some(data) = managed(texture).and_then(|id| self.textures.get_mut(&id)) {
+            for levels in &mut data.faces {
+                levels.truncate(1);
+            }
+            data.generated_mipmaps = true;
+        }
+    }
+
+    pub(super) fn texture_wrap_set(
+        &mut self,
+        texture: TextureId,
+        wrap: (TextureWrap, TextureWrap),
+    ) {
+        if let Some(data) = managed(texture).and_then(|id| self.textures.get_mut(&id)) {
+            data.wrap = Some(wrap);
+        }
+    }
+
+    pub(super) fn buffer_created(
+        &mut self,
+        buffer: BufferId,
+        usage: BufferUsage,
+        source: &BufferSource,
+    ) {
+        let bytes = match source {
+            BufferSource::Slice(data) => Some(unsafe { arg_bytes(data) }.to_vec()),
+            BufferSource::Empty { .. } => None,
+        };
+        self.buffers.insert(buffer.0, BufferData { usage, bytes });
+    }
+
+    pub(super) fn buffer_updated(&mut self, buffer: BufferId, size: usize, data: &Arg) {
+        if let Some(kept) = self.buffers.get_mut(&buffer.0) {
+            let bytes = kept.bytes.get_or_insert_with(|| vec![0; size]);
+            let source = unsafe { arg_bytes(data) };
+            bytes[..source.len()].copy_from_slice(source);
+        }
+    }
+
+    pub(super) fn shader_created(
+        &mut self,
+        shader: ShaderId,
+        vertex: &str,
+        fragment: &str,
+        meta: &ShaderMeta,
+    ) {
+        let data = ShaderData {
+            vertex: vertex.to_string(),
+            fragment: fragment.to_string(),
+            meta: meta.clone(),
+        };
+        self.shaders.insert(shader.0, data);
+    }
+
+    pub(super) fn pipeline_created(
+        &mut self,
+        pipeline: Pipeline,
+        buffer_layout: &[BufferLayout],
+        attributes: &[VertexAttribute],
+    ) {
+        let data = PipelineData {
+            buffer_layout: buffer_layout.to_vec(),
+            attributes: attributes.to_vec(),
+        };
+        self.pipelines.insert(pipeline.0, data);
+    }
+
+    pub(super) fn deleted(&mut self, handle: ResourceHandle) {
+        match handle {
+            ResourceHandle::Buffer(id) => {
+                self.buffers.remove(&id.0);
+            }
+            ResourceHandle::Texture(id) => {
+                if let Some(id) = managed(id) {
+                    self.textures.remove(&id);
+                }
+            }
+            ResourceHandle::Shader(id) => {
+                self.shaders.remove(&id.0);
+            }
+            ResourceHandle::Pipeline(id) => {
+                self.pipelines.remove(&id.0);
+            }
+            ResourceHandle::RenderPass(_) => {}
+        }
+    }
+}
+
+unsafe fn arg_bytes<'a>(data: &Arg<'a>) -> &'a [u8] {
+    std::slice::from_raw_parts(data.ptr as *const u8, data.size)
+}
+
+impl GlContext {
+    pub(super) fn context_lost(&self) -> bool {
+        if self.lost.get() {
+            return true;
+        }
+        let d = crate::native_display().lock().unwrap();
+        if d.context_lost || d.context_generation != self.context_generation {
+            return true;
+        }
+        drop(d);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self
+            .info
+            .gl_context
+            .is_some_and(|request| request.robustness)
+        {
+            let status = unsafe {
+                if is_gl_func_loaded("glGetGraphicsResetStatus") {
+                    glGetGraphicsResetStatus()
+                } else if is_gl_func_loaded("glGetGraphicsResetStatusARB") {
+                    glGetGraphicsResetStatusARB()
+                } else if is_gl_func_loaded("glGetGraphicsResetStatusEXT") {
+                    glGetGraphicsResetStatusEXT()
+                } else {
+                    GL_NO_ERROR
+                }
+            };
+            // the status is reported once, the context stays unusable
+            if status != GL_NO_ERROR {
+                self.lost.set(true);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Recreate the context-level state and every kept resource in the current context.
+    pub(super) fn restore(&mut self) {
+        unsafe {
+            let mut vao = 0;
+            glGenVertexArrays(1, &mut vao as *mut _);
+            glBindVertexArray(vao);
+        }
+        self.cache = GlCache {
+            stats: self.cache.stats,
+            ..GlCache::new()
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.khr_debug = init_khr_debug();
+        }
+        // the old GL names are gone, nothing to delete
+        let scaled = self.default_pass_scaled.take();
+        if let Some(msaa) = self.default_pass_msaa.take() {
+            self.set_default_pass_sample_count(msaa.sample_count);
+        }
+        if scaled.is_some() {
+            self.set_default_pass_scale(self.default_pass_scale);
+        }
+
+        let data = self.resource_data.take();
+        let kept = data.as_ref();
+        let mut restored = vec![];
+
+        let ids: Vec<ResourceId> = self.shaders.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            match kept.and_then(|data| data.shaders.get(&id)) {
+                Some(shader) => {
+                    self.shaders[id] =
+                        load_shader_internal(&shader.vertex, &shader.fragment, shader.meta.clone())
+                            .expect("Failed to recompile a shader after context loss");
+                    restored.push(ResourceHandle::Shader(ShaderId(id)));
+                }
+                None => self.shaders[id].program = 0,
+            }
+        }
+
+        let ids: Vec<ResourceId> = self.textures.0.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            let texture = TextureId(TextureIdInner::Managed(id));
+            let data = match kept.and_then(|data| data.textures.get(&id)) {
+                Some(data) => data,
+                None => {
+                    self.textures.0[id].raw = match self.textures.0[id].raw {
+                        TextureOrRenderbuffer::Texture(_) => TextureOrRenderbuffer::Texture(0),
+                        TextureOrRenderbuffer::Renderbuffer(_) => {
+                            TextureOrRenderbuffer::Renderbuffer(0)
+                        }
+                    };
+                    continue;
+                }
+            };
+            let params = self.textures.0[id].params;
+            let faces: Vec<Vec<&[u8]>> = data
+                .faces
+                .iter()
+                .map(|levels| levels.iter().map(|level| &level[..]).collect())
+                .collect();
+            let array: Vec<&[&[u8]]> = faces.iter().map(|levels| &levels[..]).collect();
+            let source = match (params.kind, &faces[..]) {
+                (_, []) => TextureSource::Empty,
+                (TextureKind::Texture2D, [levels]) if levels.len() == 1 => {
+                    TextureSource::Bytes(levels[0])
+                }
+                (TextureKind::Texture2D, [levels]) => TextureSource::Mips(levels),
+                _ => TextureSource::Array(&array),
+            };
+            self.textures.0[id] = Texture::new(self, data.access, source, params);
+            if data.generated_mipmaps {
+                self.texture_generate_mipmaps(texture);
+            }
+            if let Some((wrap_x, wrap_y)) = data.wrap {
+                self.texture_set_wrap(texture, wrap_x, wrap_y);
+            }
+            restored.push(ResourceHandle::Texture(texture));
+        }
+
+        let ids: Vec<ResourceId> = self.buffers.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            match kept.and_then(|data| data.buffers.get(&id)) {
+                Some(data) => {
+                    let buffer = self.buffers[id];
+                    let gl_target = gl_buffer_target(&buffer.buffer_type);
+                    let bytes = data.bytes.as_ref().map_or(std::ptr::null(), |b| b.as_ptr());
+                    let mut gl_buf = 0;
+                    unsafe {
+                        glGenBuffers(1, &mut gl_buf as *mut _);
+                        self.cache.store_buffer_binding(gl_target);
+                        self.cache.bind_buffer(gl_target, gl_buf, buffer.index_type);
+                        glBufferData(
+                            gl_target,
+                            buffer.size as _,
+                            bytes as _,
+                            gl_usage(&data.usage),
+                        );
+                        self.cache.restore_buffer_binding(gl_target);
+                    }
+                    self.buffers[id].gl_buf = gl_buf;
+                    restored.push(ResourceHandle::Buffer(BufferId(id)));
+                }
+                None => self.buffers[id].gl_buf = 0,
+            }
+        }
+
+        let ids: Vec<ResourceId> = self.pipelines.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            if let Some(data) = kept.and_then(|data| data.pipelines.get(&id)) {
+                let pipeline = &self.pipelines[id];
+                if restored.contains(&ResourceHandle::Shader(pipeline.shader)) {
+                    self.pipelines[id] = self.pipeline_internal(
+                        &data.buffer_layout,
+                        &data.attributes,
+                        pipeline.shader,
+                        pipeline.params,
+                    );
+                    restored.push(ResourceHandle::Pipeline(Pipeline(id)));
+                }
+            }
+        }
+
+        // passes keep no data, they come back whenever their attachments do
+        let ids: Vec<ResourceId> = self.passes.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            let pass = &self.passes[id];
+            let color_img = pass.color_textures.clone();
+            let resolve_img: Option<Vec<TextureId>> = pass
+                .resolves
+                .as_ref()
+                .map(|resolves| resolves.iter().map(|(_, texture)| *texture).collect());
+            let depth_img = pass.depth_texture;
+            let alive = color_img
+                .iter()
+                .chain(resolve_img.iter().flatten())
+                .chain(depth_img.iter())
+                .all(|texture| restored.contains(&ResourceHandle::Texture(*texture)));
+            if alive {
+                self.passes[id] =
+                    self.render_pass_internal(&color_img, resolve_img.as_deref(), depth_img);
+                restored.push(ResourceHandle::RenderPass(RenderPass(id)));
+            } else {
+                let pass = &mut self.passes[id];
+                pass.gl_fb = 0;
+                for (resolve_fb, _) in pass.resolves.iter_mut().flatten() {
+                    *resolve_fb = 0;
+                }
+            }
+        }
+
+        let labels: Vec<(ResourceHandle, String)> = self
+            .labels
+            .iter()
+            .filter(|(handle, _)| restored.contains(handle))
+            .map(|(handle, label)| (*handle, label.clone()))
+            .collect();
+        for (handle, label) in labels {
+            self.set_label(handle, &label);
+        }
+
+        self.resource_data = data;
+        self.context_generation = crate::native_display().lock().unwrap().context_generation;
+        self.lost.set(false);
+    }
+
+    /// Delete every GL object, as a real context loss would.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn lose_objects(&mut self) {
+        unsafe {
+            for (_, shader) in self.shaders.iter() {
+                glDeleteProgram(shader.program);
+            }
+            for (_, texture) in self.textures.0.iter() {
+                match texture.raw {
+                    TextureOrRenderbuffer::Texture(raw) => glDeleteTextures(1, &raw),
+                    TextureOrRenderbuffer::Renderbuffer(raw) => glDeleteRenderbuffers(1, &raw),
+                }
+            }
+            for (_, buffer) in self.buffers.iter() {
+                glDeleteBuffers(1, &buffer.gl_buf);
+            }
+            for (_, pass) in self.passes.iter() {
+                glDeleteFramebuffers(1, &pass.gl_fb);
+                for (resolve_fb, _) in pass.resolves.iter().flatten() {
+                    glDeleteFramebuffers(1, resolve_fb);
+                }
+            }
+            // the managed MSAA and scaled targets are recreated with the same settings
+            if let Some(msaa) = &self.default_pass_msaa {
+                glDeleteFramebuffers(1, &msaa.gl_fb);
+                glDeleteRenderbuffers(1, &msaa.color);
+                glDeleteRenderbuffers(1, &msaa.depth_stencil);
+            }
+            if let Some(scaled) = &self.default_pass_scaled {
+                glDeleteFramebuffers(1, &scaled.gl_fb);
+                glDeleteRenderbuffers(1, &scaled.color);
+                glDeleteRenderbuffers(1, &scaled.depth_stencil);
+            }
+            glBindFramebuffer(GL_FRAMEBUFFER, self.default_framebuffer);
+        }
+        self.lost.set(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texture_updated() {
+        let mut textures = ResourceManager::new("texture");
+        let texture = TextureId(TextureIdInner::Managed(textures.add(())));
+        let params = TextureParams {
+            format: TextureFormat::Alpha,
+            width: 4,
+            height: 3,
+            ..Default::default()
+        };
+        let mut data = ResourceData::default();
+        data.texture_created(texture, TextureAccess::Static, &TextureSource::Empty);
+
+        // 2x2 region out of 3 pixels wide rows
+        let region = TextureRegion {
+            level: 0,
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+        let layout = PixelLayout::with_row_length(3);
+        data.texture_updated(texture, &params, region, layout, &[1, 2, 0, 3, 4]);
+        assert_eq!(
+            data.textures[&managed(texture).unwrap()].faces,
+            [[[0, 0, 0, 0, 0, 1, 2, 0, 0, 3, 4, 0]]]
+        );
+    }
+}
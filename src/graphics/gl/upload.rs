@@ -0,0 +1,262 @@
+//! Texture and buffer uploads from a worker thread, through a GL context sharing
+//! objects with the main one.
+
+use super::*;
+use crate::native::SharedGlContext;
+
+/// GL fence, signaled when the GPU is done with everything issued before it.
+/// A null sync means the work is already known to be complete.
+struct Fence(GLsync);
+
+// sync objects are shared between the contexts and may be waited on from any of them
+unsafe impl Send for Fence {}
+
+impl Fence {
+    /// Place a fence after the commands issued so far by the current context.
+    #[cfg(not(target_arch = "wasm32"))]
+    unsafe fn new(has_sync: bool) -> Fence {
+        if !has_sync {
+            // no sync objects on GL2/GLES2, wait for the upload right away
+            glFinish();
+            return Fence(std::ptr::null_mut());
+        }
+        let sync = glFenceSync(GL_SYNC_GPU_COMMANDS_COMPLETE, 0);
+        // the fence has to reach the GPU before another context may wait on it
+        glFlush();
+        Fence(sync)
+    }
+
+    /// Check the fence without blocking, deleting it once signaled.
+    fn is_signaled(&mut self) -> bool {
+        if self.0.is_null() {
+            return true;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        unsafe {
+            let status = glClientWaitSync(self.0, 0, 0);
+            if status != GL_ALREADY_SIGNALED && status != GL_CONDITION_SATISFIED {
+                return false;
+            }
+            glDeleteSync(self.0);
+        }
+        self.0 = std::ptr::null_mut();
+        true
+    }
+}
+
+/// A texture uploaded by an `UploadContext`,
+/// to be handed over to the main context with `RenderingBackend::publish_texture`.
+///
+/// Dropping it without publishing leaks the GL texture.
+pub struct UploadedTexture {
+    texture: Texture,
+    fence: Fence,
+}
+
+impl UploadedTexture {
+    pub fn params(&self) -> TextureParams {
+        self.texture.params
+    }
+
+    /// Stand-in for backend tests, without a GL texture behind it.
+    #[cfg(test)]
+    pub(crate) fn completed(params: TextureParams) -> UploadedTexture {
+        UploadedTexture {
+            texture: Texture {
+                raw: TextureOrRenderbuffer::Texture(0),
+                params,
+            },
+            fence: Fence(std::ptr::null_mut()),
+        }
+    }
+}
+
+/// A buffer uploaded by an `UploadContext`,
+/// to be handed over to the main context with `RenderingBackend::publish_buffer`.
+///
+/// Dropping it without publishing leaks the GL buffer.
+pub struct UploadedBuffer {
+    buffer: Buffer,
+    fence: Fence,
+}
+
+impl UploadedBuffer {
+    pub fn buffer_type(&self) -> BufferType {
+        self.buffer.buffer_type
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> usize {
+        self.buffer.size
+    }
+
+    /// Size of one index for index buffers, `1` for vertex buffers.
+    pub fn element_size(&self) -> usize {
+        self.buffer.index_type.unwrap_or(1) as usize
+    }
+
+    /// Stand-in for backend tests, without a GL buffer behind it.
+    #[cfg(test)]
+    pub(crate) fn completed(buffer_type: BufferType, size: usize) -> UploadedBuffer {
+        UploadedBuffer {
+            buffer: Buffer {
+                gl_buf: 0,
+                buffer_type,
+                size,
+                index_type: None,
+            },
+            fence: Fence(std::ptr::null_mut()),
+        }
+    }
+}
+
+impl GlContext {
+    /// Take over an uploaded texture once its fence is signaled.
+    pub(crate) fn publish_uploaded_texture(
+        &mut self,
+        mut upload: UploadedTexture,
+    ) -> Result<TextureId, UploadedTexture> {
+        if !upload.fence.is_signaled() {
+            return Err(upload);
+        }
+        let id = self.textures.0.add(upload.texture);
+        Ok(TextureId(TextureIdInner::Managed(id)))
+    }
+
+    /// Take over an uploaded buffer once its fence is signaled.
+    pub(crate) fn publish_uploaded_buffer(
+        &mut self,
+        mut upload: UploadedBuffer,
+    ) -> Result<BufferId, UploadedBuffer> {
+        if !upload.fence.is_signaled() {
+            return Err(upload);
+        }
+        Ok(BufferId(self.buffers.add(upload.buffer)))
+    }
+}
+
+/// GL context for creating textures and buffers on a worker thread.
+///
+/// Created on the main thread with `window::new_upload_context`, then moved to the worker.
+/// The uploads are sent back to the main thread and published with
+/// `RenderingBackend::publish_texture`/`publish_buffer`, which hand them out only
+/// once the GPU finished the upload, so a half-uploaded texture is never sampled.
+///
+/// ```ignore
+/// let mut uploader = window::new_upload_context().expect("no shared contexts");
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// std::thread::spawn(move || {
+///     let texture = uploader.new_texture_from_rgba8(256, 256, &decode_level());
+///     tx.send(texture).unwrap();
+/// });
+///
+/// // later, in update()
+/// if let Ok(upload) = rx.try_recv() {
+///     match ctx.publish_texture(upload) {
+///         Ok(texture) => self.texture = Some(texture),
+///         Err(upload) => self.pending.push(upload), // try again next frame
+///     }
+/// }
+/// ```
+pub struct UploadContext {
+    shared: Box<dyn SharedGlContext>,
+    ctx: Option<GlContext>,
+    // thread the shared context is current on
+    thread: Option<std::thread::ThreadId>,
+    has_sync: bool,
+}
+
+// GlContext is only ever touched from the thread the shared context is current on
+unsafe impl Send for UploadContext {}
+
+impl UploadContext {
+    pub(crate) fn new(shared: Box<dyn SharedGlContext>) -> UploadContext {
+        UploadContext {
+            shared,
+            ctx: None,
+            thread: None,
+            has_sync: false,
+        }
+    }
+
+    /// Make the shared context current on the calling thread if it is not already.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn ctx(&mut self) -> &mut GlContext {
+        let thread = std::thread::current().id();
+        if self.thread != Some(thread) {
+            assert!(
+                self.shared.make_current(),
+                "UploadContext: failed to make the shared context current"
+            );
+            self.thread = Some(thread);
+        }
+        if self.ctx.is_none() {
+            let ctx = GlContext::new();
+            let version = ctx.info.gl_context.and_then(|context| context.version);
+            let gles = ctx.info.gl_context.and_then(|context| context.api)
+                == Some(crate::conf::GlApi::Gles);
+            self.has_sync = if gles {
+                version >= Some((3, 0))
+            } else {
+                version >= Some((3, 2)) || unsafe { has_gl_extension("GL_ARB_sync") }
+            };
+            self.ctx = Some(ctx);
+        }
+        self.ctx.as_mut().unwrap()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_texture(
+        &mut self,
+        access: TextureAccess,
+        source: TextureSource,
+        params: TextureParams,
+    ) -> UploadedTexture {
+        let ctx = self.ctx();
+        let texture = match ctx.new_texture(access, source, params).0 {
+            TextureIdInner::Managed(id) => ctx.textures.0.remove(id),
+            TextureIdInner::Raw(_) => unreachable!(),
+        };
+        UploadedTexture {
+            texture,
+            fence: unsafe { Fence::new(self.has_sync) },
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_texture_from_rgba8(
+        &mut self,
+        width: u16,
+        height: u16,
+        bytes: &[u8],
+    ) -> UploadedTexture {
+        assert_eq!(width as usize * height as usize * 4, bytes.len());
+
+        self.new_texture(
+            TextureAccess::Static,
+            TextureSource::Bytes(bytes),
+            TextureParams {
+                width: width as _,
+                height: height as _,
+                format: TextureFormat::RGBA8,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_buffer(
+        &mut self,
+        type_: BufferType,
+        usage: BufferUsage,
+        data: BufferSource,
+    ) -> UploadedBuffer {
+        let ctx = self.ctx();
+        let id = ctx.new_buffer(type_, usage, data);
+        let buffer = ctx.buffers.remove(id.0);
+        UploadedBuffer {
+            buffer,
+            fence: unsafe { Fence::new(self.has_sync) },
+        }
+    }
+}
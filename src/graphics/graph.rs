@@ -0,0 +1,618 @@
+//! Render graph: passes declare the textures they read and write, the graph orders
+//! them, allocates the render targets and shares them between passes.
+//!
+//! A `RenderGraph` is built every frame and borrows whatever the passes need.
+//! The textures live in a `RenderGraphCache` kept from frame to frame: targets are
+//! sized relative to the window and recreated when it is resized, so there is nothing
+//! to do in `resize_event`.
+//!
+//! ```ignore
+//! let mut graph = RenderGraph::new();
+//! let scene = graph.texture("scene", TargetDesc::default());
+//! let depth = graph.texture("scene depth", TargetDesc::relative(1.0, TextureFormat::Depth));
+//!
+//! // declaration order does not matter, readers run after the writers
+//! graph.add_pass("post", PassDesc { reads: vec![scene], ..Default::default() }, |ctx, targets| {
+//!     targets.begin(ctx, PassAction::Nothing);
+//!     ctx.apply_pipeline(&self.post_pipeline);
+//!     ctx.apply_bindings_from_slice(&[self.quad], self.quad_indices, &[targets.texture(scene)]);
+//!     ctx.draw(0, 6, 1);
+//!     ctx.end_render_pass();
+//! });
+//! graph.add_pass("scene", PassDesc { colors: vec![scene], depth: Some(depth), ..Default::default() }, |ctx, targets| {
+//!     targets.begin(ctx, PassAction::clear_color(0.1, 0.1, 0.1, 1.0));
+//!     // draw the scene
+//!     ctx.end_render_pass();
+//! });
+//!
+//! graph.execute(&mut *self.ctx, &mut self.graph_cache);
+//! ```
+//!
+//! Passes without color or depth attachments draw to the screen. Passes whose output
+//! never reaches the screen are skipped. Two transient textures with the same
+//! description whose lifetimes don't overlap share the same GPU texture, so their
+//! content is undefined when a pass starts: clear or overwrite it.
+//! `RenderGraph::dump` prints the order, the skipped passes and the sharing.
+
+use super::*;
+
+use std::{collections::HashMap, fmt::Write};
+
+/// Size of a graph texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetSize {
+    /// Window size multiplied by the factor, at least 1x1.
+    Relative(f32),
+    Absolute(u32, u32),
+}
+
+impl TargetSize {
+    fn resolve(self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            TargetSize::Relative(scale) => (
+                ((width as f32 * scale).round() as u32).max(1),
+                ((height as f32 * scale).round() as u32).max(1),
+            ),
+            TargetSize::Absolute(width, height) => (width, height),
+        }
+    }
+}
+
+/// Description of a texture allocated by the graph.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetDesc {
+    pub size: TargetSize,
+    pub format: TextureFormat,
+    /// `> 1` for MSAA targets. They can't be read, resolve them in the pass writing them.
+    pub sample_count: i32,
+    pub filter: FilterMode,
+}
+
+impl Default for TargetDesc {
+    fn default() -> Self {
+        TargetDesc {
+            size: TargetSize::Relative(1.0),
+            format: TextureFormat::RGBA8,
+            sample_count: 1,
+            filter: FilterMode::Linear,
+        }
+    }
+}
+
+impl TargetDesc {
+    pub fn relative(scale: f32, format: TextureFormat) -> TargetDesc {
+        TargetDesc {
+            size: TargetSize::Relative(scale),
+            format,
+            ..Default::default()
+        }
+    }
+}
+
+/// A texture declared in a `RenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GraphTexture(usize);
+
+/// Textures a pass reads and writes.
+#[derive(Clone, Debug, Default)]
+pub struct PassDesc {
+    /// Textures sampled by the pass.
+    pub reads: Vec<GraphTexture>,
+    /// Color attachments.
+    pub colors: Vec<GraphTexture>,
+    /// MSAA resolve targets, one for each color attachment.
+    /// Requires `ctx.info().features.resolve_attachments`.
+    pub resolves: Vec<GraphTexture>,
+    pub depth: Option<GraphTexture>,
+}
+
+impl PassDesc {
+    fn writes(&self) -> impl Iterator<Item = GraphTexture> + '_ {
+        self.colors
+            .iter()
+            .chain(&self.resolves)
+            .chain(&self.depth)
+            .copied()
+    }
+
+    fn uses(&self) -> impl Iterator<Item = GraphTexture> + '_ {
+        self.reads.iter().copied().chain(self.writes())
+    }
+
+    fn to_screen(&self) -> bool {
+        self.colors.is_empty() && self.depth.is_none()
+    }
+}
+
+/// What a pass renders into, handed to its closure.
+pub struct PassTargets<'g> {
+    render_pass: Option<RenderPass>,
+    textures: &'g [Option<TextureId>],
+    size: (u32, u32),
+}
+
+impl<'g> PassTargets<'g> {
+    /// `None` when drawing to the screen.
+    pub fn render_pass(&self) -> Option<RenderPass> {
+        self.render_pass
+    }
+
+    /// `ctx.begin_pass` with this pass's render targets.
+    pub fn begin(&self, ctx: &mut dyn RenderingBackend, action: PassAction) {
+        ctx.begin_pass(self.render_pass, action);
+    }
+
+    /// The texture backing `texture` this frame.
+    pub fn texture(&self, texture: GraphTexture) -> TextureId {
+        self.textures[texture.0].expect("texture is not used by any executed pass")
+    }
+
+    /// Size of the render targets, or of the window for screen passes.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+}
+
+type PassFn<'a> = Box<dyn FnOnce(&mut dyn RenderingBackend, &PassTargets) + 'a>;
+
+enum Resource {
+    Transient(TargetDesc),
+    /// Created outside of the graph, only read.
+    Imported(TextureId),
+}
+
+struct Pass<'a> {
+    name: String,
+    desc: PassDesc,
+    execute: PassFn<'a>,
+}
+
+// what the shared GPU textures are matched on
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TargetKey {
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    sample_count: i32,
+    filter: FilterMode,
+}
+
+/// Pass order and texture sharing for one frame.
+#[derive(Debug)]
+struct Compiled {
+    order: Vec<usize>,
+    culled: Vec<usize>,
+    /// First and last position in `order` using each texture.
+    lifetimes: Vec<Option<(usize, usize)>>,
+    /// Texture slot of each transient texture, indexes `slots`.
+    assignment: Vec<Option<usize>>,
+    slots: Vec<TargetKey>,
+}
+
+pub struct RenderGraph<'a> {
+    screen_size: (u32, u32),
+    names: Vec<String>,
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// Empty graph with targets sized after the current window.
+    pub fn new() -> RenderGraph<'a> {
+        let (width, height) = crate::window::screen_size();
+        RenderGraph::with_screen_size(width as u32, height as u32)
+    }
+
+    pub fn with_screen_size(width: u32, height: u32) -> RenderGraph<'a> {
+        RenderGraph {
+            screen_size: (width, height),
+            names: vec![],
+            resources: vec![],
+            passes: vec![],
+        }
+    }
+
+    /// Declare a render target allocated by the graph.
+    pub fn texture(&mut self, name: &str, desc: TargetDesc) -> GraphTexture {
+        self.add_resource(name, Resource::Transient(desc))
+    }
+
+    /// Make a texture created outside of the graph readable by its passes.
+    pub fn import(&mut self, name: &str, texture: TextureId) -> GraphTexture {
+        self.add_resource(name, Resource::Imported(texture))
+    }
+
+    fn add_resource(&mut self, name: &str, resource: Resource) -> GraphTexture {
+        self.names.push(name.to_owned());
+        self.resources.push(resource);
+        GraphTexture(self.resources.len() - 1)
+    }
+
+    /// Add a pass. `execute` runs in `RenderGraph::execute` after the writers of
+    /// everything in `desc.reads`, and is expected to begin and end its render passes.
+    pub fn add_pass<F>(&mut self, name: &str, desc: PassDesc, execute: F)
+    where
+        F: FnOnce(&mut dyn RenderingBackend, &PassTargets) + 'a,
+    {
+        for texture in desc.writes() {
+            assert!(
+                matches!(self.resources[texture.0], Resource::Transient(_)),
+                "pass \"{}\" writes \"{}\": imported textures are read-only",
+                name,
+                self.names[texture.0]
+            );
+            assert!(
+                !desc.reads.contains(&texture),
+                "pass \"{}\" reads and writes \"{}\"",
+                name,
+                self.names[texture.0]
+            );
+        }
+        for &texture in &desc.reads {
+            if let Resource::Transient(target) = self.resources[texture.0] {
+                assert!(
+                    target.sample_count <= 1,
+                    "pass \"{}\" reads \"{}\", multisampled textures have to be resolved first",
+                    name,
+                    self.names[texture.0]
+                );
+            }
+        }
+        assert!(
+            desc.resolves.is_empty() || desc.resolves.len() == desc.colors.len(),
+            "pass \"{}\": one resolve texture for each color attachment",
+            name
+        );
+        self.passes.push(Pass {
+            name: name.to_owned(),
+            desc,
+            execute: Box::new(execute),
+        });
+    }
+
+    fn writers(&self, texture: GraphTexture) -> impl Iterator<Item = usize> + '_ {
+        self.passes
+            .iter()
+            .enumerate()
+            .filter(move |(_, pass)| pass.desc.writes().any(|t| t == texture))
+            .map(|(i, _)| i)
+    }
+
+    /// Passes that have to run before `pass`: the writers of what it reads and the
+    /// previous writers of what it writes.
+    fn dependencies(&self, pass: usize) -> Vec<usize> {
+        let desc = &self.passes[pass].desc;
+        let mut dependencies = vec![];
+        for &texture in &desc.reads {
+            dependencies.extend(self.writers(texture));
+        }
+        for texture in desc.writes() {
+            dependencies.extend(self.writers(texture).take_while(|&writer| writer < pass));
+        }
+        dependencies.sort_unstable();
+        dependencies.dedup();
+        dependencies
+    }
+
+    fn compile(&self) -> Compiled {
+        let dependencies: Vec<_> = (0..self.passes.len())
+            .map(|pass| self.dependencies(pass))
+            .collect();
+
+        // everything the screen passes depend on
+        let mut live = vec![false; self.passes.len()];
+        let mut stack: Vec<_> = (0..self.passes.len())
+            .filter(|&pass| self.passes[pass].desc.to_screen())
+            .collect();
+        while let Some(pass) = stack.pop() {
+            if !live[pass] {
+                live[pass] = true;
+                stack.extend(&dependencies[pass]);
+            }
+        }
+
+        // topological sort, declaration order between independent passes
+        let mut order = vec![];
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < live.iter().filter(|&&live| live).count() {
+            let next = (0..self.passes.len()).find(|&pass| {
+                live[pass] && !done[pass] && dependencies[pass].iter().all(|&dep| done[dep])
+            });
+            match next {
+                Some(pass) => {
+                    done[pass] = true;
+                    order.push(pass);
+                }
+                None => {
+                    let stuck: Vec<_> = (0..self.passes.len())
+                        .filter(|&pass| live[pass] && !done[pass])
+                        .map(|pass| self.passes[pass].name.as_str())
+                        .collect();
+                    panic!("render graph has a cycle between {:?}", stuck);
+                }
+            }
+        }
+        let culled = (0..self.passes.len()).filter(|&pass| !live[pass]).collect();
+
+        let mut lifetimes = vec![None; self.resources.len()];
+        for (position, &pass) in order.iter().enumerate() {
+            for texture in self.passes[pass].desc.uses() {
+                let lifetime = lifetimes[texture.0].get_or_insert((position, position));
+                lifetime.1 = position;
+            }
+        }
+
+        // share a slot with a texture of the same description that is dead by then
+        let mut transients: Vec<_> = (0..self.resources.len())
+            .filter(|&texture| matches!(self.resources[texture], Resource::Transient(_)))
+            .filter_map(|texture| lifetimes[texture].map(|lifetime| (texture, lifetime)))
+            .collect();
+        transients.sort_by_key(|&(_, (first, _))| first);
+        let mut assignment = vec![None; self.resources.len()];
+        let mut slots = vec![];
+        let mut slot_ends: Vec<usize> = vec![];
+        for (texture, (first, last)) in transients {
+            let key = match self.resources[texture] {
+                Resource::Transient(desc) => {
+                    let (width, height) = desc.size.resolve(self.screen_size);
+                    TargetKey {
+                        width,
+                        height,
+                        format: desc.format,
+                        sample_count: desc.sample_count,
+                        filter: desc.filter,
+                    }
+                }
+                Resource::Imported(_) => unreachable!(),
+            };
+            let slot = (0..slots.len())
+                .find(|&slot| slots[slot] == key && slot_ends[slot] < first)
+                .unwrap_or_else(|| {
+                    slots.push(key);
+                    slot_ends.push(0);
+                    slots.len() - 1
+                });
+            slot_ends[slot] = last;
+            assignment[texture] = Some(slot);
+        }
+
+        Compiled {
+            order,
+            culled,
+            lifetimes,
+            assignment,
+            slots,
+        }
+    }
+
+    /// Human readable pass order, skipped passes and texture sharing.
+    pub fn dump(&self) -> String {
+        let compiled = self.compile();
+        let mut out = String::new();
+        let (width, height) = self.screen_size;
+        writeln!(out, "render graph, screen {}x{}", width, height).unwrap();
+        writeln!(out, "passes:").unwrap();
+        for (position, &pass) in compiled.order.iter().enumerate() {
+            let Pass { name, desc, .. } = &self.passes[pass];
+            let names = |textures: &mut dyn Iterator<Item = GraphTexture>| {
+                textures
+                    .map(|texture| self.names[texture.0].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let writes = if desc.to_screen() {
+                "screen".to_owned()
+            } else {
+                names(&mut desc.writes())
+            };
+            writeln!(
+                out,
+                "  {}. {}: reads [{}], writes [{}]",
+                position,
+                name,
+                names(&mut desc.reads.iter().copied()),
+                writes
+            )
+            .unwrap();
+        }
+        for &pass in &compiled.culled {
+            writeln!(out, "  skipped {}: output unused", self.passes[pass].name).unwrap();
+        }
+        writeln!(out, "textures:").unwrap();
+        for (texture, resource) in self.resources.iter().enumerate() {
+            let name = &self.names[texture];
+            let lifetime = match compiled.lifetimes[texture] {
+                Some((first, last)) => format!("passes {}..={}", first, last),
+                None => "unused".to_owned(),
+            };
+            match (resource, compiled.assignment[texture]) {
+                (Resource::Imported(id), _) => {
+                    writeln!(out, "  {}: imported {:?}, {}", name, id, lifetime).unwrap()
+                }
+                (Resource::Transient(_), Some(slot)) => {
+                    let key = compiled.slots[slot];
+                    writeln!(
+                        out,
+                        "  {}: slot {} ({}x{} {:?} x{}), {}",
+                        name, slot, key.width, key.height, key.format, key.sample_count, lifetime
+                    )
+                    .unwrap()
+                }
+                (Resource::Transient(_), None) => {
+                    writeln!(out, "  {}: {}", name, lifetime).unwrap()
+                }
+            }
+        }
+        out
+    }
+
+    /// Run the passes in dependency order, allocating the targets from `cache`.
+    pub fn execute(self, ctx: &mut dyn RenderingBackend, cache: &mut RenderGraphCache) {
+        let compiled = self.compile();
+        if cache.screen_size != self.screen_size {
+            cache.release(ctx);
+            cache.screen_size = self.screen_size;
+        }
+
+        // the n-th slot of a kind always gets the n-th pooled texture of that kind,
+        // so the same graph reuses the same textures and render passes every frame
+        let mut taken = vec![false; cache.targets.len()];
+        let slot_textures: Vec<TextureId> = compiled
+            .slots
+            .iter()
+            .map(|&key| {
+                let pooled =
+                    (0..cache.targets.len()).find(|&i| !taken[i] && cache.targets[i].0 == key);
+                let index = pooled.unwrap_or_else(|| {
+                    let texture = ctx.new_render_texture(TextureParams {
+                        format: key.format,
+                        width: key.width,
+                        height: key.height,
+                        min_filter: key.filter,
+                        mag_filter: key.filter,
+                        sample_count: key.sample_count,
+                        ..Default::default()
+                    });
+                    cache.targets.push((key, texture));
+                    taken.push(false);
+                    cache.targets.len() - 1
+                });
+                taken[index] = true;
+                cache.targets[index].1
+            })
+            .collect();
+        let textures: Vec<Option<TextureId>> = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(texture, resource)| match resource {
+                Resource::Imported(id) => Some(*id),
+                Resource::Transient(_) => {
+                    compiled.assignment[texture].map(|slot| slot_textures[slot])
+                }
+            })
+            .collect();
+
+        let mut passes: Vec<Option<Pass>> = self.passes.into_iter().map(Some).collect();
+        for pass in compiled.order {
+            let Pass {
+                name,
+                desc,
+                execute,
+            } = passes[pass].take().unwrap();
+            let attachments = |list: &[GraphTexture]| -> Vec<TextureId> {
+                list.iter()
+                    .map(|texture| textures[texture.0].unwrap())
+                    .collect()
+            };
+            let (render_pass, size) = if desc.to_screen() {
+                (None, self.screen_size)
+            } else {
+                let key = (
+                    attachments(&desc.colors),
+                    attachments(&desc.resolves),
+                    desc.depth.map(|depth| textures[depth.0].unwrap()),
+                );
+                let size = ctx.texture_size(key.0.first().copied().or(key.2).unwrap());
+                let render_pass =
+                    *cache
+                        .passes
+                        .entry(key)
+                        .or_insert_with_key(|(colors, resolves, depth)| {
+                            let resolves = (!resolves.is_empty()).then_some(&resolves[..]);
+                            let render_pass = ctx.new_render_pass_mrt(colors, resolves, *depth);
+                            ctx.set_label(render_pass.into(), &name);
+                            render_pass
+                        });
+                (Some(render_pass), size)
+            };
+            execute(
+                ctx,
+                &PassTargets {
+                    render_pass,
+                    textures: &textures,
+                    size,
+                },
+            );
+        }
+    }
+}
+
+impl<'a> Default for RenderGraph<'a> {
+    fn default() -> Self {
+        RenderGraph::new()
+    }
+}
+
+/// GPU textures and render passes of a `RenderGraph`, kept between frames.
+///
+/// Everything is recreated when the window size changes. Call `release` before
+/// dropping it, or to free the memory.
+#[derive(Default)]
+pub struct RenderGraphCache {
+    screen_size: (u32, u32),
+    targets: Vec<(TargetKey, TextureId)>,
+    #[allow(clippy::type_complexity)]
+    passes: HashMap<(Vec<TextureId>, Vec<TextureId>, Option<TextureId>), RenderPass>,
+}
+
+impl RenderGraphCache {
+    pub fn new() -> RenderGraphCache {
+        RenderGraphCache::default()
+    }
+
+    /// Delete all the textures and render passes.
+    pub fn release(&mut self, ctx: &mut dyn RenderingBackend) {
+        // deleting a render pass deletes its attachments
+        for (_, render_pass) in self.passes.drain() {
+            ctx.delete_render_pass(render_pass);
+        }
+        for (_, texture) in self.targets.drain(..) {
+            if ctx.is_valid(texture.into()) {
+                ctx.delete_texture(texture);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let mut graph = RenderGraph::with_screen_size(800, 600);
+        let scene = graph.texture("scene", TargetDesc::default());
+        let half = TargetDesc::relative(0.5, TextureFormat::RGBA8);
+        let bright = graph.texture("bright", half);
+        let blur = graph.texture("blur", half);
+        let unused = graph.texture("unused", TargetDesc::default());
+        let blur2 = graph.texture("blur 2", half);
+
+        let colors = |colors: Vec<GraphTexture>, reads: Vec<GraphTexture>| PassDesc {
+            colors,
+            reads,
+            ..Default::default()
+        };
+        graph.add_pass("present", colors(vec![], vec![scene, blur2]), |_, _| {});
+        graph.add_pass("blur y", colors(vec![blur2], vec![blur]), |_, _| {});
+        graph.add_pass("blur x", colors(vec![blur], vec![bright]), |_, _| {});
+        graph.add_pass("debug", colors(vec![unused], vec![scene]), |_, _| {});
+        graph.add_pass("bright", colors(vec![bright], vec![scene]), |_, _| {});
+        graph.add_pass("scene", colors(vec![scene], vec![]), |_, _| {});
+
+        let compiled = graph.compile();
+        assert_eq!(compiled.order, [5, 4, 2, 1, 0]);
+        assert_eq!(compiled.culled, [3]);
+        assert_eq!(compiled.lifetimes[scene.0], Some((0, 4)));
+        assert_eq!(compiled.lifetimes[unused.0], None);
+        // "blur 2" is written once "bright" is dead, "blur" is still being read
+        assert_eq!(compiled.assignment[bright.0], compiled.assignment[blur2.0]);
+        assert_ne!(compiled.assignment[blur.0], compiled.assignment[blur2.0]);
+        assert_eq!(compiled.slots.len(), 3);
+        assert_eq!(
+            compiled.slots[compiled.assignment[blur.0].unwrap()].width,
+            400
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Vertex layouts and uniform blocks described by Rust structs.
+//!
+//! With the `derive` feature, `#[derive(Vertex)]` and `#[derive(Uniforms)]` implement
+//! `VertexLayout` and `UniformsLayout` from the struct fields, so the struct and the
+//! layout handed to `new_pipeline` and `ShaderMeta` can't drift apart.
+//! The struct has to be `#[repr(C)]` and tightly packed, just like miniquad reads it.
+//!
+//! ```ignore
+//! #[derive(Vertex)]
+//! #[repr(C)]
+//! struct Vert {
+//!     #[vertex(name = "in_pos")]
+//!     pos: [f32; 2],
+//!     #[vertex(name = "in_color")]
+//!     color: [u8; 4],
+//! }
+//!
+//! #[derive(Uniforms)]
+//! #[repr(C)]
+//! struct Uniforms {
+//!     mvp: glam::Mat4,
+//!     #[uniform(array)]
+//!     lights: [[f32; 4]; 8],
+//! }
+//!
+//! let pipeline = ctx.new_pipeline(
+//!     &[Vert::buffer_layout()],
+//!     &Vert::attributes(0),
+//!     shader,
+//!     PipelineParams::default(),
+//! );
+//! let meta = ShaderMeta {
+//!     uniforms: Uniforms::uniform_block_layout(),
+//!     images: vec![],
+//! };
+//! ```
+//!
+//! Field types map to formats through `VertexField` and `UniformField`: `f32`, `[f32; N]`,
+//! `[u8; N]`, `[u16; N]`, `[u32; N]`, `[i32; N]`, `[[f32; 4]; 4]` and, with the `glam`
+//! feature, glam vectors and `Mat4`. Implement them for your own math types.
+
+use super::*;
+
+/// A struct uploaded as vertices, usually implemented with `#[derive(Vertex)]`.
+pub trait VertexLayout {
+    /// Layout of a buffer holding only this vertex type.
+    fn buffer_layout() -> BufferLayout;
+
+    /// One attribute per field, read from the buffer at `buffer_index`.
+    fn attributes(buffer_index: usize) -> Vec<VertexAttribute>;
+}
+
+/// A struct passed to `apply_uniforms`, usually implemented with `#[derive(Uniforms)]`.
+pub trait UniformsLayout {
+    fn uniform_block_layout() -> UniformBlockLayout;
+}
+
+/// Field type of a `#[derive(Vertex)]` struct.
+///
+/// `size_of::<Self>()` has to match `FORMAT.size_bytes()`.
+pub trait VertexField {
+    const FORMAT: VertexFormat;
+}
+
+/// Field type of a `#[derive(Uniforms)]` struct.
+///
+/// `size_of::<Self>()` has to match `TYPE.size()`.
+pub trait UniformField {
+    const TYPE: UniformType;
+}
+
+macro_rules! impl_fields {
+    ($trait:ident, $const:ident, $kind:ident: $($t:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl $trait for $t {
+                const $const: $kind = $kind::$variant;
+            }
+        )*
+    };
+}
+
+impl_fields!(VertexField, FORMAT, VertexFormat:
+    f32 => Float1,
+    [f32; 1] => Float1,
+    [f32; 2] => Float2,
+    [f32; 3] => Float3,
+    [f32; 4] => Float4,
+    u8 => Byte1,
+    [u8; 1] => Byte1,
+    [u8; 2] => Byte2,
+    [u8; 3] => Byte3,
+    [u8; 4] => Byte4,
+    u16 => Short1,
+    [u16; 1] => Short1,
+    [u16; 2] => Short2,
+    [u16; 3] => Short3,
+    [u16; 4] => Short4,
+    u32 => Int1,
+    [u32; 1] => Int1,
+    [u32; 2] => Int2,
+    [u32; 3] => Int3,
+    [u32; 4] => Int4,
+    [f32; 16] => Mat4,
+    [[f32; 4]; 4] => Mat4,
+);
+
+impl_fields!(UniformField, TYPE, UniformType:
+    f32 => Float1,
+    [f32; 1] => Float1,
+    [f32; 2] => Float2,
+    [f32; 3] => Float3,
+    [f32; 4] => Float4,
+    i32 => Int1,
+    [i32; 1] => Int1,
+    [i32; 2] => Int2,
+    [i32; 3] => Int3,
+    [i32; 4] => Int4,
+    u32 => Int1,
+    [u32; 1] => Int1,
+    [u32; 2] => Int2,
+    [u32; 3] => Int3,
+    [u32; 4] => Int4,
+    [f32; 16] => Mat4,
+    [[f32; 4]; 4] => Mat4,
+);
+
+#[cfg(feature = "glam")]
+impl_fields!(VertexField, FORMAT, VertexFormat:
+    glam::Vec2 => Float2,
+    glam::Vec3 => Float3,
+    glam::Vec4 => Float4,
+    glam::UVec2 => Int2,
+    glam::UVec3 => Int3,
+    glam::UVec4 => Int4,
+    glam::Mat4 => Mat4,
+);
+
+#[cfg(feature = "glam")]
+impl_fields!(UniformField, TYPE, UniformType:
+    glam::Vec2 => Float2,
+    glam::Vec3 => Float3,
+    glam::Vec4 => Float4,
+    glam::IVec2 => Int2,
+    glam::IVec3 => Int3,
+    glam::IVec4 => Int4,
+    glam::UVec2 => Int2,
+    glam::UVec3 => Int3,
+    glam::UVec4 => Int4,
+    glam::Mat4 => Mat4,
+);
@@ -6,6 +6,8 @@ use crate::native::apple::{
 };
 
 use super::*;
+use crate::ResourceManager;
+use std::{cell::Cell, collections::HashMap};
 
 // https://developer.apple.com/metal/Metal-Feature-Set-Tables.pdf
 const MAX_UNIFORM_BUFFER_SIZE: u64 = 4 * 1024 * 1024;
@@ -162,6 +164,15 @@ impl From<TextureFormat> for MTLPixelFormat {
             //TODO: Depth16Unorm ?
             TextureFormat::Depth => MTLPixelFormat::Depth32Float_Stencil8,
             TextureFormat::RGBA16F => MTLPixelFormat::RGBA16Float,
+            TextureFormat::Alpha => MTLPixelFormat::A8Unorm,
+            TextureFormat::RG8 => MTLPixelFormat::RG8Unorm,
+            TextureFormat::SRGBA8 => MTLPixelFormat::RGBA8Unorm_sRGB,
+            TextureFormat::Bc1 => MTLPixelFormat::BC1_RGBA,
+            TextureFormat::Bc3 => MTLPixelFormat::BC3_RGBA,
+            TextureFormat::Bc7 => MTLPixelFormat::BC7_RGBAUnorm,
+            TextureFormat::Etc2Rgb8 => MTLPixelFormat::ETC2_RGB8,
+            TextureFormat::Etc2Rgba8 => MTLPixelFormat::EAC_RGBA8,
+            TextureFormat::Astc4x4 => MTLPixelFormat::ASTC_4x4_LDR,
             _ => todo!(),
         }
     }
@@ -242,7 +253,7 @@ struct Texture {
     sampler_descriptor: ObjcId,
     params: TextureParams,
 }
-struct Textures(Vec<Texture>);
+struct Textures(ResourceManager<Texture>);
 
 impl Textures {
     fn get(&self, texture: TextureId) -> Texture {
@@ -262,11 +273,11 @@ impl Textures {
     }
 }
 pub struct MetalContext {
-    buffers: Vec<Buffer>,
-    shaders: Vec<ShaderInternal>,
-    pipelines: Vec<PipelineInternal>,
+    buffers: ResourceManager<Buffer>,
+    shaders: ResourceManager<ShaderInternal>,
+    pipelines: ResourceManager<PipelineInternal>,
     textures: Textures,
-    passes: Vec<RenderPassInternal>,
+    passes: ResourceManager<RenderPassInternal>,
     command_queue: ObjcId,
     command_buffer: Option<ObjcId>,
     render_encoder: Option<ObjcId>,
@@ -279,6 +290,21 @@ pub struct MetalContext {
     // cached pipeline from apply_pipeline
     current_pipeline: Option<Pipeline>,
     current_ub_offset: u64,
+    // counters of the current frame, draw() takes &self hence the Cell
+    stats: FrameStats,
+    draw_calls: Cell<usize>,
+    last_frame_stats: FrameStats,
+    leak_report: bool,
+    deferred: DeferredDeletions,
+    labels: HashMap<ResourceHandle, String>,
+}
+
+impl Drop for MetalContext {
+    fn drop(&mut self) {
+        if self.leak_report {
+            print_leak_report(&self.live_resources());
+        }
+    }
 }
 
 impl Default for MetalContext {
@@ -363,16 +389,57 @@ impl MetalContext {
                 render_encoder: None,
                 view,
                 device,
-                buffers: vec![],
-                shaders: vec![],
-                pipelines: vec![],
-                textures: Textures(Vec::new()),
-                passes: vec![],
+                buffers: ResourceManager::new("buffer"),
+                shaders: ResourceManager::new("shader"),
+                pipelines: ResourceManager::new("pipeline"),
+                textures: Textures(ResourceManager::new("texture")),
+                passes: ResourceManager::new("render pass"),
                 index_buffer: None,
                 current_pipeline: None,
                 uniform_buffers,
                 current_frame_index: 1,
                 current_ub_offset: 0,
+                stats: FrameStats::default(),
+                draw_calls: Cell::new(0),
+                last_frame_stats: FrameStats::default(),
+                leak_report: false,
+                deferred: DeferredDeletions::default(),
+                labels: HashMap::new(),
+            }
+        }
+    }
+
+    /// Upload a mipmap chain into one slice (cubemap face) of the texture.
+    fn upload_levels(&mut self, texture: TextureId, slice: usize, levels: &[&[u8]]) {
+        let texture = self.textures.get(texture);
+        for (mipmap_level, bytes) in levels.iter().enumerate() {
+            let (width, height) = texture.params.level_size(mipmap_level as _);
+            assert_eq!(
+                texture.params.format.size(width, height) as usize,
+                bytes.len(),
+                "wrong size of mipmap level {mipmap_level}"
+            );
+            let region = MTLRegion {
+                origin: MTLOrigin {
+                    x: 0_u64,
+                    y: 0_u64,
+                    z: 0,
+                },
+                size: MTLSize {
+                    width: width as u64,
+                    height: height as u64,
+                    depth: 1,
+                },
+            };
+            self.stats.bytes_uploaded += bytes.len();
+            unsafe {
+                msg_send_![texture.texture, replaceRegion:region
+                      mipmapLevel:mipmap_level
+                      slice: slice
+                      withBytes:bytes.as_ptr()
+                      bytesPerRow:texture.params.format.size(width, 1) as u64
+                      bytesPerImage:0
+                ];
             }
         }
     }
@@ -387,15 +454,174 @@ impl RenderingBackend for MetalContext {
             features: Features {
                 instancing: true,
                 resolve_attachments: false,
+                // BC on Mac GPUs, ETC2 and ASTC on Apple GPUs
+                texture_compression_bc: cfg!(target_os = "macos"),
+                texture_compression_etc2: cfg!(target_os = "ios"),
+                texture_compression_astc: cfg!(target_os = "ios"),
+            },
+            gl_context: None,
+            debug_context: false,
+            framebuffer: unsafe {
+                let sample_count: u64 = msg_send![self.view, sampleCount];
+                let depth_stencil: u64 = msg_send![self.view, depthStencilPixelFormat];
+                let has_depth_stencil =
+                    depth_stencil == MTLPixelFormat::Depth32Float_Stencil8 as u64;
+                // the color format is BGRA8Unorm on both macos and ios
+                FramebufferInfo {
+                    red_bits: 8,
+                    green_bits: 8,
+                    blue_bits: 8,
+                    alpha_bits: 8,
+                    depth_bits: if has_depth_stencil { 32 } else { 0 },
+                    stencil_bits: if has_depth_stencil { 8 } else { 0 },
+                    sample_count: sample_count as i32,
+                    srgb: false,
+                    float: false,
+                }
             },
         }
     }
+    fn resource_stats(&self) -> ResourceStats {
+        let mut stats = ResourceStats {
+            shaders: self.shaders.len(),
+            pipelines: self.pipelines.len(),
+            render_passes: self.passes.len(),
+            ..Default::default()
+        };
+        for (_, buffer) in self.buffers.iter() {
+            stats.buffers.count += 1;
+            stats.buffers.bytes += buffer.size * BUFFERS_IN_ROTATION;
+        }
+        for (_, texture) in self.textures.0.iter() {
+            stats.textures.count += 1;
+            stats.textures.bytes += texture_memory_size(&texture.params);
+        }
+        stats
+    }
+    fn frame_stats(&self) -> FrameStats {
+        self.last_frame_stats
+    }
+    fn live_resources(&self) -> Vec<LiveResource> {
+        let mut live = vec![];
+        for (id, buffer) in self.buffers.iter() {
+            let handle = ResourceHandle::Buffer(BufferId(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "buffer".to_string()),
+                bytes: buffer.size * BUFFERS_IN_ROTATION,
+            });
+        }
+        for (id, texture) in self.textures.0.iter() {
+            let handle = ResourceHandle::Texture(TextureId(TextureIdInner::Managed(id)));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, texture_label(&texture.params)),
+                bytes: texture_memory_size(&texture.params),
+            });
+        }
+        for (id, _) in self.shaders.iter() {
+            let handle = ResourceHandle::Shader(ShaderId(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "shader".to_string()),
+                bytes: 0,
+            });
+        }
+        for (id, _) in self.pipelines.iter() {
+            let handle = ResourceHandle::Pipeline(Pipeline(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "pipeline".to_string()),
+                bytes: 0,
+            });
+        }
+        for (id, pass) in self.passes.iter() {
+            let handle = ResourceHandle::RenderPass(RenderPass(id));
+            let description = format!("render pass, {} color attachments", pass.texture.len());
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, description),
+                bytes: 0,
+            });
+        }
+        live
+    }
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.leak_report = enabled;
+    }
+
+    fn set_deferred_deletion(&mut self, enabled: bool) {
+        self.deferred.set_enabled(enabled);
+    }
+
+    fn deletion_queue(&mut self) -> DeletionQueue {
+        self.deferred.deletion_queue()
+    }
+    fn is_context_lost(&self) -> bool {
+        false
+    }
+    fn restore_context(&mut self) {}
+    fn set_restore_resources(&mut self, _enabled: bool) {}
+    fn simulate_context_loss(&mut self) -> bool {
+        false
+    }
+    fn set_label(&mut self, handle: ResourceHandle, label: &str) {
+        unsafe {
+            match handle {
+                ResourceHandle::Buffer(id) => {
+                    for raw in &self.buffers[id.0].raw {
+                        msg_send_![*raw, setLabel: apple_util::str_to_nsstring(label)];
+                    }
+                }
+                ResourceHandle::Texture(id) => {
+                    let texture = self.textures.get(id).texture;
+                    msg_send_![texture, setLabel: apple_util::str_to_nsstring(label)];
+                }
+                ResourceHandle::Shader(id) => {
+                    let shader = &self.shaders[id.0];
+                    msg_send_![shader.vertex_function, setLabel: apple_util::str_to_nsstring(label)];
+                    msg_send_![shader.fragment_function, setLabel: apple_util::str_to_nsstring(label)];
+                }
+                // pipeline states and pass descriptors have no mutable label,
+                // the name is only kept on our side
+                ResourceHandle::Pipeline(id) => {
+                    let _ = &self.pipelines[id.0];
+                }
+                ResourceHandle::RenderPass(id) => {
+                    let _ = &self.passes[id.0];
+                }
+            }
+        }
+        self.labels.insert(handle, label.to_string());
+    }
+    fn push_debug_group(&mut self, name: &str) {
+        // groups should not cross begin_pass/end_render_pass boundaries
+        if let Some(target) = self.render_encoder.or(self.command_buffer) {
+            unsafe { msg_send_![target, pushDebugGroup: apple_util::str_to_nsstring(name)] };
+        }
+    }
+    fn pop_debug_group(&mut self) {
+        if let Some(target) = self.render_encoder.or(self.command_buffer) {
+            unsafe { msg_send_![target, popDebugGroup] };
+        }
+    }
     fn buffer_size(&mut self, buffer: BufferId) -> usize {
         let buffer = &self.buffers[buffer.0];
         buffer.size
     }
+    // new_upload_context is None with Metal, there is never anything to publish
+    fn publish_texture(&mut self, upload: UploadedTexture) -> Result<TextureId, UploadedTexture> {
+        Err(upload)
+    }
+    fn publish_buffer(&mut self, upload: UploadedBuffer) -> Result<BufferId, UploadedBuffer> {
+        Err(upload)
+    }
     fn delete_buffer(&mut self, buffer: BufferId) {
-        let buffer = &self.buffers[buffer.0];
+        if self.deferred.defer(buffer.into()) {
+            return;
+        }
+        self.labels.remove(&buffer.into());
+        let buffer = self.buffers.remove(buffer.0);
         unsafe {
             for buffer in &buffer.raw {
                 msg_send_![*buffer, release];
@@ -403,9 +629,28 @@ impl RenderingBackend for MetalContext {
         }
     }
     fn delete_texture(&mut self, texture: TextureId) {
-        let texture = self.textures.get(texture);
+        if self.deferred.defer(texture.into()) {
+            return;
+        }
+        let t = self.textures.get(texture);
         unsafe {
-            msg_send_![texture.texture, release];
+            msg_send_![t.texture, release];
+        }
+        if let TextureIdInner::Managed(id) = texture.0 {
+            self.textures.0.remove(id);
+        }
+        self.labels.remove(&texture.into());
+    }
+    fn is_valid(&self, handle: ResourceHandle) -> bool {
+        match handle {
+            ResourceHandle::Buffer(id) => self.buffers.contains(id.0),
+            ResourceHandle::Texture(TextureId(TextureIdInner::Managed(id))) => {
+                self.textures.0.contains(id)
+            }
+            ResourceHandle::Texture(TextureId(TextureIdInner::Raw(_))) => true,
+            ResourceHandle::Shader(id) => self.shaders.contains(id.0),
+            ResourceHandle::Pipeline(id) => self.pipelines.contains(id.0),
+            ResourceHandle::RenderPass(id) => self.passes.contains(id.0),
         }
     }
     fn apply_viewport(&mut self, _x: i32, _y: i32, _w: i32, _h: i32) {}
@@ -572,14 +817,16 @@ impl RenderingBackend for MetalContext {
                 _depth_texture: depth_img,
             };
 
-            self.passes.push(pass);
-
-            RenderPass(self.passes.len() - 1)
+            RenderPass(self.passes.add(pass))
         }
     }
 
     fn delete_render_pass(&mut self, render_pass: RenderPass) {
-        let render_pass = &self.passes[render_pass.0];
+        if self.deferred.defer(render_pass.into()) {
+            return;
+        }
+        self.labels.remove(&render_pass.into());
+        let render_pass = self.passes.remove(render_pass.0);
         unsafe {
             msg_send_![render_pass.render_pass_desc, release];
         }
@@ -624,14 +871,16 @@ impl RenderingBackend for MetalContext {
             }
             raw[i] = buffer;
         }
+        if let BufferSource::Slice(_) = data {
+            self.stats.bytes_uploaded += size;
+        }
         let buffer = Buffer {
             raw,
             size,
             value: 0,
             next_value: 0,
         };
-        self.buffers.push(buffer);
-        BufferId(self.buffers.len() - 1)
+        BufferId(self.buffers.add(buffer))
     }
 
     fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
@@ -641,6 +890,7 @@ impl RenderingBackend for MetalContext {
         };
         let buffer = &mut self.buffers[buffer.0];
         assert!(data.size <= buffer.size);
+        self.stats.bytes_uploaded += data.size;
 
         unsafe {
             let dest: *mut std::ffi::c_void = msg_send![buffer.raw[buffer.next_value], contents];
@@ -684,8 +934,7 @@ impl RenderingBackend for MetalContext {
                 vertex_function,
                 fragment_function,
             };
-            self.shaders.push(shader);
-            Ok(ShaderId(self.shaders.len() - 1))
+            Ok(ShaderId(self.shaders.add(shader)))
         }
     }
 
@@ -695,12 +944,24 @@ impl RenderingBackend for MetalContext {
         bytes: TextureSource,
         params: TextureParams,
     ) -> TextureId {
+        if params.kind == TextureKind::Texture2DMultisample {
+            assert!(
+                self.info().features.multisample_textures,
+                "Multisampled textures are not supported"
+            );
+        }
+        let mipmapped = params.allocate_mipmaps
+            || match bytes {
+                TextureSource::Array(array) => array.iter().any(|face| face.len() > 1),
+                TextureSource::Mips(levels) => levels.len() > 1,
+                _ => false,
+            };
         let descriptor = unsafe {
             msg_send_![class!(MTLTextureDescriptor),
                        texture2DDescriptorWithPixelFormat:MTLPixelFormat::from(params.format)
                        width: params.width as u64
                        height: params.height as u64
-                       mipmapped: params.allocate_mipmaps as BOOL]
+                       mipmapped: mipmapped as BOOL]
         };
 
         // unsafe {
@@ -749,6 +1010,8 @@ impl RenderingBackend for MetalContext {
             TextureKind::CubeMap => unsafe {
                 msg_send_![descriptor, setTextureType: MTLTextureType::CubeArray];
             },
+            // features.multisample_textures is false, checked above
+            TextureKind::Texture2DMultisample => unreachable!(),
         }
 
         let texture = unsafe {
@@ -779,13 +1042,12 @@ impl RenderingBackend for MetalContext {
             ];
             let raw_texture = msg_send_![self.device, newTextureWithDescriptor: descriptor];
             msg_send_![raw_texture, retain];
-            self.textures.0.push(Texture {
+            TextureId(TextureIdInner::Managed(self.textures.0.add(Texture {
                 sampler: sampler_state,
                 texture: raw_texture,
                 sampler_descriptor,
                 params,
-            });
-            TextureId(TextureIdInner::Managed(self.textures.0.len() - 1))
+            })))
         };
 
         match bytes {
@@ -807,37 +1069,59 @@ impl RenderingBackend for MetalContext {
             }
             TextureSource::Array(array) => {
                 for (n, face) in array.iter().enumerate() {
-                    for (mipmap_level, bytes) in face.iter().enumerate() {
-                        let raw_texture = self.textures.get(texture).texture;
-                        let region = MTLRegion {
-                            origin: MTLOrigin {
-                                x: 0_u64,
-                                y: 0_u64,
-                                z: 0,
-                            },
-                            size: MTLSize {
-                                width: params.width as u64,
-                                height: params.height as u64,
-                                depth: 1,
-                            },
-                        };
-                        assert!(bytes.len() as u32 == params.width * params.height * 4);
-                        unsafe {
-                            msg_send_![raw_texture, replaceRegion:region
-                                  mipmapLevel:mipmap_level
-                                  slice: n
-                                  withBytes:bytes.as_ptr()
-                                  bytesPerRow:(params.width * 4) as u64
-                                  bytesPerImage:0
-                            ];
-                        }
-                    }
+                    self.upload_levels(texture, n, face);
                 }
             }
+            TextureSource::Mips(levels) => {
+                assert!(
+                    params.kind == TextureKind::Texture2D,
+                    "TextureSource::Mips is for 2D textures, use TextureSource::Array for cubemaps."
+                );
+                self.upload_levels(texture, 0, levels);
+            }
         }
         texture
     }
 
+    fn texture_update_region(
+        &mut self,
+        texture: TextureId,
+        region: TextureRegion,
+        layout: PixelLayout,
+        bytes: &[u8],
+    ) {
+        let texture = self.textures.get(texture);
+        let format = texture.params.format;
+        let (width, height) = (region.width as u32, region.height as u32);
+        let (level_width, level_height) = texture.params.level_size(region.level);
+        assert!(region.x >= 0 && region.y >= 0);
+        assert!(region.x as u32 + width <= level_width);
+        assert!(region.y as u32 + height <= level_height);
+        assert!(
+            bytes.len() >= layout.data_size(format, width, height),
+            "not enough pixel data for the texture region"
+        );
+        self.stats.bytes_uploaded += format.size(width, height) as usize;
+        let mtl_region = MTLRegion {
+            origin: MTLOrigin {
+                x: region.x as u64,
+                y: region.y as u64,
+                z: 0,
+            },
+            size: MTLSize {
+                width: width as u64,
+                height: height as u64,
+                depth: 1,
+            },
+        };
+        unsafe {
+            msg_send_![texture.texture, replaceRegion:mtl_region
+                       mipmapLevel:region.level as u64
+                       withBytes:bytes.as_ptr()
+                       bytesPerRow:layout.row_stride(format, width) as u64];
+        }
+    }
+
     fn texture_update_part(
         &mut self,
         texture: TextureId,
@@ -847,6 +1131,7 @@ impl RenderingBackend for MetalContext {
         height: i32,
         bytes: &[u8],
     ) {
+        self.stats.bytes_uploaded += bytes.len();
         let raw_texture = self.textures.get(texture).texture;
         let region = MTLRegion {
             origin: MTLOrigin {
@@ -1040,9 +1325,7 @@ impl RenderingBackend for MetalContext {
                 //params,
             };
 
-            self.pipelines.push(pipeline);
-
-            Pipeline(self.pipelines.len() - 1)
+            Pipeline(self.pipelines.add(pipeline))
         }
     }
 
@@ -1053,6 +1336,9 @@ impl RenderingBackend for MetalContext {
         );
         let render_encoder = self.render_encoder.unwrap();
 
+        if self.current_pipeline != Some(*pipeline) {
+            self.stats.pipeline_switches += 1;
+        }
         unsafe {
             self.current_pipeline = Some(*pipeline);
             let pipeline = &self.pipelines[pipeline.0];
@@ -1101,6 +1387,7 @@ impl RenderingBackend for MetalContext {
                     msg_send_![render_encoder, setFragmentTexture:texture
                                atIndex:n
                     ];
+                    self.stats.texture_binds += 1;
                 }
             }
         }
@@ -1249,6 +1536,7 @@ impl RenderingBackend for MetalContext {
         let index_buffer = self.index_buffer.unwrap();
 
         assert!(base_element == 0); // TODO: figure indexBufferOffset/baseVertex
+        self.draw_calls.set(self.draw_calls.get() + 1);
         unsafe {
             msg_send_![render_encoder, drawIndexedPrimitives:MTLPrimitiveType::Triangle
                        indexCount:num_elements as u64
@@ -1262,11 +1550,47 @@ impl RenderingBackend for MetalContext {
         }
     }
 
-    fn delete_shader(&mut self, _shader: ShaderId) {
-        // TODO: place holder
+    fn delete_shader(&mut self, shader: ShaderId) {
+        if self.deferred.defer(shader.into()) {
+            return;
+        }
+        // TODO: release metal functions
+        self.shaders.remove(shader.0);
+        self.labels.remove(&shader.into());
     }
-    fn delete_pipeline(&mut self, _pipeline: Pipeline) {
-        // TODO: place holder
+    fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        if self.deferred.defer(pipeline.into()) {
+            return;
+        }
+        // TODO: release pipeline state
+        self.pipelines.remove(pipeline.0);
+        self.labels.remove(&pipeline.into());
+    }
+
+    fn set_default_pass_sample_count(&mut self, _sample_count: i32) {
+        // the view's sample count is fixed by conf::Conf::sample_count
+        eprintln!("Multisampled default pass is not supported on Metal");
+    }
+
+    fn default_pass_sample_count(&self) -> i32 {
+        unsafe {
+            let sample_count: u64 = msg_send![self.view, sampleCount];
+            sample_count as i32
+        }
+    }
+
+    fn set_default_pass_scale(&mut self, _scale: f32) {
+        eprintln!("Scaled default pass is not supported on Metal");
+    }
+
+    fn default_pass_scale(&self) -> f32 {
+        1.0
+    }
+
+    fn set_default_pass_upscale_filter(&mut self, _filter: FilterMode) {}
+
+    fn capture_frame(&mut self) -> Option<capture::CapturedFrame> {
+        None
     }
 
     fn commit_frame(&mut self) {
@@ -1278,14 +1602,20 @@ impl RenderingBackend for MetalContext {
             msg_send_![self.command_buffer.unwrap(), commit];
             msg_send_![self.command_buffer.unwrap(), waitUntilCompleted];
         }
-        for buffer in &mut self.buffers {
+        for buffer in self.buffers.iter_mut() {
             buffer.next_value = 0;
         }
+        self.last_frame_stats = FrameStats {
+            draw_calls: self.draw_calls.replace(0),
+            ..std::mem::take(&mut self.stats)
+        };
         self.current_ub_offset = 0;
         self.current_pipeline = None;
         self.command_buffer = None;
         if (self.current_frame_index + 1) >= 3 {
             self.current_frame_index = 0;
         }
+        // waitUntilCompleted above: the GPU is done with the frame
+        DeferredDeletions::flush(self, |ctx| &mut ctx.deferred);
     }
 }
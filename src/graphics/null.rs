@@ -0,0 +1,833 @@
+//! A `RenderingBackend` that never touches a GPU, for tests of code owning a context.
+//!
+//! Resources get real, generation-checked handles and keep their parameters, so
+//! `texture_size`, `buffer_size`, `resource_stats` and `live_resources` answer like
+//! on a GPU, and using a deleted handle panics the same way. Draw calls only bump
+//! `NullContext::calls`.
+//!
+//! ```ignore
+//! let mut ctx = NullContext::new();
+//! ctx.set_features(Features { instancing: false, ..Default::default() });
+//! let mut renderer = Renderer::new(&mut ctx);
+//! renderer.draw(&mut ctx);
+//! assert_eq!(ctx.calls().draws, 100); // fell back to one draw per sprite
+//! assert!(ctx.live_resources().is_empty());
+//! ```
+
+use super::*;
+use crate::ResourceManager;
+
+use std::cell::Cell;
+
+/// How many times the `RenderingBackend` methods were called, see `NullContext::calls`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallCounts {
+    /// `draw` calls that drew something, not the ones ignored for lack of instancing.
+    pub draws: usize,
+    /// Sum of `num_instances` of those draws.
+    pub instances: usize,
+    pub apply_pipeline: usize,
+    pub apply_bindings: usize,
+    pub apply_uniforms: usize,
+    pub apply_viewport: usize,
+    pub apply_scissor_rect: usize,
+    pub clear: usize,
+    pub begin_pass: usize,
+    pub end_render_pass: usize,
+    pub commit_frame: usize,
+    pub buffer_updates: usize,
+    pub texture_updates: usize,
+}
+
+struct Buffer {
+    type_: BufferType,
+    size: usize,
+    index_size: Option<usize>,
+}
+
+struct Pass {
+    colors: Vec<TextureId>,
+    depth: Option<TextureId>,
+}
+
+struct PipelineInternal {
+    shader: ShaderId,
+}
+
+pub struct NullContext {
+    info: ContextInfo,
+    screen_size: (u32, u32),
+    buffers: ResourceManager<Buffer>,
+    textures: ResourceManager<TextureParams>,
+    shaders: ResourceManager<ShaderMeta>,
+    pipelines: ResourceManager<PipelineInternal>,
+    passes: ResourceManager<Pass>,
+    labels: HashMap<ResourceHandle, String>,
+    current_pipeline: Option<Pipeline>,
+    in_pass: bool,
+    default_pass_sample_count: i32,
+    default_pass_scale: f32,
+    lost: bool,
+    leak_report: bool,
+    deferred: DeferredDeletions,
+    // draw() takes &self hence the Cells
+    calls: Cell<CallCounts>,
+    stats: Cell<FrameStats>,
+    last_frame_stats: FrameStats,
+}
+
+impl Default for NullContext {
+    fn default() -> Self {
+        NullContext::new()
+    }
+}
+
+impl Drop for NullContext {
+    fn drop(&mut self) {
+        if self.leak_report {
+            print_leak_report(&self.live_resources());
+        }
+    }
+}
+
+impl NullContext {
+    /// A context reporting itself as OpenGL with every feature and GLSL version,
+    /// and an 800x600 window.
+    pub fn new() -> NullContext {
+        NullContext::with_info(ContextInfo {
+            backend: Backend::OpenGl,
+            gl_version_string: "null".to_string(),
+            glsl_support: GlslSupport {
+                v130: true,
+                v150: true,
+                v330: true,
+                v300es: true,
+                v100_ext: true,
+                v100: true,
+            },
+            features: Features::default(),
+            gl_context: None,
+            debug_context: false,
+            framebuffer: FramebufferInfo {
+                red_bits: 8,
+                green_bits: 8,
+                blue_bits: 8,
+                alpha_bits: 8,
+                depth_bits: 24,
+                stencil_bits: 8,
+                sample_count: 1,
+                srgb: false,
+                float: false,
+            },
+        })
+    }
+
+    /// A context answering `info()` with `info`.
+    pub fn with_info(info: ContextInfo) -> NullContext {
+        NullContext {
+            default_pass_sample_count: info.framebuffer.sample_count,
+            default_pass_scale: 1.0,
+            info,
+            screen_size: (800, 600),
+            buffers: ResourceManager::new("buffer"),
+            textures: ResourceManager::new("texture"),
+            shaders: ResourceManager::new("shader"),
+            pipelines: ResourceManager::new("pipeline"),
+            passes: ResourceManager::new("render pass"),
+            labels: HashMap::new(),
+            current_pipeline: None,
+            in_pass: false,
+            lost: false,
+            leak_report: false,
+            deferred: DeferredDeletions::default(),
+            calls: Cell::new(CallCounts::default()),
+            stats: Cell::new(FrameStats::default()),
+            last_frame_stats: FrameStats::default(),
+        }
+    }
+
+    pub fn set_info(&mut self, info: ContextInfo) {
+        self.info = info;
+    }
+
+    /// Pretend to run on hardware with only these features.
+    pub fn set_features(&mut self, features: Features) {
+        self.info.features = features;
+    }
+
+    /// Size of the frames returned by `capture_frame`.
+    pub fn set_screen_size(&mut self, width: u32, height: u32) {
+        self.screen_size = (width, height);
+    }
+
+    /// Calls since the context was created or since `reset_calls`.
+    pub fn calls(&self) -> CallCounts {
+        self.calls.get()
+    }
+
+    pub fn reset_calls(&mut self) {
+        self.calls.set(CallCounts::default());
+    }
+
+    fn count(&self, f: impl FnOnce(&mut CallCounts)) {
+        let mut calls = self.calls.get();
+        f(&mut calls);
+        self.calls.set(calls);
+    }
+
+    fn count_stats(&self, f: impl FnOnce(&mut FrameStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    fn texture(&self, texture: TextureId) -> &TextureParams {
+        match texture.0 {
+            TextureIdInner::Managed(id) => &self.textures[id],
+            TextureIdInner::Raw(_) => panic!("raw textures are unknown to NullContext"),
+        }
+    }
+
+    fn texture_mut(&mut self, texture: TextureId) -> &mut TextureParams {
+        match texture.0 {
+            TextureIdInner::Managed(id) => &mut self.textures[id],
+            TextureIdInner::Raw(_) => panic!("raw textures are unknown to NullContext"),
+        }
+    }
+
+    fn texture_updated(&mut self, bytes: usize) {
+        self.count(|calls| calls.texture_updates += 1);
+        self.count_stats(|stats| stats.bytes_uploaded += bytes);
+    }
+}
+
+impl RenderingBackend for NullContext {
+    fn info(&self) -> ContextInfo {
+        self.info.clone()
+    }
+    fn resource_stats(&self) -> ResourceStats {
+        let mut stats = ResourceStats {
+            shaders: self.shaders.len(),
+            pipelines: self.pipelines.len(),
+            render_passes: self.passes.len(),
+            ..Default::default()
+        };
+        for (_, buffer) in self.buffers.iter() {
+            stats.buffers.count += 1;
+            stats.buffers.bytes += buffer.size;
+        }
+        for (_, params) in self.textures.iter() {
+            stats.textures.count += 1;
+            stats.textures.bytes += texture_memory_size(params);
+        }
+        stats
+    }
+    fn frame_stats(&self) -> FrameStats {
+        self.last_frame_stats
+    }
+    fn live_resources(&self) -> Vec<LiveResource> {
+        let mut live = vec![];
+        for (id, buffer) in self.buffers.iter() {
+            let handle = ResourceHandle::Buffer(BufferId(id));
+            let description = match buffer.type_ {
+                BufferType::VertexBuffer => "vertex buffer",
+                BufferType::IndexBuffer => "index buffer",
+            };
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, description.to_string()),
+                bytes: buffer.size,
+            });
+        }
+        for (id, params) in self.textures.iter() {
+            let handle = ResourceHandle::Texture(TextureId(TextureIdInner::Managed(id)));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, texture_label(params)),
+                bytes: texture_memory_size(params),
+            });
+        }
+        for (id, _) in self.shaders.iter() {
+            let handle = ResourceHandle::Shader(ShaderId(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "shader".to_string()),
+                bytes: 0,
+            });
+        }
+        for (id, _) in self.pipelines.iter() {
+            let handle = ResourceHandle::Pipeline(Pipeline(id));
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, "pipeline".to_string()),
+                bytes: 0,
+            });
+        }
+        for (id, pass) in self.passes.iter() {
+            let handle = ResourceHandle::RenderPass(RenderPass(id));
+            let description = format!("render pass, {} color attachments", pass.colors.len());
+            live.push(LiveResource {
+                handle,
+                label: labeled(&self.labels, handle, description),
+                bytes: 0,
+            });
+        }
+        live
+    }
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.leak_report = enabled;
+    }
+    fn set_deferred_deletion(&mut self, enabled: bool) {
+        self.deferred.set_enabled(enabled);
+    }
+    fn deletion_queue(&mut self) -> DeletionQueue {
+        self.deferred.deletion_queue()
+    }
+    fn is_context_lost(&self) -> bool {
+        self.lost
+    }
+    // nothing is lost for real, the resources stay
+    fn restore_context(&mut self) {
+        self.lost = false;
+    }
+    fn set_restore_resources(&mut self, _enabled: bool) {}
+    fn simulate_context_loss(&mut self) -> bool {
+        self.lost = true;
+        true
+    }
+    fn set_label(&mut self, handle: ResourceHandle, label: &str) {
+        assert!(self.is_valid(handle), "set_label on a deleted resource");
+        self.labels.insert(handle, label.to_string());
+    }
+    fn push_debug_group(&mut self, _name: &str) {}
+    fn pop_debug_group(&mut self) {}
+
+    fn new_shader(
+        &mut self,
+        _shader: ShaderSource,
+        meta: ShaderMeta,
+    ) -> Result<ShaderId, ShaderError> {
+        Ok(ShaderId(self.shaders.add(meta)))
+    }
+    fn new_texture(
+        &mut self,
+        access: TextureAccess,
+        data: TextureSource,
+        params: TextureParams,
+    ) -> TextureId {
+        if params.kind == TextureKind::Texture2DMultisample {
+            assert!(
+                self.info.features.multisample_textures,
+                "Multisampled textures are not supported"
+            );
+            assert!(
+                access == TextureAccess::RenderTarget,
+                "Multisampled textures are render targets"
+            );
+        }
+        let bytes = match data {
+            TextureSource::Empty => 0,
+            TextureSource::Bytes(bytes) => bytes.len(),
+            TextureSource::Array(faces) => {
+                faces.iter().flat_map(|f| f.iter()).map(|l| l.len()).sum()
+            }
+            TextureSource::Mips(levels) => levels.iter().map(|l| l.len()).sum(),
+        };
+        self.count_stats(|stats| stats.bytes_uploaded += bytes);
+        TextureId(TextureIdInner::Managed(self.textures.add(params)))
+    }
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        *self.texture(texture)
+    }
+    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
+        match texture.0 {
+            TextureIdInner::Raw(raw) => raw,
+            TextureIdInner::Managed(_) => {
+                let _ = self.texture(texture);
+                RawId::OpenGl(0)
+            }
+        }
+    }
+    fn texture_set_min_filter(
+        &mut self,
+        texture: TextureId,
+        filter: FilterMode,
+        mipmap_filter: MipmapFilterMode,
+    ) {
+        let params = self.texture_mut(texture);
+        params.min_filter = filter;
+        params.mipmap_filter = mipmap_filter;
+    }
+    fn texture_set_mag_filter(&mut self, texture: TextureId, filter: FilterMode) {
+        self.texture_mut(texture).mag_filter = filter;
+    }
+    fn texture_set_wrap(&mut self, texture: TextureId, wrap_x: TextureWrap, _wrap_y: TextureWrap) {
+        self.texture_mut(texture).wrap = wrap_x;
+    }
+    fn texture_generate_mipmaps(&mut self, texture: TextureId) {
+        self.texture_mut(texture).allocate_mipmaps = true;
+    }
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        bytes: Option<&[u8]>,
+    ) {
+        let params = self.texture_mut(texture);
+        params.width = width;
+        params.height = height;
+        self.texture_updated(bytes.map_or(0, |bytes| bytes.len()));
+    }
+    fn texture_read_pixels(&mut self, texture: TextureId, bytes: &mut [u8]) {
+        let _ = self.texture(texture);
+        bytes.fill(0);
+    }
+    fn texture_update_part(
+        &mut self,
+        texture: TextureId,
+        _x_offset: i32,
+        _y_offset: i32,
+        width: i32,
+        height: i32,
+        _bytes: &[u8],
+    ) {
+        let size = self.texture(texture).format.size(width as _, height as _);
+        self.texture_updated(size as usize);
+    }
+    fn texture_update_region(
+        &mut self,
+        texture: TextureId,
+        region: TextureRegion,
+        _layout: PixelLayout,
+        _bytes: &[u8],
+    ) {
+        let size = self
+            .texture(texture)
+            .format
+            .size(region.width.max(0) as _, region.height.max(0) as _);
+        self.texture_updated(size as usize);
+    }
+    fn new_render_pass_mrt(
+        &mut self,
+        color_img: &[TextureId],
+        resolve_img: Option<&[TextureId]>,
+        depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        assert!(
+            resolve_img.is_none() || self.info.features.resolve_attachments,
+            "Resolve attachments are not supported by current backend"
+        );
+        for texture in color_img
+            .iter()
+            .chain(resolve_img.unwrap_or(&[]))
+            .chain(&depth_img)
+        {
+            let _ = self.texture(*texture);
+        }
+        // what GL needs for a complete framebuffer
+        let samples = |texture: &TextureId| self.texture(*texture).sample_count.max(1);
+        let mut attachments = color_img.iter().chain(&depth_img);
+        if let Some(first) = attachments.next() {
+            assert!(
+                attachments.all(|texture| samples(texture) == samples(first)),
+                "Render pass attachments must have the same sample count"
+            );
+        }
+        for texture in resolve_img.unwrap_or(&[]) {
+            assert!(
+                samples(texture) == 1,
+                "Resolve attachments must be single-sampled"
+            );
+        }
+        RenderPass(self.passes.add(Pass {
+            colors: color_img.to_vec(),
+            depth: depth_img,
+        }))
+    }
+    fn render_pass_color_attachments(&self, render_pass: RenderPass) -> &[TextureId] {
+        &self.passes[render_pass.0].colors
+    }
+    fn delete_render_pass(&mut self, render_pass: RenderPass) {
+        if self.deferred.defer(render_pass.into()) {
+            return;
+        }
+        self.labels.remove(&render_pass.into());
+        let pass = self.passes.remove(render_pass.0);
+        // like on GL, the attachments go with the pass
+        for texture in pass.colors.into_iter().chain(pass.depth) {
+            if self.is_valid(texture.into()) {
+                self.delete_texture(texture);
+            }
+        }
+    }
+    fn new_pipeline(
+        &mut self,
+        _buffer_layout: &[BufferLayout],
+        _attributes: &[VertexAttribute],
+        shader: ShaderId,
+        _params: PipelineParams,
+    ) -> Pipeline {
+        let _ = &self.shaders[shader.0];
+        Pipeline(self.pipelines.add(PipelineInternal { shader }))
+    }
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        let _ = &self.pipelines[pipeline.0];
+        self.count(|calls| calls.apply_pipeline += 1);
+        if self.current_pipeline != Some(*pipeline) {
+            self.count_stats(|stats| stats.pipeline_switches += 1);
+        }
+        self.current_pipeline = Some(*pipeline);
+    }
+    fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        if self.deferred.defer(pipeline.into()) {
+            return;
+        }
+        self.labels.remove(&pipeline.into());
+        self.pipelines.remove(pipeline.0);
+        if self.current_pipeline == Some(pipeline) {
+            self.current_pipeline = None;
+        }
+    }
+    fn new_buffer(
+        &mut self,
+        type_: BufferType,
+        _usage: BufferUsage,
+        data: BufferSource,
+    ) -> BufferId {
+        let (size, element_size) = match &data {
+            BufferSource::Slice(data) => {
+                self.count_stats(|stats| stats.bytes_uploaded += data.size);
+                (data.size, data.element_size)
+            }
+            BufferSource::Empty { size, element_size } => (*size, *element_size),
+        };
+        let index_size = match type_ {
+            BufferType::IndexBuffer => {
+                assert!(
+                    matches!(element_size, 1 | 2 | 4),
+                    "index buffers take u8, u16 or u32"
+                );
+                Some(element_size)
+            }
+            BufferType::VertexBuffer => None,
+        };
+        BufferId(self.buffers.add(Buffer {
+            type_,
+            size,
+            index_size,
+        }))
+    }
+    fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
+        let data = match data {
+            BufferSource::Slice(data) => data,
+            _ => panic!("buffer_update expects BufferSource::slice"),
+        };
+        let buffer = &self.buffers[buffer.0];
+        if let Some(index_size) = buffer.index_size {
+            assert!(data.element_size == index_size);
+        }
+        assert!(data.size <= buffer.size);
+        self.count(|calls| calls.buffer_updates += 1);
+        self.count_stats(|stats| stats.bytes_uploaded += data.size);
+    }
+    fn buffer_size(&mut self, buffer: BufferId) -> usize {
+        self.buffers[buffer.0].size
+    }
+    // uploads hold GL objects of another context, like Metal they are handed back
+    fn publish_texture(&mut self, upload: UploadedTexture) -> Result<TextureId, UploadedTexture> {
+        Err(upload)
+    }
+    fn publish_buffer(&mut self, upload: UploadedBuffer) -> Result<BufferId, UploadedBuffer> {
+        Err(upload)
+    }
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        if self.deferred.defer(buffer.into()) {
+            return;
+        }
+        self.labels.remove(&buffer.into());
+        self.buffers.remove(buffer.0);
+    }
+    fn delete_texture(&mut self, texture: TextureId) {
+        if self.deferred.defer(texture.into()) {
+            return;
+        }
+        if let TextureIdInner::Managed(id) = texture.0 {
+            self.textures.remove(id);
+        }
+        self.labels.remove(&texture.into());
+    }
+    fn delete_shader(&mut self, program: ShaderId) {
+        if self.deferred.defer(program.into()) {
+            return;
+        }
+        self.labels.remove(&program.into());
+        self.shaders.remove(program.0);
+    }
+    fn is_valid(&self, handle: ResourceHandle) -> bool {
+        match handle {
+            ResourceHandle::Buffer(id) => self.buffers.contains(id.0),
+            ResourceHandle::Texture(TextureId(TextureIdInner::Managed(id))) => {
+                self.textures.contains(id)
+            }
+            ResourceHandle::Texture(TextureId(TextureIdInner::Raw(_))) => true,
+            ResourceHandle::Shader(id) => self.shaders.contains(id.0),
+            ResourceHandle::Pipeline(id) => self.pipelines.contains(id.0),
+            ResourceHandle::RenderPass(id) => self.passes.contains(id.0),
+        }
+    }
+    fn apply_viewport(&mut self, _x: i32, _y: i32, _w: i32, _h: i32) {
+        self.count(|calls| calls.apply_viewport += 1);
+    }
+    fn apply_scissor_rect(&mut self, _x: i32, _y: i32, _w: i32, _h: i32) {
+        self.count(|calls| calls.apply_scissor_rect += 1);
+    }
+    fn apply_bindings_from_slice(
+        &mut self,
+        vertex_buffers: &[BufferId],
+        index_buffer: BufferId,
+        textures: &[TextureId],
+    ) {
+        for buffer in vertex_buffers {
+            let _ = &self.buffers[buffer.0];
+        }
+        assert!(
+            self.buffers[index_buffer.0].index_size.is_some(),
+            "apply_bindings: index_buffer is not an index buffer"
+        );
+        for texture in textures {
+            if let TextureIdInner::Managed(id) = texture.0 {
+                let _ = &self.textures[id];
+            }
+        }
+        self.count(|calls| calls.apply_bindings += 1);
+        self.count_stats(|stats| stats.texture_binds += textures.len());
+    }
+    fn apply_uniforms_from_bytes(&mut self, _uniform_ptr: *const u8, size: usize) {
+        let pipeline = self
+            .current_pipeline
+            .expect("apply_uniforms without any binded pipeline");
+        let shader = &self.shaders[self.pipelines[pipeline.0].shader.0];
+        let expected: usize = shader
+            .uniforms
+            .uniforms
+            .iter()
+            .map(|uniform| uniform.uniform_type.size() * uniform.array_count)
+            .sum();
+        assert!(
+            size >= expected,
+            "Uniforms struct does not match shader uniforms layout"
+        );
+        self.count(|calls| calls.apply_uniforms += 1);
+    }
+    fn clear(
+        &mut self,
+        _color: Option<(f32, f32, f32, f32)>,
+        _depth: Option<f32>,
+        _stencil: Option<i32>,
+    ) {
+        self.count(|calls| calls.clear += 1);
+    }
+    fn begin_default_pass(&mut self, action: PassAction) {
+        self.begin_pass(None, action);
+    }
+    fn begin_pass(&mut self, pass: Option<RenderPass>, _action: PassAction) {
+        assert!(!self.in_pass, "begin_pass inside of another pass");
+        if let Some(pass) = pass {
+            let _ = &self.passes[pass.0];
+        }
+        self.in_pass = true;
+        self.count(|calls| calls.begin_pass += 1);
+    }
+    fn end_render_pass(&mut self) {
+        assert!(self.in_pass, "end_render_pass without begin_pass");
+        self.in_pass = false;
+        self.count(|calls| calls.end_render_pass += 1);
+    }
+    fn set_default_pass_sample_count(&mut self, sample_count: i32) {
+        self.default_pass_sample_count = sample_count.max(1);
+    }
+    fn default_pass_sample_count(&self) -> i32 {
+        self.default_pass_sample_count
+    }
+    fn set_default_pass_scale(&mut self, scale: f32) {
+        self.default_pass_scale = if scale.is_finite() {
+            scale.clamp(0.1, 1.0)
+        } else {
+            1.0
+        };
+    }
+    fn default_pass_scale(&self) -> f32 {
+        self.default_pass_scale
+    }
+    fn set_default_pass_upscale_filter(&mut self, _filter: FilterMode) {}
+    fn commit_frame(&mut self) {
+        self.current_pipeline = None;
+        self.last_frame_stats = self.stats.take();
+        self.count(|calls| calls.commit_frame += 1);
+        DeferredDeletions::flush(self, |ctx| &mut ctx.deferred);
+    }
+    fn capture_frame(&mut self) -> Option<capture::CapturedFrame> {
+        let (width, height) = self.screen_size;
+        let mut rgba = vec![0; width as usize * height as usize * 4];
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+        Some(capture::CapturedFrame {
+            width,
+            height,
+            rgba,
+        })
+    }
+    fn draw(&self, _base_element: i32, _num_elements: i32, num_instances: i32) {
+        assert!(
+            self.current_pipeline.is_some(),
+            "Drawing without any binded pipeline"
+        );
+        // like GL, without instancing only single instances are drawn
+        if !self.info.features.instancing && num_instances != 1 {
+            return;
+        }
+        self.count(|calls| {
+            calls.draws += 1;
+            calls.instances += num_instances.max(0) as usize;
+        });
+        self.count_stats(|stats| stats.draw_calls += 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_null_context() {
+        let mut ctx = NullContext::new();
+        ctx.set_features(Features {
+            instancing: false,
+            ..Default::default()
+        });
+        let texture = ctx.new_texture_from_rgba8(4, 2, &[0; 32]);
+        let buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<u16>(6),
+        );
+        assert_eq!(ctx.texture_size(texture), (4, 2));
+        assert_eq!(ctx.buffer_size(buffer), 12);
+        assert_eq!(ctx.resource_stats().textures.bytes, 32);
+
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: "",
+                    fragment: "",
+                },
+                ShaderMeta {
+                    images: vec![],
+                    uniforms: UniformBlockLayout { uniforms: vec![] },
+                },
+            )
+            .unwrap();
+        let pipeline = ctx.new_pipeline(&[], &[], shader, PipelineParams::default());
+        ctx.begin_default_pass(PassAction::Nothing);
+        ctx.apply_pipeline(&pipeline);
+        ctx.apply_bindings_from_slice(&[], buffer, &[texture]);
+        ctx.draw(0, 6, 1);
+        ctx.draw(0, 6, 10);
+        ctx.end_render_pass();
+        ctx.commit_frame();
+        assert_eq!(ctx.calls().draws, 1);
+        assert_eq!(ctx.frame_stats().draw_calls, 1);
+        assert_eq!(ctx.frame_stats().bytes_uploaded, 32);
+
+        ctx.delete_texture(texture);
+        assert!(!ctx.is_valid(texture.into()));
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ctx.texture_size(texture)
+        }))
+        .is_err());
+        assert_eq!(ctx.live_resources().len(), 3);
+
+        let upload = UploadedTexture::completed(TextureParams::default());
+        assert!(ctx.publish_texture(upload).is_err());
+        let upload = UploadedBuffer::completed(BufferType::VertexBuffer, 16);
+        assert!(ctx.publish_buffer(upload).is_err());
+    }
+
+    #[test]
+    fn test_multisample_textures() {
+        let params = TextureParams {
+            kind: TextureKind::Texture2DMultisample,
+            width: 4,
+            height: 4,
+            sample_count: 4,
+            ..Default::default()
+        };
+        let mut ctx = NullContext::new();
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ctx.new_render_texture(params)
+        }))
+        .is_err());
+
+        ctx.set_features(Features {
+            multisample_textures: true,
+            ..Default::default()
+        });
+        let colors = [
+            ctx.new_render_texture(params),
+            ctx.new_render_texture(params),
+        ];
+        let depth = ctx.new_render_texture(TextureParams {
+            format: TextureFormat::Depth,
+            ..params
+        });
+        let pass = ctx.new_render_pass_mrt(&colors, None, Some(depth));
+        assert_eq!(ctx.render_pass_color_attachments(pass), colors);
+        assert_eq!(ctx.texture_params(colors[0]).kind, params.kind);
+
+        // a custom resolve samples the multisampled attachments in a shader,
+        // resolve attachments have to be single-sampled
+        let single = ctx.new_render_texture(TextureParams {
+            kind: TextureKind::Texture2D,
+            sample_count: 1,
+            ..params
+        });
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ctx.new_render_pass_mrt(&[colors[0], single], None, None)
+        }))
+        .is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            ctx.new_render_pass_mrt(&[colors[0]], Some(&[colors[1]]), None)
+        }))
+        .is_err());
+        ctx.new_render_pass_mrt(&[colors[0]], Some(&[single]), None);
+    }
+
+    #[test]
+    fn test_deferred_deletion() {
+        let mut ctx = NullContext::new();
+        ctx.set_deferred_deletion(true);
+        let texture = ctx.new_texture_from_rgba8(4, 2, &[0; 32]);
+        ctx.delete_texture(texture);
+        assert!(ctx.is_valid(texture.into()));
+        ctx.commit_frame();
+        assert!(!ctx.is_valid(texture.into()));
+
+        // the pass deletes its attachment before the attachment's own deletion runs
+        let color = ctx.new_render_texture(TextureParams {
+            width: 4,
+            height: 4,
+            ..Default::default()
+        });
+        let pass = ctx.new_render_pass(color, None);
+        ctx.delete_render_pass(pass);
+        if ctx.is_valid(color.into()) {
+            ctx.delete_texture(color);
+        }
+        ctx.commit_frame();
+        assert!(!ctx.is_valid(pass.into()));
+        assert!(!ctx.is_valid(color.into()));
+        assert!(ctx.live_resources().is_empty());
+    }
+}
@@ -0,0 +1,465 @@
+//! Debug overlay: FPS graph, CPU and GPU frame time, draw calls and resource counts.
+//!
+//! `DebugOverlay` wraps the application's `EventHandler` and draws on top of each
+//! frame with its own pipeline, so the application's draw code stays the same.
+//! It needs a way to reach the application's rendering backend:
+//!
+//! ```ignore
+//! miniquad::start(conf::Conf::default(), || {
+//!     Box::new(DebugOverlay::new(Stage::new(), |stage| &mut *stage.ctx))
+//! });
+//! ```
+//!
+//! F3 shows and hides it. GPU time is measured with `ElapsedQuery` when
+//! `ElapsedQuery::is_supported`, and reported a few frames late.
+//!
+//! The overlay draws after `EventHandler::draw` returns, that is after `commit_frame`:
+//! fine on OpenGL, where the frame is presented later, but with the metal backend
+//! nothing shows up. Its own draw calls are counted in the next frame's stats.
+
+use super::{batch::Batch, *};
+use crate::event::{EventHandler, KeyCode, KeyMods, MouseButton, TouchPhase};
+
+use std::collections::VecDeque;
+
+// frames in the graph
+const HISTORY: usize = 120;
+// in flight, results come a couple frames later
+const GPU_QUERIES: usize = 3;
+
+const SCALE: f32 = 2.0;
+const GLYPH_ADVANCE: f32 = 4.0 * SCALE;
+const LINE_HEIGHT: f32 = 7.0 * SCALE;
+const MARGIN: f32 = 8.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+// bar height of a full graph
+const GRAPH_MAX_MS: f32 = 100.0 / 3.0;
+
+pub struct DebugOverlay<H> {
+    handler: H,
+    context: fn(&mut H) -> &mut dyn RenderingBackend,
+    toggle_key: KeyCode,
+    visible: bool,
+    batch: Option<Batch>,
+    timings: Timings,
+    gpu: GpuTimer,
+}
+
+impl<H: EventHandler> DebugOverlay<H> {
+    /// Wrap `handler`, drawing with the backend returned by `context`. Visible at start.
+    pub fn new(handler: H, context: fn(&mut H) -> &mut dyn RenderingBackend) -> DebugOverlay<H> {
+        DebugOverlay {
+            handler,
+            context,
+            toggle_key: KeyCode::F3,
+            visible: true,
+            batch: None,
+            timings: Timings::default(),
+            gpu: GpuTimer::default(),
+        }
+    }
+
+    /// Key showing and hiding the overlay, F3 by default. Its presses don't reach the handler.
+    pub fn with_toggle_key(self, toggle_key: KeyCode) -> DebugOverlay<H> {
+        DebugOverlay { toggle_key, ..self }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    fn draw_overlay(&mut self) {
+        let ctx = (self.context)(&mut self.handler);
+        if ctx.is_context_lost() {
+            return;
+        }
+        let batch = self.batch.get_or_insert_with(|| Batch::new(&mut *ctx));
+        let lines = self.timings.lines(
+            self.gpu.last_ms,
+            self.gpu.supported == Some(true),
+            ctx.frame_stats(),
+            ctx.resource_stats(),
+        );
+
+        let width = lines.iter().map(|line| line.len()).max().unwrap_or(0) as f32 * GLYPH_ADVANCE;
+        let width = width.max(HISTORY as f32 * 2.0) + MARGIN * 2.0;
+        let height = lines.len() as f32 * LINE_HEIGHT + GRAPH_HEIGHT + MARGIN * 3.0;
+        batch.rect(0.0, 0.0, width, height, [0, 0, 0, 180]);
+
+        let mut y = MARGIN;
+        for line in &lines {
+            text(batch, MARGIN, y, line, [255, 255, 255, 255]);
+            y += LINE_HEIGHT;
+        }
+
+        // frame time graph, newest on the right
+        let bottom = y + MARGIN + GRAPH_HEIGHT;
+        let left = MARGIN + (HISTORY - self.timings.frames.len()) as f32 * 2.0;
+        for (i, &ms) in self.timings.frames.iter().enumerate() {
+            let bar = (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT;
+            let color = if ms < 17.0 {
+                [80, 220, 80, 255]
+            } else if ms < 34.0 {
+                [230, 200, 60, 255]
+            } else {
+                [230, 70, 60, 255]
+            };
+            batch.rect(left + i as f32 * 2.0, bottom - bar, 2.0, bar, color);
+        }
+        // 60 fps
+        let target = bottom - 1000.0 / 60.0 / GRAPH_MAX_MS * GRAPH_HEIGHT;
+        batch.rect(
+            MARGIN,
+            target,
+            HISTORY as f32 * 2.0,
+            1.0,
+            [255, 255, 255, 120],
+        );
+
+        ctx.begin_default_pass(PassAction::Nothing);
+        batch.draw(ctx);
+        ctx.end_render_pass();
+    }
+
+    // the GL objects died with the context, nothing to delete
+    fn forget_resources(&mut self) {
+        self.batch = None;
+        self.gpu = GpuTimer::default();
+    }
+}
+
+impl<H: EventHandler> EventHandler for DebugOverlay<H> {
+    fn update(&mut self) {
+        let start = crate::date::now();
+        self.handler.update();
+        self.timings.cpu += crate::date::now() - start;
+    }
+
+    fn draw(&mut self) {
+        let start = crate::date::now();
+        let measure_gpu = self.visible && self.gpu.begin();
+        self.handler.draw();
+        if measure_gpu {
+            self.gpu.end();
+        }
+        let end = crate::date::now();
+        self.timings.frame(start, end);
+
+        if self.visible {
+            self.draw_overlay();
+        }
+    }
+
+    fn resize_event(&mut self, width: f32, height: f32) {
+        self.handler.resize_event(width, height);
+    }
+
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        self.handler.mouse_motion_event(x, y);
+    }
+
+    fn mouse_wheel_event(&mut self, x: f32, y: f32) {
+        self.handler.mouse_wheel_event(x, y);
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.handler.mouse_button_down_event(button, x, y);
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.handler.mouse_button_up_event(button, x, y);
+    }
+
+    fn char_event(&mut self, character: char, keymods: KeyMods, repeat: bool) {
+        self.handler.char_event(character, keymods, repeat);
+    }
+
+    fn key_down_event(&mut self, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
+        if keycode == self.toggle_key {
+            if !repeat {
+                self.visible = !self.visible;
+            }
+            return;
+        }
+        self.handler.key_down_event(keycode, keymods, repeat);
+    }
+
+    fn key_up_event(&mut self, keycode: KeyCode, keymods: KeyMods) {
+        if keycode != self.toggle_key {
+            self.handler.key_up_event(keycode, keymods);
+        }
+    }
+
+    fn touch_event(&mut self, phase: TouchPhase, id: u64, x: f32, y: f32) {
+        self.handler.touch_event(phase, id, x, y);
+    }
+
+    fn raw_mouse_motion(&mut self, dx: f32, dy: f32) {
+        self.handler.raw_mouse_motion(dx, dy);
+    }
+
+    fn window_minimized_event(&mut self) {
+        self.handler.window_minimized_event();
+    }
+
+    fn window_restored_event(&mut self) {
+        self.handler.window_restored_event();
+    }
+
+    fn quit_requested_event(&mut self) {
+        self.handler.quit_requested_event();
+    }
+
+    fn files_dropped_event(&mut self) {
+        self.handler.files_dropped_event();
+    }
+
+    fn context_lost_event(&mut self) {
+        self.forget_resources();
+        self.handler.context_lost_event();
+    }
+
+    fn context_restored_event(&mut self) {
+        self.handler.context_restored_event();
+    }
+}
+
+#[derive(Default)]
+struct Timings {
+    /// Milliseconds between draws, oldest first.
+    frames: VecDeque<f32>,
+    last_draw: Option<f64>,
+    /// Seconds spent in update and draw since the last draw.
+    cpu: f64,
+    cpu_ms: f32,
+}
+
+impl Timings {
+    fn frame(&mut self, draw_start: f64, draw_end: f64) {
+        self.cpu += draw_end - draw_start;
+        self.cpu_ms = (self.cpu * 1000.0) as f32;
+        self.cpu = 0.0;
+        if let Some(last) = self.last_draw {
+            if self.frames.len() == HISTORY {
+                self.frames.pop_front();
+            }
+            self.frames.push_back(((draw_start - last) * 1000.0) as f32);
+        }
+        self.last_draw = Some(draw_start);
+    }
+
+    fn lines(
+        &self,
+        gpu_ms: Option<f32>,
+        gpu_supported: bool,
+        frame: FrameStats,
+        resources: ResourceStats,
+    ) -> Vec<String> {
+        let average = if self.frames.is_empty() {
+            0.0
+        } else {
+            self.frames.iter().sum::<f32>() / self.frames.len() as f32
+        };
+        let fps = if average > 0.0 { 1000.0 / average } else { 0.0 };
+        let gpu = match (gpu_supported, gpu_ms) {
+            (false, _) => "N/A".to_owned(),
+            (true, None) => "...".to_owned(),
+            (true, Some(ms)) => format!("{:.2} MS", ms),
+        };
+        let mb = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+        vec![
+            format!("FPS {:.1}  {:.2} MS", fps, average),
+            format!("CPU {:.2} MS  GPU {}", self.cpu_ms, gpu),
+            format!(
+                "DRAWS {}  PIPELINES {}  BINDS {}  UPLOAD {:.1} MB",
+                frame.draw_calls,
+                frame.pipeline_switches,
+                frame.texture_binds,
+                mb(frame.bytes_uploaded)
+            ),
+            format!(
+                "TEXTURES {} ({:.1} MB)  BUFFERS {} ({:.1} MB)",
+                resources.textures.count,
+                mb(resources.textures.bytes),
+                resources.buffers.count,
+                mb(resources.buffers.bytes)
+            ),
+            format!(
+                "SHADERS {}  PIPELINES {}  PASSES {}",
+                resources.shaders, resources.pipelines, resources.render_passes
+            ),
+        ]
+    }
+}
+
+/// Frame GPU time from a few `ElapsedQuery` in flight, also used by
+/// `resolution::DynamicResolution`.
+#[derive(Default)]
+pub(super) struct GpuTimer {
+    /// Checked on the first frame, the GL functions are loaded by then.
+    pub(super) supported: Option<bool>,
+    queries: [ElapsedQuery; GPU_QUERIES],
+    pending: [bool; GPU_QUERIES],
+    next: usize,
+    pub(super) last_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    /// Start measuring a frame, false if not possible now.
+    pub(super) fn begin(&mut self) -> bool {
+        if !*self
+            .supported
+            .get_or_insert_with(ElapsedQuery::is_supported)
+        {
+            return false;
+        }
+        for (query, pending) in self.queries.iter().zip(&mut self.pending) {
+            if *pending && query.is_available() {
+                self.last_ms = Some(query.get_result() as f32 / 1_000_000.0);
+                *pending = false;
+            }
+        }
+        if self.pending[self.next] {
+            return false;
+        }
+        self.queries[self.next].begin_query();
+        true
+    }
+
+    pub(super) fn end(&mut self) {
+        self.queries[self.next].end_query();
+        self.pending[self.next] = true;
+        self.next = (self.next + 1) % GPU_QUERIES;
+    }
+}
+
+/// Draw `text` with the built-in font, top left corner at `x`, `y`.
+fn text(batch: &mut Batch, x: f32, y: f32, text: &str, color: [u8; 4]) {
+    for (i, c) in text.chars().enumerate() {
+        let rows = glyph(c);
+        let left = x + i as f32 * GLYPH_ADVANCE;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let (px, py) = (left + column as f32 * SCALE, y + row as f32 * SCALE);
+                    batch.rect(px, py, SCALE, SCALE, color);
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 rows of `c`, most significant bit on the left. Lowercase letters are drawn
+/// as uppercase, characters outside of the font as `?`.
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    match c {
+        ' '..='_' => FONT[c as usize - ' ' as usize],
+        _ => FONT['?' as usize - ' ' as usize],
+    }
+}
+
+/// ASCII from space to underscore.
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 64] = [
+    [0, 0, 0, 0, 0], // space
+    [2, 2, 2, 0, 2], // !
+    [5, 5, 0, 0, 0], // "
+    [5, 7, 5, 7, 5], // #
+    [3, 6, 2, 3, 6], // $
+    [5, 1, 2, 4, 5], // %
+    [2, 5, 2, 5, 3], // &
+    [2, 2, 0, 0, 0], // '
+    [1, 2, 2, 2, 1], // (
+    [4, 2, 2, 2, 4], // )
+    [0, 5, 2, 5, 0], // *
+    [0, 2, 7, 2, 0], // +
+    [0, 0, 0, 2, 4], // ,
+    [0, 0, 7, 0, 0], // -
+    [0, 0, 0, 0, 2], // .
+    [1, 1, 2, 4, 4], // /
+    [7, 5, 5, 5, 7], // 0
+    [2, 6, 2, 2, 7], // 1
+    [7, 1, 7, 4, 7], // 2
+    [7, 1, 3, 1, 7], // 3
+    [5, 5, 7, 1, 1], // 4
+    [7, 4, 7, 1, 7], // 5
+    [7, 4, 7, 5, 7], // 6
+    [7, 1, 1, 2, 2], // 7
+    [7, 5, 7, 5, 7], // 8
+    [7, 5, 7, 1, 7], // 9
+    [0, 2, 0, 2, 0], // :
+    [0, 2, 0, 2, 4], // ;
+    [1, 2, 4, 2, 1], // <
+    [0, 7, 0, 7, 0], // =
+    [4, 2, 1, 2, 4], // >
+    [7, 1, 3, 0, 2], // ?
+    [7, 5, 7, 4, 3], // @
+    [2, 5, 7, 5, 5], // A
+    [6, 5, 6, 5, 6], // B
+    [3, 4, 4, 4, 3], // C
+    [6, 5, 5, 5, 6], // D
+    [7, 4, 6, 4, 7], // E
+    [7, 4, 6, 4, 4], // F
+    [3, 4, 5, 5, 3], // G
+    [5, 5, 7, 5, 5], // H
+    [7, 2, 2, 2, 7], // I
+    [1, 1, 1, 5, 2], // J
+    [5, 5, 6, 5, 5], // K
+    [4, 4, 4, 4, 7], // L
+    [5, 7, 7, 5, 5], // M
+    [6, 5, 5, 5, 5], // N
+    [2, 5, 5, 5, 2], // O
+    [6, 5, 6, 4, 4], // P
+    [2, 5, 5, 6, 3], // Q
+    [6, 5, 6, 5, 5], // R
+    [3, 4, 2, 1, 6], // S
+    [7, 2, 2, 2, 2], // T
+    [5, 5, 5, 5, 7], // U
+    [5, 5, 5, 5, 2], // V
+    [5, 5, 7, 7, 5], // W
+    [5, 5, 2, 5, 5], // X
+    [5, 5, 2, 2, 2], // Y
+    [7, 1, 2, 4, 7], // Z
+    [6, 4, 4, 4, 6], // [
+    [4, 4, 2, 1, 1], // \
+    [3, 1, 1, 1, 3], // ]
+    [2, 5, 0, 0, 0], // ^
+    [0, 0, 0, 0, 7], // _
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings() {
+        let mut timings = Timings {
+            cpu: 0.001,
+            ..Default::default()
+        };
+        timings.frame(0.0, 0.002);
+        timings.frame(0.020, 0.021);
+        timings.frame(0.040, 0.041);
+        assert_eq!(timings.frames.len(), 2);
+        assert!((timings.cpu_ms - 1.0).abs() < 1e-3);
+
+        let lines = timings.lines(None, false, FrameStats::default(), ResourceStats::default());
+        assert_eq!(lines[0], "FPS 50.0  20.00 MS");
+        assert_eq!(lines[1], "CPU 1.00 MS  GPU N/A");
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('~'), glyph('?'));
+    }
+}
@@ -0,0 +1,176 @@
+//! Owned resource handles, deleted when dropped.
+//!
+//! Plain handles have to be deleted explicitly. The wrappers here send their handle
+//! to the backend's `DeletionQueue` on `Drop` instead, the backend deletes them at the
+//! end of the next `commit_frame`. They don't borrow the backend, so structs
+//! holding them can be dropped from anywhere:
+//!
+//! ```ignore
+//! let texture = ctx.new_texture_from_rgba8(4, 4, &pixels);
+//! let texture = OwnedTexture::new(&mut *ctx, texture);
+//! ctx.apply_bindings(&Bindings {
+//!     vertex_buffers: vec![vertex_buffer.id()],
+//!     index_buffer: index_buffer.id(),
+//!     images: vec![texture.id()],
+//! });
+//! ```
+//!
+//! Opt-in: nothing changes for plain handles. Owned handles must not be deleted by
+//! hand as well, `into_raw` gives the handle back without deleting it.
+//!
+//! A render pass deletes its attachments along with it. Owning an attachment as well
+//! as the `OwnedRenderPass` does not delete it twice: handles that are no longer valid
+//! when the queue is flushed are skipped. Deleting such an attachment by hand after
+//! the pass is gone still panics.
+
+use super::*;
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Sends handles to delete to a backend, see `RenderingBackend::deletion_queue`.
+#[derive(Clone, Debug)]
+pub struct DeletionQueue(Sender<ResourceHandle>);
+
+impl DeletionQueue {
+    /// Delete `handle` at the end of the next `commit_frame`.
+    /// Does nothing once the backend is gone.
+    pub fn delete(&self, handle: impl Into<ResourceHandle>) {
+        let _ = self.0.send(handle.into());
+    }
+}
+
+/// Receiving end of the backend's `DeletionQueue`.
+#[derive(Debug)]
+pub(crate) struct DeletionReceiver {
+    sender: Sender<ResourceHandle>,
+    receiver: Receiver<ResourceHandle>,
+}
+
+impl Default for DeletionReceiver {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        DeletionReceiver { sender, receiver }
+    }
+}
+
+impl DeletionReceiver {
+    pub(crate) fn queue(&self) -> DeletionQueue {
+        DeletionQueue(self.sender.clone())
+    }
+
+    pub(crate) fn try_recv(&self) -> Option<ResourceHandle> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// A resource handle deleted on `Drop`.
+#[derive(Debug)]
+pub struct Owned<T: Copy + Into<ResourceHandle>> {
+    handle: T,
+    queue: DeletionQueue,
+}
+
+pub type OwnedBuffer = Owned<BufferId>;
+pub type OwnedTexture = Owned<TextureId>;
+pub type OwnedShader = Owned<ShaderId>;
+pub type OwnedPipeline = Owned<Pipeline>;
+/// Its attachments get deleted with it, as with `delete_render_pass`.
+pub type OwnedRenderPass = Owned<RenderPass>;
+
+impl<T: Copy + Into<ResourceHandle>> Owned<T> {
+    /// Take ownership of `handle`, created by `ctx`.
+    pub fn new(ctx: &mut dyn RenderingBackend, handle: T) -> Owned<T> {
+        Owned {
+            handle,
+            queue: ctx.deletion_queue(),
+        }
+    }
+
+    /// Plain handle, for bindings and backend calls. Valid as long as `self` is.
+    pub fn id(&self) -> T {
+        self.handle
+    }
+
+    /// Give up ownership: the handle is not deleted on drop anymore.
+    pub fn into_raw(self) -> T {
+        let handle = self.handle;
+        std::mem::forget(self);
+        handle
+    }
+}
+
+impl<T: Copy + Into<ResourceHandle>> Drop for Owned<T> {
+    fn drop(&mut self) {
+        self.queue.delete(self.handle);
+    }
+}
+
+impl<T: Copy + Into<ResourceHandle>> std::ops::Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+macro_rules! owned_into_id {
+    ($($id:ty),*) => {
+        $(
+            impl From<&Owned<$id>> for $id {
+                fn from(owned: &Owned<$id>) -> $id {
+                    owned.handle
+                }
+            }
+        )*
+    };
+}
+
+owned_into_id!(BufferId, TextureId, ShaderId, Pipeline, RenderPass);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owned() {
+        let mut ctx = NullContext::new();
+        let texture = ctx.new_texture_from_rgba8(1, 1, &[0; 4]);
+        let buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&[0u8; 4]),
+        );
+        let owned_texture = OwnedTexture::new(&mut ctx, texture);
+        let owned_buffer = OwnedBuffer::new(&mut ctx, buffer);
+        assert_eq!(TextureId::from(&owned_texture), texture);
+
+        drop(owned_texture);
+        assert!(ctx.is_valid(texture.into()));
+        ctx.commit_frame();
+        assert!(!ctx.is_valid(texture.into()));
+
+        assert_eq!(owned_buffer.into_raw(), buffer);
+        ctx.commit_frame();
+        assert!(ctx.is_valid(buffer.into()));
+
+        // the pass deletes its attachment, the attachment's own deletion is skipped
+        let color = ctx.new_render_texture(TextureParams::default());
+        let pass = ctx.new_render_pass(color, None);
+        let owned_color = OwnedTexture::new(&mut ctx, color);
+        let owned_pass = OwnedRenderPass::new(&mut ctx, pass);
+        drop(owned_pass);
+        drop(owned_color);
+        ctx.commit_frame();
+        assert!(!ctx.is_valid(color.into()));
+
+        // same when the attachment outlives the pass by a frame
+        let color = ctx.new_render_texture(TextureParams::default());
+        let pass = ctx.new_render_pass(color, None);
+        let owned_color = OwnedTexture::new(&mut ctx, color);
+        drop(OwnedRenderPass::new(&mut ctx, pass));
+        ctx.commit_frame();
+        drop(owned_color);
+        ctx.commit_frame();
+        assert!(!ctx.is_valid(color.into()));
+    }
+}
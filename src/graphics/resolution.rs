@@ -0,0 +1,213 @@
+//! Dynamic resolution: the default pass renders at a reduced internal resolution,
+//! adjusted from the frame time, and gets upscaled into the window.
+//!
+//! The scaling itself is done by the backend, see
+//! `RenderingBackend::set_default_pass_scale`, so the application's passes stay the
+//! same. `DynamicResolution` measures each frame and picks the scale:
+//!
+//! ```ignore
+//! // in Stage::new
+//! let resolution = DynamicResolution::new(1.0 / 60.0);
+//!
+//! // in EventHandler::draw
+//! self.resolution.begin_frame(&mut *self.ctx);
+//! self.ctx.begin_default_pass(PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+//! ..
+//! self.ctx.end_render_pass();
+//! self.resolution.end_frame(&mut *self.ctx);
+//! self.ctx.commit_frame();
+//! ```
+//!
+//! The scale applies to `window::screen_size()`, in physical pixels: on a high-dpi
+//! screen `1.0 / window::dpi_scale()` renders at the logical resolution, usually
+//! a good `min_scale` there.
+//!
+//! CPU timings include waiting for the driver and, once the GPU is the bottleneck,
+//! for the GPU, so measure the frame's work only, without the vsync wait of
+//! `commit_frame`.
+
+use super::{overlay::GpuTimer, *};
+
+// scales are multiples of it, every change reallocates the target
+const STEP: f32 = 0.05;
+// frames to wait after a change before the next one, lets the average settle
+const COOLDOWN: u32 = 30;
+// weight of a new frame in the average
+const SMOOTHING: f64 = 0.1;
+// average frame time over the target scaling down
+const OVER_BUDGET: f64 = 1.05;
+// average frame time over the target scaling up
+const UNDER_BUDGET: f64 = 0.8;
+
+/// What `DynamicResolution` measures between `begin_frame` and `end_frame`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameTiming {
+    Cpu,
+    /// `ElapsedQuery`, CPU timings when it is not supported.
+    Gpu,
+}
+
+/// Scales the default pass to keep the frame time around a target.
+pub struct DynamicResolution {
+    target_frame_time: f64,
+    min_scale: f32,
+    max_scale: f32,
+    timing: FrameTiming,
+    scale: f32,
+    average: Option<f64>,
+    cooldown: u32,
+    // the backend can't scale the default pass
+    disabled: bool,
+    cpu_start: Option<f64>,
+    gpu: GpuTimer,
+    gpu_pending: bool,
+}
+
+impl DynamicResolution {
+    /// Aim for `target_frame_time` seconds per frame, GPU timings, scale in `0.5..=1.0`.
+    pub fn new(target_frame_time: f64) -> DynamicResolution {
+        DynamicResolution {
+            target_frame_time,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            timing: FrameTiming::Gpu,
+            scale: 1.0,
+            average: None,
+            cooldown: 0,
+            disabled: false,
+            cpu_start: None,
+            gpu: GpuTimer::default(),
+            gpu_pending: false,
+        }
+    }
+
+    pub fn with_timing(self, timing: FrameTiming) -> DynamicResolution {
+        DynamicResolution { timing, ..self }
+    }
+
+    /// Limits of the scale, clamped to `0.1..=1.0`. Starts at `max_scale`.
+    pub fn with_scale_range(self, min_scale: f32, max_scale: f32) -> DynamicResolution {
+        let max_scale = max_scale.clamp(0.1, 1.0);
+        let min_scale = min_scale.clamp(0.1, max_scale);
+        DynamicResolution {
+            min_scale,
+            max_scale,
+            scale: max_scale,
+            ..self
+        }
+    }
+
+    pub fn set_target_frame_time(&mut self, target_frame_time: f64) {
+        self.target_frame_time = target_frame_time;
+    }
+
+    /// Scale picked so far, `ctx.default_pass_scale()` once applied.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Start measuring a frame, before its first pass.
+    pub fn begin_frame(&mut self, ctx: &mut dyn RenderingBackend) {
+        self.cpu_start = Some(crate::date::now());
+        // ElapsedQuery is GL only
+        if self.timing == FrameTiming::Gpu && ctx.info().backend == Backend::OpenGl {
+            self.gpu_pending = self.gpu.begin();
+        }
+    }
+
+    /// Stop measuring the frame, before `commit_frame`, and apply a new scale
+    /// if needed. GPU timings come a few frames late.
+    pub fn end_frame(&mut self, ctx: &mut dyn RenderingBackend) {
+        let cpu = self
+            .cpu_start
+            .take()
+            .map(|start| crate::date::now() - start);
+        if self.gpu_pending {
+            self.gpu.end();
+            self.gpu_pending = false;
+        }
+        let gpu = self.timing == FrameTiming::Gpu
+            && ctx.info().backend == Backend::OpenGl
+            && self.gpu.supported == Some(true);
+        let frame_time = if gpu {
+            self.gpu.last_ms.take().map(|ms| ms as f64 / 1000.0)
+        } else {
+            cpu
+        };
+        if let Some(frame_time) = frame_time {
+            self.update(ctx, frame_time);
+        }
+    }
+
+    /// Feed a frame time measured by the application, in seconds,
+    /// instead of using `begin_frame` and `end_frame`.
+    pub fn update(&mut self, ctx: &mut dyn RenderingBackend, frame_time: f64) {
+        if self.disabled {
+            return;
+        }
+        let scale = self.next_scale(frame_time);
+        if scale != ctx.default_pass_scale() {
+            ctx.set_default_pass_scale(scale);
+            if ctx.default_pass_scale() != scale {
+                self.disabled = true;
+            }
+        }
+    }
+
+    fn next_scale(&mut self, frame_time: f64) -> f32 {
+        let average = match self.average {
+            Some(average) => average + (frame_time - average) * SMOOTHING,
+            None => frame_time,
+        };
+        self.average = Some(average);
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return self.scale;
+        }
+
+        let ratio = average / self.target_frame_time;
+        if ratio > UNDER_BUDGET && ratio < OVER_BUDGET {
+            return self.scale;
+        }
+        // the frame time follows the pixel count, the square of the scale
+        let wanted = self.scale * (1.0 / ratio).sqrt() as f32;
+        // going up a step at a time, overshooting is what drops frames
+        let wanted = wanted.min(self.scale + STEP);
+        let scale = ((wanted / STEP).round() * STEP).clamp(self.min_scale, self.max_scale);
+        if scale != self.scale {
+            self.scale = scale;
+            self.cooldown = COOLDOWN;
+        }
+        self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_scale() {
+        let mut resolution = DynamicResolution::new(0.01);
+
+        // within budget
+        assert_eq!(resolution.next_scale(0.009), 1.0);
+
+        // twice over budget: half the pixels
+        let mut resolution = DynamicResolution::new(0.01);
+        let scale = resolution.next_scale(0.02);
+        assert!((scale - 0.7).abs() < 1e-4, "{}", scale);
+
+        // waits for the average to settle, then goes back up a step
+        for _ in 0..COOLDOWN {
+            assert_eq!(resolution.next_scale(0.002), scale);
+        }
+        let up = resolution.next_scale(0.002);
+        assert!((up - (scale + STEP)).abs() < 1e-4, "{}", up);
+
+        // way over budget, clamped to min_scale
+        let mut resolution = DynamicResolution::new(0.01).with_scale_range(0.6, 0.9);
+        assert_eq!(resolution.scale(), 0.9);
+        assert_eq!(resolution.next_scale(1.0), 0.6);
+    }
+}
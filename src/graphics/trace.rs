@@ -0,0 +1,1757 @@
+//! Recording of `RenderingBackend` calls into a binary trace and replaying them later.
+//!
+//! ```ignore
+//! // record: wrap the context right after creation
+//! let ctx = window::new_rendering_backend();
+//! let mut ctx = Box::new(TraceRecorder::to_file(ctx, "bug.mqtrace").unwrap());
+//!
+//! // replay: feed the trace into any other context, frame by frame
+//! let mut replayer = TraceReplayer::from_file("bug.mqtrace").unwrap();
+//! while replayer.replay_frame(&mut *ctx, |ctx, pass| {
+//!     if let Some(pass) = pass {
+//!         let images = read_pass_images(ctx, pass);
+//!     }
+//! })? {}
+//! ```
+//!
+//! The trace is self-contained: shader sources, texture and buffer data and
+//! uniforms are all stored in the file.
+//! Calls that only query state (`texture_params`, `is_valid`, `texture_read_pixels`, ...)
+//! are not recorded.
+
+use super::*;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    io::{self, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"MQTRACE\0";
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    /// Not a miniquad trace file.
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// The trace ended in the middle of a command.
+    UnexpectedEof,
+    InvalidData(&'static str),
+    /// The trace refers to a resource that was never created or already deleted.
+    UnknownHandle(u32),
+    Shader(ShaderError),
+}
+
+impl From<io::Error> for TraceError {
+    fn from(e: io::Error) -> TraceError {
+        TraceError::Io(e)
+    }
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::BadMagic => write!(f, "Not a miniquad trace"),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported trace version {v}"),
+            Self::UnexpectedEof => write!(f, "Unexpected end of trace"),
+            Self::InvalidData(what) => write!(f, "Invalid {what} in trace"),
+            Self::UnknownHandle(id) => write!(f, "Unknown resource #{id} in trace"),
+            Self::Shader(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+mod op {
+    pub const NEW_SHADER: u8 = 1;
+    pub const NEW_TEXTURE: u8 = 2;
+    pub const RAW_TEXTURE: u8 = 3;
+    pub const TEXTURE_SET_MIN_FILTER: u8 = 4;
+    pub const TEXTURE_SET_MAG_FILTER: u8 = 5;
+    pub const TEXTURE_SET_WRAP: u8 = 6;
+    pub const TEXTURE_GENERATE_MIPMAPS: u8 = 7;
+    pub const TEXTURE_RESIZE: u8 = 8;
+    pub const TEXTURE_UPDATE_PART: u8 = 9;
+    pub const NEW_RENDER_PASS: u8 = 10;
+    pub const DELETE_RENDER_PASS: u8 = 11;
+    pub const NEW_PIPELINE: u8 = 12;
+    pub const APPLY_PIPELINE: u8 = 13;
+    pub const DELETE_PIPELINE: u8 = 14;
+    pub const NEW_BUFFER: u8 = 15;
+    pub const BUFFER_UPDATE: u8 = 16;
+    pub const DELETE_BUFFER: u8 = 17;
+    pub const DELETE_TEXTURE: u8 = 18;
+    pub const DELETE_SHADER: u8 = 19;
+    pub const APPLY_VIEWPORT: u8 = 20;
+    pub const APPLY_SCISSOR_RECT: u8 = 21;
+    pub const APPLY_BINDINGS: u8 = 22;
+    pub const APPLY_UNIFORMS: u8 = 23;
+    pub const CLEAR: u8 = 24;
+    pub const BEGIN_DEFAULT_PASS: u8 = 25;
+    pub const BEGIN_PASS: u8 = 26;
+    pub const END_RENDER_PASS: u8 = 27;
+    pub const COMMIT_FRAME: u8 = 28;
+    pub const DRAW: u8 = 29;
+    pub const SET_LABEL: u8 = 30;
+    pub const PUSH_DEBUG_GROUP: u8 = 31;
+    pub const POP_DEBUG_GROUP: u8 = 32;
+    pub const SET_DEFAULT_PASS_SAMPLE_COUNT: u8 = 33;
+    pub const TEXTURE_UPDATE_REGION: u8 = 34;
+    pub const SET_DEFAULT_PASS_SCALE: u8 = 35;
+    pub const SET_DEFAULT_PASS_UPSCALE_FILTER: u8 = 36;
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], TraceError> {
+        let end = self.pos.checked_add(len).ok_or(TraceError::UnexpectedEof)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(TraceError::UnexpectedEof)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn get<T: Wire>(&mut self) -> Result<T, TraceError> {
+        T::get(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+/// Little-endian binary encoding of the values passed to `RenderingBackend`.
+trait Wire: Sized {
+    fn put(&self, out: &mut Vec<u8>);
+    fn get(r: &mut Reader) -> Result<Self, TraceError>;
+}
+
+macro_rules! wire_num {
+    ($($t:ty),*) => {
+        $(
+            impl Wire for $t {
+                fn put(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+                fn get(r: &mut Reader) -> Result<Self, TraceError> {
+                    let bytes = r.bytes(std::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+wire_num!(u8, u32, i32, u64, f32);
+
+impl Wire for usize {
+    fn put(&self, out: &mut Vec<u8>) {
+        (*self as u64).put(out)
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        usize::try_from(r.get::<u64>()?).map_err(|_| TraceError::InvalidData("size"))
+    }
+}
+
+impl Wire for bool {
+    fn put(&self, out: &mut Vec<u8>) {
+        (*self as u8).put(out)
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        match r.get::<u8>()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(TraceError::InvalidData("bool")),
+        }
+    }
+}
+
+/// Length-prefixed blob, unlike `Vec<u8>` which would go byte by byte.
+struct Bytes(Vec<u8>);
+
+impl Wire for Bytes {
+    fn put(&self, out: &mut Vec<u8>) {
+        put_bytes(out, &self.0)
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        let len = r.get::<usize>()?;
+        Ok(Bytes(r.bytes(len)?.to_vec()))
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    bytes.len().put(out);
+    out.extend_from_slice(bytes);
+}
+
+impl Wire for String {
+    fn put(&self, out: &mut Vec<u8>) {
+        put_bytes(out, self.as_bytes())
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        String::from_utf8(r.get::<Bytes>()?.0).map_err(|_| TraceError::InvalidData("string"))
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_bytes(out, s.as_bytes())
+}
+
+impl<T: Wire> Wire for Option<T> {
+    fn put(&self, out: &mut Vec<u8>) {
+        match self {
+            None => false.put(out),
+            Some(value) => {
+                true.put(out);
+                value.put(out);
+            }
+        }
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        Ok(if r.get::<bool>()? {
+            Some(r.get()?)
+        } else {
+            None
+        })
+    }
+}
+
+impl<T: Wire> Wire for Vec<T> {
+    fn put(&self, out: &mut Vec<u8>) {
+        put_slice(out, self)
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        let len = r.get::<usize>()?;
+        // do not trust the length for preallocation, the trace may be truncated
+        let mut items = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            items.push(r.get()?);
+        }
+        Ok(items)
+    }
+}
+
+fn put_slice<T: Wire>(out: &mut Vec<u8>, items: &[T]) {
+    items.len().put(out);
+    for item in items {
+        item.put(out);
+    }
+}
+
+impl<A: Wire, B: Wire> Wire for (A, B) {
+    fn put(&self, out: &mut Vec<u8>) {
+        self.0.put(out);
+        self.1.put(out);
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        Ok((r.get()?, r.get()?))
+    }
+}
+
+impl<A: Wire, B: Wire, C: Wire, D: Wire> Wire for (A, B, C, D) {
+    fn put(&self, out: &mut Vec<u8>) {
+        self.0.put(out);
+        self.1.put(out);
+        self.2.put(out);
+        self.3.put(out);
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        Ok((r.get()?, r.get()?, r.get()?, r.get()?))
+    }
+}
+
+/// Fieldless enums are stored as their discriminant.
+macro_rules! wire_enum {
+    ($t:ident { $($variant:ident),* }) => {
+        impl Wire for $t {
+            fn put(&self, out: &mut Vec<u8>) {
+                (*self as u8).put(out)
+            }
+            fn get(r: &mut Reader) -> Result<Self, TraceError> {
+                let value = r.get::<u8>()?;
+                [$($t::$variant),*]
+                    .iter()
+                    .find(|v| **v as u8 == value)
+                    .copied()
+                    .ok_or(TraceError::InvalidData(stringify!($t)))
+            }
+        }
+    };
+}
+
+macro_rules! wire_struct {
+    ($t:ident { $($field:ident),* }) => {
+        impl Wire for $t {
+            fn put(&self, out: &mut Vec<u8>) {
+                $(self.$field.put(out);)*
+            }
+            fn get(r: &mut Reader) -> Result<Self, TraceError> {
+                Ok($t {
+                    $($field: r.get()?,)*
+                })
+            }
+        }
+    };
+}
+
+wire_enum!(UniformType {
+    Float1,
+    Float2,
+    Float3,
+    Float4,
+    Int1,
+    Int2,
+    Int3,
+    Int4,
+    Mat4
+});
+wire_enum!(VertexFormat {
+    Float1,
+    Float2,
+    Float3,
+    Float4,
+    Byte1,
+    Byte2,
+    Byte3,
+    Byte4,
+    Short1,
+    Short2,
+    Short3,
+    Short4,
+    Int1,
+    Int2,
+    Int3,
+    Int4,
+    Mat4
+});
+wire_enum!(VertexStep {
+    PerVertex,
+    PerInstance
+});
+wire_enum!(TextureFormat {
+    RGB8,
+    RGBA8,
+    RGBA16F,
+    Depth,
+    Depth32,
+    Alpha,
+    RG8,
+    SRGBA8,
+    Bc1,
+    Bc3,
+    Bc7,
+    Etc2Rgb8,
+    Etc2Rgba8,
+    Astc4x4
+});
+wire_enum!(TextureWrap {
+    Repeat,
+    Mirror,
+    Clamp
+});
+wire_enum!(FilterMode { Linear, Nearest });
+wire_enum!(MipmapFilterMode {
+    None,
+    Linear,
+    Nearest
+});
+wire_enum!(TextureAccess {
+    Static,
+    RenderTarget
+});
+wire_enum!(TextureKind {
+    Texture2D,
+    CubeMap,
+    Texture2DMultisample
+});
+wire_enum!(StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap
+});
+wire_enum!(CompareFunc {
+    Always,
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual
+});
+wire_enum!(CullFace {
+    Nothing,
+    Front,
+    Back
+});
+wire_enum!(FrontFaceOrder {
+    Clockwise,
+    CounterClockwise
+});
+wire_enum!(Comparison {
+    Never,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    Always
+});
+wire_enum!(Equation {
+    Add,
+    Subtract,
+    ReverseSubtract
+});
+wire_enum!(BlendValue {
+    SourceColor,
+    SourceAlpha,
+    DestinationColor,
+    DestinationAlpha
+});
+wire_enum!(PrimitiveType {
+    Triangles,
+    Lines,
+    Points
+});
+wire_enum!(BufferType {
+    VertexBuffer,
+    IndexBuffer
+});
+wire_enum!(BufferUsage {
+    Immutable,
+    Dynamic,
+    Stream
+});
+
+wire_struct!(UniformDesc {
+    name,
+    uniform_type,
+    array_count
+});
+wire_struct!(BufferLayout {
+    stride,
+    step_func,
+    step_rate
+});
+wire_struct!(BlendState {
+    equation,
+    sfactor,
+    dfactor
+});
+wire_struct!(StencilState { front, back });
+wire_struct!(StencilFaceState {
+    fail_op,
+    depth_fail_op,
+    pass_op,
+    test_func,
+    test_ref,
+    test_mask,
+    write_mask
+});
+wire_struct!(PipelineParams {
+    cull_face,
+    front_face_order,
+    depth_test,
+    depth_write,
+    depth_write_offset,
+    color_blend,
+    alpha_blend,
+    stencil_test,
+    color_write,
+    primitive_type
+});
+wire_struct!(TextureRegion {
+    level,
+    x,
+    y,
+    width,
+    height
+});
+wire_struct!(PixelLayout {
+    row_length,
+    alignment
+});
+wire_struct!(TextureParams {
+    kind,
+    format,
+    wrap,
+    min_filter,
+    mag_filter,
+    mipmap_filter,
+    width,
+    height,
+    allocate_mipmaps,
+    sample_count
+});
+
+impl Wire for BlendFactor {
+    fn put(&self, out: &mut Vec<u8>) {
+        match self {
+            BlendFactor::Zero => 0u8.put(out),
+            BlendFactor::One => 1u8.put(out),
+            BlendFactor::Value(value) => {
+                2u8.put(out);
+                value.put(out);
+            }
+            BlendFactor::OneMinusValue(value) => {
+                3u8.put(out);
+                value.put(out);
+            }
+            BlendFactor::SourceAlphaSaturate => 4u8.put(out),
+        }
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        Ok(match r.get::<u8>()? {
+            0 => BlendFactor::Zero,
+            1 => BlendFactor::One,
+            2 => BlendFactor::Value(r.get()?),
+            3 => BlendFactor::OneMinusValue(r.get()?),
+            4 => BlendFactor::SourceAlphaSaturate,
+            _ => return Err(TraceError::InvalidData("BlendFactor")),
+        })
+    }
+}
+
+impl Wire for VertexAttribute {
+    fn put(&self, out: &mut Vec<u8>) {
+        put_str(out, self.name);
+        self.format.put(out);
+        self.buffer_index.put(out);
+        self.gl_pass_as_float.put(out);
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        let name: String = r.get()?;
+        Ok(VertexAttribute {
+            // attribute names are &'static, a replay creates only a handful of pipelines
+            name: Box::leak(name.into_boxed_str()),
+            format: r.get()?,
+            buffer_index: r.get()?,
+            gl_pass_as_float: r.get()?,
+        })
+    }
+}
+
+impl Wire for PassAction {
+    fn put(&self, out: &mut Vec<u8>) {
+        match self {
+            PassAction::Nothing => false.put(out),
+            PassAction::Clear {
+                color,
+                depth,
+                stencil,
+            } => {
+                true.put(out);
+                color.put(out);
+                depth.put(out);
+                stencil.put(out);
+            }
+        }
+    }
+    fn get(r: &mut Reader) -> Result<Self, TraceError> {
+        Ok(if r.get::<bool>()? {
+            PassAction::Clear {
+                color: r.get()?,
+                depth: r.get()?,
+                stencil: r.get()?,
+            }
+        } else {
+            PassAction::Nothing
+        })
+    }
+}
+
+/// Wraps a context and writes every state-changing call into a trace.
+///
+/// Commands are buffered and written out on each `commit_frame`, so a crash
+/// loses at most the current frame.
+///
+/// Wrap the context before creating resources. Textures created earlier get an empty
+/// stand-in in the trace, other resources can't be recreated from the context: using
+/// one stops the recording, the trace keeps the frames written until then.
+pub struct TraceRecorder {
+    inner: Box<dyn RenderingBackend>,
+    // draw() takes &self
+    commands: RefCell<Vec<u8>>,
+    sink: Box<dyn Write>,
+    ids: HashMap<ResourceHandle, u32>,
+    next_id: u32,
+    failed: bool,
+    // dropped owned handles get deleted through the recorder, to be recorded
+    deferred: DeferredDeletions,
+}
+
+impl TraceRecorder {
+    pub fn new(inner: Box<dyn RenderingBackend>, sink: impl Write + 'static) -> TraceRecorder {
+        let mut header = MAGIC.to_vec();
+        VERSION.put(&mut header);
+        TraceRecorder {
+            inner,
+            commands: RefCell::new(header),
+            sink: Box::new(sink),
+            ids: HashMap::new(),
+            next_id: 0,
+            failed: false,
+            deferred: DeferredDeletions::default(),
+        }
+    }
+
+    pub fn to_file(
+        inner: Box<dyn RenderingBackend>,
+        path: impl AsRef<Path>,
+    ) -> io::Result<TraceRecorder> {
+        let file = std::fs::File::create(path)?;
+        Ok(TraceRecorder::new(inner, io::BufWriter::new(file)))
+    }
+
+    /// Write out the buffered commands.
+    /// Called automatically on `commit_frame` and when the recorder is dropped.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "trace recording stopped",
+            ));
+        }
+        let commands = std::mem::take(&mut *self.commands.borrow_mut());
+        self.sink.write_all(&commands)?;
+        self.sink.flush()
+    }
+
+    fn register(&mut self, handle: ResourceHandle) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ids.insert(handle, id);
+        id
+    }
+
+    fn id(&mut self, handle: ResourceHandle) -> u32 {
+        if let Some(id) = self.ids.get(&handle) {
+            return *id;
+        }
+        match handle {
+            // raw textures (and textures created before recording) can't be captured,
+            // an empty texture with the same params stands in for them in the trace
+            ResourceHandle::Texture(texture) => {
+                let params = self.inner.texture_params(texture);
+                let id = self.register(handle);
+                let mut out = self.commands.borrow_mut();
+                op::RAW_TEXTURE.put(&mut out);
+                id.put(&mut out);
+                params.put(&mut out);
+                id
+            }
+            // created before recording, there is no way to get their sources,
+            // layouts or attachments back from the context
+            _ => {
+                if !self.failed {
+                    eprintln!(
+                        "miniquad: trace recording stopped: {:?} was created before recording",
+                        handle
+                    );
+                    self.failed = true;
+                }
+                u32::MAX
+            }
+        }
+    }
+
+    fn texture_ids(&mut self, textures: &[TextureId]) -> Vec<u32> {
+        textures.iter().map(|t| self.id((*t).into())).collect()
+    }
+
+    fn record(&self, f: impl FnOnce(&mut Vec<u8>)) {
+        f(&mut self.commands.borrow_mut())
+    }
+}
+
+impl Drop for TraceRecorder {
+    fn drop(&mut self) {
+        if !self.failed {
+            let _ = self.flush();
+        }
+    }
+}
+
+fn put_buffer_source(out: &mut Vec<u8>, data: &BufferSource) {
+    match data {
+        BufferSource::Slice(arg) => {
+            true.put(out);
+            arg.element_size.put(out);
+            let bytes = unsafe { std::slice::from_raw_parts(arg.ptr as *const u8, arg.size) };
+            put_bytes(out, bytes);
+        }
+        BufferSource::Empty { size, element_size } => {
+            false.put(out);
+            element_size.put(out);
+            size.put(out);
+        }
+    }
+}
+
+impl RenderingBackend for TraceRecorder {
+    fn info(&self) -> ContextInfo {
+        self.inner.info()
+    }
+    fn resource_stats(&self) -> ResourceStats {
+        self.inner.resource_stats()
+    }
+    fn frame_stats(&self) -> FrameStats {
+        self.inner.frame_stats()
+    }
+    fn live_resources(&self) -> Vec<LiveResource> {
+        self.inner.live_resources()
+    }
+    fn set_leak_report(&mut self, enabled: bool) {
+        self.inner.set_leak_report(enabled)
+    }
+    fn set_deferred_deletion(&mut self, enabled: bool) {
+        self.inner.set_deferred_deletion(enabled)
+    }
+    fn deletion_queue(&mut self) -> DeletionQueue {
+        self.deferred.deletion_queue()
+    }
+    fn is_context_lost(&self) -> bool {
+        self.inner.is_context_lost()
+    }
+    fn restore_context(&mut self) {
+        self.inner.restore_context()
+    }
+    fn set_restore_resources(&mut self, enabled: bool) {
+        self.inner.set_restore_resources(enabled)
+    }
+    fn simulate_context_loss(&mut self) -> bool {
+        self.inner.simulate_context_loss()
+    }
+    fn set_label(&mut self, handle: ResourceHandle, label: &str) {
+        self.inner.set_label(handle, label);
+        let id = self.id(handle);
+        self.record(|out| {
+            op::SET_LABEL.put(out);
+            id.put(out);
+            put_str(out, label);
+        });
+    }
+    fn push_debug_group(&mut self, name: &str) {
+        self.inner.push_debug_group(name);
+        self.record(|out| {
+            op::PUSH_DEBUG_GROUP.put(out);
+            put_str(out, name);
+        });
+    }
+    fn pop_debug_group(&mut self) {
+        self.inner.pop_debug_group();
+        self.record(|out| op::POP_DEBUG_GROUP.put(out));
+    }
+    fn new_shader(
+        &mut self,
+        shader: ShaderSource,
+        meta: ShaderMeta,
+    ) -> Result<ShaderId, ShaderError> {
+        let mut out = vec![];
+        match shader {
+            ShaderSource::Glsl { vertex, fragment } => {
+                0u8.put(&mut out);
+                put_str(&mut out, vertex);
+                put_str(&mut out, fragment);
+            }
+            ShaderSource::Msl { program } => {
+                1u8.put(&mut out);
+                put_str(&mut out, program);
+            }
+        }
+        meta.uniforms.uniforms.put(&mut out);
+        meta.images.put(&mut out);
+
+        let shader = self.inner.new_shader(shader, meta)?;
+        let id = self.register(shader.into());
+        self.record(|commands| {
+            op::NEW_SHADER.put(commands);
+            id.put(commands);
+            commands.extend_from_slice(&out);
+        });
+        Ok(shader)
+    }
+    fn new_texture(
+        &mut self,
+        access: TextureAccess,
+        data: TextureSource,
+        params: TextureParams,
+    ) -> TextureId {
+        let mut out = vec![];
+        access.put(&mut out);
+        params.put(&mut out);
+        match &data {
+            TextureSource::Empty => 0u8.put(&mut out),
+            TextureSource::Bytes(bytes) => {
+                1u8.put(&mut out);
+                put_bytes(&mut out, bytes);
+            }
+            TextureSource::Array(faces) => {
+                2u8.put(&mut out);
+                faces.len().put(&mut out);
+                for levels in faces.iter() {
+                    levels.len().put(&mut out);
+                    for level in levels.iter() {
+                        put_bytes(&mut out, level);
+                    }
+                }
+            }
+            TextureSource::Mips(levels) => {
+                3u8.put(&mut out);
+                levels.len().put(&mut out);
+                for level in levels.iter() {
+                    put_bytes(&mut out, level);
+                }
+            }
+        }
+
+        let texture = self.inner.new_texture(access, data, params);
+        let id = self.register(texture.into());
+        self.record(|commands| {
+            op::NEW_TEXTURE.put(commands);
+            id.put(commands);
+            commands.extend_from_slice(&out);
+        });
+        texture
+    }
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        self.inner.texture_params(texture)
+    }
+    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
+        self.inner.texture_raw_id(texture)
+    }
+    fn texture_set_min_filter(
+        &mut self,
+        texture: TextureId,
+        filter: FilterMode,
+        mipmap_filter: MipmapFilterMode,
+    ) {
+        self.inner
+            .texture_set_min_filter(texture, filter, mipmap_filter);
+        let id = self.id(texture.into());
+        self.record(|out| {
+            op::TEXTURE_SET_MIN_FILTER.put(out);
+            id.put(out);
+            filter.put(out);
+            mipmap_filter.put(out);
+        });
+    }
+    fn texture_set_mag_filter(&mut self, texture: TextureId, filter: FilterMode) {
+        self.inner.texture_set_mag_filter(texture, filter);
+        let id = self.id(texture.into());
+        self.record(|out| {
+            op::TEXTURE_SET_MAG_FILTER.put(out);
+            id.put(out);
+            filter.put(out);
+        });
+    }
+    fn texture_set_wrap(&mut self, texture: TextureId, wrap_x: TextureWrap, wrap_y: TextureWrap) {
+        self.inner.texture_set_wrap(texture, wrap_x, wrap_y);
+        let id = self.id(texture.into());
+        self.record(|out| {
+            op::TEXTURE_SET_WRAP.put(out);
+            id.put(out);
+            wrap_x.put(out);
+            wrap_y.put(out);
+        });
+    }
+    fn texture_generate_mipmaps(&mut self, texture: TextureId) {
+        self.inner.texture_generate_mipmaps(texture);
+        let id = self.id(texture.into());
+        self.record(|out| {
+            op::TEXTURE_GENERATE_MIPMAPS.put(out);
+            id.put(out);
+        });
+    }
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        bytes: Option<&[u8]>,
+    ) {
+        self.inner.texture_resize(texture, width, height, bytes);
+        let id = self.id(texture.into());
+        self.record(|out| {
+            op::TEXTURE_RESIZE.put(out);
+            id.put(out);
+            width.put(out);
+            height.put(out);
+            bytes.map(|bytes| Bytes(bytes.to_vec())).put(out);
+        });
+    }
+    fn texture_read_pixels(&mut self, texture: TextureId, bytes: &mut [u8]) {
+        self.inner.texture_read_pixels(texture, bytes)
+    }
+    fn texture_update_part(
+        &mut self,
+        texture: TextureId,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        bytes: &[u8],
+    ) {
+        self.inner
+            .texture_update_part(texture, x_offset, y_offset, width, height, bytes);
+        let id = self.id(texture.into());
+        self.record(|out| {
+            op::TEXTURE_UPDATE_PART.put(out);
+            id.put(out);
+            (x_offset, y_offset, width, height).put(out);
+            put_bytes(out, bytes);
+        });
+    }
+    fn texture_update_region(
+        &mut self,
+        texture: TextureId,
+        region: TextureRegion,
+        layout: PixelLayout,
+        bytes: &[u8],
+    ) {
+        self.inner
+            .texture_update_region(texture, region, layout, bytes);
+        let id = self.id(texture.into());
+        let format = self.inner.texture_params(texture).format;
+        let size = layout.data_size(format, region.width as _, region.height as _);
+        self.record(|out| {
+            op::TEXTURE_UPDATE_REGION.put(out);
+            id.put(out);
+            region.put(out);
+            layout.put(out);
+            put_bytes(out, &bytes[..size]);
+        });
+    }
+    fn new_render_pass_mrt(
+        &mut self,
+        color_img: &[TextureId],
+        resolve_img: Option<&[TextureId]>,
+        depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        let color = self.texture_ids(color_img);
+        let resolve = resolve_img.map(|resolve| self.texture_ids(resolve));
+        let depth = depth_img.map(|depth| self.id(depth.into()));
+
+        let pass = self
+            .inner
+            .new_render_pass_mrt(color_img, resolve_img, depth_img);
+        let id = self.register(pass.into());
+        self.record(|out| {
+            op::NEW_RENDER_PASS.put(out);
+            id.put(out);
+            color.put(out);
+            resolve.put(out);
+            depth.put(out);
+        });
+        pass
+    }
+    fn render_pass_color_attachments(&self, render_pass: RenderPass) -> &[TextureId] {
+        self.inner.render_pass_color_attachments(render_pass)
+    }
+    fn delete_render_pass(&mut self, render_pass: RenderPass) {
+        let id = self.id(render_pass.into());
+        self.inner.delete_render_pass(render_pass);
+        self.ids.remove(&render_pass.into());
+        self.record(|out| {
+            op::DELETE_RENDER_PASS.put(out);
+            id.put(out);
+        });
+    }
+    fn new_pipeline(
+        &mut self,
+        buffer_layout: &[BufferLayout],
+        attributes: &[VertexAttribute],
+        shader: ShaderId,
+        params: PipelineParams,
+    ) -> Pipeline {
+        let shader_id = self.id(shader.into());
+        let pipeline = self
+            .inner
+            .new_pipeline(buffer_layout, attributes, shader, params);
+        let id = self.register(pipeline.into());
+        self.record(|out| {
+            op::NEW_PIPELINE.put(out);
+            id.put(out);
+            put_slice(out, buffer_layout);
+            put_slice(out, attributes);
+            shader_id.put(out);
+            params.put(out);
+        });
+        pipeline
+    }
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        self.inner.apply_pipeline(pipeline);
+        let id = self.id((*pipeline).into());
+        self.record(|out| {
+            op::APPLY_PIPELINE.put(out);
+            id.put(out);
+        });
+    }
+    fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        let id = self.id(pipeline.into());
+        self.inner.delete_pipeline(pipeline);
+        self.ids.remove(&pipeline.into());
+        self.record(|out| {
+            op::DELETE_PIPELINE.put(out);
+            id.put(out);
+        });
+    }
+    fn new_buffer(
+        &mut self,
+        type_: BufferType,
+        usage: BufferUsage,
+        data: BufferSource,
+    ) -> BufferId {
+        let mut out = vec![];
+        type_.put(&mut out);
+        usage.put(&mut out);
+        put_buffer_source(&mut out, &data);
+
+        let buffer = self.inner.new_buffer(type_, usage, data);
+        let id = self.register(buffer.into());
+        self.record(|commands| {
+            op::NEW_BUFFER.put(commands);
+            id.put(commands);
+            commands.extend_from_slice(&out);
+        });
+        buffer
+    }
+    fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
+        let id = self.id(buffer.into());
+        self.record(|out| {
+            op::BUFFER_UPDATE.put(out);
+            id.put(out);
+            put_buffer_source(out, &data);
+        });
+        self.inner.buffer_update(buffer, data);
+    }
+    fn buffer_size(&mut self, buffer: BufferId) -> usize {
+        self.inner.buffer_size(buffer)
+    }
+    fn publish_texture(&mut self, upload: UploadedTexture) -> Result<TextureId, UploadedTexture> {
+        // recorded as a raw texture on first use
+        self.inner.publish_texture(upload)
+    }
+    fn publish_buffer(&mut self, upload: UploadedBuffer) -> Result<BufferId, UploadedBuffer> {
+        let (type_, size, element_size) =
+            (upload.buffer_type(), upload.size(), upload.element_size());
+        let buffer = self.inner.publish_buffer(upload)?;
+        // the contents were uploaded elsewhere, an empty buffer stands in for it
+        let id = self.register(buffer.into());
+        self.record(|out| {
+            op::NEW_BUFFER.put(out);
+            id.put(out);
+            type_.put(out);
+            BufferUsage::Stream.put(out);
+            put_buffer_source(out, &BufferSource::Empty { size, element_size });
+        });
+        Ok(buffer)
+    }
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        let id = self.id(buffer.into());
+        self.inner.delete_buffer(buffer);
+        self.ids.remove(&buffer.into());
+        self.record(|out| {
+            op::DELETE_BUFFER.put(out);
+            id.put(out);
+        });
+    }
+    fn delete_texture(&mut self, texture: TextureId) {
+        let id = self.id(texture.into());
+        self.inner.delete_texture(texture);
+        self.ids.remove(&texture.into());
+        self.record(|out| {
+            op::DELETE_TEXTURE.put(out);
+            id.put(out);
+        });
+    }
+    fn delete_shader(&mut self, program: ShaderId) {
+        let id = self.id(program.into());
+        self.inner.delete_shader(program);
+        self.ids.remove(&program.into());
+        self.record(|out| {
+            op::DELETE_SHADER.put(out);
+            id.put(out);
+        });
+    }
+    fn is_valid(&self, handle: ResourceHandle) -> bool {
+        self.inner.is_valid(handle)
+    }
+    fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.inner.apply_viewport(x, y, w, h);
+        self.record(|out| {
+            op::APPLY_VIEWPORT.put(out);
+            (x, y, w, h).put(out);
+        });
+    }
+    fn apply_scissor_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.inner.apply_scissor_rect(x, y, w, h);
+        self.record(|out| {
+            op::APPLY_SCISSOR_RECT.put(out);
+            (x, y, w, h).put(out);
+        });
+    }
+    fn apply_bindings_from_slice(
+        &mut self,
+        vertex_buffers: &[BufferId],
+        index_buffer: BufferId,
+        textures: &[TextureId],
+    ) {
+        self.inner
+            .apply_bindings_from_slice(vertex_buffers, index_buffer, textures);
+        let vertex_ids: Vec<u32> = vertex_buffers
+            .iter()
+            .map(|b| self.id((*b).into()))
+            .collect();
+        let index_id = self.id(index_buffer.into());
+        let texture_ids = self.texture_ids(textures);
+        self.record(|out| {
+            op::APPLY_BINDINGS.put(out);
+            vertex_ids.put(out);
+            index_id.put(out);
+            texture_ids.put(out);
+        });
+    }
+    // the trait signature is not unsafe, the pointer comes from `UniformsSource`
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn apply_uniforms_from_bytes(&mut self, uniform_ptr: *const u8, size: usize) {
+        self.inner.apply_uniforms_from_bytes(uniform_ptr, size);
+        let bytes = unsafe { std::slice::from_raw_parts(uniform_ptr, size) };
+        self.record(|out| {
+            op::APPLY_UNIFORMS.put(out);
+            put_bytes(out, bytes);
+        });
+    }
+    fn clear(
+        &mut self,
+        color: Option<(f32, f32, f32, f32)>,
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) {
+        self.inner.clear(color, depth, stencil);
+        self.record(|out| {
+            op::CLEAR.put(out);
+            color.put(out);
+            depth.put(out);
+            stencil.put(out);
+        });
+    }
+    fn begin_default_pass(&mut self, action: PassAction) {
+        self.record(|out| {
+            op::BEGIN_DEFAULT_PASS.put(out);
+            action.put(out);
+        });
+        self.inner.begin_default_pass(action);
+    }
+    fn begin_pass(&mut self, pass: Option<RenderPass>, action: PassAction) {
+        let id = pass.map(|pass| self.id(pass.into()));
+        self.record(|out| {
+            op::BEGIN_PASS.put(out);
+            id.put(out);
+            action.put(out);
+        });
+        self.inner.begin_pass(pass, action);
+    }
+    fn end_render_pass(&mut self) {
+        self.inner.end_render_pass();
+        self.record(|out| op::END_RENDER_PASS.put(out));
+    }
+    fn set_default_pass_sample_count(&mut self, sample_count: i32) {
+        self.record(|out| {
+            op::SET_DEFAULT_PASS_SAMPLE_COUNT.put(out);
+            sample_count.put(out);
+        });
+        self.inner.set_default_pass_sample_count(sample_count);
+    }
+    fn default_pass_sample_count(&self) -> i32 {
+        self.inner.default_pass_sample_count()
+    }
+    fn set_default_pass_scale(&mut self, scale: f32) {
+        self.record(|out| {
+            op::SET_DEFAULT_PASS_SCALE.put(out);
+            scale.put(out);
+        });
+        self.inner.set_default_pass_scale(scale);
+    }
+    fn default_pass_scale(&self) -> f32 {
+        self.inner.default_pass_scale()
+    }
+    fn set_default_pass_upscale_filter(&mut self, filter: FilterMode) {
+        self.record(|out| {
+            op::SET_DEFAULT_PASS_UPSCALE_FILTER.put(out);
+            filter.put(out);
+        });
+        self.inner.set_default_pass_upscale_filter(filter);
+    }
+    fn capture_frame(&mut self) -> Option<capture::CapturedFrame> {
+        self.inner.capture_frame()
+    }
+
+    fn commit_frame(&mut self) {
+        self.inner.commit_frame();
+        self.record(|out| op::COMMIT_FRAME.put(out));
+        if !self.failed {
+            if let Err(e) = self.flush() {
+                eprintln!("miniquad: trace recording stopped: {e}");
+                self.failed = true;
+            }
+        }
+        // the frame may refer to resources the trace doesn't have, drop it
+        if self.failed {
+            self.commands.borrow_mut().clear();
+        }
+        // recorded at the start of the next frame
+        DeferredDeletions::flush(self, |recorder| &mut recorder.deferred);
+    }
+    fn draw(&self, base_element: i32, num_elements: i32, num_instances: i32) {
+        self.inner.draw(base_element, num_elements, num_instances);
+        self.record(|out| {
+            op::DRAW.put(out);
+            base_element.put(out);
+            num_elements.put(out);
+            num_instances.put(out);
+        });
+    }
+}
+
+/// Plays a trace recorded by `TraceRecorder` back into a context.
+pub struct TraceReplayer {
+    data: Vec<u8>,
+    pos: usize,
+    handles: Vec<Option<ResourceHandle>>,
+    // render pass being drawn, reported to the callback on end_render_pass
+    current_pass: Option<RenderPass>,
+}
+
+impl TraceReplayer {
+    pub fn new(data: Vec<u8>) -> Result<TraceReplayer, TraceError> {
+        let mut r = Reader {
+            data: &data,
+            pos: 0,
+        };
+        if r.bytes(MAGIC.len()).map_err(|_| TraceError::BadMagic)? != MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+        let version: u32 = r.get()?;
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+        let pos = r.pos;
+        Ok(TraceReplayer {
+            data,
+            pos,
+            handles: vec![],
+            current_pass: None,
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<TraceReplayer, TraceError> {
+        TraceReplayer::new(std::fs::read(path)?)
+    }
+
+    /// True when all the commands were replayed.
+    pub fn is_finished(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Replay commands up to and including the next `commit_frame`.
+    ///
+    /// `on_pass_end` is called right after each `end_render_pass`, with `None`
+    /// for the default pass; it is the place to read back attachments,
+    /// see [`read_pass_images`].
+    /// Returns `false` once the trace is over.
+    pub fn replay_frame(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        mut on_pass_end: impl FnMut(&mut dyn RenderingBackend, Option<RenderPass>),
+    ) -> Result<bool, TraceError> {
+        let data = std::mem::take(&mut self.data);
+        let mut r = Reader {
+            data: &data,
+            pos: self.pos,
+        };
+        let res = loop {
+            if r.is_empty() {
+                break Ok(false);
+            }
+            match self.replay_command(&mut r, ctx, &mut on_pass_end) {
+                Ok(true) => break Ok(true),
+                Ok(false) => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.pos = r.pos;
+        self.data = data;
+        res
+    }
+
+    /// Replay the whole remaining trace.
+    pub fn replay_all(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        mut on_pass_end: impl FnMut(&mut dyn RenderingBackend, Option<RenderPass>),
+    ) -> Result<(), TraceError> {
+        while self.replay_frame(ctx, &mut on_pass_end)? {}
+        Ok(())
+    }
+
+    fn add(&mut self, id: u32, handle: ResourceHandle) {
+        let id = id as usize;
+        if self.handles.len() <= id {
+            self.handles.resize(id + 1, None);
+        }
+        self.handles[id] = Some(handle);
+    }
+
+    fn handle(&self, id: u32) -> Result<ResourceHandle, TraceError> {
+        self.handles
+            .get(id as usize)
+            .copied()
+            .flatten()
+            .ok_or(TraceError::UnknownHandle(id))
+    }
+
+    fn remove(&mut self, id: u32) {
+        if let Some(handle) = self.handles.get_mut(id as usize) {
+            *handle = None;
+        }
+    }
+
+    fn texture(&self, id: u32) -> Result<TextureId, TraceError> {
+        match self.handle(id)? {
+            ResourceHandle::Texture(texture) => Ok(texture),
+            _ => Err(TraceError::InvalidData("texture handle")),
+        }
+    }
+
+    fn textures(&self, ids: &[u32]) -> Result<Vec<TextureId>, TraceError> {
+        ids.iter().map(|id| self.texture(*id)).collect()
+    }
+
+    fn buffer(&self, id: u32) -> Result<BufferId, TraceError> {
+        match self.handle(id)? {
+            ResourceHandle::Buffer(buffer) => Ok(buffer),
+            _ => Err(TraceError::InvalidData("buffer handle")),
+        }
+    }
+
+    fn shader(&self, id: u32) -> Result<ShaderId, TraceError> {
+        match self.handle(id)? {
+            ResourceHandle::Shader(shader) => Ok(shader),
+            _ => Err(TraceError::InvalidData("shader handle")),
+        }
+    }
+
+    fn pipeline(&self, id: u32) -> Result<Pipeline, TraceError> {
+        match self.handle(id)? {
+            ResourceHandle::Pipeline(pipeline) => Ok(pipeline),
+            _ => Err(TraceError::InvalidData("pipeline handle")),
+        }
+    }
+
+    fn render_pass(&self, id: u32) -> Result<RenderPass, TraceError> {
+        match self.handle(id)? {
+            ResourceHandle::RenderPass(pass) => Ok(pass),
+            _ => Err(TraceError::InvalidData("render pass handle")),
+        }
+    }
+
+    /// Returns true on commit_frame.
+    fn replay_command(
+        &mut self,
+        r: &mut Reader,
+        ctx: &mut dyn RenderingBackend,
+        on_pass_end: &mut impl FnMut(&mut dyn RenderingBackend, Option<RenderPass>),
+    ) -> Result<bool, TraceError> {
+        match r.get::<u8>()? {
+            op::NEW_SHADER => {
+                let id = r.get()?;
+                let (vertex, fragment, msl): (String, String, bool) = match r.get::<u8>()? {
+                    0 => (r.get()?, r.get()?, false),
+                    1 => (r.get()?, String::new(), true),
+                    _ => return Err(TraceError::InvalidData("shader source")),
+                };
+                let source = if msl {
+                    ShaderSource::Msl { program: &vertex }
+                } else {
+                    ShaderSource::Glsl {
+                        vertex: &vertex,
+                        fragment: &fragment,
+                    }
+                };
+                let meta = ShaderMeta {
+                    uniforms: UniformBlockLayout { uniforms: r.get()? },
+                    images: r.get()?,
+                };
+                let shader = ctx.new_shader(source, meta).map_err(TraceError::Shader)?;
+                self.add(id, shader.into());
+            }
+            op::NEW_TEXTURE => {
+                let id = r.get()?;
+                let access = r.get()?;
+                let params = r.get()?;
+                let texture = match r.get::<u8>()? {
+                    0 => ctx.new_texture(access, TextureSource::Empty, params),
+                    1 => {
+                        let bytes = r.get::<Bytes>()?.0;
+                        ctx.new_texture(access, TextureSource::Bytes(&bytes), params)
+                    }
+                    2 => {
+                        let faces: Vec<Vec<Bytes>> = r.get()?;
+                        let levels: Vec<Vec<&[u8]>> = faces
+                            .iter()
+                            .map(|face| face.iter().map(|level| &level.0[..]).collect())
+                            .collect();
+                        let faces: Vec<&[&[u8]]> = levels.iter().map(|face| &face[..]).collect();
+                        ctx.new_texture(access, TextureSource::Array(&faces), params)
+                    }
+                    3 => {
+                        let levels: Vec<Bytes> = r.get()?;
+                        let levels: Vec<&[u8]> = levels.iter().map(|level| &level.0[..]).collect();
+                        ctx.new_texture(access, TextureSource::Mips(&levels), params)
+                    }
+                    _ => return Err(TraceError::InvalidData("texture source")),
+                };
+                self.add(id, texture.into());
+            }
+            op::RAW_TEXTURE => {
+                let id = r.get()?;
+                let params = r.get()?;
+                let texture = ctx.new_texture(TextureAccess::Static, TextureSource::Empty, params);
+                self.add(id, texture.into());
+            }
+            op::TEXTURE_SET_MIN_FILTER => {
+                let texture = self.texture(r.get()?)?;
+                ctx.texture_set_min_filter(texture, r.get()?, r.get()?);
+            }
+            op::TEXTURE_SET_MAG_FILTER => {
+                let texture = self.texture(r.get()?)?;
+                ctx.texture_set_mag_filter(texture, r.get()?);
+            }
+            op::TEXTURE_SET_WRAP => {
+                let texture = self.texture(r.get()?)?;
+                ctx.texture_set_wrap(texture, r.get()?, r.get()?);
+            }
+            op::TEXTURE_GENERATE_MIPMAPS => {
+                let texture = self.texture(r.get()?)?;
+                ctx.texture_generate_mipmaps(texture);
+            }
+            op::TEXTURE_RESIZE => {
+                let texture = self.texture(r.get()?)?;
+                let (width, height) = r.get()?;
+                let bytes: Option<Bytes> = r.get()?;
+                ctx.texture_resize(texture, width, height, bytes.as_ref().map(|b| &b.0[..]));
+            }
+            op::TEXTURE_UPDATE_PART => {
+                let texture = self.texture(r.get()?)?;
+                let (x, y, w, h) = r.get()?;
+                let bytes = r.get::<Bytes>()?.0;
+                ctx.texture_update_part(texture, x, y, w, h, &bytes);
+            }
+            op::TEXTURE_UPDATE_REGION => {
+                let texture = self.texture(r.get()?)?;
+                let region = r.get()?;
+                let layout = r.get()?;
+                let bytes = r.get::<Bytes>()?.0;
+                ctx.texture_update_region(texture, region, layout, &bytes);
+            }
+            op::NEW_RENDER_PASS => {
+                let id = r.get()?;
+                let color = self.textures(&r.get::<Vec<u32>>()?)?;
+                let resolve = match r.get::<Option<Vec<u32>>>()? {
+                    Some(ids) => Some(self.textures(&ids)?),
+                    None => None,
+                };
+                let depth = match r.get::<Option<u32>>()? {
+                    Some(id) => Some(self.texture(id)?),
+                    None => None,
+                };
+                let pass = ctx.new_render_pass_mrt(&color, resolve.as_deref(), depth);
+                self.add(id, pass.into());
+            }
+            op::DELETE_RENDER_PASS => {
+                let id = r.get()?;
+                ctx.delete_render_pass(self.render_pass(id)?);
+                self.remove(id);
+            }
+            op::NEW_PIPELINE => {
+                let id = r.get()?;
+                let layout: Vec<BufferLayout> = r.get()?;
+                let attributes: Vec<VertexAttribute> = r.get()?;
+                let shader = self.shader(r.get()?)?;
+                let pipeline = ctx.new_pipeline(&layout, &attributes, shader, r.get()?);
+                self.add(id, pipeline.into());
+            }
+            op::APPLY_PIPELINE => {
+                let pipeline = self.pipeline(r.get()?)?;
+                ctx.apply_pipeline(&pipeline);
+            }
+            op::DELETE_PIPELINE => {
+                let id = r.get()?;
+                ctx.delete_pipeline(self.pipeline(id)?);
+                self.remove(id);
+            }
+            op::NEW_BUFFER => {
+                let id = r.get()?;
+                let type_ = r.get()?;
+                let usage = r.get()?;
+                let buffer = with_buffer_source(r, |data| ctx.new_buffer(type_, usage, data))?;
+                self.add(id, buffer.into());
+            }
+            op::BUFFER_UPDATE => {
+                let buffer = self.buffer(r.get()?)?;
+                with_buffer_source(r, |data| ctx.buffer_update(buffer, data))?;
+            }
+            op::DELETE_BUFFER => {
+                let id = r.get()?;
+                ctx.delete_buffer(self.buffer(id)?);
+                self.remove(id);
+            }
+            op::DELETE_TEXTURE => {
+                let id = r.get()?;
+                ctx.delete_texture(self.texture(id)?);
+                self.remove(id);
+            }
+            op::DELETE_SHADER => {
+                let id = r.get()?;
+                ctx.delete_shader(self.shader(id)?);
+                self.remove(id);
+            }
+            op::APPLY_VIEWPORT => {
+                let (x, y, w, h) = r.get()?;
+                ctx.apply_viewport(x, y, w, h);
+            }
+            op::APPLY_SCISSOR_RECT => {
+                let (x, y, w, h) = r.get()?;
+                ctx.apply_scissor_rect(x, y, w, h);
+            }
+            op::APPLY_BINDINGS => {
+                let vertex_buffers = r
+                    .get::<Vec<u32>>()?
+                    .into_iter()
+                    .map(|id| self.buffer(id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let index_buffer = self.buffer(r.get()?)?;
+                let textures = self.textures(&r.get::<Vec<u32>>()?)?;
+                ctx.apply_bindings_from_slice(&vertex_buffers, index_buffer, &textures);
+            }
+            op::APPLY_UNIFORMS => {
+                let bytes = r.get::<Bytes>()?.0;
+                ctx.apply_uniforms_from_bytes(bytes.as_ptr(), bytes.len());
+            }
+            op::CLEAR => {
+                ctx.clear(r.get()?, r.get()?, r.get()?);
+            }
+            op::BEGIN_DEFAULT_PASS => {
+                self.current_pass = None;
+                ctx.begin_default_pass(r.get()?);
+            }
+            op::BEGIN_PASS => {
+                let pass = match r.get::<Option<u32>>()? {
+                    Some(id) => Some(self.render_pass(id)?),
+                    None => None,
+                };
+                self.current_pass = pass;
+                ctx.begin_pass(pass, r.get()?);
+            }
+            op::END_RENDER_PASS => {
+                ctx.end_render_pass();
+                on_pass_end(ctx, self.current_pass.take());
+            }
+            op::SET_DEFAULT_PASS_SAMPLE_COUNT => {
+                ctx.set_default_pass_sample_count(r.get()?);
+            }
+            op::SET_DEFAULT_PASS_SCALE => {
+                ctx.set_default_pass_scale(r.get()?);
+            }
+            op::SET_DEFAULT_PASS_UPSCALE_FILTER => {
+                ctx.set_default_pass_upscale_filter(r.get()?);
+            }
+            op::COMMIT_FRAME => {
+                ctx.commit_frame();
+                return Ok(true);
+            }
+            op::DRAW => {
+                ctx.draw(r.get()?, r.get()?, r.get()?);
+            }
+            op::SET_LABEL => {
+                let handle = self.handle(r.get()?)?;
+                let label: String = r.get()?;
+                ctx.set_label(handle, &label);
+            }
+            op::PUSH_DEBUG_GROUP => {
+                let name: String = r.get()?;
+                ctx.push_debug_group(&name);
+            }
+            op::POP_DEBUG_GROUP => ctx.pop_debug_group(),
+            _ => return Err(TraceError::InvalidData("command")),
+        }
+        Ok(false)
+    }
+}
+
+fn with_buffer_source<T>(
+    r: &mut Reader,
+    f: impl FnOnce(BufferSource) -> T,
+) -> Result<T, TraceError> {
+    let is_slice = r.get::<bool>()?;
+    let element_size = r.get()?;
+    Ok(if is_slice {
+        let bytes = r.get::<Bytes>()?.0;
+        f(unsafe { BufferSource::pointer(bytes.as_ptr(), bytes.len(), element_size) })
+    } else {
+        f(BufferSource::Empty {
+            size: r.get()?,
+            element_size,
+        })
+    })
+}
+
+/// Read back the color attachments of a render pass, in the attachment order.
+///
+/// Multisampled attachments can't be read directly, read the resolve targets instead.
+pub fn read_pass_images(
+    ctx: &mut dyn RenderingBackend,
+    pass: RenderPass,
+) -> Vec<(TextureParams, Vec<u8>)> {
+    let attachments = ctx.render_pass_color_attachments(pass).to_vec();
+    attachments
+        .into_iter()
+        .map(|texture| {
+            let params = ctx.texture_params(texture);
+            let mut bytes = vec![0; params.format.size(params.width, params.height) as usize];
+            ctx.texture_read_pixels(texture, &mut bytes);
+            (params, bytes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_roundtrip() {
+        let params = PipelineParams {
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            depth_write_offset: Some((1.0, 2.0)),
+            color_write: (true, false, true, false),
+            ..Default::default()
+        };
+        let texture = TextureParams {
+            format: TextureFormat::Alpha,
+            width: 17,
+            height: 3,
+            ..Default::default()
+        };
+        let mut out = vec![];
+        params.put(&mut out);
+        texture.put(&mut out);
+        PassAction::clear_color(0.1, 0.2, 0.3, 1.0).put(&mut out);
+
+        let mut r = Reader { data: &out, pos: 0 };
+        assert_eq!(r.get::<PipelineParams>().unwrap(), params);
+        let decoded: TextureParams = r.get().unwrap();
+        assert_eq!(
+            (decoded.format, decoded.width, decoded.height),
+            (TextureFormat::Alpha, 17, 3)
+        );
+        match r.get::<PassAction>().unwrap() {
+            PassAction::Clear { color, depth, .. } => {
+                assert_eq!(color, Some((0.1, 0.2, 0.3, 1.0)));
+                assert_eq!(depth, Some(1.0));
+            }
+            PassAction::Nothing => panic!("PassAction::Clear expected"),
+        }
+        assert!(r.is_empty());
+        assert!(matches!(r.get::<u32>(), Err(TraceError::UnexpectedEof)));
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedSink(std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn draw_frame(ctx: &mut dyn RenderingBackend, buffer: BufferId, texture: TextureId) {
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: "",
+                    fragment: "",
+                },
+                ShaderMeta {
+                    images: vec![],
+                    uniforms: UniformBlockLayout { uniforms: vec![] },
+                },
+            )
+            .unwrap();
+        let pipeline = ctx.new_pipeline(&[], &[], shader, PipelineParams::default());
+        ctx.begin_default_pass(PassAction::Nothing);
+        ctx.apply_pipeline(&pipeline);
+        ctx.apply_bindings_from_slice(&[], buffer, &[texture]);
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+        ctx.commit_frame();
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let mut inner = NullContext::new();
+        // a stand-in gets recorded for it
+        let early_texture = inner.new_texture_from_rgba8(2, 2, &[0; 16]);
+        // can't be recorded, stops the recording once used
+        let early_buffer = inner.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&[0u16; 6]),
+        );
+        let sink = SharedSink::default();
+        let mut recorder = TraceRecorder::new(Box::new(inner), sink.clone());
+
+        let buffer = recorder.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&[0u16; 6]),
+        );
+        draw_frame(&mut recorder, buffer, early_texture);
+        draw_frame(&mut recorder, buffer, early_texture);
+        draw_frame(&mut recorder, early_buffer, early_texture);
+        assert!(recorder.flush().is_err());
+
+        let mut replayer = TraceReplayer::new(sink.0.borrow().clone()).unwrap();
+        let mut ctx = NullContext::new();
+        replayer.replay_all(&mut ctx, |_, _| {}).unwrap();
+        assert!(replayer.is_finished());
+        assert_eq!(ctx.calls().draws, 2);
+        // the stand-in for early_texture
+        assert_eq!(ctx.resource_stats().textures.bytes, 16);
+    }
+}
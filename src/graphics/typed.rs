@@ -0,0 +1,347 @@
+//! Buffers and pipelines that carry their vertex and uniform types.
+//!
+//! `BufferSource::slice` and `UniformsSource::table` accept anything, so binding a
+//! buffer of the wrong vertex type or passing the wrong uniforms struct only shows up
+//! as garbage on screen. The wrappers here move that mistake to compile time and
+//! lower to the usual `RenderingBackend` calls.
+//!
+//! ```ignore
+//! let pipeline = TypedPipeline::<Vertex, Uniforms>::new(&mut *ctx, shader, PipelineParams::default());
+//! let vertices = TypedBuffer::vertices(&mut *ctx, BufferUsage::Immutable, &vertices);
+//! let indices = TypedBuffer::indices(&mut *ctx, BufferUsage::Immutable, &[0u16, 1, 2]);
+//! let bindings = TypedBindings::new(vertices, indices, vec![texture]);
+//!
+//! pipeline.apply(&mut *ctx);
+//! pipeline.apply_bindings(&mut *ctx, &bindings);
+//! // does not compile with bindings of another vertex type, or other uniforms
+//! pipeline.apply_uniforms(&mut *ctx, &Uniforms { offset: [0.5, 0.5] });
+//! ```
+//!
+//! Typed pipelines read a single vertex buffer, use the untyped API for instancing.
+
+use super::*;
+use std::marker::PhantomData;
+
+/// Index buffer element: `u8`, `u16` or `u32`.
+pub trait IndexType: Copy {}
+impl IndexType for u8 {}
+impl IndexType for u16 {}
+impl IndexType for u32 {}
+
+/// A `BufferId` holding elements of type `T`.
+pub struct TypedBuffer<T> {
+    id: BufferId,
+    len: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// derives would require T: Clone
+impl<T> Clone for TypedBuffer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for TypedBuffer<T> {}
+
+impl<T> std::fmt::Debug for TypedBuffer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TypedBuffer")
+            .field("id", &self.id)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T> TypedBuffer<T> {
+    /// Vertex buffer filled with `data`.
+    pub fn vertices(
+        ctx: &mut dyn RenderingBackend,
+        usage: BufferUsage,
+        data: &[T],
+    ) -> TypedBuffer<T> {
+        let id = ctx.new_buffer(BufferType::VertexBuffer, usage, BufferSource::slice(data));
+        Self::from_id(id, data.len())
+    }
+
+    /// Vertex buffer with room for `len` elements, filled later with `update`.
+    pub fn empty_vertices(
+        ctx: &mut dyn RenderingBackend,
+        usage: BufferUsage,
+        len: usize,
+    ) -> TypedBuffer<T> {
+        let id = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            usage,
+            BufferSource::empty::<T>(len),
+        );
+        Self::from_id(id, len)
+    }
+
+    /// Wrap an untyped buffer of `len` elements of type `T`, nothing checks it.
+    pub fn from_id(id: BufferId, len: usize) -> TypedBuffer<T> {
+        TypedBuffer {
+            id,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> BufferId {
+        self.id
+    }
+
+    /// Number of elements the buffer was created with.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Overwrite the first `data.len()` elements.
+    pub fn update(&self, ctx: &mut dyn RenderingBackend, data: &[T]) {
+        assert!(
+            data.len() <= self.len,
+            "more elements than the buffer holds"
+        );
+        ctx.buffer_update(self.id, BufferSource::slice(data));
+    }
+
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_buffer(self.id);
+    }
+}
+
+impl<I: IndexType> TypedBuffer<I> {
+    /// Index buffer filled with `data`.
+    pub fn indices(
+        ctx: &mut dyn RenderingBackend,
+        usage: BufferUsage,
+        data: &[I],
+    ) -> TypedBuffer<I> {
+        let id = ctx.new_buffer(BufferType::IndexBuffer, usage, BufferSource::slice(data));
+        Self::from_id(id, data.len())
+    }
+
+    /// Index buffer with room for `len` indices, filled later with `update`.
+    pub fn empty_indices(
+        ctx: &mut dyn RenderingBackend,
+        usage: BufferUsage,
+        len: usize,
+    ) -> TypedBuffer<I> {
+        let id = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            usage,
+            BufferSource::empty::<I>(len),
+        );
+        Self::from_id(id, len)
+    }
+}
+
+/// `Bindings` with a vertex buffer of `V`.
+pub struct TypedBindings<V> {
+    vertex_buffer: TypedBuffer<V>,
+    index_buffer: BufferId,
+    pub images: Vec<TextureId>,
+}
+
+impl<V> Clone for TypedBindings<V> {
+    fn clone(&self) -> Self {
+        TypedBindings {
+            images: self.images.clone(),
+            ..*self
+        }
+    }
+}
+
+impl<V> std::fmt::Debug for TypedBindings<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TypedBindings")
+            .field("vertex_buffer", &self.vertex_buffer)
+            .field("index_buffer", &self.index_buffer)
+            .field("images", &self.images)
+            .finish()
+    }
+}
+
+impl<V> TypedBindings<V> {
+    pub fn new<I: IndexType>(
+        vertex_buffer: TypedBuffer<V>,
+        index_buffer: TypedBuffer<I>,
+        images: Vec<TextureId>,
+    ) -> TypedBindings<V> {
+        TypedBindings {
+            vertex_buffer,
+            index_buffer: index_buffer.id(),
+            images,
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> TypedBuffer<V> {
+        self.vertex_buffer
+    }
+
+    pub fn set_vertex_buffer(&mut self, vertex_buffer: TypedBuffer<V>) {
+        self.vertex_buffer = vertex_buffer;
+    }
+
+    pub fn index_buffer(&self) -> BufferId {
+        self.index_buffer
+    }
+
+    pub fn set_index_buffer<I: IndexType>(&mut self, index_buffer: TypedBuffer<I>) {
+        self.index_buffer = index_buffer.id();
+    }
+
+    /// The untyped bindings for `RenderingBackend::apply_bindings`.
+    pub fn bindings(&self) -> Bindings {
+        Bindings {
+            vertex_buffers: vec![self.vertex_buffer.id()],
+            index_buffer: self.index_buffer,
+            images: self.images.clone(),
+        }
+    }
+}
+
+/// A `Pipeline` drawing vertices of type `V` with uniforms of type `U`.
+pub struct TypedPipeline<V, U> {
+    pipeline: Pipeline,
+    _marker: PhantomData<fn() -> (V, U)>,
+}
+
+impl<V, U> Clone for TypedPipeline<V, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<V, U> Copy for TypedPipeline<V, U> {}
+
+impl<V, U> std::fmt::Debug for TypedPipeline<V, U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("TypedPipeline")
+            .field(&self.pipeline)
+            .finish()
+    }
+}
+
+impl<V: VertexLayout, U> TypedPipeline<V, U> {
+    /// Pipeline with the layout of `V` in buffer 0, see `layout::VertexLayout`.
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        shader: ShaderId,
+        params: PipelineParams,
+    ) -> TypedPipeline<V, U> {
+        let pipeline = ctx.new_pipeline(&[V::buffer_layout()], &V::attributes(0), shader, params);
+        Self::from_pipeline(pipeline)
+    }
+}
+
+impl<V, U> TypedPipeline<V, U> {
+    /// Wrap an untyped pipeline reading a single vertex buffer of `V` and uniforms
+    /// laid out as `U`, nothing checks it.
+    pub fn from_pipeline(pipeline: Pipeline) -> TypedPipeline<V, U> {
+        TypedPipeline {
+            pipeline,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn pipeline(&self) -> Pipeline {
+        self.pipeline
+    }
+
+    pub fn apply(&self, ctx: &mut dyn RenderingBackend) {
+        ctx.apply_pipeline(&self.pipeline);
+    }
+
+    /// Bindings of another vertex type are rejected:
+    ///
+    /// ```compile_fail
+    /// # use miniquad::*;
+    /// struct Vertex2D([f32; 2]);
+    /// struct Vertex3D([f32; 3]);
+    /// struct Uniforms;
+    ///
+    /// fn draw(
+    ///     ctx: &mut dyn RenderingBackend,
+    ///     pipeline: TypedPipeline<Vertex3D, Uniforms>,
+    ///     bindings: TypedBindings<Vertex2D>,
+    /// ) {
+    ///     pipeline.apply_bindings(ctx, &bindings);
+    /// }
+    /// ```
+    pub fn apply_bindings(&self, ctx: &mut dyn RenderingBackend, bindings: &TypedBindings<V>) {
+        ctx.apply_bindings_from_slice(
+            &[bindings.vertex_buffer.id()],
+            bindings.index_buffer,
+            &bindings.images,
+        );
+    }
+
+    /// Uniforms of another struct are rejected:
+    ///
+    /// ```compile_fail
+    /// # use miniquad::*;
+    /// struct Vertex([f32; 2]);
+    /// struct Uniforms {
+    ///     offset: [f32; 2],
+    /// }
+    /// struct OtherUniforms {
+    ///     color: [f32; 4],
+    /// }
+    ///
+    /// fn draw(ctx: &mut dyn RenderingBackend, pipeline: TypedPipeline<Vertex, Uniforms>) {
+    ///     pipeline.apply_uniforms(ctx, &OtherUniforms { color: [1.0; 4] });
+    /// }
+    /// ```
+    pub fn apply_uniforms(&self, ctx: &mut dyn RenderingBackend, uniforms: &U) {
+        ctx.apply_uniforms(UniformsSource::table(uniforms));
+    }
+
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_pipeline(self.pipeline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed() {
+        let mut ctx = NullContext::new();
+        let vertices = TypedBuffer::vertices(&mut ctx, BufferUsage::Immutable, &[[0f32; 2]; 3]);
+        let indices = TypedBuffer::indices(&mut ctx, BufferUsage::Immutable, &[0u16, 1, 2]);
+        assert_eq!(vertices.len(), 3);
+        assert_eq!(ctx.buffer_size(vertices.id()), 24);
+        assert_eq!(ctx.buffer_size(indices.id()), 6);
+
+        let texture = ctx.new_texture_from_rgba8(1, 1, &[0; 4]);
+        let bindings = TypedBindings::new(vertices, indices, vec![texture]);
+        let untyped = bindings.bindings();
+        assert_eq!(untyped.vertex_buffers, [vertices.id()]);
+        assert_eq!(untyped.index_buffer, indices.id());
+        assert_eq!(untyped.images, [texture]);
+
+        let shader = ctx
+            .new_shader(
+                ShaderSource::Glsl {
+                    vertex: "",
+                    fragment: "",
+                },
+                ShaderMeta {
+                    images: vec![],
+                    uniforms: UniformBlockLayout { uniforms: vec![] },
+                },
+            )
+            .unwrap();
+        let pipeline = ctx.new_pipeline(&[], &[], shader, PipelineParams::default());
+        let pipeline = TypedPipeline::<[f32; 2], ()>::from_pipeline(pipeline);
+        pipeline.apply(&mut ctx);
+        pipeline.apply_bindings(&mut ctx, &bindings);
+        pipeline.apply_uniforms(&mut ctx, &());
+        assert_eq!(ctx.calls().apply_bindings, 1);
+        assert_eq!(ctx.calls().apply_uniforms, 1);
+    }
+}
@@ -0,0 +1,541 @@
+//! Video frame textures: planar YUV upload and conversion to RGB.
+//!
+//! Decoded video usually comes as YUV planes. `VideoTexture` uploads them as they are
+//! and converts them on the GPU into an RGBA8 texture, which shaders sample like any
+//! other texture.
+//!
+//! ```ignore
+//! let mut video = VideoTexture::new(&mut *ctx, YuvFormat::I420, 1920, 1080, YuvConversion::BT709);
+//! // for each decoded frame, outside of a render pass
+//! video.update(&mut *ctx, &[
+//!     YuvPlane::strided(y, y_stride),
+//!     YuvPlane::strided(u, u_stride),
+//!     YuvPlane::strided(v, v_stride),
+//! ]);
+//! let rgb = video.convert(&mut *ctx);
+//! ```
+
+use super::*;
+
+/// Memory layout of a YUV frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum YuvFormat {
+    /// 4:2:0, three planes: Y, then U and V at half the width and height. Also known as YUV420P.
+    I420,
+    /// 4:2:0, two planes: Y, then interleaved UV at half the width and height.
+    Nv12,
+    /// 4:2:2, one plane of `Y0 U Y1 V` macropixels, each covering two pixels.
+    Yuyv,
+}
+
+impl YuvFormat {
+    /// Texture format, width and height of every plane of a `width` x `height` frame.
+    pub fn planes(self, width: u32, height: u32) -> Vec<(TextureFormat, u32, u32)> {
+        let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+        match self {
+            YuvFormat::I420 => vec![
+                (TextureFormat::Alpha, width, height),
+                (TextureFormat::Alpha, chroma_width, chroma_height),
+                (TextureFormat::Alpha, chroma_width, chroma_height),
+            ],
+            YuvFormat::Nv12 => vec![
+                (TextureFormat::Alpha, width, height),
+                (TextureFormat::RG8, chroma_width, chroma_height),
+            ],
+            YuvFormat::Yuyv => vec![(TextureFormat::RGBA8, chroma_width, height)],
+        }
+    }
+}
+
+/// YUV to RGB coefficients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum YuvColorSpace {
+    /// SD video.
+    Bt601,
+    /// HD video.
+    Bt709,
+}
+
+/// Value range of the YUV samples.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum YuvRange {
+    /// Y in 16..=235, U and V in 16..=240, used by most video.
+    Limited,
+    /// 0..=255, used by JPEG and some camera streams.
+    Full,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct YuvConversion {
+    pub color_space: YuvColorSpace,
+    pub range: YuvRange,
+}
+
+impl YuvConversion {
+    pub const BT601: YuvConversion = YuvConversion {
+        color_space: YuvColorSpace::Bt601,
+        range: YuvRange::Limited,
+    };
+    pub const BT709: YuvConversion = YuvConversion {
+        color_space: YuvColorSpace::Bt709,
+        range: YuvRange::Limited,
+    };
+
+    /// Column-major matrix taking `(y, u, v, 1)`, as sampled from the planes in 0..1,
+    /// to `(r, g, b, 1)`. For writing your own shaders on top of `VideoTexture::planes`.
+    pub fn matrix(&self) -> [f32; 16] {
+        let (kr, kb) = match self.color_space {
+            YuvColorSpace::Bt601 => (0.299, 0.114),
+            YuvColorSpace::Bt709 => (0.2126, 0.0722),
+        };
+        let kg = 1.0 - kr - kb;
+        let (y_scale, y_offset, c_scale) = match self.range {
+            YuvRange::Limited => (255.0 / 219.0, 16.0 / 255.0, 255.0 / 224.0),
+            YuvRange::Full => (1.0, 0.0, 1.0),
+        };
+        let c_offset = 128.0 / 255.0;
+
+        // R = Y + cr_r * V, G = Y + cb_g * U + cr_g * V, B = Y + cb_b * U
+        let cr_r = 2.0 * (1.0 - kr) * c_scale;
+        let cb_g = -2.0 * (1.0 - kb) * kb / kg * c_scale;
+        let cr_g = -2.0 * (1.0 - kr) * kr / kg * c_scale;
+        let cb_b = 2.0 * (1.0 - kb) * c_scale;
+        let y_bias = -y_offset * y_scale;
+        #[rustfmt::skip]
+        let matrix = [
+            y_scale, y_scale, y_scale, 0.0,
+            0.0, cb_g, cb_b, 0.0,
+            cr_r, cr_g, 0.0, 0.0,
+            y_bias - cr_r * c_offset,
+            y_bias - (cb_g + cr_g) * c_offset,
+            y_bias - cb_b * c_offset,
+            1.0,
+        ];
+        matrix
+    }
+}
+
+/// One plane of a frame, `stride` bytes from one row to the next.
+#[derive(Clone, Copy, Debug)]
+pub struct YuvPlane<'a> {
+    pub bytes: &'a [u8],
+    /// 0 for tightly packed rows.
+    pub stride: usize,
+}
+
+impl<'a> YuvPlane<'a> {
+    pub fn new(bytes: &'a [u8]) -> YuvPlane<'a> {
+        YuvPlane { bytes, stride: 0 }
+    }
+
+    /// A plane with the row stride (linesize) reported by the decoder.
+    pub fn strided(bytes: &'a [u8], stride: usize) -> YuvPlane<'a> {
+        YuvPlane { bytes, stride }
+    }
+}
+
+/// YUV planes and the RGBA8 texture they are converted into.
+pub struct VideoTexture {
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    pub conversion: YuvConversion,
+    planes: Vec<TextureId>,
+    texture: TextureId,
+    pass: RenderPass,
+    pipeline: Pipeline,
+    bindings: Bindings,
+    shader: ShaderId,
+    dirty: bool,
+}
+
+impl VideoTexture {
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        format: YuvFormat,
+        width: u32,
+        height: u32,
+        conversion: YuvConversion,
+    ) -> VideoTexture {
+        assert!(
+            width > 0 && height > 0,
+            "VideoTexture size should be positive"
+        );
+        let planes: Vec<TextureId> = format
+            .planes(width, height)
+            .into_iter()
+            .map(|(format, width, height)| {
+                // a YUYV texel holds two different luma samples, it can't be interpolated
+                let filter = match format {
+                    TextureFormat::RGBA8 => FilterMode::Nearest,
+                    _ => FilterMode::Linear,
+                };
+                ctx.new_texture(
+                    TextureAccess::Static,
+                    TextureSource::Empty,
+                    TextureParams {
+                        format,
+                        width,
+                        height,
+                        wrap: TextureWrap::Clamp,
+                        min_filter: filter,
+                        mag_filter: filter,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        let texture = ctx.new_render_texture(TextureParams {
+            format: TextureFormat::RGBA8,
+            width,
+            height,
+            wrap: TextureWrap::Clamp,
+            ..Default::default()
+        });
+        let pass = ctx.new_render_pass(texture, None);
+
+        let source = match ctx.info().backend {
+            Backend::OpenGl => ShaderSource::Glsl {
+                vertex: shader::VERTEX,
+                fragment: match format {
+                    YuvFormat::I420 => shader::FRAGMENT_I420,
+                    YuvFormat::Nv12 => shader::FRAGMENT_NV12,
+                    YuvFormat::Yuyv => shader::FRAGMENT_YUYV,
+                },
+            },
+            Backend::Metal => ShaderSource::Msl {
+                program: match format {
+                    YuvFormat::I420 => shader::METAL_I420,
+                    YuvFormat::Nv12 => shader::METAL_NV12,
+                    YuvFormat::Yuyv => shader::METAL_YUYV,
+                },
+            },
+        };
+        let shader = ctx
+            .new_shader(source, shader::meta(planes.len()))
+            .expect("YUV conversion shader");
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("in_pos", VertexFormat::Float2)],
+            shader,
+            PipelineParams::default(),
+        );
+
+        // a triangle covering the whole target
+        let vertices: [f32; 6] = [-1.0, -1.0, 3.0, -1.0, -1.0, 3.0];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&[0u16, 1, 2]),
+        );
+        let bindings = Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: planes.clone(),
+        };
+
+        VideoTexture {
+            format,
+            width,
+            height,
+            conversion,
+            planes,
+            texture,
+            pass,
+            pipeline,
+            bindings,
+            shader,
+            dirty: false,
+        }
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        self.format
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The plane textures, in the order of `YuvFormat::planes`.
+    ///
+    /// Y, U and V are sampled from `.a`, NV12 UV from `.ra` (`.rg` in Metal),
+    /// YUYV macropixels from `.rgba`.
+    pub fn planes(&self) -> &[TextureId] {
+        &self.planes
+    }
+
+    /// Upload a frame, one `YuvPlane` per plane of the format.
+    pub fn update(&mut self, ctx: &mut dyn RenderingBackend, planes: &[YuvPlane]) {
+        let layouts = self.format.planes(self.width, self.height);
+        assert_eq!(
+            planes.len(),
+            layouts.len(),
+            "{:?} frames have {} planes",
+            self.format,
+            layouts.len()
+        );
+        for ((plane, texture), (format, width, height)) in
+            planes.iter().zip(&self.planes).zip(layouts)
+        {
+            let pixel_size = format.size(1, 1) as usize;
+            assert!(
+                plane.stride % pixel_size == 0,
+                "YuvPlane stride should be a multiple of {}",
+                pixel_size
+            );
+            let layout = PixelLayout::with_row_length((plane.stride / pixel_size) as u32);
+            ctx.texture_update_region(
+                *texture,
+                TextureRegion {
+                    level: 0,
+                    x: 0,
+                    y: 0,
+                    width: width as _,
+                    height: height as _,
+                },
+                layout,
+                plane.bytes,
+            );
+        }
+        self.dirty = true;
+    }
+
+    /// Convert the last uploaded frame into the RGBA8 texture and return it.
+    /// Does nothing when no frame was uploaded since the last call.
+    ///
+    /// Runs a render pass, so call it outside of other passes.
+    /// Row 0 of the texture is the top row of the frame, the same as uploading
+    /// RGBA8 frames with `texture_update`.
+    pub fn convert(&mut self, ctx: &mut dyn RenderingBackend) -> TextureId {
+        if self.dirty {
+            self.dirty = false;
+            ctx.begin_pass(Some(self.pass), PassAction::Nothing);
+            ctx.apply_pipeline(&self.pipeline);
+            ctx.apply_bindings(&self.bindings);
+            ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+                yuv_to_rgb: self.conversion.matrix(),
+                width: self.width as f32,
+            }));
+            ctx.draw(0, 3, 1);
+            ctx.end_render_pass();
+        }
+        self.texture
+    }
+
+    /// The RGBA8 texture, as of the last `convert`.
+    pub fn texture(&self) -> TextureId {
+        self.texture
+    }
+
+    /// Delete all the GPU resources.
+    pub fn delete(self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_render_pass(self.pass);
+        ctx.delete_texture(self.texture);
+        for plane in self.planes {
+            ctx.delete_texture(plane);
+        }
+        ctx.delete_pipeline(self.pipeline);
+        ctx.delete_shader(self.shader);
+        for buffer in self.bindings.vertex_buffers {
+            ctx.delete_buffer(buffer);
+        }
+        ctx.delete_buffer(self.bindings.index_buffer);
+    }
+}
+
+mod shader {
+    use super::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 in_pos;
+
+    varying highp vec2 uv;
+
+    void main() {
+        gl_Position = vec4(in_pos, 0, 1);
+        uv = in_pos * 0.5 + 0.5;
+    }"#;
+
+    pub const FRAGMENT_I420: &str = r#"#version 100
+    precision highp float;
+    varying vec2 uv;
+
+    uniform mat4 yuv_to_rgb;
+    uniform sampler2D plane0;
+    uniform sampler2D plane1;
+    uniform sampler2D plane2;
+
+    void main() {
+        vec3 yuv = vec3(texture2D(plane0, uv).a, texture2D(plane1, uv).a, texture2D(plane2, uv).a);
+        gl_FragColor = vec4((yuv_to_rgb * vec4(yuv, 1.0)).rgb, 1.0);
+    }"#;
+
+    pub const FRAGMENT_NV12: &str = r#"#version 100
+    precision highp float;
+    varying vec2 uv;
+
+    uniform mat4 yuv_to_rgb;
+    uniform sampler2D plane0;
+    uniform sampler2D plane1;
+
+    void main() {
+        vec3 yuv = vec3(texture2D(plane0, uv).a, texture2D(plane1, uv).ra);
+        gl_FragColor = vec4((yuv_to_rgb * vec4(yuv, 1.0)).rgb, 1.0);
+    }"#;
+
+    pub const FRAGMENT_YUYV: &str = r#"#version 100
+    precision highp float;
+    varying vec2 uv;
+
+    uniform mat4 yuv_to_rgb;
+    uniform float width;
+    uniform sampler2D plane0;
+
+    void main() {
+        vec4 texel = texture2D(plane0, uv);
+        // even pixels take Y0, odd ones Y1
+        float y = mod(floor(uv.x * width), 2.0) < 0.5 ? texel.r : texel.b;
+        gl_FragColor = vec4((yuv_to_rgb * vec4(y, texel.ga, 1.0)).rgb, 1.0);
+    }"#;
+
+    macro_rules! metal_shader {
+        ($textures:expr, $yuv:expr) => {
+            concat!(
+                r#"
+    #include <metal_stdlib>
+
+    using namespace metal;
+
+    struct Uniforms
+    {
+        float4x4 yuv_to_rgb;
+        float width;
+    };
+
+    struct Vertex
+    {
+        float2 in_pos [[attribute(0)]];
+    };
+
+    struct RasterizerData
+    {
+        float4 position [[position]];
+        float2 uv [[user(locn0)]];
+    };
+
+    vertex RasterizerData vertexShader(Vertex v [[stage_in]])
+    {
+        RasterizerData out;
+        out.position = float4(v.in_pos, 0.0, 1.0);
+        // Metal textures start at the top
+        out.uv = float2(v.in_pos.x * 0.5 + 0.5, 0.5 - v.in_pos.y * 0.5);
+        return out;
+    }
+
+    fragment float4 fragmentShader(
+        RasterizerData in [[stage_in]],
+        constant Uniforms& uniforms [[buffer(0)]]"#,
+                $textures,
+                r#")
+    {
+"#,
+                $yuv,
+                r#"
+        return float4((uniforms.yuv_to_rgb * float4(yuv, 1.0)).rgb, 1.0);
+    }"#
+            )
+        };
+    }
+
+    pub const METAL_I420: &str = metal_shader!(
+        r#",
+        texture2d<float> plane0 [[texture(0)]], sampler plane0Smplr [[sampler(0)]],
+        texture2d<float> plane1 [[texture(1)]], sampler plane1Smplr [[sampler(1)]],
+        texture2d<float> plane2 [[texture(2)]], sampler plane2Smplr [[sampler(2)]]"#,
+        r#"
+        float3 yuv = float3(
+            plane0.sample(plane0Smplr, in.uv).a,
+            plane1.sample(plane1Smplr, in.uv).a,
+            plane2.sample(plane2Smplr, in.uv).a);"#
+    );
+
+    pub const METAL_NV12: &str = metal_shader!(
+        r#",
+        texture2d<float> plane0 [[texture(0)]], sampler plane0Smplr [[sampler(0)]],
+        texture2d<float> plane1 [[texture(1)]], sampler plane1Smplr [[sampler(1)]]"#,
+        r#"
+        float3 yuv = float3(
+            plane0.sample(plane0Smplr, in.uv).a,
+            plane1.sample(plane1Smplr, in.uv).rg);"#
+    );
+
+    pub const METAL_YUYV: &str = metal_shader!(
+        r#",
+        texture2d<float> plane0 [[texture(0)]], sampler plane0Smplr [[sampler(0)]]"#,
+        r#"
+        float4 texel = plane0.sample(plane0Smplr, in.uv);
+        // even pixels take Y0, odd ones Y1
+        float y = fmod(floor(in.uv.x * uniforms.width), 2.0) < 0.5 ? texel.r : texel.b;
+        float3 yuv = float3(y, texel.ga);"#
+    );
+
+    pub fn meta(planes: usize) -> ShaderMeta {
+        ShaderMeta {
+            images: (0..planes).map(|i| format!("plane{i}")).collect(),
+            uniforms: UniformBlockLayout {
+                uniforms: vec![
+                    UniformDesc::new("yuv_to_rgb", UniformType::Mat4),
+                    UniformDesc::new("width", UniformType::Float1),
+                ],
+            },
+        }
+    }
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub yuv_to_rgb: [f32; 16],
+        pub width: f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_rgb(conversion: YuvConversion, yuv: [u8; 3]) -> [i32; 3] {
+        let m = conversion.matrix();
+        let v = [
+            yuv[0] as f32 / 255.0,
+            yuv[1] as f32 / 255.0,
+            yuv[2] as f32 / 255.0,
+            1.0,
+        ];
+        let mut rgb = [0; 3];
+        for (row, out) in rgb.iter_mut().enumerate() {
+            let c: f32 = (0..4).map(|col| m[col * 4 + row] * v[col]).sum();
+            *out = (c * 255.0).round() as i32;
+        }
+        rgb
+    }
+
+    #[test]
+    fn test_matrix() {
+        for conversion in [YuvConversion::BT601, YuvConversion::BT709] {
+            assert_eq!(to_rgb(conversion, [16, 128, 128]), [0, 0, 0]);
+            assert_eq!(to_rgb(conversion, [235, 128, 128]), [255, 255, 255]);
+        }
+        let full = YuvConversion {
+            range: YuvRange::Full,
+            ..YuvConversion::BT601
+        };
+        assert_eq!(to_rgb(full, [128, 128, 128]), [128, 128, 128]);
+        // JPEG red
+        assert_eq!(to_rgb(full, [76, 85, 255]), [254, 0, 0]);
+    }
+}
@@ -0,0 +1,229 @@
+//! Built-in image decoding, behind the `image` feature.
+//!
+//! Small, dependency-free readers for PNG, QOI and KTX2 files, producing data ready
+//! for `RenderingBackend::new_texture`.
+//! KTX2 brings its own mip chain, cubemap faces and compressed formats;
+//! PNG and QOI decode to RGBA8.
+//!
+//! ```ignore
+//! let texture = image::decode(include_bytes!("ferris.png"))?.new_texture(
+//!     &mut *ctx,
+//!     TextureParams {
+//!         format: TextureFormat::SRGBA8,
+//!         ..Default::default()
+//!     },
+//! )?;
+//! ```
+
+use crate::{fs, graphics::*};
+
+mod inflate;
+mod ktx2;
+mod png;
+mod qoi;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Fs(fs::Error),
+    /// Not a PNG, QOI or KTX2 file.
+    UnknownFormat,
+    /// Corrupted file.
+    Invalid(&'static str),
+    /// A valid file using a feature this decoder does not implement.
+    Unsupported(String),
+}
+
+impl From<fs::Error> for ImageError {
+    fn from(e: fs::Error) -> ImageError {
+        ImageError::Fs(e)
+    }
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Fs(e) => write!(f, "{e}"),
+            Self::UnknownFormat => write!(f, "Unknown image format"),
+            Self::Invalid(what) => write!(f, "Invalid {what}"),
+            Self::Unsupported(what) => write!(f, "Unsupported {what}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// A decoded image.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA8 for PNG and QOI, KTX2 files keep their own format.
+    pub format: TextureFormat,
+    pub kind: TextureKind,
+    /// `[cubemap_face][mipmap_level][bytes]`, one face for 2D textures.
+    pub faces: Vec<Vec<Vec<u8>>>,
+}
+
+impl Image {
+    /// Create a texture from the image.
+    ///
+    /// `params` provides the sampling, wrap and mipmap settings; size, kind and
+    /// format come from the image. The only exception is choosing between `RGBA8`
+    /// and `SRGBA8` for RGBA8 images: `params.format` decides whether the color
+    /// is sRGB encoded.
+    ///
+    /// Mipmaps stored in the file are uploaded as is. Otherwise, with
+    /// `params.allocate_mipmaps`, they are generated, which is not possible
+    /// for compressed formats.
+    ///
+    /// Fails for `TextureKind::Texture2DMultisample`: multisampled textures are
+    /// render targets only, no file can fill them.
+    pub fn new_texture(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        params: TextureParams,
+    ) -> Result<TextureId, ImageError> {
+        let rgba8 = |format| matches!(format, TextureFormat::RGBA8 | TextureFormat::SRGBA8);
+        let format = if rgba8(self.format) && rgba8(params.format) {
+            params.format
+        } else {
+            self.format
+        };
+        let levels = self.faces[0].len();
+        let generate_mipmaps = levels == 1 && params.allocate_mipmaps;
+        if generate_mipmaps && format.is_compressed() {
+            eprintln!("Can't generate mipmaps for a compressed image, use a file with mipmaps");
+        }
+        let params = TextureParams {
+            kind: self.kind,
+            format,
+            width: self.width,
+            height: self.height,
+            allocate_mipmaps: levels > 1 || generate_mipmaps && !format.is_compressed(),
+            ..params
+        };
+
+        let faces: Vec<Vec<&[u8]>> = self
+            .faces
+            .iter()
+            .map(|face| face.iter().map(|level| &level[..]).collect())
+            .collect();
+        let cubemap_faces: Vec<&[&[u8]]> = faces.iter().map(|face| &face[..]).collect();
+        let source = match self.kind {
+            TextureKind::Texture2D if levels == 1 => TextureSource::Bytes(faces[0][0]),
+            TextureKind::Texture2D => TextureSource::Mips(&faces[0]),
+            TextureKind::CubeMap => TextureSource::Array(&cubemap_faces),
+            TextureKind::Texture2DMultisample => {
+                return Err(ImageError::Unsupported(
+                    "multisampled texture from an image".to_string(),
+                ))
+            }
+        };
+        let texture = ctx.new_texture(TextureAccess::Static, source, params);
+        if generate_mipmaps && params.allocate_mipmaps {
+            ctx.texture_generate_mipmaps(texture);
+        }
+        Ok(texture)
+    }
+}
+
+/// Decode a PNG, QOI or KTX2 file, telling them apart by their signature.
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.starts_with(&png::SIGNATURE) {
+        png::decode(bytes)
+    } else if bytes.starts_with(qoi::MAGIC) {
+        qoi::decode(bytes)
+    } else if bytes.starts_with(&ktx2::IDENTIFIER) {
+        ktx2::decode(bytes)
+    } else {
+        Err(ImageError::UnknownFormat)
+    }
+}
+
+/// Load and decode an image with `fs::load_file`.
+pub fn load_image<F: Fn(Result<Image, ImageError>) + 'static>(path: &str, on_loaded: F) {
+    fs::load_file(path, move |response| {
+        on_loaded(
+            response
+                .map_err(ImageError::from)
+                .and_then(|bytes| decode(&bytes)),
+        )
+    });
+}
+
+/// Load an image with `fs::load_file` and create a texture from it,
+/// see `Image::new_texture` for how `params` are used.
+///
+/// Not available on WASM, where files are loaded asynchronously and `ctx` can't be held
+/// until then: use `load_image` and create the texture once the image arrives.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_texture<F: FnOnce(Result<TextureId, ImageError>)>(
+    ctx: &mut dyn RenderingBackend,
+    path: &str,
+    params: TextureParams,
+    on_loaded: F,
+) {
+    let image = std::rc::Rc::new(std::cell::RefCell::new(None));
+    load_image(path, {
+        let image = image.clone();
+        move |result| *image.borrow_mut() = Some(result)
+    });
+    // everywhere but on WASM load_file calls back right away
+    let result = image
+        .borrow_mut()
+        .take()
+        .expect("fs::load_file did not complete");
+    on_loaded(result.and_then(|image| image.new_texture(ctx, params)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qoi() {
+        #[rustfmt::skip]
+        let file = [
+            b'q', b'o', b'i', b'f', 0, 0, 0, 3, 0, 0, 0, 1, 4, 0,
+            0xfe, 10, 20, 30, // OP_RGB
+            0x7f,             // OP_DIFF +1 +1 +1
+            0xc0,             // OP_RUN 1
+            0, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        let image = decode(&file).unwrap();
+        assert_eq!((image.width, image.height), (3, 1));
+        assert_eq!(
+            image.faces[0][0],
+            [10, 20, 30, 255, 11, 21, 31, 255, 11, 21, 31, 255]
+        );
+    }
+
+    #[test]
+    fn png() {
+        // 2x2 RGBA, sub and up filters
+        #[rustfmt::skip]
+        let file = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d,
+            0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02,
+            0x08, 0x06, 0x00, 0x00, 0x00, 0x72, 0xb6, 0x0d, 0x24, 0x00, 0x00, 0x00,
+            0x14, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x64, 0x64, 0x62, 0xfe,
+            0xcf, 0xc2, 0xc2, 0xc2, 0xc0, 0xc4, 0x00, 0x05, 0x00, 0x0f, 0x16, 0x01,
+            0x15, 0xc1, 0xee, 0xa0, 0x07, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e,
+            0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        let image = decode(&file).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(
+            image.faces[0][0],
+            [1, 2, 3, 255, 5, 6, 7, 255, 1, 2, 3, 255, 5, 6, 7, 255]
+        );
+
+        // IHDR claiming 0xffffffff x 0xffffffff
+        let mut huge = file;
+        huge[16..24].fill(0xff);
+        assert!(matches!(
+            decode(&huge),
+            Err(ImageError::Invalid("PNG size"))
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+//! zlib/DEFLATE decompression (RFC 1950, RFC 1951), just enough for PNG.
+
+use super::ImageError;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const INVALID: ImageError = ImageError::Invalid("deflate stream");
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            match self.data.get(self.pos) {
+                Some(byte) => {
+                    self.bits |= (*byte as u64) << self.count;
+                    self.pos += 1;
+                    self.count += 8;
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, ImageError> {
+        if n == 0 {
+            return Ok(0);
+        }
+        if self.count < n {
+            self.refill();
+            if self.count < n {
+                return Err(ImageError::Invalid("truncated deflate stream"));
+            }
+        }
+        let value = (self.bits & ((1 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drop the bits up to the next byte boundary.
+    fn align(&mut self) {
+        let n = self.count % 8;
+        self.bits >>= n;
+        self.count -= n;
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    // number of codes of each length
+    counts: [u16; 16],
+    // symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, ImageError> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for i in 1..15 {
+            offsets[i + 1] = offsets[i] + counts[i];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, r: &mut BitReader) -> Result<u16, ImageError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= r.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(INVALID)
+    }
+}
+
+/// Decompress a zlib stream. `size_hint` is the expected output size.
+pub(super) fn zlib_decompress(data: &[u8], size_hint: usize) -> Result<Vec<u8>, ImageError> {
+    if data.len() < 2 {
+        return Err(ImageError::Invalid("zlib header"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || ((cmf as u16) << 8 | flg as u16) % 31 != 0 || flg & 0x20 != 0 {
+        return Err(ImageError::Invalid("zlib header"));
+    }
+    let mut out = Vec::with_capacity(size_hint);
+    inflate(&data[2..], &mut out)?;
+    Ok(out)
+}
+
+/// Decompress a raw DEFLATE stream, appending to `out`.
+fn inflate(data: &[u8], out: &mut Vec<u8>) -> Result<(), ImageError> {
+    let mut r = BitReader {
+        data,
+        pos: 0,
+        bits: 0,
+        count: 0,
+    };
+    loop {
+        let last = r.bits(1)? == 1;
+        match r.bits(2)? {
+            0 => stored_block(&mut r, out)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                huffman_block(&mut r, out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut r)?;
+                huffman_block(&mut r, out, &literals, &distances)?;
+            }
+            _ => return Err(INVALID),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn stored_block(r: &mut BitReader, out: &mut Vec<u8>) -> Result<(), ImageError> {
+    r.align();
+    let len = r.bits(16)?;
+    let nlen = r.bits(16)?;
+    if len != !nlen & 0xffff {
+        return Err(INVALID);
+    }
+    // whole bytes still sitting in the bit buffer come first
+    for _ in 0..len {
+        out.push(r.bits(8)? as u8);
+    }
+    Ok(())
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), ImageError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(r: &mut BitReader) -> Result<(Huffman, Huffman), ImageError> {
+    let literal_count = r.bits(5)? as usize + 257;
+    let distance_count = r.bits(5)? as usize + 1;
+    let code_length_count = r.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(INVALID);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for i in 0..code_length_count {
+        code_lengths[CODE_LENGTH_ORDER[i]] = r.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_lengths.decode(r)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i].last().ok_or(INVALID)?;
+                (previous, 3 + r.bits(2)? as usize)
+            }
+            17 => (0, 3 + r.bits(3)? as usize),
+            18 => (0, 11 + r.bits(7)? as usize),
+            _ => return Err(INVALID),
+        };
+        if i + repeat > lengths.len() {
+            return Err(INVALID);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        // no end of block code
+        return Err(INVALID);
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn huffman_block(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ImageError> {
+    loop {
+        let symbol = literals.decode(r)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let code = symbol - 257;
+                let length =
+                    LENGTH_BASE[code] as usize + r.bits(LENGTH_EXTRA[code] as u32)? as usize;
+                let code = distances.decode(r)? as usize;
+                if code >= 30 {
+                    return Err(INVALID);
+                }
+                let distance = DIST_BASE[code] as usize + r.bits(DIST_EXTRA[code] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(INVALID);
+                }
+                let start = out.len() - distance;
+                // the copy may overlap with itself, byte by byte
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err(INVALID),
+        }
+    }
+}
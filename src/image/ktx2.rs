@@ -0,0 +1,117 @@
+//! KTX2 container reader, see <https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html>.
+//!
+//! Supports 2D textures and cubemaps with their mip chains, in the formats that map to a
+//! `TextureFormat`. Basis Universal and supercompressed files are not supported.
+
+use super::{Image, ImageError};
+use crate::graphics::{TextureFormat, TextureKind};
+use std::convert::TryInto;
+
+pub(super) const IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+fn format(vk_format: u32) -> Result<TextureFormat, ImageError> {
+    Ok(match vk_format {
+        23 => TextureFormat::RGB8,    // VK_FORMAT_R8G8B8_UNORM
+        37 => TextureFormat::RGBA8,   // VK_FORMAT_R8G8B8A8_UNORM
+        43 => TextureFormat::SRGBA8,  // VK_FORMAT_R8G8B8A8_SRGB
+        97 => TextureFormat::RGBA16F, // VK_FORMAT_R16G16B16A16_SFLOAT
+        // BC1 RGB blocks decode the same way as RGBA ones
+        131 | 133 => TextureFormat::Bc1, // VK_FORMAT_BC1_RGB(A)_UNORM_BLOCK
+        137 => TextureFormat::Bc3,       // VK_FORMAT_BC3_UNORM_BLOCK
+        145 => TextureFormat::Bc7,       // VK_FORMAT_BC7_UNORM_BLOCK
+        147 => TextureFormat::Etc2Rgb8,  // VK_FORMAT_ETC2_R8G8B8_UNORM_BLOCK
+        151 => TextureFormat::Etc2Rgba8, // VK_FORMAT_ETC2_R8G8B8A8_UNORM_BLOCK
+        157 => TextureFormat::Astc4x4,   // VK_FORMAT_ASTC_4x4_UNORM_BLOCK
+        0 => {
+            return Err(ImageError::Unsupported(
+                "KTX2 with Basis Universal data".to_string(),
+            ))
+        }
+        _ => {
+            return Err(ImageError::Unsupported(format!(
+                "KTX2 vkFormat {vk_format}"
+            )))
+        }
+    })
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.len() < HEADER_SIZE || !bytes.starts_with(&IDENTIFIER) {
+        return Err(ImageError::Invalid("KTX2 header"));
+    }
+    let vk_format = le_u32(bytes, 12);
+    let width = le_u32(bytes, 20);
+    let height = le_u32(bytes, 24);
+    let depth = le_u32(bytes, 28);
+    let layer_count = le_u32(bytes, 32);
+    let face_count = le_u32(bytes, 36);
+    let level_count = le_u32(bytes, 40);
+    let supercompression = le_u32(bytes, 44);
+
+    let format = format(vk_format)?;
+    if supercompression != 0 {
+        return Err(ImageError::Unsupported(format!(
+            "KTX2 supercompression scheme {supercompression}"
+        )));
+    }
+    if height == 0 || depth != 0 || layer_count > 1 {
+        return Err(ImageError::Unsupported(
+            "KTX2 1D, 3D and array textures".to_string(),
+        ));
+    }
+    let kind = match face_count {
+        1 => TextureKind::Texture2D,
+        6 => TextureKind::CubeMap,
+        _ => return Err(ImageError::Invalid("KTX2 face count")),
+    };
+    if width == 0 || width > 1 << 16 || height > 1 << 16 {
+        return Err(ImageError::Invalid("KTX2 size"));
+    }
+    // 0 asks for generating the mipmaps, only the base level is stored then
+    let level_count = level_count.clamp(1, 32) as usize;
+    if bytes.len() < HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE {
+        return Err(ImageError::Invalid("KTX2 level index"));
+    }
+
+    let mut faces = vec![Vec::with_capacity(level_count); face_count as usize];
+    for level in 0..level_count {
+        let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = le_u64(bytes, entry) as usize;
+        let length = le_u64(bytes, entry + 8) as usize;
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or(ImageError::Invalid("KTX2 level data"))?;
+
+        let level_width = (width >> level).max(1);
+        let level_height = (height >> level).max(1);
+        let face_size = format.size(level_width, level_height) as usize;
+        if data.len() < face_size * face_count as usize {
+            return Err(ImageError::Invalid("KTX2 level size"));
+        }
+        for (face, levels) in faces.iter_mut().enumerate() {
+            levels.push(data[face * face_size..(face + 1) * face_size].to_vec());
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        format,
+        kind,
+        faces,
+    })
+}
@@ -0,0 +1,266 @@
+//! PNG decoder: all color types and bit depths, interlaced or not, decoded to RGBA8.
+
+use super::{inflate, Image, ImageError};
+use crate::graphics::{TextureFormat, TextureKind};
+
+pub(super) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+// (x, y, dx, dy) of the Adam7 passes
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    /// Bytes of one row of `width` pixels, without the filter byte.
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.channels() * self.bit_depth as usize + 7) / 8
+    }
+
+    /// Distance to the corresponding byte of the previous pixel, for filtering.
+    fn filter_stride(&self) -> usize {
+        ((self.channels() * self.bit_depth as usize + 7) / 8).max(1)
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(ImageError::Invalid("PNG signature"));
+    }
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut data = vec![];
+
+    let mut chunks = &bytes[SIGNATURE.len()..];
+    while chunks.len() >= 12 {
+        let len = be_u32(chunks) as usize;
+        let kind = &chunks[4..8];
+        let chunk = chunks
+            .get(8..8 + len)
+            .ok_or(ImageError::Invalid("truncated PNG chunk"))?;
+        // skip the CRC
+        chunks = chunks.get(12 + len..).unwrap_or(&[]);
+        match kind {
+            b"IHDR" if chunk.len() >= 13 => {
+                header = Some(Header {
+                    width: be_u32(chunk) as usize,
+                    height: be_u32(&chunk[4..]) as usize,
+                    bit_depth: chunk[8],
+                    color_type: chunk[9],
+                    interlaced: chunk[12] == 1,
+                })
+            }
+            b"PLTE" => palette = chunk,
+            b"tRNS" => transparency = chunk,
+            b"IDAT" => data.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or(ImageError::Invalid("PNG header"))?;
+    let valid_depth = match header.color_type {
+        0 => matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(header.bit_depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(header.bit_depth, 8 | 16),
+        _ => false,
+    };
+    if !valid_depth {
+        return Err(ImageError::Unsupported(format!(
+            "PNG color type {} with bit depth {}",
+            header.color_type, header.bit_depth
+        )));
+    }
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(ImageError::Invalid("PNG palette"));
+    }
+    let (width, height) = (header.width, header.height);
+    // larger than any texture, and keeps the sizes below from overflowing
+    if width == 0 || height == 0 || width > 1 << 16 || height > 1 << 16 {
+        return Err(ImageError::Invalid("PNG size"));
+    }
+    let rgba_size = width
+        .checked_mul(height)
+        .filter(|&pixels| pixels <= 400_000_000)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(ImageError::Invalid("PNG size"))?;
+
+    let size_hint = height
+        .checked_mul(header.row_bytes(width) + 1)
+        .ok_or(ImageError::Invalid("PNG size"))?;
+    let data = inflate::zlib_decompress(&data, size_hint)?;
+
+    let mut rgba = vec![0u8; rgba_size];
+    let passes: &[_] = if header.interlaced {
+        &ADAM7
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    let mut data = &data[..];
+    for (x0, y0, dx, dy) in passes.iter().copied() {
+        let pass_width = (width + dx - 1 - x0) / dx;
+        let pass_height = (height + dy - 1 - y0) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let row_bytes = header.row_bytes(pass_width);
+        let size = (row_bytes + 1) * pass_height;
+        let pass = data
+            .get(..size)
+            .ok_or(ImageError::Invalid("truncated PNG data"))?;
+        data = &data[size..];
+
+        let pixels = unfilter(pass, row_bytes, header.filter_stride())?;
+        for (y, row) in pixels.chunks_exact(row_bytes).enumerate() {
+            for x in 0..pass_width {
+                let i = ((y0 + y * dy) * width + x0 + x * dx) * 4;
+                let pixel = to_rgba(&header, row, x, palette, transparency);
+                rgba[i..i + 4].copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    Ok(Image {
+        width: width as u32,
+        height: height as u32,
+        format: TextureFormat::RGBA8,
+        kind: TextureKind::Texture2D,
+        faces: vec![vec![rgba]],
+    })
+}
+
+/// Undo the per-row filters, `data` is rows prefixed with their filter type.
+fn unfilter(data: &[u8], row_bytes: usize, stride: usize) -> Result<Vec<u8>, ImageError> {
+    let rows = data.len() / (row_bytes + 1);
+    let mut out = vec![0u8; rows * row_bytes];
+    for y in 0..rows {
+        let filter = data[y * (row_bytes + 1)];
+        let src = &data[y * (row_bytes + 1) + 1..(y + 1) * (row_bytes + 1)];
+        let (previous, current) = out.split_at_mut(y * row_bytes);
+        let up = match y {
+            0 => None,
+            _ => Some(&previous[(y - 1) * row_bytes..]),
+        };
+        let current = &mut current[..row_bytes];
+        for x in 0..row_bytes {
+            let a = if x >= stride { current[x - stride] } else { 0 };
+            let b = up.map_or(0, |up| up[x]);
+            let c = match up {
+                Some(up) if x >= stride => up[x - stride],
+                _ => 0,
+            };
+            current[x] = src[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::Invalid("PNG filter")),
+            });
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// `n`-th sample of a row, as stored.
+fn sample(row: &[u8], n: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[n * 2], row[n * 2 + 1]]),
+        8 => row[n] as u16,
+        _ => {
+            let bit = n * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << bit_depth) - 1)) as u16
+        }
+    }
+}
+
+fn to_rgba(header: &Header, row: &[u8], x: usize, palette: &[u8], transparency: &[u8]) -> [u8; 4] {
+    let depth = header.bit_depth;
+    let channels = header.channels();
+    let get = |c: usize| sample(row, x * channels + c, depth);
+    let to_u8 = |v: u16| match depth {
+        16 => (v >> 8) as u8,
+        8 => v as u8,
+        _ => (v as u32 * 255 / ((1 << depth) - 1)) as u8,
+    };
+    // the tRNS color of gray and RGB images is stored as 16 bit samples
+    let transparent =
+        |c: usize, v: u16| transparency.len() >= c * 2 + 2 && be_u16(&transparency[c * 2..]) == v;
+
+    match header.color_type {
+        0 => {
+            let v = get(0);
+            let alpha = if transparent(0, v) { 0 } else { 255 };
+            let v = to_u8(v);
+            [v, v, v, alpha]
+        }
+        2 => {
+            let (r, g, b) = (get(0), get(1), get(2));
+            let alpha = if transparent(0, r) && transparent(1, g) && transparent(2, b) {
+                0
+            } else {
+                255
+            };
+            [to_u8(r), to_u8(g), to_u8(b), alpha]
+        }
+        3 => {
+            let index = get(0) as usize;
+            let color = palette.get(index * 3..index * 3 + 3).unwrap_or(&[0, 0, 0]);
+            let alpha = transparency.get(index).copied().unwrap_or(255);
+            [color[0], color[1], color[2], alpha]
+        }
+        4 => {
+            let v = to_u8(get(0));
+            [v, v, v, to_u8(get(1))]
+        }
+        _ => [to_u8(get(0)), to_u8(get(1)), to_u8(get(2)), to_u8(get(3))],
+    }
+}
+
+fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
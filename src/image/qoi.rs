@@ -0,0 +1,84 @@
+//! QOI decoder, see <https://qoiformat.org/qoi-specification.pdf>.
+
+use super::{Image, ImageError};
+use crate::graphics::{TextureFormat, TextureKind};
+
+pub(super) const MAGIC: &[u8; 4] = b"qoif";
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const MASK: u8 = 0xc0;
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.len() < 14 || !bytes.starts_with(MAGIC) {
+        return Err(ImageError::Invalid("QOI header"));
+    }
+    let width = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let height = u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+    let pixel_count = width as usize * height as usize;
+    if pixel_count == 0 || pixel_count > 400_000_000 {
+        return Err(ImageError::Invalid("QOI size"));
+    }
+
+    let mut data = bytes[14..].iter().copied();
+    let mut next = || data.next().ok_or(ImageError::Invalid("truncated QOI data"));
+
+    let mut rgba = Vec::with_capacity(pixel_count * 4);
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0u8, 0, 0, 255];
+    let mut run = 0;
+    for _ in 0..pixel_count {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let op = next()?;
+            match op {
+                OP_RGB => {
+                    pixel[0] = next()?;
+                    pixel[1] = next()?;
+                    pixel[2] = next()?;
+                }
+                OP_RGBA => {
+                    pixel = [next()?, next()?, next()?, next()?];
+                }
+                _ => match op & MASK {
+                    OP_INDEX => pixel = index[op as usize],
+                    OP_DIFF => {
+                        pixel[0] = pixel[0].wrapping_add((op >> 4 & 0x03).wrapping_sub(2));
+                        pixel[1] = pixel[1].wrapping_add((op >> 2 & 0x03).wrapping_sub(2));
+                        pixel[2] = pixel[2].wrapping_add((op & 0x03).wrapping_sub(2));
+                    }
+                    OP_LUMA => {
+                        let dg = (op & 0x3f).wrapping_sub(32);
+                        let drdb = next()?;
+                        pixel[0] =
+                            pixel[0].wrapping_add(dg.wrapping_sub(8).wrapping_add(drdb >> 4));
+                        pixel[1] = pixel[1].wrapping_add(dg);
+                        pixel[2] =
+                            pixel[2].wrapping_add(dg.wrapping_sub(8).wrapping_add(drdb & 0x0f));
+                    }
+                    OP_RUN => run = op & 0x3f,
+                    _ => unreachable!(),
+                },
+            }
+            let hash = pixel[0] as usize * 3
+                + pixel[1] as usize * 5
+                + pixel[2] as usize * 7
+                + pixel[3] as usize * 11;
+            index[hash % 64] = pixel;
+        }
+        rgba.extend_from_slice(&pixel);
+    }
+
+    Ok(Image {
+        width,
+        height,
+        format: TextureFormat::RGBA8,
+        kind: TextureKind::Texture2D,
+        faces: vec![vec![rgba]],
+    })
+}
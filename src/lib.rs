@@ -12,61 +12,180 @@ mod event;
 pub mod fs;
 pub mod graphics;
 pub mod native;
-use std::collections::HashMap;
 use std::ops::{Index, IndexMut};
 
 #[cfg(feature = "log-impl")]
 pub mod log;
 
+#[cfg(feature = "image")]
+pub mod image;
+
 pub use event::*;
 
 pub use graphics::*;
 
+#[cfg(feature = "derive")]
+pub use miniquad_derive::{Uniforms, Vertex};
+
 mod default_icon;
 
 pub use native::gl;
 
+/// Generation-checked key into a `ResourceManager`.
+///
+/// Slots are reused after deletion, but each reuse bumps the slot generation,
+/// so a key to a deleted resource never aliases a newer one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ResourceId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Clone)]
+struct ResourceSlot<T> {
+    generation: u32,
+    resource: Option<T>,
+}
+
 #[derive(Clone)]
 pub(crate) struct ResourceManager<T> {
-    id: usize,
-    resources: HashMap<usize, T>,
+    // Used in panic messages, "buffer", "texture" etc.
+    kind: &'static str,
+    slots: Vec<ResourceSlot<T>>,
+    free: Vec<u32>,
 }
 
-impl<T> Default for ResourceManager<T> {
-    fn default() -> Self {
+impl<T> ResourceManager<T> {
+    pub fn new(kind: &'static str) -> Self {
         Self {
-            id: 0,
-            resources: HashMap::new(),
+            kind,
+            slots: vec![],
+            free: vec![],
         }
     }
-}
 
-impl<T> ResourceManager<T> {
-    pub fn add(&mut self, resource: T) -> usize {
-        self.resources.insert(self.id, resource);
-        self.id += 1;
-        self.id - 1
+    pub fn add(&mut self, resource: T) -> ResourceId {
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            debug_assert!(slot.resource.is_none());
+            slot.resource = Some(resource);
+            return ResourceId {
+                index,
+                generation: slot.generation,
+            };
+        }
+        self.slots.push(ResourceSlot {
+            generation: 0,
+            resource: Some(resource),
+        });
+        ResourceId {
+            index: self.slots.len() as u32 - 1,
+            generation: 0,
+        }
     }
 
-    pub fn remove(&mut self, id: usize) -> T {
-        // Let it crash if the resource is not found
-        self.resources.remove(&id).unwrap()
+    pub fn remove(&mut self, id: ResourceId) -> T {
+        // Let it crash if the resource is not found, but tell why
+        if !self.contains(id) {
+            self.stale(id);
+        }
+        let slot = &mut self.slots[id.index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        slot.resource.take().unwrap()
+    }
+
+    pub fn contains(&self, id: ResourceId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get(&self, id: ResourceId) -> Option<&T> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.resource.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: ResourceId) -> Option<&mut T> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.resource.as_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ResourceId, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = ResourceId {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            slot.resource.as_ref().map(|resource| (id, resource))
+        })
+    }
+
+    #[cfg_attr(not(target_vendor = "apple"), allow(dead_code))]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.resource.as_mut())
+    }
+
+    #[cold]
+    fn stale(&self, id: ResourceId) -> ! {
+        match self.slots.get(id.index as usize) {
+            Some(slot) if slot.generation != id.generation => panic!(
+                "Stale {} handle {:?}: the {} was deleted (slot is at generation {})",
+                self.kind, id, self.kind, slot.generation
+            ),
+            Some(_) => panic!(
+                "Stale {} handle {:?}: the {} was deleted",
+                self.kind, id, self.kind
+            ),
+            None => panic!(
+                "Invalid {} handle {:?}: it was not created by this context",
+                self.kind, id
+            ),
+        }
     }
 }
 
-impl<T> Index<usize> for ResourceManager<T> {
+impl<T> Index<ResourceId> for ResourceManager<T> {
     type Output = T;
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.resources[&index]
+    fn index(&self, id: ResourceId) -> &Self::Output {
+        match self.get(id) {
+            Some(resource) => resource,
+            None => self.stale(id),
+        }
     }
 }
 
-impl<T> IndexMut<usize> for ResourceManager<T> {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        self.resources.get_mut(&index).unwrap()
+impl<T> IndexMut<ResourceId> for ResourceManager<T> {
+    fn index_mut(&mut self, id: ResourceId) -> &mut Self::Output {
+        if !self.contains(id) {
+            self.stale(id);
+        }
+        self.get_mut(id).unwrap()
     }
 }
 
+#[test]
+fn test_resource_manager_generations() {
+    let mut buffers = ResourceManager::new("buffer");
+    let a = buffers.add(1);
+    buffers.remove(a);
+    let b = buffers.add(2);
+
+    // the slot is reused, but the stale key does not alias the new resource
+    assert_eq!(a.index, b.index);
+    assert!(!buffers.contains(a));
+    assert_eq!(buffers[b], 2);
+    assert!(std::panic::catch_unwind(move || buffers[a]).is_err());
+}
+
 pub mod date {
     #[cfg(not(target_arch = "wasm32"))]
     pub fn now() -> f64 {
@@ -130,6 +249,17 @@ pub mod window {
         Box::new(GlContext::new())
     }
 
+    /// A GL context sharing textures and buffers with the main one, for uploads
+    /// from a worker thread. See `UploadContext`.
+    ///
+    /// Has to be called on the main thread. Each call creates a new context.
+    /// None on Metal, WebGL, Windows and macOS, or if the driver refused to create one.
+    pub fn new_upload_context() -> Option<UploadContext> {
+        let mut d = native_display().lock().unwrap();
+        let shared = d.shared_contexts.as_mut()?.new_shared_context()?;
+        Some(UploadContext::new(shared))
+    }
+
     /// The current framebuffer size in pixels
     /// NOTE: [High DPI Rendering](../conf/index.html#high-dpi-rendering)
     pub fn screen_size() -> (f32, f32) {
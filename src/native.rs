@@ -19,6 +19,11 @@ pub(crate) struct NativeDisplayData {
     pub clipboard: Box<dyn Clipboard>,
     pub dropped_files: DroppedFiles,
     pub blocking_event_loop: bool,
+    pub shared_contexts: Option<Box<dyn SharedContextFactory>>,
+    // set between context_lost_event and context_restored_event
+    pub context_lost: bool,
+    // bumped every time the platform replaces the GL context
+    pub context_generation: u32,
 
     #[cfg(target_vendor = "apple")]
     pub view: crate::native::apple::frameworks::ObjcId,
@@ -51,6 +56,9 @@ impl NativeDisplayData {
             clipboard,
             dropped_files: Default::default(),
             blocking_event_loop: false,
+            shared_contexts: None,
+            context_lost: false,
+            context_generation: 0,
             #[cfg(target_vendor = "apple")]
             gfx_api: crate::conf::AppleGfxApi::OpenGl,
             #[cfg(target_vendor = "apple")]
@@ -78,6 +86,18 @@ pub trait Clipboard: Send + Sync {
     fn set(&mut self, string: &str);
 }
 
+/// Creates GL contexts sharing objects with the main one.
+/// Called on the main thread.
+pub trait SharedContextFactory: Send {
+    fn new_shared_context(&mut self) -> Option<Box<dyn SharedGlContext>>;
+}
+
+/// GL context sharing objects with the main one, usable from another thread.
+pub trait SharedGlContext: Send {
+    /// Make the context current on the calling thread.
+    fn make_current(&mut self) -> bool;
+}
+
 pub mod module;
 
 #[cfg(target_os = "linux")]
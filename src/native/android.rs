@@ -130,7 +130,11 @@ struct MainThreadState {
     libegl: LibEgl,
     egl_display: egl::EGLDisplay,
     egl_config: egl::EGLConfig,
+    surface_attributes: Vec<egl::EGLint>,
     egl_context: egl::EGLContext,
+    // to create the same kind of context when the old one is lost
+    gl_context_request: crate::conf::GlContextRequest,
+    debug_context: bool,
     surface: egl::EGLSurface,
     window: *mut ndk_sys::ANativeWindow,
     event_handler: Box<dyn EventHandler>,
@@ -165,7 +169,7 @@ impl MainThreadState {
             self.egl_display,
             self.egl_config,
             window as _,
-            std::ptr::null_mut(),
+            self.surface_attributes.as_ptr() as _,
         );
 
         assert!(!self.surface.is_null());
@@ -177,9 +181,59 @@ impl MainThreadState {
             self.egl_context,
         );
 
+        if res == 0 && (self.libegl.eglGetError)() as u32 == egl::EGL_CONTEXT_LOST {
+            // the context was lost while the app was paused
+            self.recreate_context();
+            return;
+        }
         assert!(res != 0);
     }
 
+    /// Replace a lost context and tell the application about it.
+    unsafe fn recreate_context(&mut self) {
+        crate::native_display().lock().unwrap().context_lost = true;
+        self.event_handler.context_lost_event();
+
+        (self.libegl.eglMakeCurrent)(
+            self.egl_display,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        (self.libegl.eglDestroyContext)(self.egl_display, self.egl_context);
+        self.egl_context = egl::recreate_context(
+            &mut self.libegl,
+            self.egl_display,
+            self.egl_config,
+            &self.gl_context_request,
+            self.debug_context,
+        )
+        .expect("Cant recreate EGL context");
+        let res = (self.libegl.eglMakeCurrent)(
+            self.egl_display,
+            self.surface,
+            self.surface,
+            self.egl_context,
+        );
+        assert!(res != 0);
+
+        {
+            let mut d = crate::native_display().lock().unwrap();
+            d.context_lost = false;
+            d.context_generation += 1;
+            // shared contexts of the old context are gone with it
+            d.shared_contexts = egl::EglSharedContexts::new(
+                self.egl_display,
+                self.egl_config,
+                self.egl_context,
+                &self.gl_context_request,
+                self.debug_context,
+            )
+            .map(|f| Box::new(f) as _);
+        }
+        self.event_handler.context_restored_event();
+    }
+
     fn process_message(&mut self, msg: Message) {
         match msg {
             Message::SurfaceCreated { window } => unsafe {
@@ -264,7 +318,11 @@ impl MainThreadState {
             self.event_handler.draw();
 
             unsafe {
-                (self.libegl.eglSwapBuffers)(self.egl_display, self.surface);
+                if (self.libegl.eglSwapBuffers)(self.egl_display, self.surface) == 0
+                    && (self.libegl.eglGetError)() as u32 == egl::EGL_CONTEXT_LOST
+                {
+                    self.recreate_context();
+                }
             }
         }
     }
@@ -425,6 +483,9 @@ where
             std::ptr::null_mut(), /* EGL_DEFAULT_DISPLAY */
             conf.platform.framebuffer_alpha,
             conf.sample_count,
+            &conf.framebuffer,
+            &conf.platform.gl_context,
+            conf.platform.debug_context,
         )
         .expect("Cant create EGL context");
 
@@ -436,11 +497,16 @@ where
             (libegl.eglGetProcAddress)(name.as_ptr() as _)
         });
 
+        let surface_attributes = crate::native::egl::window_surface_attributes(
+            &mut libegl,
+            egl_display,
+            &conf.framebuffer,
+        );
         let surface = (libegl.eglCreateWindowSurface)(
             egl_display,
             egl_config,
             window as _,
-            std::ptr::null_mut(),
+            surface_attributes.as_ptr() as _,
         );
 
         if (libegl.eglMakeCurrent)(egl_display, surface, surface, egl_context) == 0 {
@@ -449,9 +515,17 @@ where
 
         let (tx, requests_rx) = std::sync::mpsc::channel();
         let clipboard = Box::new(AndroidClipboard::new());
+        let shared_contexts = crate::native::egl::EglSharedContexts::new(
+            egl_display,
+            egl_config,
+            egl_context,
+            &conf.platform.gl_context,
+            conf.platform.debug_context,
+        );
         crate::set_display(NativeDisplayData {
             high_dpi: conf.high_dpi,
             blocking_event_loop: conf.platform.blocking_event_loop,
+            shared_contexts: shared_contexts.map(|f| Box::new(f) as _),
             ..NativeDisplayData::new(screen_width as _, screen_height as _, tx, clipboard)
         });
 
@@ -460,7 +534,10 @@ where
             libegl,
             egl_display,
             egl_config,
+            surface_attributes,
             egl_context,
+            gl_context_request: conf.platform.gl_context,
+            debug_context: conf.platform.debug_context,
             surface,
             window,
             event_handler,
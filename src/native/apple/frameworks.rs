@@ -560,13 +560,22 @@ impl MTLClearColor {
 #[allow(non_camel_case_types)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub enum MTLPixelFormat {
+    A8Unorm = 1,
+    RG8Unorm = 30,
     BGRA8Unorm = 80,
     Depth32Float = 252,
     Stencil8 = 253,
     Depth24Unorm_Stencil8 = 255,
     Depth32Float_Stencil8 = 260,
     RGBA8Unorm = 70,
+    RGBA8Unorm_sRGB = 71,
     RGBA16Float = 115,
+    BC1_RGBA = 130,
+    BC3_RGBA = 134,
+    BC7_RGBAUnorm = 152,
+    EAC_RGBA8 = 178,
+    ETC2_RGB8 = 180,
+    ASTC_4x4_LDR = 204,
 }
 
 /// See <https://developer.apple.com/documentation/metal/mtlsamplerminmagfilter>
@@ -18,6 +18,7 @@ pub use core::ptr::null_mut;
 use std::fmt::Display;
 
 pub const EGL_SUCCESS: u32 = 12288;
+pub const EGL_CONTEXT_LOST: u32 = 12302;
 
 pub const EGL_WINDOW_BIT: u32 = 4;
 
@@ -34,6 +35,29 @@ pub const EGL_HEIGHT: u32 = 12374;
 pub const EGL_SURFACE_TYPE: u32 = 12339;
 pub const EGL_NONE: u32 = 12344;
 pub const EGL_CONTEXT_CLIENT_VERSION: u32 = 12440;
+pub const EGL_CONTEXT_MAJOR_VERSION: u32 = 0x3098;
+pub const EGL_CONTEXT_MINOR_VERSION: u32 = 0x30FB;
+pub const EGL_CONTEXT_OPENGL_PROFILE_MASK: u32 = 0x30FD;
+pub const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: u32 = 0x1;
+pub const EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT: u32 = 0x2;
+pub const EGL_CONTEXT_OPENGL_DEBUG: u32 = 0x31B0;
+pub const EGL_CONTEXT_OPENGL_ROBUST_ACCESS: u32 = 0x31B2;
+pub const EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY: u32 = 0x31BD;
+pub const EGL_LOSE_CONTEXT_ON_RESET: u32 = 0x31BF;
+pub const EGL_CONTEXT_OPENGL_NO_ERROR_KHR: u32 = 0x31B3;
+pub const EGL_RENDERABLE_TYPE: u32 = 0x3040;
+pub const EGL_OPENGL_ES2_BIT: u32 = 0x0004;
+pub const EGL_OPENGL_ES3_BIT: u32 = 0x0040;
+pub const EGL_OPENGL_BIT: u32 = 0x0008;
+pub const EGL_OPENGL_ES_API: u32 = 0x30A0;
+pub const EGL_OPENGL_API: u32 = 0x30A2;
+pub const EGL_TRUE: u32 = 1;
+pub const EGL_EXTENSIONS: u32 = 0x3055;
+pub const EGL_COLOR_COMPONENT_TYPE_EXT: u32 = 0x3339;
+pub const EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT: u32 = 0x333B;
+pub const EGL_GL_COLORSPACE_KHR: u32 = 0x309D;
+pub const EGL_GL_COLORSPACE_SRGB_KHR: u32 = 0x3089;
+pub const EGL_CONTEXT_CLIENT_TYPE: u32 = 0x3097;
 
 pub type NativeDisplayType = EGLNativeDisplayType;
 pub type NativePixmapType = EGLNativePixmapType;
@@ -101,6 +125,7 @@ crate::declare_module! {
         EGLint,
         *mut EGLint,
     ) -> EGLBoolean,
+    pub fn eglGetCurrentContext() -> EGLContext,
     pub fn eglGetCurrentDisplay() -> EGLDisplay,
     pub fn eglGetCurrentSurface(EGLint) -> EGLSurface,
     pub fn eglGetDisplay(EGLNativeDisplayType) -> EGLDisplay,
@@ -141,6 +166,7 @@ crate::declare_module! {
         EGLint,
     ) -> EGLBoolean,
     pub fn eglSwapInterval(EGLDisplay, EGLint) -> EGLBoolean,
+    pub fn eglBindAPI(u32) -> EGLBoolean,
     ...
     ...
 }
@@ -171,6 +197,9 @@ pub unsafe fn create_egl_context(
     display: *mut std::ffi::c_void,
     alpha: bool,
     sample_count: i32,
+    framebuffer: &crate::conf::FramebufferConfig,
+    request: &crate::conf::GlContextRequest,
+    debug: bool,
 ) -> Result<(EGLContext, EGLConfig, EGLDisplay), EglError> {
     let display = (egl.eglGetDisplay)(display as _);
     if display.is_null() {
@@ -182,70 +211,384 @@ pub unsafe fn create_egl_context(
         return Err(EglError::InitializeFailed);
     }
 
-    let alpha_size = if alpha { 8 } else { 0 };
+    for (attempt, debug) in request.fallbacks(debug) {
+        if let Some((context, config)) = try_create_context(
+            egl,
+            display,
+            alpha,
+            sample_count,
+            framebuffer,
+            &attempt,
+            debug,
+        ) {
+            return Ok((context, config, display));
+        }
+        eprintln!("EGL: failed to create {attempt:?} (debug: {debug}), trying a simpler context");
+    }
+    Err(EglError::CreateContextFailed)
+}
+
+unsafe fn try_create_context(
+    egl: &mut LibEgl,
+    display: EGLDisplay,
+    alpha: bool,
+    sample_count: i32,
+    framebuffer: &crate::conf::FramebufferConfig,
+    request: &crate::conf::GlContextRequest,
+    debug: bool,
+) -> Option<(EGLContext, EGLConfig)> {
+    use crate::conf::GlApi;
+
+    let desktop = request.api == Some(GlApi::OpenGl);
+    let major = request.version.map_or(2, |(major, _)| major);
+    // with the default request the config is chosen exactly as it always was
+    let renderable_type = match (request.api, request.version) {
+        (None, None) => None,
+        _ if desktop => Some(EGL_OPENGL_BIT),
+        _ if major >= 3 => Some(EGL_OPENGL_ES3_BIT),
+        _ => Some(EGL_OPENGL_ES2_BIT),
+    };
+    // the old, minimal config: RGB8 with a 16 bit depth buffer
+    let minimal = crate::conf::FramebufferConfig {
+        color: crate::conf::FramebufferColor::Rgba8,
+        depth_bits: 16,
+        stencil_bits: 0,
+        srgb: false,
+    };
+    // an untouched `Conf::framebuffer` keeps the config EGL apps always got
+    let framebuffer = if *framebuffer == crate::conf::FramebufferConfig::default() {
+        &minimal
+    } else {
+        framebuffer
+    };
+    let config = choose_config(
+        egl,
+        display,
+        alpha,
+        sample_count,
+        framebuffer,
+        renderable_type,
+    )
+    .or_else(|| choose_config(egl, display, alpha, sample_count, &minimal, renderable_type))?;
+
+    let context = create_context(
+        egl,
+        display,
+        config,
+        /* EGL_NO_CONTEXT */ null_mut(),
+        request,
+        debug,
+    );
+    if context.is_null() {
+        return None;
+    }
+    Some((context, config))
+}
+
+/// Create a context replacing a lost one, with the config the window surface was made for.
+/// Tries the same fallbacks as `create_egl_context`.
+pub(crate) unsafe fn recreate_context(
+    egl: &mut LibEgl,
+    display: EGLDisplay,
+    config: EGLConfig,
+    request: &crate::conf::GlContextRequest,
+    debug: bool,
+) -> Option<EGLContext> {
+    request
+        .fallbacks(debug)
+        .iter()
+        .find_map(|(attempt, debug)| {
+            let context = create_context(
+                egl,
+                display,
+                config,
+                /* EGL_NO_CONTEXT */ null_mut(),
+                attempt,
+                *debug,
+            );
+            (!context.is_null()).then_some(context)
+        })
+}
+
+/// Bind the requested API and create a context, sharing objects with `share` if it is not null.
+unsafe fn create_context(
+    egl: &mut LibEgl,
+    display: EGLDisplay,
+    config: EGLConfig,
+    share: EGLContext,
+    request: &crate::conf::GlContextRequest,
+    debug: bool,
+) -> EGLContext {
+    use crate::conf::{GlApi, GlProfile};
+
+    let desktop = request.api == Some(GlApi::OpenGl);
+    let (major, minor) = request.version.unwrap_or((2, 0));
+    (egl.eglBindAPI)(if desktop {
+        EGL_OPENGL_API
+    } else {
+        EGL_OPENGL_ES_API
+    });
+
+    let mut ctx_attributes = vec![EGL_CONTEXT_MAJOR_VERSION, major as u32];
+    // everything but the major version needs EGL 1.5 or EGL_KHR_create_context
+    if request.version.is_some() {
+        ctx_attributes.extend([EGL_CONTEXT_MINOR_VERSION, minor as u32]);
+    }
+    match request.profile {
+        Some(GlProfile::Core) if desktop => ctx_attributes.extend([
+            EGL_CONTEXT_OPENGL_PROFILE_MASK,
+            EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+        ]),
+        Some(GlProfile::Compatibility) if desktop => ctx_attributes.extend([
+            EGL_CONTEXT_OPENGL_PROFILE_MASK,
+            EGL_CONTEXT_OPENGL_COMPATIBILITY_PROFILE_BIT,
+        ]),
+        _ => {}
+    }
+    if debug {
+        ctx_attributes.extend([EGL_CONTEXT_OPENGL_DEBUG, EGL_TRUE]);
+    }
+    if request.robustness {
+        ctx_attributes.extend([
+            EGL_CONTEXT_OPENGL_ROBUST_ACCESS,
+            EGL_TRUE,
+            EGL_CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY,
+            EGL_LOSE_CONTEXT_ON_RESET,
+        ]);
+    }
+    if request.no_error {
+        ctx_attributes.extend([EGL_CONTEXT_OPENGL_NO_ERROR_KHR, EGL_TRUE]);
+    }
+    ctx_attributes.push(EGL_NONE);
+
+    (egl.eglCreateContext)(display, config, share, ctx_attributes.as_ptr() as _)
+}
+
+unsafe fn choose_config(
+    egl: &mut LibEgl,
+    display: EGLDisplay,
+    alpha: bool,
+    sample_count: i32,
+    framebuffer: &crate::conf::FramebufferConfig,
+    renderable_type: Option<u32>,
+) -> Option<EGLConfig> {
+    let (color_size, alpha_size) = framebuffer.color.bits();
+    let alpha_size = if alpha { alpha_size as i32 } else { 0 };
+    let color_size = color_size as i32;
+    let depth_size = framebuffer.depth_bits as i32;
+    let stencil_size = framebuffer.stencil_bits as i32;
     #[rustfmt::skip]
-    let cfg_attributes = [
+    let mut cfg_attributes = vec![
         EGL_SURFACE_TYPE, EGL_WINDOW_BIT,
-        EGL_RED_SIZE, 8,
-        EGL_GREEN_SIZE, 8,
-        EGL_BLUE_SIZE, 8,
-        EGL_ALPHA_SIZE, alpha_size,
-        EGL_DEPTH_SIZE, 16,
-        EGL_STENCIL_SIZE, 0,
+        EGL_RED_SIZE, color_size as u32,
+        EGL_GREEN_SIZE, color_size as u32,
+        EGL_BLUE_SIZE, color_size as u32,
+        EGL_ALPHA_SIZE, alpha_size as u32,
+        EGL_DEPTH_SIZE, depth_size as u32,
+        EGL_STENCIL_SIZE, stencil_size as u32,
         EGL_SAMPLES, sample_count as u32,
-        EGL_NONE,
     ];
+    if let Some(renderable_type) = renderable_type {
+        cfg_attributes.extend([EGL_RENDERABLE_TYPE, renderable_type]);
+    }
+    if framebuffer.color == crate::conf::FramebufferColor::Rgba16F {
+        if !has_extension(egl, display, "EGL_EXT_pixel_format_float") {
+            return None;
+        }
+        cfg_attributes.extend([
+            EGL_COLOR_COMPONENT_TYPE_EXT,
+            EGL_COLOR_COMPONENT_TYPE_FLOAT_EXT,
+        ]);
+    }
+    cfg_attributes.push(EGL_NONE);
     let mut available_cfgs: Vec<EGLConfig> = vec![null_mut(); 32];
-    let mut cfg_count = 0;
+    let mut cfg_count: EGLint = 0;
 
     (egl.eglChooseConfig)(
         display,
         cfg_attributes.as_ptr() as _,
         available_cfgs.as_ptr() as _,
         32,
-        &mut cfg_count as *mut _ as *mut _,
+        &mut cfg_count,
     );
-    assert!(cfg_count > 0);
+    if cfg_count <= 0 {
+        return None;
+    }
     assert!(cfg_count <= 32);
 
-    // find config with 8-bit rgb buffer if available, ndk sample does not trust egl spec
-    let mut config: EGLConfig = null_mut();
-    let mut exact_cfg_found = false;
-    for c in &mut available_cfgs[0..cfg_count] {
+    // eglChooseConfig treats sizes as minimums and sorts deeper color buffers first,
+    // so look for an exact match. The ndk sample does not trust egl spec either.
+    for c in &mut available_cfgs[0..cfg_count as usize] {
         let mut r: i32 = 0;
         let mut g: i32 = 0;
         let mut b: i32 = 0;
         let mut a: i32 = 0;
         let mut d: i32 = 0;
+        let mut s: i32 = 0;
         if (egl.eglGetConfigAttrib)(display, *c, EGL_RED_SIZE as _, &mut r) == 1
             && (egl.eglGetConfigAttrib)(display, *c, EGL_GREEN_SIZE as _, &mut g) == 1
             && (egl.eglGetConfigAttrib)(display, *c, EGL_BLUE_SIZE as _, &mut b) == 1
             && (egl.eglGetConfigAttrib)(display, *c, EGL_ALPHA_SIZE as _, &mut a) == 1
             && (egl.eglGetConfigAttrib)(display, *c, EGL_DEPTH_SIZE as _, &mut d) == 1
-            && r == 8
-            && g == 8
-            && b == 8
-            && (alpha_size == 0 || a == alpha_size as _)
-            && d == 16
+            && (egl.eglGetConfigAttrib)(display, *c, EGL_STENCIL_SIZE as _, &mut s) == 1
+            && r == color_size
+            && g == color_size
+            && b == color_size
+            && (alpha_size == 0 || a == alpha_size)
+            && d == depth_size
+            && s == stencil_size
         {
-            exact_cfg_found = true;
-            config = *c;
-            break;
+            return Some(*c);
         }
     }
-    if !exact_cfg_found {
-        config = available_cfgs[0];
+    Some(available_cfgs[0])
+}
+
+unsafe fn has_extension(egl: &mut LibEgl, display: EGLDisplay, name: &str) -> bool {
+    let extensions = (egl.eglQueryString)(display, EGL_EXTENSIONS as _);
+    !extensions.is_null()
+        && std::ffi::CStr::from_ptr(extensions)
+            .to_string_lossy()
+            .split(' ')
+            .any(|ext| ext == name)
+}
+
+/// Attributes for `eglCreateWindowSurface`, requesting an sRGB surface
+/// when `framebuffer.srgb` is set and `EGL_KHR_gl_colorspace` is available.
+pub unsafe fn window_surface_attributes(
+    egl: &mut LibEgl,
+    display: EGLDisplay,
+    framebuffer: &crate::conf::FramebufferConfig,
+) -> Vec<EGLint> {
+    let mut attributes = vec![];
+    if framebuffer.srgb && has_extension(egl, display, "EGL_KHR_gl_colorspace") {
+        attributes.extend([
+            EGL_GL_COLORSPACE_KHR as EGLint,
+            EGL_GL_COLORSPACE_SRGB_KHR as EGLint,
+        ]);
     }
-    let ctx_attributes = [EGL_CONTEXT_CLIENT_VERSION, 2, EGL_NONE];
-    let context = (egl.eglCreateContext)(
-        display,
-        config,
-        /* EGL_NO_CONTEXT */ null_mut(),
-        ctx_attributes.as_ptr() as _,
-    );
-    if context.is_null() {
-        return Err(EglError::CreateContextFailed);
+    attributes.push(EGL_NONE as EGLint);
+    attributes
+}
+
+/// Creates contexts sharing objects with the main one, see `window::new_upload_context`.
+pub struct EglSharedContexts {
+    egl: LibEgl,
+    display: EGLDisplay,
+    config: EGLConfig,
+    share: EGLContext,
+    request: crate::conf::GlContextRequest,
+    debug: bool,
+}
+
+// only used on the main thread, through the native display mutex
+unsafe impl Send for EglSharedContexts {}
+
+impl EglSharedContexts {
+    pub fn new(
+        display: EGLDisplay,
+        config: EGLConfig,
+        share: EGLContext,
+        request: &crate::conf::GlContextRequest,
+        debug: bool,
+    ) -> Option<EglSharedContexts> {
+        Some(EglSharedContexts {
+            egl: LibEgl::try_load().ok()?,
+            display,
+            config,
+            share,
+            request: *request,
+            debug,
+        })
     }
+}
 
-    Ok((context, config, display))
+impl crate::native::SharedContextFactory for EglSharedContexts {
+    fn new_shared_context(&mut self) -> Option<Box<dyn crate::native::SharedGlContext>> {
+        unsafe {
+            // same fallbacks as the main context, the first one that works is compatible with it
+            let context =
+                self.request
+                    .fallbacks(self.debug)
+                    .iter()
+                    .find_map(|(attempt, debug)| {
+                        let context = create_context(
+                            &mut self.egl,
+                            self.display,
+                            self.config,
+                            self.share,
+                            attempt,
+                            *debug,
+                        );
+                        (!context.is_null()).then_some(context)
+                    })?;
+            let mut api: EGLint = 0;
+            (self.egl.eglQueryContext)(
+                self.display,
+                context,
+                EGL_CONTEXT_CLIENT_TYPE as _,
+                &mut api,
+            );
+
+            let surface =
+                if has_extension(&mut self.egl, self.display, "EGL_KHR_surfaceless_context") {
+                    /* EGL_NO_SURFACE */
+                    null_mut()
+                } else {
+                    let attributes = [EGL_WIDTH, 1, EGL_HEIGHT, 1, EGL_NONE];
+                    let surface = (self.egl.eglCreatePbufferSurface)(
+                        self.display,
+                        self.config,
+                        attributes.as_ptr() as _,
+                    );
+                    if surface.is_null() {
+                        (self.egl.eglDestroyContext)(self.display, context);
+                        return None;
+                    }
+                    surface
+                };
+            Some(Box::new(EglSharedContext {
+                egl: LibEgl::try_load().ok()?,
+                display: self.display,
+                context,
+                surface,
+                api: api as _,
+            }))
+        }
+    }
+}
+
+struct EglSharedContext {
+    egl: LibEgl,
+    display: EGLDisplay,
+    context: EGLContext,
+    surface: EGLSurface,
+    api: u32,
+}
+
+// EGL handles may be used from any thread, and `egl` is not shared with anyone
+unsafe impl Send for EglSharedContext {}
+
+impl crate::native::SharedGlContext for EglSharedContext {
+    fn make_current(&mut self) -> bool {
+        unsafe {
+            // the bound API is per thread
+            (self.egl.eglBindAPI)(self.api);
+            (self.egl.eglMakeCurrent)(self.display, self.surface, self.surface, self.context) != 0
+        }
+    }
+}
+
+impl Drop for EglSharedContext {
+    fn drop(&mut self) {
+        unsafe {
+            if (self.egl.eglGetCurrentContext)() == self.context {
+                (self.egl.eglMakeCurrent)(self.display, null_mut(), null_mut(), null_mut());
+            }
+            if !self.surface.is_null() {
+                (self.egl.eglDestroySurface)(self.display, self.surface);
+            }
+            (self.egl.eglDestroyContext)(self.display, self.context);
+        }
+    }
 }
@@ -25,6 +25,19 @@ pub type GLfloat = f32;
 pub type GLclampf = f32;
 pub type GLdouble = f64;
 pub type GLclampd = f64;
+pub type GLsync = *mut ::core::ffi::c_void;
+
+pub type GLDEBUGPROC = Option<
+    extern "C" fn(
+        source: GLenum,
+        type_: GLenum,
+        id: GLuint,
+        severity: GLenum,
+        length: GLsizei,
+        message: *const GLchar,
+        user_param: *mut GLvoid,
+    ),
+>;
 
 pub const GL_INT_2_10_10_10_REV: u32 = 0x8D9F;
 pub const GL_PROGRAM_POINT_SIZE: u32 = 0x8642;
@@ -36,6 +49,45 @@ pub const GL_COLOR_ATTACHMENT22: u32 = 0x8CF6;
 pub const GL_DRAW_FRAMEBUFFER: u32 = 0x8CA9;
 pub const GL_FRAMEBUFFER_COMPLETE: u32 = 0x8CD5;
 pub const GL_NUM_EXTENSIONS: u32 = 0x821D;
+pub const GL_CONTEXT_FLAGS: u32 = 0x821E;
+pub const GL_CONTEXT_FLAG_DEBUG_BIT: u32 = 0x00000002;
+pub const GL_CONTEXT_FLAG_ROBUST_ACCESS_BIT: u32 = 0x00000004;
+pub const GL_CONTEXT_FLAG_NO_ERROR_BIT: u32 = 0x00000008;
+pub const GL_CONTEXT_PROFILE_MASK: u32 = 0x9126;
+pub const GL_CONTEXT_CORE_PROFILE_BIT: u32 = 0x00000001;
+pub const GL_CONTEXT_COMPATIBILITY_PROFILE_BIT: u32 = 0x00000002;
+pub const GL_RESET_NOTIFICATION_STRATEGY: u32 = 0x8256;
+pub const GL_LOSE_CONTEXT_ON_RESET: u32 = 0x8252;
+pub const GL_FRAMEBUFFER_SRGB: u32 = 0x8DB9;
+pub const GL_SYNC_GPU_COMMANDS_COMPLETE: u32 = 0x9117;
+pub const GL_ALREADY_SIGNALED: u32 = 0x911A;
+pub const GL_CONDITION_SATISFIED: u32 = 0x911C;
+pub const GL_SRGB: u32 = 0x8C40;
+pub const GL_BACK_LEFT: u32 = 0x0402;
+pub const GL_DEPTH_BITS: u32 = 0x0D56;
+pub const GL_STENCIL_BITS: u32 = 0x0D57;
+pub const GL_SAMPLES: u32 = 0x80A9;
+pub const GL_FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING: u32 = 0x8210;
+pub const GL_FRAMEBUFFER_ATTACHMENT_COMPONENT_TYPE: u32 = 0x8211;
+pub const GL_FRAMEBUFFER_ATTACHMENT_RED_SIZE: u32 = 0x8212;
+pub const GL_FRAMEBUFFER_ATTACHMENT_GREEN_SIZE: u32 = 0x8213;
+pub const GL_FRAMEBUFFER_ATTACHMENT_BLUE_SIZE: u32 = 0x8214;
+pub const GL_FRAMEBUFFER_ATTACHMENT_ALPHA_SIZE: u32 = 0x8215;
+pub const GL_FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE: u32 = 0x8216;
+pub const GL_FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE: u32 = 0x8217;
+pub const GL_DEBUG_OUTPUT: u32 = 0x92E0;
+pub const GL_DEBUG_OUTPUT_SYNCHRONOUS: u32 = 0x8242;
+pub const GL_DEBUG_SOURCE_APPLICATION: u32 = 0x824A;
+pub const GL_DEBUG_TYPE_ERROR: u32 = 0x824C;
+pub const GL_DEBUG_TYPE_PUSH_GROUP: u32 = 0x8269;
+pub const GL_DEBUG_TYPE_POP_GROUP: u32 = 0x826A;
+pub const GL_DEBUG_SEVERITY_HIGH: u32 = 0x9146;
+pub const GL_DEBUG_SEVERITY_MEDIUM: u32 = 0x9147;
+pub const GL_DEBUG_SEVERITY_LOW: u32 = 0x9148;
+pub const GL_DEBUG_SEVERITY_NOTIFICATION: u32 = 0x826B;
+pub const GL_BUFFER: u32 = 0x82E0;
+pub const GL_PROGRAM: u32 = 0x82E2;
+pub const GL_TEXTURE: u32 = 0x1702;
 pub const GL_INFO_LOG_LENGTH: u32 = 0x8B84;
 pub const GL_VERTEX_SHADER: u32 = 0x8B31;
 pub const GL_INCR: u32 = 0x1E02;
@@ -56,6 +108,16 @@ pub const GL_LINK_STATUS: u32 = 0x8B82;
 pub const GL_TEXTURE_CUBE_MAP_POSITIVE_Y: u32 = 0x8517;
 pub const GL_SAMPLE_ALPHA_TO_COVERAGE: u32 = 0x809E;
 pub const GL_RGBA16F: u32 = 0x881A;
+pub const GL_SRGB8_ALPHA8: u32 = 0x8C43;
+pub const GL_COMPRESSED_RGBA_S3TC_DXT1_EXT: u32 = 0x83F1;
+pub const GL_COMPRESSED_RGBA_S3TC_DXT5_EXT: u32 = 0x83F3;
+pub const GL_COMPRESSED_RGBA_BPTC_UNORM: u32 = 0x8E8C;
+pub const GL_COMPRESSED_RGB8_ETC2: u32 = 0x9274;
+pub const GL_COMPRESSED_RGBA8_ETC2_EAC: u32 = 0x9278;
+pub const GL_COMPRESSED_RGBA_ASTC_4x4_KHR: u32 = 0x93B0;
+pub const GL_DEPTH24_STENCIL8: u32 = 0x88F0;
+pub const GL_DEPTH_STENCIL_ATTACHMENT: u32 = 0x821A;
+pub const GL_MAX_SAMPLES: u32 = 0x8D57;
 pub const GL_CONSTANT_ALPHA: u32 = 0x8003;
 pub const GL_READ_FRAMEBUFFER: u32 = 0x8CA8;
 pub const GL_TEXTURE0: u32 = 0x84C0;
@@ -117,6 +179,8 @@ pub const GL_TEXTURE_MIN_FILTER: u32 = 0x2801;
 pub const GL_LINEAR_MIPMAP_NEAREST: u32 = 0x2701;
 pub const GL_EXTENSIONS: u32 = 0x1F03;
 pub const GL_NO_ERROR: u32 = 0;
+pub const GL_TEXTURE_BINDING_2D: u32 = 0x8069;
+pub const GL_TEXTURE_2D_MULTISAMPLE: u32 = 0x9100;
 pub const GL_REPLACE: u32 = 0x1E01;
 pub const GL_KEEP: u32 = 0x1E00;
 pub const GL_CCW: u32 = 0x0901;
@@ -236,6 +300,7 @@ pub const GL_MAX_ARRAY_TEXTURE_LAYERS: u32 = 0x88FF;
 pub const GL_MAX_VERTEX_ATTRIBS: u32 = 0x8869;
 pub const GL_CLAMP_TO_BORDER: u32 = 0x812D;
 pub const GL_TEXTURE_BORDER_COLOR: u32 = 0x1004;
+pub const GL_UNPACK_ROW_LENGTH: u32 = 3314;
 pub const GL_UNPACK_ALIGNMENT: u32 = 3317;
 pub const GL_TEXTURE_SWIZZLE_R: u32 = 36418;
 pub const GL_TEXTURE_SWIZZLE_G: u32 = 36419;
@@ -325,6 +390,16 @@ macro_rules! gl_loader {
             }
         )*
 
+        /// `false` if the loader did not find the function, calling it would panic.
+        pub fn is_gl_func_loaded(name: &str) -> bool {
+            match name {
+                $(
+                    stringify!($fn) => unsafe { (*::std::ptr::addr_of!(__pfns::$fn)).is_some() },
+                )*
+                _ => false,
+            }
+        }
+
         pub fn load_gl_funcs<T: FnMut(&str) -> Option<unsafe extern "C" fn() -> ()>>(mut getprocaddr: T) {
             $(
                 unsafe {
@@ -400,6 +475,22 @@ gl_loader!(
         width: GLsizei,
         height: GLsizei
     ) -> (),
+    fn glTexStorage2DMultisample(
+        target: GLenum,
+        samples: GLsizei,
+        internalformat: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+        fixedsamplelocations: GLboolean
+    ) -> (),
+    fn glTexImage2DMultisample(
+        target: GLenum,
+        samples: GLsizei,
+        internalformat: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+        fixedsamplelocations: GLboolean
+    ) -> (),
     fn glDrawBuffers(n: GLsizei, bufs: *const GLenum) -> (),
     fn glVertexAttribDivisor(index: GLuint, divisor: GLuint) -> (),
     fn glBufferSubData(
@@ -426,6 +517,17 @@ gl_loader!(
         imageSize: GLsizei,
         data: *const GLvoid
     ) -> (),
+    fn glCompressedTexSubImage2D(
+        target: GLenum,
+        level: GLint,
+        xoffset: GLint,
+        yoffset: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        imageSize: GLsizei,
+        data: *const GLvoid
+    ) -> (),
     fn glCompressedTexImage3D(
         target: GLenum,
         level: GLint,
@@ -582,6 +684,7 @@ gl_loader!(
     fn glTexParameteri(target: GLenum, pname: GLenum, param: GLint) -> (),
     fn glGetIntegerv(pname: GLenum, params: *mut GLint) -> (),
     fn glEnable(cap: GLenum) -> (),
+    fn glIsEnabled(cap: GLenum) -> GLboolean,
     fn glBlitFramebuffer(
         srcX0: GLint,
         srcY0: GLint,
@@ -648,7 +751,28 @@ gl_loader!(
     fn glGetQueryObjectui64v(id: GLuint, pname: GLenum, params: *mut GLuint64) -> (),
     fn glFlush() -> (),
     fn glFinish() -> (),
-    fn glPolygonMode(face: GLenum, mode: GLenum) -> ()
+    fn glPolygonMode(face: GLenum, mode: GLenum) -> (),
+    fn glDebugMessageCallback(callback: GLDEBUGPROC, user_param: *const GLvoid) -> (),
+    fn glObjectLabel(
+        identifier: GLenum,
+        name: GLuint,
+        length: GLsizei,
+        label: *const GLchar
+    ) -> (),
+    fn glPushDebugGroup(source: GLenum, id: GLuint, length: GLsizei, message: *const GLchar) -> (),
+    fn glPopDebugGroup() -> (),
+    fn glFenceSync(condition: GLenum, flags: GLbitfield) -> GLsync,
+    fn glClientWaitSync(sync: GLsync, flags: GLbitfield, timeout: GLuint64) -> GLenum,
+    fn glDeleteSync(sync: GLsync) -> (),
+    fn glGetGraphicsResetStatus() -> GLenum,
+    fn glGetGraphicsResetStatusARB() -> GLenum,
+    fn glGetGraphicsResetStatusEXT() -> GLenum,
+    fn glGetFramebufferAttachmentParameteriv(
+        target: GLenum,
+        attachment: GLenum,
+        pname: GLenum,
+        params: *mut GLint
+    ) -> ()
 );
 
 // note that glGetString only works after first glSwapBuffer,
@@ -663,3 +787,16 @@ pub unsafe fn is_gl2() -> bool {
         || version_string.starts_with("2")
         || version_string.starts_with("OpenGL ES 2")
 }
+
+/// Turn on linear to sRGB conversion when writing to an sRGB capable default framebuffer.
+/// GLES does it for sRGB surfaces without being asked, and has no `GL_FRAMEBUFFER_SRGB`.
+pub unsafe fn enable_framebuffer_srgb() {
+    let version_string = glGetString(super::gl::GL_VERSION);
+    if !version_string.is_null()
+        && !std::ffi::CStr::from_ptr(version_string as _)
+            .to_bytes()
+            .starts_with(b"OpenGL ES")
+    {
+        glEnable(GL_FRAMEBUFFER_SRGB);
+    }
+}
@@ -631,6 +631,9 @@ where
             wdisplay as *mut _,
             conf.platform.framebuffer_alpha,
             conf.sample_count,
+            &conf.framebuffer,
+            &conf.platform.gl_context,
+            conf.platform.debug_context,
         )
         .unwrap();
 
@@ -698,11 +701,13 @@ where
             conf.window_height as _,
         );
 
+        let surface_attributes =
+            egl::window_surface_attributes(&mut libegl, egl_display, &conf.framebuffer);
         let egl_surface = (libegl.eglCreateWindowSurface)(
             egl_display,
             config,
             display.egl_window as _,
-            std::ptr::null_mut(),
+            surface_attributes.as_ptr() as _,
         );
 
         if egl_surface.is_null() {
@@ -728,6 +733,17 @@ where
             let name = std::ffi::CString::new(proc).unwrap();
             (libegl.eglGetProcAddress)(name.as_ptr() as _)
         });
+        if conf.framebuffer.srgb {
+            crate::native::gl::enable_framebuffer_srgb();
+        }
+        crate::native_display().lock().unwrap().shared_contexts = egl::EglSharedContexts::new(
+            egl_display,
+            config,
+            context,
+            &conf.platform.gl_context,
+            conf.platform.debug_context,
+        )
+        .map(|f| Box::new(f) as _);
 
         if !display.decoration_manager.is_null() {
             let server_decoration: *mut extensions::xdg_decoration::zxdg_toplevel_decoration_v1 = wl_request_constructor!(
@@ -445,7 +445,13 @@ where
             .libx11
             .create_window(display.root, display.display, visual, depth, conf);
 
-    let (glx_context, glx_window) = glx.create_context(display.display, display.window);
+    let (glx_context, glx_window) = glx.create_context(
+        &mut display.libx11,
+        display.display,
+        display.window,
+        &conf.platform.gl_context,
+        conf.platform.debug_context,
+    );
     glx.swap_interval(
         display.display,
         glx_window,
@@ -453,6 +459,9 @@ where
         conf.platform.swap_interval.unwrap_or(1),
     );
     gl::load_gl_funcs(|proc| glx.libgl.get_procaddr(proc));
+    if conf.framebuffer.srgb {
+        gl::enable_framebuffer_srgb();
+    }
 
     display.init_drag_n_drop();
     display.libx11.show_window(display.display, display.window);
@@ -469,10 +478,12 @@ where
         display.display,
         display.window,
     ));
+    let shared_contexts = glx.shared_contexts(&display.libx11, display.display, glx_context);
     crate::set_display(NativeDisplayData {
         high_dpi: conf.high_dpi,
         dpi_scale: display.libx11.update_system_dpi(display.display),
         blocking_event_loop: conf.platform.blocking_event_loop,
+        shared_contexts: shared_contexts.map(|f| Box::new(f) as _),
         ..NativeDisplayData::new(w, h, tx, clipboard)
     });
     if conf.fullscreen {
@@ -544,11 +555,20 @@ where
         display.display as *mut _,
         conf.platform.framebuffer_alpha,
         conf.sample_count,
+        &conf.framebuffer,
+        &conf.platform.gl_context,
+        conf.platform.debug_context,
     )
     .unwrap();
 
-    let egl_surface =
-        (egl_lib.eglCreateWindowSurface)(egl_display, config, display.window, std::ptr::null_mut());
+    let surface_attributes =
+        egl::window_surface_attributes(&mut egl_lib, egl_display, &conf.framebuffer);
+    let egl_surface = (egl_lib.eglCreateWindowSurface)(
+        egl_display,
+        config,
+        display.window,
+        surface_attributes.as_ptr() as _,
+    );
 
     if egl_surface.is_null() {
         // == EGL_NO_SURFACE
@@ -562,6 +582,9 @@ where
         let name = std::ffi::CString::new(proc).unwrap();
         (egl_lib.eglGetProcAddress)(name.as_ptr() as _)
     });
+    if conf.framebuffer.srgb {
+        crate::native::gl::enable_framebuffer_srgb();
+    }
 
     display.init_drag_n_drop();
     display.libx11.show_window(display.display, display.window);
@@ -575,10 +598,18 @@ where
         display.display,
         display.window,
     ));
+    let shared_contexts = egl::EglSharedContexts::new(
+        egl_display,
+        config,
+        context,
+        &conf.platform.gl_context,
+        conf.platform.debug_context,
+    );
     crate::set_display(NativeDisplayData {
         high_dpi: conf.high_dpi,
         dpi_scale: display.libx11.update_system_dpi(display.display),
         blocking_event_loop: conf.platform.blocking_event_loop,
+        shared_contexts: shared_contexts.map(|f| Box::new(f) as _),
         ..NativeDisplayData::new(w, h, tx, clipboard)
     });
     if conf.fullscreen {
@@ -8,6 +8,7 @@ pub type GLXContext = *mut ();
 pub type GLXFBConfig = *mut ();
 pub type GLXWindow = XID;
 pub type GLXDrawable = XID;
+pub type GLXPbuffer = XID;
 
 pub const GLX_VENDOR: libc::c_int = 1 as libc::c_int;
 pub const GLX_RENDER_TYPE: libc::c_int = 0x8011 as libc::c_int;
@@ -22,13 +23,25 @@ pub const GLX_DEPTH_SIZE: libc::c_int = 12 as libc::c_int;
 pub const GLX_STENCIL_SIZE: libc::c_int = 13 as libc::c_int;
 pub const GLX_DOUBLEBUFFER: libc::c_int = 5 as libc::c_int;
 pub const GLX_SAMPLES: libc::c_int = 0x186a1 as libc::c_int;
+pub const GLX_RGBA_FLOAT_BIT_ARB: libc::c_int = 0x4 as libc::c_int;
+pub const GLX_RGBA_FLOAT_TYPE_ARB: libc::c_int = 0x20b9 as libc::c_int;
+pub const GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB: libc::c_int = 0x20b2 as libc::c_int;
+pub const GLX_PBUFFER_WIDTH: libc::c_int = 0x8041 as libc::c_int;
+pub const GLX_PBUFFER_HEIGHT: libc::c_int = 0x8040 as libc::c_int;
 
 pub const GLX_CONTEXT_MAJOR_VERSION_ARB: libc::c_int = 0x2091 as libc::c_int;
 pub const GLX_CONTEXT_MINOR_VERSION_ARB: libc::c_int = 0x2092 as libc::c_int;
 pub const GLX_CONTEXT_PROFILE_MASK_ARB: libc::c_int = 0x9126 as libc::c_int;
 pub const GLX_CONTEXT_CORE_PROFILE_BIT_ARB: libc::c_int = 0x1 as libc::c_int;
+pub const GLX_CONTEXT_DEBUG_BIT_ARB: libc::c_int = 0x1 as libc::c_int;
 pub const GLX_CONTEXT_FLAGS_ARB: libc::c_int = 0x2094 as libc::c_int;
 pub const GLX_CONTEXT_FORWARD_COMPATIBLE_BIT_ARB: libc::c_int = 0x2 as libc::c_int;
+pub const GLX_CONTEXT_ROBUST_ACCESS_BIT_ARB: libc::c_int = 0x4 as libc::c_int;
+pub const GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB: libc::c_int = 0x2 as libc::c_int;
+pub const GLX_CONTEXT_ES2_PROFILE_BIT_EXT: libc::c_int = 0x4 as libc::c_int;
+pub const GLX_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB: libc::c_int = 0x8256 as libc::c_int;
+pub const GLX_LOSE_CONTEXT_ON_RESET_ARB: libc::c_int = 0x8252 as libc::c_int;
+pub const GLX_CONTEXT_OPENGL_NO_ERROR_ARB: libc::c_int = 0x31b3 as libc::c_int;
 
 pub type GLenum = ::core::ffi::c_uint;
 pub type GLboolean = ::core::ffi::c_uchar;
@@ -64,6 +77,20 @@ pub type PFNGLXGETFBCONFIGSPROC = Option<
 >;
 pub type PFNGLXMAKECURRENTPROC =
     Option<unsafe extern "C" fn(_: *mut Display, _: GLXDrawable, _: GLXContext) -> libc::c_int>;
+pub type PFNGLXMAKECONTEXTCURRENTPROC = Option<
+    unsafe extern "C" fn(
+        _: *mut Display,
+        _: GLXDrawable,
+        _: GLXDrawable,
+        _: GLXContext,
+    ) -> libc::c_int,
+>;
+pub type PFNGLXGETCURRENTCONTEXTPROC = Option<unsafe extern "C" fn() -> GLXContext>;
+pub type PFNGLXCREATEPBUFFERPROC = Option<
+    unsafe extern "C" fn(_: *mut Display, _: GLXFBConfig, _: *const libc::c_int) -> GLXPbuffer,
+>;
+pub type PFNGLXDESTROYPBUFFERPROC =
+    Option<unsafe extern "C" fn(_: *mut Display, _: GLXPbuffer) -> ()>;
 pub type PFNGLXSWAPINTERVALMESAPROC = Option<unsafe extern "C" fn(_: libc::c_int) -> libc::c_int>;
 pub type PFNGLXSWAPINTERVALEXTPROC =
     Option<unsafe extern "C" fn(_: *mut Display, _: GLXDrawable, _: libc::c_int) -> ()>;
@@ -117,6 +144,10 @@ pub struct LibGlx {
     pub glxQueryVersion: PFNGLXQUERYVERSIONPROC,
     pub glxDestroyContext: PFNGLXDESTROYCONTEXTPROC,
     pub glxMakeCurrent: PFNGLXMAKECURRENTPROC,
+    pub glxMakeContextCurrent: PFNGLXMAKECONTEXTCURRENTPROC,
+    pub glxGetCurrentContext: PFNGLXGETCURRENTCONTEXTPROC,
+    pub glxCreatePbuffer: PFNGLXCREATEPBUFFERPROC,
+    pub glxDestroyPbuffer: PFNGLXDESTROYPBUFFERPROC,
     pub glxSwapBuffers: PFNGLXSWAPBUFFERSPROC,
     pub glxQueryExtensionsString: PFNGLXQUERYEXTENSIONSSTRINGPROC,
     pub glxCreateNewContext: PFNGLXCREATENEWCONTEXTPROC,
@@ -139,6 +170,10 @@ impl LibGlx {
                 glxQueryVersion: module.get_symbol("glXQueryVersion").ok(),
                 glxDestroyContext: module.get_symbol("glXDestroyContext").ok(),
                 glxMakeCurrent: module.get_symbol("glXMakeCurrent").ok(),
+                glxMakeContextCurrent: module.get_symbol("glXMakeContextCurrent").ok(),
+                glxGetCurrentContext: module.get_symbol("glXGetCurrentContext").ok(),
+                glxCreatePbuffer: module.get_symbol("glXCreatePbuffer").ok(),
+                glxDestroyPbuffer: module.get_symbol("glXDestroyPbuffer").ok(),
                 glxSwapBuffers: module.get_symbol("glXSwapBuffers").ok(),
                 glxQueryExtensionsString: module.get_symbol("glXQueryExtensionsString").ok(),
                 glxCreateNewContext: module.get_symbol("glXCreateNewContext").ok(),
@@ -175,6 +210,8 @@ pub struct GLFBConfig {
     pub stencil_bits: libc::c_int,
     pub samples: libc::c_int,
     pub doublebuffer: bool,
+    pub srgb: bool,
+    pub float: bool,
     pub handle: libc::c_ulong,
 }
 
@@ -189,6 +226,8 @@ impl Default for GLFBConfig {
             stencil_bits: -1,
             samples: -1,
             doublebuffer: false,
+            srgb: false,
+            float: false,
             handle: 0,
         }
     }
@@ -206,6 +245,9 @@ pub struct Glx {
     multisample: bool,
     extensions: GlxExtensions,
     fbconfig: GLXFBConfig,
+    float_fbconfig: bool,
+    // attributes the main context was created with, shared contexts reuse them
+    context_attribs: Vec<libc::c_int>,
     pub visual: *mut Visual,
     pub depth: i32,
 }
@@ -256,18 +298,17 @@ impl Glx {
         // _sapp_glx_ARB_create_context_profile =
         //     _sapp_glx_extsupported(b"GLX_ARB_create_context_profile\x00", exts);
 
-        let fbconfig = choose_fbconfig(
+        let chosen = choose_fbconfig(
             &mut libgl,
             libx11,
             display,
             screen,
-            multisample,
+            &extensions,
             conf.sample_count,
-        );
-        assert!(
-            !fbconfig.is_null(),
-            "GLX: Failed to find a suitable GLXFBConfig"
-        );
+            &conf.framebuffer,
+        )
+        .expect("GLX: Failed to find a suitable GLXFBConfig");
+        let fbconfig = chosen.handle as GLXFBConfig;
 
         let result = libgl.glxGetVisualFromFBConfig.unwrap()(display, fbconfig);
         assert!(
@@ -310,38 +351,49 @@ impl Glx {
             depth,
             extensions,
             fbconfig,
+            float_fbconfig: chosen.float,
+            context_attribs: vec![],
         })
     }
 
     pub unsafe fn create_context(
         &mut self,
+        libx11: &mut LibX11,
         display: *mut Display,
         window: Window,
+        request: &crate::conf::GlContextRequest,
+        debug: bool,
     ) -> (GLXContext, GLXWindow) {
         if self.extensions.glxCreateContextAttribsARB.is_none() {
             panic!("GLX: ARB_create_context and ARB_create_context_profile required");
         }
 
-        // _sapp_x11_grab_error_handler(libx11);
-        let attribs: [libc::c_int; 8] = [
-            GLX_CONTEXT_MAJOR_VERSION_ARB,
-            2,
-            GLX_CONTEXT_MINOR_VERSION_ARB,
-            1,
-            GLX_CONTEXT_FLAGS_ARB,
-            GLX_CONTEXT_CORE_PROFILE_BIT_ARB,
-            0,
-            0,
-        ];
-        let glx_ctx = self.extensions.glxCreateContextAttribsARB.unwrap()(
-            display,
-            self.fbconfig,
-            std::ptr::null_mut(),
-            true as _,
-            attribs.as_ptr(),
-        );
+        let mut glx_ctx = std::ptr::null_mut();
+        for (attempt, debug) in request.fallbacks(debug) {
+            let attribs = match self.context_attribs(&attempt, debug) {
+                Some(attribs) => attribs,
+                None => continue,
+            };
+            // a failed glXCreateContextAttribsARB is reported as an X error,
+            // which would terminate the app with the default handler
+            libx11.grab_error_handler();
+            glx_ctx = self.extensions.glxCreateContextAttribsARB.unwrap()(
+                display,
+                self.fbconfig,
+                std::ptr::null_mut(),
+                true as _,
+                attribs.as_ptr(),
+            );
+            libx11.release_error_handler(display);
+            if !glx_ctx.is_null() {
+                self.context_attribs = attribs;
+                break;
+            }
+            eprintln!(
+                "GLX: failed to create {attempt:?} (debug: {debug}), trying a simpler context"
+            );
+        }
         assert!(!glx_ctx.is_null(), "GLX: failed to create GL context");
-        // _sapp_x11_release_error_handler(libx11);
 
         let glx_window =
             self.libgl.glxCreateWindow.unwrap()(display, self.fbconfig, window, std::ptr::null());
@@ -350,6 +402,62 @@ impl Glx {
         (glx_ctx, glx_window)
     }
 
+    /// None if the GLX implementation lacks an extension required by the request.
+    fn context_attribs(
+        &self,
+        request: &crate::conf::GlContextRequest,
+        debug: bool,
+    ) -> Option<Vec<libc::c_int>> {
+        use crate::conf::{GlApi, GlProfile};
+
+        let has = |ext: &str| self.extensions.extensions_string.contains(ext);
+        let (major, minor) = request.version.unwrap_or((2, 1));
+
+        let mut attribs = vec![
+            GLX_CONTEXT_MAJOR_VERSION_ARB,
+            major as _,
+            GLX_CONTEXT_MINOR_VERSION_ARB,
+            minor as _,
+        ];
+        if self.float_fbconfig {
+            attribs.extend([GLX_RENDER_TYPE, GLX_RGBA_FLOAT_TYPE_ARB]);
+        }
+        let profile = match (request.api, request.profile) {
+            (Some(GlApi::Gles), _) if !has("GLX_EXT_create_context_es2_profile") => return None,
+            (Some(GlApi::Gles), _) => Some(GLX_CONTEXT_ES2_PROFILE_BIT_EXT),
+            (_, Some(GlProfile::Core)) => Some(GLX_CONTEXT_CORE_PROFILE_BIT_ARB),
+            (_, Some(GlProfile::Compatibility)) => Some(GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB),
+            (_, None) => None,
+        };
+        if let Some(profile) = profile {
+            attribs.extend([GLX_CONTEXT_PROFILE_MASK_ARB, profile]);
+        }
+
+        let mut flags = 0;
+        if debug {
+            flags |= GLX_CONTEXT_DEBUG_BIT_ARB;
+        }
+        if request.robustness {
+            if !has("GLX_ARB_create_context_robustness") {
+                return None;
+            }
+            flags |= GLX_CONTEXT_ROBUST_ACCESS_BIT_ARB;
+            attribs.extend([
+                GLX_CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB,
+                GLX_LOSE_CONTEXT_ON_RESET_ARB,
+            ]);
+        }
+        attribs.extend([GLX_CONTEXT_FLAGS_ARB, flags]);
+        if request.no_error {
+            if !has("GLX_ARB_create_context_no_error") {
+                return None;
+            }
+            attribs.extend([GLX_CONTEXT_OPENGL_NO_ERROR_ARB, 1]);
+        }
+        attribs.extend([0, 0]);
+        Some(attribs)
+    }
+
     pub unsafe fn destroy_context(
         &mut self,
         display: *mut Display,
@@ -394,15 +502,133 @@ impl Glx {
     }
 }
 
+impl Glx {
+    /// Factory for contexts sharing objects with `share`, see `window::new_upload_context`.
+    pub fn shared_contexts(
+        &self,
+        libx11: &LibX11,
+        display: *mut Display,
+        share: GLXContext,
+    ) -> Option<GlxSharedContexts> {
+        Some(GlxSharedContexts {
+            libx11: libx11.clone(),
+            libgl: LibGlx::try_load().ok()?,
+            display,
+            fbconfig: self.fbconfig,
+            share,
+            create_context: self.extensions.glxCreateContextAttribsARB,
+            attribs: self.context_attribs.clone(),
+        })
+    }
+}
+
+pub struct GlxSharedContexts {
+    libx11: LibX11,
+    libgl: LibGlx,
+    display: *mut Display,
+    fbconfig: GLXFBConfig,
+    share: GLXContext,
+    create_context: PFNGLXCREATECONTEXTATTRIBSARBPROC,
+    attribs: Vec<libc::c_int>,
+}
+
+// only used on the main thread, through the native display mutex
+unsafe impl Send for GlxSharedContexts {}
+
+impl crate::native::SharedContextFactory for GlxSharedContexts {
+    fn new_shared_context(&mut self) -> Option<Box<dyn crate::native::SharedGlContext>> {
+        unsafe {
+            let libgl = LibGlx::try_load().ok()?;
+            let make_context_current = libgl.glxMakeContextCurrent?;
+            let pbuffer_attribs = [GLX_PBUFFER_WIDTH, 1, GLX_PBUFFER_HEIGHT, 1, 0];
+
+            self.libx11.grab_error_handler();
+            let context = (self.create_context?)(
+                self.display,
+                self.fbconfig,
+                self.share,
+                true as _,
+                self.attribs.as_ptr(),
+            );
+            // window configs do not have to support pbuffers, GL3+ contexts
+            // can be made current without a drawable then
+            let pbuffer = match self.libgl.glxCreatePbuffer {
+                Some(create_pbuffer) if !context.is_null() => {
+                    create_pbuffer(self.display, self.fbconfig, pbuffer_attribs.as_ptr())
+                }
+                _ => 0,
+            };
+            self.libx11.release_error_handler(self.display);
+            if context.is_null() {
+                return None;
+            }
+            // attribs start with GLX_CONTEXT_MAJOR_VERSION_ARB
+            if pbuffer == 0 && self.attribs[1] < 3 {
+                self.libgl.glxDestroyContext.unwrap()(self.display, context);
+                return None;
+            }
+            Some(Box::new(GlxSharedContext {
+                libgl,
+                make_context_current,
+                display: self.display,
+                context,
+                pbuffer,
+            }))
+        }
+    }
+}
+
+struct GlxSharedContext {
+    libgl: LibGlx,
+    make_context_current:
+        unsafe extern "C" fn(*mut Display, GLXDrawable, GLXDrawable, GLXContext) -> libc::c_int,
+    display: *mut Display,
+    context: GLXContext,
+    pbuffer: GLXPbuffer,
+}
+
+// the display is opened after XInitThreads, so it can be used from other threads
+unsafe impl Send for GlxSharedContext {}
+
+impl crate::native::SharedGlContext for GlxSharedContext {
+    fn make_current(&mut self) -> bool {
+        unsafe {
+            (self.make_context_current)(self.display, self.pbuffer, self.pbuffer, self.context) != 0
+        }
+    }
+}
+
+impl Drop for GlxSharedContext {
+    fn drop(&mut self) {
+        unsafe {
+            if self.libgl.glxGetCurrentContext.map(|f| f()) == Some(self.context) {
+                (self.make_context_current)(self.display, 0, 0, std::ptr::null_mut());
+            }
+            if self.pbuffer != 0 {
+                if let Some(destroy) = self.libgl.glxDestroyPbuffer {
+                    destroy(self.display, self.pbuffer);
+                }
+            }
+            self.libgl.glxDestroyContext.unwrap()(self.display, self.context);
+        }
+    }
+}
+
 // TODO: this code came a long way from sokol_app, better reimplement it!
 unsafe fn choose_fbconfig(
     libgl: &mut LibGlx,
     libx11: &mut super::LibX11,
     display: *mut Display,
     screen: i32,
-    multisample: bool,
+    extensions: &str,
     desired_sample_count: i32,
-) -> GLXFBConfig {
+    framebuffer: &crate::conf::FramebufferConfig,
+) -> Option<GLFBConfig> {
+    let multisample = extensions.contains("GLX_ARB_multisample");
+    let srgb = extensions.contains("GLX_ARB_framebuffer_sRGB")
+        || extensions.contains("GLX_EXT_framebuffer_sRGB");
+    let float = extensions.contains("GLX_ARB_fbconfig_float");
+
     let mut native_count: libc::c_int = 0;
     let mut usable_count;
     let mut trust_window_bit = true;
@@ -433,7 +659,10 @@ unsafe fn choose_fbconfig(
             value
         };
 
-        if 0 == glx_attrib(n, GLX_RENDER_TYPE) & GLX_RGBA_BIT {
+        let render_type = glx_attrib(n, GLX_RENDER_TYPE);
+        if float && render_type & GLX_RGBA_FLOAT_BIT_ARB != 0 {
+            u.float = true;
+        } else if 0 == render_type & GLX_RGBA_BIT {
             continue;
         }
         if 0 == glx_attrib(n, GLX_DRAWABLE_TYPE) & GLX_WINDOW_BIT {
@@ -454,21 +683,27 @@ unsafe fn choose_fbconfig(
         if multisample {
             u.samples = glx_attrib(n, GLX_SAMPLES)
         }
+        if srgb {
+            u.srgb = glx_attrib(n, GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB) != 0;
+        }
         u.handle = n as libc::c_ulong;
         usable_configs.push(u);
         usable_count += 1
     }
 
-    let mut result = 0 as GLXFBConfig;
+    let mut result = None;
     #[allow(clippy::field_reassign_with_default)]
     {
+        let (color_bits, alpha_bits) = framebuffer.color.bits();
         let mut desired = GLFBConfig::default();
-        desired.red_bits = 8;
-        desired.green_bits = 8;
-        desired.blue_bits = 8;
-        desired.alpha_bits = 8;
-        desired.depth_bits = 24;
-        desired.stencil_bits = 8;
+        desired.red_bits = color_bits as _;
+        desired.green_bits = color_bits as _;
+        desired.blue_bits = color_bits as _;
+        desired.alpha_bits = alpha_bits as _;
+        desired.depth_bits = framebuffer.depth_bits as _;
+        desired.stencil_bits = framebuffer.stencil_bits as _;
+        desired.srgb = framebuffer.srgb;
+        desired.float = framebuffer.color == crate::conf::FramebufferColor::Rgba16F;
         desired.doublebuffer = true;
         desired.samples = if desired_sample_count > 1 {
             desired_sample_count
@@ -482,7 +717,7 @@ unsafe fn choose_fbconfig(
             usable_count as libc::c_uint,
         );
         if !closest.is_null() {
-            result = (*closest).handle as GLXFBConfig
+            result = Some(*closest)
         }
         (libx11.XFree)(native_configs as *mut libc::c_void);
     }
@@ -517,6 +752,13 @@ pub unsafe extern "C" fn gl_choose_fbconfig(
             if (*desired).stencil_bits > 0 && (*current).stencil_bits == 0 {
                 missing += 1;
             }
+            if (*desired).srgb && !(*current).srgb {
+                missing += 1;
+            }
+            // a float buffer is only fine when asked for, it changes the context type
+            if (*desired).float != (*current).float {
+                missing += 1;
+            }
             if (*desired).samples > 0 && (*current).samples == 0 {
                 // Technically, several multisampling buffers could be
                 //  involved, but that's a lower level implentation detail and
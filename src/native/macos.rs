@@ -795,10 +795,13 @@ unsafe fn create_metal_view(_: &mut MacosDisplay, sample_count: i32, _: bool) ->
 unsafe fn create_opengl_view(
     display: &mut MacosDisplay,
     sample_count: i32,
+    framebuffer: &crate::conf::FramebufferConfig,
     high_dpi: bool,
 ) -> ObjcId {
     use NSOpenGLPixelFormatAttribute::*;
 
+    let (color_bits, alpha_bits) = framebuffer.color.bits();
+
     let mut attrs: Vec<u32> = vec![];
 
     attrs.push(NSOpenGLPFAAccelerated as _);
@@ -806,13 +809,16 @@ unsafe fn create_opengl_view(
     attrs.push(NSOpenGLPFAOpenGLProfile as _);
     attrs.push(NSOpenGLPFAOpenGLProfiles::NSOpenGLProfileVersion3_2Core as _);
     attrs.push(NSOpenGLPFAColorSize as _);
-    attrs.push(24);
+    attrs.push(color_bits as u32 * 3);
     attrs.push(NSOpenGLPFAAlphaSize as _);
-    attrs.push(8);
+    attrs.push(alpha_bits as _);
+    if framebuffer.color == crate::conf::FramebufferColor::Rgba16F {
+        attrs.push(NSOpenGLPFAColorFloat as _);
+    }
     attrs.push(NSOpenGLPFADepthSize as _);
-    attrs.push(24);
+    attrs.push(framebuffer.depth_bits as _);
     attrs.push(NSOpenGLPFAStencilSize as _);
-    attrs.push(8);
+    attrs.push(framebuffer.stencil_bits as _);
     if sample_count > 1 {
         attrs.push(NSOpenGLPFAMultisample as _);
         attrs.push(NSOpenGLPFASampleBuffers as _);
@@ -1081,7 +1087,12 @@ where
     let () = msg_send![window, setTitle: title];
 
     let view = match conf.platform.apple_gfx_api {
-        AppleGfxApi::OpenGl => create_opengl_view(&mut display, conf.sample_count, conf.high_dpi),
+        AppleGfxApi::OpenGl => create_opengl_view(
+            &mut display,
+            conf.sample_count,
+            &conf.framebuffer,
+            conf.high_dpi,
+        ),
         AppleGfxApi::Metal => create_metal_view(&mut display, conf.sample_count, conf.high_dpi),
     };
     {
@@ -137,6 +137,8 @@ extern "C" {
     pub fn sapp_set_window_size(new_width: u32, new_height: u32);
     pub fn sapp_schedule_update();
     pub fn init_webgl(version: i32);
+    /// Lose the WebGL context with `WEBGL_lose_context` and restore it a second later.
+    pub fn sapp_lose_context();
     pub fn now() -> f64;
 }
 
@@ -340,6 +342,22 @@ pub extern "C" fn focus(has_focus: bool) {
     });
 }
 
+#[no_mangle]
+pub extern "C" fn on_context_lost() {
+    crate::native_display().lock().unwrap().context_lost = true;
+    tl_event_handler(|event_handler| event_handler.context_lost_event());
+}
+
+#[no_mangle]
+pub extern "C" fn on_context_restored() {
+    {
+        let mut d = crate::native_display().lock().unwrap();
+        d.context_lost = false;
+        d.context_generation += 1;
+    }
+    tl_event_handler(|event_handler| event_handler.context_restored_event());
+}
+
 #[no_mangle]
 pub extern "C" fn on_files_dropped_start() {
     let mut d = crate::native_display().lock().unwrap();
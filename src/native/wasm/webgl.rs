@@ -61,6 +61,16 @@ pub const GL_LINK_STATUS: u32 = 0x8B82;
 pub const GL_TEXTURE_CUBE_MAP_POSITIVE_Y: u32 = 0x8517;
 pub const GL_SAMPLE_ALPHA_TO_COVERAGE: u32 = 0x809E;
 pub const GL_RGBA16F: u32 = 0x881A;
+pub const GL_SRGB8_ALPHA8: u32 = 0x8C43;
+pub const GL_COMPRESSED_RGBA_S3TC_DXT1_EXT: u32 = 0x83F1;
+pub const GL_COMPRESSED_RGBA_S3TC_DXT5_EXT: u32 = 0x83F3;
+pub const GL_COMPRESSED_RGBA_BPTC_UNORM: u32 = 0x8E8C;
+pub const GL_COMPRESSED_RGB8_ETC2: u32 = 0x9274;
+pub const GL_COMPRESSED_RGBA8_ETC2_EAC: u32 = 0x9278;
+pub const GL_COMPRESSED_RGBA_ASTC_4x4_KHR: u32 = 0x93B0;
+pub const GL_DEPTH24_STENCIL8: u32 = 0x88F0;
+pub const GL_DEPTH_STENCIL_ATTACHMENT: u32 = 0x821A;
+pub const GL_MAX_SAMPLES: u32 = 0x8D57;
 pub const GL_CONSTANT_ALPHA: u32 = 0x8003;
 pub const GL_READ_FRAMEBUFFER: u32 = 0x8CA8;
 pub const GL_TEXTURE0: u32 = 0x84C0;
@@ -168,6 +178,9 @@ pub const GL_LUMINANCE: u32 = 6409;
 pub const GL_LUMINANCE_ALPHA: u32 = 6410;
 pub const GL_ALPHA_BITS: u32 = 3413;
 pub const GL_RED_BITS: u32 = 3410;
+pub const GL_DEPTH_BITS: u32 = 0x0D56;
+pub const GL_STENCIL_BITS: u32 = 0x0D57;
+pub const GL_SAMPLES: u32 = 0x80A9;
 pub const GL_GREEN_BITS: u32 = 3411;
 pub const GL_BLUE_BITS: u32 = 3412;
 pub const GL_INDEX_BITS: u32 = 3409;
@@ -241,6 +254,7 @@ pub const GL_MAX_ARRAY_TEXTURE_LAYERS: u32 = 0x88FF;
 pub const GL_MAX_VERTEX_ATTRIBS: u32 = 0x8869;
 pub const GL_CLAMP_TO_BORDER: u32 = 0x812D;
 pub const GL_TEXTURE_BORDER_COLOR: u32 = 0x1004;
+pub const GL_UNPACK_ROW_LENGTH: u32 = 3314;
 pub const GL_UNPACK_ALIGNMENT: u32 = 3317;
 pub const GL_TEXTURE_SWIZZLE_R: u32 = 36418;
 pub const GL_TEXTURE_SWIZZLE_G: u32 = 36419;
@@ -935,6 +935,7 @@ where
         let gl_ctx = wgl.create_context(
             &mut display,
             conf.sample_count,
+            &conf.framebuffer,
             conf.platform.swap_interval.unwrap_or(1),
         );
 
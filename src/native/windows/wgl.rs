@@ -315,7 +315,12 @@ impl Wgl {
         value
     }
 
-    unsafe fn wgl_find_pixel_format(&self, display: &mut WindowsDisplay, sample_count: i32) -> u32 {
+    unsafe fn wgl_find_pixel_format(
+        &self,
+        display: &mut WindowsDisplay,
+        sample_count: i32,
+        framebuffer: &crate::conf::FramebufferConfig,
+    ) -> u32 {
         let native_count = self.wgl_attrib(display, 1, WGL_NUMBER_PIXEL_FORMATS_ARB as _);
         let mut usable_configs = vec![GlFbconfig::default(); native_count as usize];
 
@@ -357,13 +362,15 @@ impl Wgl {
         let mut pixel_format = 0;
         #[allow(clippy::field_reassign_with_default)]
         {
+            // float and sRGB pixel formats are not requested on WGL yet
+            let (color_bits, alpha_bits) = framebuffer.color.bits();
             let mut desired = GlFbconfig::default();
-            desired.red_bits = 8;
-            desired.green_bits = 8;
-            desired.blue_bits = 8;
-            desired.alpha_bits = 8;
-            desired.depth_bits = 24;
-            desired.stencil_bits = 8;
+            desired.red_bits = color_bits as _;
+            desired.green_bits = color_bits as _;
+            desired.blue_bits = color_bits as _;
+            desired.alpha_bits = alpha_bits as _;
+            desired.depth_bits = framebuffer.depth_bits as _;
+            desired.stencil_bits = framebuffer.stencil_bits as _;
             desired.doublebuffer = true;
             desired.samples = sample_count;
             let closest = gl_choose_fbconfig(&mut desired, &usable_configs[..]);
@@ -378,9 +385,10 @@ impl Wgl {
         &mut self,
         display: &mut WindowsDisplay,
         sample_count: i32,
+        framebuffer: &crate::conf::FramebufferConfig,
         swap_interval: i32,
     ) -> HGLRC {
-        let pixel_format = self.wgl_find_pixel_format(display, sample_count);
+        let pixel_format = self.wgl_find_pixel_format(display, sample_count, framebuffer);
         if 0 == pixel_format {
             panic!("WGL: Didn't find matching pixel format.");
         }